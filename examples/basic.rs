@@ -3,9 +3,9 @@ use system_tray::client::Client;
 #[tokio::main]
 async fn main() {
     let client = Client::new().await.unwrap();
-    let mut tray_rx = client.subscribe();
+    let (snapshot, mut tray_rx) = client.subscribe_with_state();
 
-    let initial_items = client.items();
+    let initial_items = snapshot.items;
 
     // do something with initial items...
     drop(initial_items);
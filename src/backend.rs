@@ -0,0 +1,56 @@
+use crate::client::{ActivateRequest, Client, Event, Snapshot};
+use tokio::sync::broadcast;
+use zbus::export::futures_util::future::BoxFuture;
+
+/// A source of tray items.
+///
+/// Consumers written against this trait rather than [`Client`]
+/// can be used with other sources of items,
+/// such as a bridge for legacy `XEmbed` icons, a simulator for tests, or a remote tray.
+///
+/// [`Client`] is the default backend,
+/// serving items using the `StatusNotifierItem` and `DBusMenu` protocols.
+///
+/// ```no_run
+/// # use system_tray::backend::TrayBackend;
+/// # use system_tray::client::Client;
+/// async fn run(backend: &dyn TrayBackend) {
+///     let mut tray_rx = backend.subscribe();
+///     let initial_items = backend.snapshot().items;
+///
+///     while let Ok(ev) = tray_rx.recv().await {
+///         println!("{ev:?}");
+///     }
+/// }
+///
+/// # async fn main_() {
+/// let client = Client::new().await.unwrap();
+/// run(&client).await;
+/// # }
+/// ```
+pub trait TrayBackend: Send + Sync {
+    /// Subscribes to item and menu events,
+    /// returning a new receiver.
+    fn subscribe(&self) -> broadcast::Receiver<Event>;
+
+    /// Takes a consistent copy of all current items and their menus.
+    fn snapshot(&self) -> Snapshot;
+
+    /// Sends an activate request to an item,
+    /// resolving once it has been handled.
+    fn activate(&self, req: ActivateRequest) -> BoxFuture<'_, crate::error::Result<()>>;
+}
+
+impl TrayBackend for Client {
+    fn subscribe(&self) -> broadcast::Receiver<Event> {
+        Client::subscribe(self)
+    }
+
+    fn snapshot(&self) -> Snapshot {
+        Client::snapshot(self)
+    }
+
+    fn activate(&self, req: ActivateRequest) -> BoxFuture<'_, crate::error::Result<()>> {
+        Box::pin(Client::activate(self, req))
+    }
+}
@@ -1,66 +1,366 @@
-use crate::dbus::dbus_menu_proxy::{DBusMenuProxy, PropertiesUpdate};
+use crate::dbus::dbus_menu_proxy::{DBusMenuProxy, LayoutUpdatedStream, PropertiesUpdate};
 use crate::dbus::notifier_item_proxy::StatusNotifierItemProxy;
 use crate::dbus::notifier_watcher_proxy::StatusNotifierWatcherProxy;
-use crate::dbus::status_notifier_watcher::StatusNotifierWatcher;
+use crate::dbus::status_notifier_watcher::{StatusNotifierWatcher, WatcherEvent};
 use crate::dbus::{self, OwnedValueExt};
-use crate::error::Error;
-use crate::item::{self, Status, StatusNotifierItem, Tooltip};
-use crate::menu::{MenuDiff, TrayMenu};
+use crate::desktop::{DesktopEntries, DesktopEntry};
+use crate::error::{ActivationError, Error};
+use crate::item::{self, AnimatedIcon, IconFrame, IconPixmap, Status, StatusNotifierItem, Tooltip};
+use crate::menu::{MenuDiff, MenuItem, MenuSearchResult, Modifier, TrayMenu};
 use crate::names;
+use crate::sandbox::Sandbox;
 use dbus::DBusProps;
-use std::collections::HashMap;
-use std::sync::{Arc, Mutex};
-use std::time::{Duration, SystemTime, UNIX_EPOCH};
-use tokio::spawn;
-use tokio::sync::broadcast;
-use tokio::time::timeout;
+use std::collections::{HashMap, HashSet};
+use std::future::Future;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, OnceLock, RwLock};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tokio::sync::{broadcast, oneshot, watch};
+use tokio::task::{AbortHandle, JoinSet};
+use tokio::time::{sleep, sleep_until, timeout, timeout_at};
 use tracing::{debug, error, trace, warn};
+use zbus::export::futures_util::stream::{self, select_all};
 use zbus::export::futures_util::StreamExt;
 use zbus::fdo::{DBusProxy, PropertiesProxy};
-use zbus::names::InterfaceName;
-use zbus::zvariant::{Structure, Value};
-use zbus::{Connection, Message};
+use zbus::names::{BusName, InterfaceName, OwnedUniqueName, UniqueName, WellKnownName};
+use zbus::zvariant::{Array, ObjectPath, OwnedValue, Structure, Value};
+use zbus::{CacheProperties, Connection, Message};
 
+use self::animation::AnimationDetector;
+use self::batch::UpdateBatcher;
+use self::command::spawn_processor;
+use self::debounce::UpdateDebouncer;
+use self::disconnect::DisconnectDispatcher;
+use self::instrument::observe;
+use self::latency::LatencyTracker;
 use self::names::ITEM_OBJECT;
+use self::pixmap_cache::PixmapCache;
+use self::queue::ActivationQueue;
+use self::queued::QueuedSubscribers;
+use self::retry::retry;
+
+mod animation;
+mod batch;
+mod command;
+mod debounce;
+mod disconnect;
+mod filter;
+mod handler;
+mod instrument;
+mod latency;
+mod menu_handle;
+mod options;
+mod pixmap_cache;
+mod queue;
+mod queued;
+mod resync;
+mod retry;
+pub mod simulate;
+
+pub use crate::dbus::status_notifier_watcher::{AdmissionPolicy, Registration, RegistrationKind};
+pub use command::{Command, CommandSender};
+pub use filter::{Filter, FilteredReceiver};
+pub use handler::HandlerHandle;
+pub use instrument::{CallInfo, CallObserver};
+pub use menu_handle::MenuHandle;
+pub use options::{ClientBuilder, ClientOptions};
+pub use queued::{OverflowPolicy, QueuedReceiver};
+pub use resync::ResyncReceiver;
+pub use retry::RetryPolicy;
 
 /// An event emitted by the client
 /// representing a change from either the `StatusNotifierItem`
 /// or `DBusMenu` protocols.
+///
+/// Items are identified by their address.
+/// This is the bus name of the process exposing the item,
+/// followed by its object path if that is not the standard `/StatusNotifierItem`,
+/// for example `:1.72/org/ayatana/NotificationItem/dropbox`.
+/// A process may expose several items at different paths,
+/// each of which is tracked separately.
 #[derive(Debug, Clone)]
 pub enum Event {
     /// A new `StatusNotifierItem` was added.
-    Add(String, Box<StatusNotifierItem>),
+    ///
+    /// The item is shared between all subscribers rather than copied for each,
+    /// as it may hold large icon pixmaps.
+    Add(String, Arc<StatusNotifierItem>),
     /// An update was received for an existing `StatusNotifierItem`.
     /// This could be either an update to the item itself,
     /// or an update to the associated menu.
     Update(String, UpdateEvent),
+    /// Several updates were received for an existing `StatusNotifierItem`
+    /// in quick succession, and should be applied together.
+    ///
+    /// This is only sent if enabled using [`ClientOptions::batch_window`].
+    UpdateBatch(String, Vec<UpdateEvent>),
     /// A `StatusNotifierItem` was unregistered.
     Remove(String),
+    /// An item has consistently been slow to respond to calls,
+    /// which may make the tray feel sluggish.
+    /// The average call latency is sent.
+    ///
+    /// This is sent once each time the item becomes slow.
+    /// See [`ClientOptions::slow_item_threshold`].
+    SlowItem(String, Duration),
+    /// An item is animating its icon by changing it rapidly.
+    /// The collected frames are sent.
+    ///
+    /// While an item is animating, individual [`UpdateEvent::Icon`] updates are not sent,
+    /// although the item's icon in the client's state is still kept current.
+    /// When the animation stops, a single `Icon` update is sent with the final icon.
+    /// See [`ClientOptions::animation_threshold`].
+    AnimatedIcon(String, AnimatedIcon),
+    /// The embedded watcher was found to be detached from the bus
+    /// and has been restarted.
+    ///
+    /// Items are re-registered with the new watcher,
+    /// so consumers will receive `Remove` and `Add` events for them.
+    WatcherRestarted,
+    /// The watcher owning `org.kde.StatusNotifierWatcher` changed,
+    /// for example because the previous one exited and a queued one took over.
+    ///
+    /// The host has been registered with the new watcher,
+    /// and the items replaced with those registered with it,
+    /// so consumers will receive `Remove` and `Add` events for them.
+    WatcherChanged,
+    /// All items registered with the watcher when the client started have been processed,
+    /// and `Add` events sent for them.
+    ///
+    /// This is sent once, and can be used to avoid rendering a partial tray at startup.
+    /// See [`Client::wait_ready`].
+    Ready,
+    /// The receiver fell behind and events were dropped before it could receive them.
+    /// The snapshot holds the current state,
+    /// which should replace the consumer's copy entirely.
+    ///
+    /// This is only sent by a [`ResyncReceiver`].
+    /// See [`Client::subscribe_resync`].
+    Resync(Arc<Snapshot>),
+    /// An activate request sent to an item failed.
+    /// The error message is sent.
+    ActivationFailed(String, String),
+    /// An item's menu layout could not be fetched, even after retrying,
+    /// so its menu is no longer being watched and may be out of date.
+    /// The error message is sent.
+    ///
+    /// See [`ClientOptions::fetch_retry`].
+    MenuAbandoned(String, String),
+    /// Something went wrong with an item,
+    /// for example it sent invalid properties or stopped responding.
+    ///
+    /// If this happens while an item is being added, no `Add` event is sent for it.
+    /// Otherwise, the item may stop receiving updates.
+    Error {
+        /// The address of the item.
+        address: String,
+        /// What went wrong.
+        error: Arc<Error>,
+    },
 }
 
 /// The specific change associated with an update event.
 #[derive(Debug, Clone)]
 pub enum UpdateEvent {
-    AttentionIcon(Option<String>),
-    Icon(Option<String>),
-    OverlayIcon(Option<String>),
+    /// The attention icon changed.
+    /// Items may provide a themed icon name, a pixmap, or both.
+    AttentionIcon {
+        icon_name: Option<String>,
+        icon_pixmap: Option<Vec<IconPixmap>>,
+    },
+    /// The icon changed.
+    /// Items may provide a themed icon name, a pixmap, or both.
+    Icon {
+        icon_name: Option<String>,
+        icon_pixmap: Option<Vec<IconPixmap>>,
+    },
+    /// The overlay icon changed.
+    /// Items may provide a themed icon name, a pixmap, or both.
+    OverlayIcon {
+        icon_name: Option<String>,
+        icon_pixmap: Option<Vec<IconPixmap>>,
+    },
     Status(Status),
     Title(Option<String>),
     Tooltip(Option<Tooltip>),
+    /// The directory the item's icon names should be looked up in changed.
+    /// This is translated into a host path for sandboxed items,
+    /// as with [`StatusNotifierItem::icon_theme_path`].
+    IconThemePath(Option<String>),
+    /// The item's Ayatana label changed.
+    /// See [`StatusNotifierItem::label`].
+    Label {
+        label: Option<String>,
+        guide: Option<String>,
+    },
+    /// The item's Ayatana ordering index changed.
+    /// See [`StatusNotifierItem::ordering_index`].
+    OrderingIndex(Option<u32>),
     /// A menu layout has changed.
-    /// The entire layout is sent.
-    Menu(TrayMenu),
+    /// The entire layout is sent,
+    /// shared between all subscribers rather than copied for each.
+    Menu(Arc<TrayMenu>),
     /// One or more menu properties have changed.
     /// Only the updated properties are sent.
     MenuDiff(Vec<MenuDiff>),
+    /// Part of a menu layout has changed.
+    /// The new children of the item with id `parent_id` are sent,
+    /// and should replace its existing children.
+    ///
+    /// This is sent instead of [`UpdateEvent::Menu`] when the item reports
+    /// that only a single branch of its menu changed.
+    /// See [`TrayMenu::replace_submenu`].
+    MenuSubtree {
+        parent_id: i32,
+        subtree: Arc<Vec<MenuItem>>,
+    },
     /// A new menu has connected to the item.
     /// Its name on bus is sent.
+    ///
+    /// This is sent again if the item later changes its `Menu` property,
+    /// in which case any previous menu should be discarded.
     MenuConnect(String),
+    /// The object the item's `Menu` property points at
+    /// does not exist or never responded.
+    ///
+    /// Hosts should hide any menu affordance for the item,
+    /// rather than waiting for a menu which will never arrive.
+    MenuOrphaned,
+}
+
+impl UpdateEvent {
+    /// Applies the change to a copy of the item it was sent for.
+    ///
+    /// Menu layout changes do not affect the item itself, so are ignored.
+    pub fn apply_to(&self, item: &mut StatusNotifierItem) {
+        match self {
+            Self::AttentionIcon {
+                icon_name,
+                icon_pixmap,
+            } => {
+                item.attention_icon_name.clone_from(icon_name);
+                item.attention_icon_pixmap.clone_from(icon_pixmap);
+            }
+            Self::Icon {
+                icon_name,
+                icon_pixmap,
+            } => {
+                item.icon_name.clone_from(icon_name);
+                item.icon_pixmap.clone_from(icon_pixmap);
+            }
+            Self::OverlayIcon {
+                icon_name,
+                icon_pixmap,
+            } => {
+                item.overlay_icon_name.clone_from(icon_name);
+                item.overlay_icon_pixmap.clone_from(icon_pixmap);
+            }
+            Self::Status(status) => item.status = *status,
+            Self::Title(title) => item.title.clone_from(title),
+            Self::Tooltip(tooltip) => item.tool_tip.clone_from(tooltip),
+            Self::IconThemePath(path) => item.icon_theme_path.clone_from(path),
+            Self::Label { label, guide } => {
+                item.label.clone_from(label);
+                item.label_guide.clone_from(guide);
+            }
+            Self::OrderingIndex(index) => item.ordering_index = *index,
+            Self::MenuConnect(menu) => item.menu = Some(menu.clone()),
+            Self::MenuOrphaned => item.menu = None,
+            Self::Menu(_) | Self::MenuDiff(_) | Self::MenuSubtree { .. } => {}
+        }
+    }
+
+    /// Gets the kind of item property the change is for,
+    /// or `None` if it is a change to the item's menu.
+    #[must_use]
+    pub fn kind(&self) -> Option<UpdateKind> {
+        match self {
+            Self::AttentionIcon { .. } => Some(UpdateKind::AttentionIcon),
+            Self::Icon { .. } => Some(UpdateKind::Icon),
+            Self::OverlayIcon { .. } => Some(UpdateKind::OverlayIcon),
+            Self::Status(_) => Some(UpdateKind::Status),
+            Self::Title(_) => Some(UpdateKind::Title),
+            Self::Tooltip(_) => Some(UpdateKind::Tooltip),
+            Self::IconThemePath(_) => Some(UpdateKind::IconThemePath),
+            Self::Label { .. } => Some(UpdateKind::Label),
+            Self::OrderingIndex(_) => Some(UpdateKind::OrderingIndex),
+            Self::Menu(_)
+            | Self::MenuDiff(_)
+            | Self::MenuSubtree { .. }
+            | Self::MenuConnect(_)
+            | Self::MenuOrphaned => None,
+        }
+    }
+
+    /// Checks whether the change would leave a copy of the item it was sent for as it is.
+    ///
+    /// Menu changes are never considered unchanged.
+    #[must_use]
+    pub fn is_unchanged(&self, item: &StatusNotifierItem) -> bool {
+        match self {
+            Self::AttentionIcon {
+                icon_name,
+                icon_pixmap,
+            } => {
+                item.attention_icon_name == *icon_name && item.attention_icon_pixmap == *icon_pixmap
+            }
+            Self::Icon {
+                icon_name,
+                icon_pixmap,
+            } => item.icon_name == *icon_name && item.icon_pixmap == *icon_pixmap,
+            Self::OverlayIcon {
+                icon_name,
+                icon_pixmap,
+            } => item.overlay_icon_name == *icon_name && item.overlay_icon_pixmap == *icon_pixmap,
+            Self::Status(status) => item.status == *status,
+            Self::Title(title) => item.title == *title,
+            Self::Tooltip(tooltip) => item.tool_tip == *tooltip,
+            Self::IconThemePath(path) => item.icon_theme_path == *path,
+            Self::Label { label, guide } => item.label == *label && item.label_guide == *guide,
+            Self::OrderingIndex(index) => item.ordering_index == *index,
+            Self::Menu(_)
+            | Self::MenuDiff(_)
+            | Self::MenuSubtree { .. }
+            | Self::MenuConnect(_)
+            | Self::MenuOrphaned => false,
+        }
+    }
+}
+
+/// The item property an [`UpdateEvent`] changes.
+/// See [`ClientOptions::suppress_unchanged`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum UpdateKind {
+    AttentionIcon,
+    Icon,
+    OverlayIcon,
+    Status,
+    Title,
+    Tooltip,
+    IconThemePath,
+    Label,
+    OrderingIndex,
+}
+
+impl UpdateKind {
+    /// Every kind of update.
+    pub const ALL: [Self; 9] = [
+        Self::AttentionIcon,
+        Self::Icon,
+        Self::OverlayIcon,
+        Self::Status,
+        Self::Title,
+        Self::Tooltip,
+        Self::IconThemePath,
+        Self::Label,
+        Self::OrderingIndex,
+    ];
 }
 
 /// A request to 'activate' one of the menu items,
 /// typically sent when it is clicked.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum ActivateRequest {
     /// Submenu ID
     MenuItem {
@@ -70,600 +370,3473 @@ pub enum ActivateRequest {
     },
     /// Default activation for the tray.
     /// The parameter(x and y) represents screen coordinates and is to be considered an hint to the item where to show eventual windows (if any).
-    Default { address: String, x: i32, y: i32 },
+    ///
+    /// On Wayland, an XDG activation token from the compositor can be passed as `token`,
+    /// allowing the item to focus the window it shows.
+    /// It is sent first if the item supports receiving one.
+    Default {
+        address: String,
+        x: i32,
+        y: i32,
+        token: Option<String>,
+    },
     /// Secondary activation(less important) for the tray.
     /// The parameter(x and y) represents screen coordinates and is to be considered an hint to the item where to show eventual windows (if any).
-    Secondary { address: String, x: i32, y: i32 },
+    ///
+    /// See [`ActivateRequest::Default`] for `token`.
+    Secondary {
+        address: String,
+        x: i32,
+        y: i32,
+        token: Option<String>,
+    },
+    /// Asks the item to show its own context menu,
+    /// typically sent on right-click for items which do not export a menu.
+    /// The parameter(x and y) represents screen coordinates and is to be considered an hint to the item where to show the menu.
+    ContextMenu { address: String, x: i32, y: i32 },
+    /// Scrolls over the item, for example using the mouse wheel.
+    /// The delta is the amount scrolled, in no particular unit,
+    /// and is negative when scrolling up or left.
+    Scroll {
+        address: String,
+        delta: i32,
+        orientation: Orientation,
+    },
 }
 
-type State = HashMap<String, (StatusNotifierItem, Option<TrayMenu>)>;
+/// An event sent to a menu item,
+/// describing how the user interacted with it.
+///
+/// See [`MenuHandle`] and [`Client::event_group`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MenuEvent {
+    /// The item was clicked.
+    Clicked,
+    /// The pointer is hovering over the item.
+    Hovered,
+    /// The item's submenu was opened.
+    Opened,
+    /// The item's submenu was closed.
+    Closed,
+}
 
-const PROPERTIES_INTERFACE: &str = "org.kde.StatusNotifierItem";
+impl MenuEvent {
+    /// Gets the name of the event as sent to items.
+    #[must_use]
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Clicked => "clicked",
+            Self::Hovered => "hovered",
+            Self::Opened => "opened",
+            Self::Closed => "closed",
+        }
+    }
+}
 
-/// Client for watching the tray.
-#[derive(Debug)]
-pub struct Client {
-    tx: broadcast::Sender<Event>,
-    _rx: broadcast::Receiver<Event>,
-    connection: Connection,
+/// The direction of a scroll.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Orientation {
+    /// Up and down.
+    Vertical,
+    /// Left and right.
+    Horizontal,
+}
 
-    items: Arc<Mutex<State>>,
+impl Orientation {
+    /// Gets the name of the orientation as sent to items.
+    #[must_use]
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Vertical => "vertical",
+            Self::Horizontal => "horizontal",
+        }
+    }
 }
 
-impl Client {
-    /// Creates and initializes the client.
-    ///
-    /// The client will begin listening to items and menus and sending events immediately.
-    /// It is recommended that consumers immediately follow the call to `new` with a `subscribe` call,
-    /// then immediately follow that with a call to `items` to get the state to not miss any events.
-    ///
-    /// The value of `service_name` must be unique on the session bus.
-    /// It is recommended to use something similar to the format of `appid-numid`,
-    /// where `numid` is a short-ish random integer.
-    ///
-    /// # Errors
-    ///
-    /// If the initialization fails for any reason,
-    /// for example if unable to connect to the bus,
-    /// this method will return an error.
-    ///
-    /// # Panics
+impl ActivateRequest {
+    /// Gets the address of the item the request is sent to.
+    #[must_use]
+    pub fn address(&self) -> &str {
+        match self {
+            Self::MenuItem { address, .. }
+            | Self::Default { address, .. }
+            | Self::Secondary { address, .. }
+            | Self::ContextMenu { address, .. }
+            | Self::Scroll { address, .. } => address,
+        }
+    }
+}
+
+/// Information about the `StatusNotifierWatcher` currently serving the session.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WatcherInfo {
+    /// Whether the watcher embedded in this client owns the watcher name on the bus.
+    /// If this is false, another tray implementation is acting as the watcher.
+    pub is_embedded: bool,
+    /// The unique bus name of the current watcher, if there is one.
+    pub owner: Option<String>,
+}
+
+/// Information about the process behind an item.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProcessInfo {
+    /// The ID of the process which owns the item's bus connection.
+    pub pid: u32,
+    /// The ID of the user the process is running as.
+    pub uid: u32,
+    /// The path to the process executable, if it can be read.
+    pub exe: Option<PathBuf>,
+    /// The sandbox the process is running in, if any.
+    pub sandbox: Option<Sandbox>,
+}
+
+/// An event tagged with the state generation it brings the consumer up to.
+///
+/// See [`Client::subscribe_stamped`].
+#[derive(Debug, Clone)]
+pub struct StampedEvent {
+    /// The state generation after applying this event.
+    pub generation: u64,
+    /// The event itself.
+    pub event: Event,
+}
+
+/// A consistent copy of the client state.
+///
+/// See [`Client::snapshot`].
+#[derive(Debug, Clone)]
+pub struct Snapshot {
+    /// The state generation the snapshot was taken at.
     ///
-    /// If the generated well-known name is invalid, the library will panic
-    /// as this indicates a major bug.
+    /// Any [`StampedEvent`] with a generation less than or equal to this
+    /// is already reflected in the snapshot, and can be ignored.
+    pub generation: u64,
+    /// All items, including their menus if present.
+    pub items: State,
+    /// When each item and its menu were last updated.
+    pub timestamps: HashMap<String, ItemTimestamps>,
+    /// The addresses of items whose icon pixmaps were dropped from `items`
+    /// to stay within [`ClientOptions::pixmap_budget`].
     ///
-    /// Likewise, the spawned tasks may panic if they cannot get a `Mutex` lock.
-    pub async fn new() -> crate::error::Result<Self> {
-        let connection = Connection::session().await?;
-        let (tx, rx) = broadcast::channel(32);
+    /// Their pixmaps are missing rather than unset,
+    /// so consumers should keep any they already have,
+    /// or use [`Client::item_with_pixmaps`] to fetch them again.
+    pub evicted: HashSet<String>,
+}
 
-        // first start server...
-        StatusNotifierWatcher::new().attach_to(&connection).await?;
+/// A point in time, recorded using both the monotonic and wall clocks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Timestamp {
+    /// Monotonic time, for measuring how long ago something happened.
+    pub instant: Instant,
+    /// Wall-clock time, for display.
+    pub system: SystemTime,
+}
 
-        // ...then connect to it
-        let watcher_proxy = StatusNotifierWatcherProxy::new(&connection).await?;
+impl Timestamp {
+    /// Gets the current time.
+    #[must_use]
+    pub fn now() -> Self {
+        Self {
+            instant: Instant::now(),
+            system: SystemTime::now(),
+        }
+    }
 
-        // register a host on the watcher to declare we want to watch items
-        // get a well-known name
-        let pid = std::process::id();
-        let mut i = 0;
-        let wellknown = loop {
-            use zbus::fdo::RequestNameReply::*;
+    /// Gets the time elapsed since the timestamp.
+    #[must_use]
+    pub fn elapsed(&self) -> Duration {
+        self.instant.elapsed()
+    }
+}
 
-            i += 1;
-            let wellknown = format!("org.freedesktop.StatusNotifierHost-{pid}-{i}");
-            let wellknown: zbus::names::WellKnownName = wellknown
-                .try_into()
-                .expect("generated well-known name is invalid");
+/// When an item and its menu were last changed.
+///
+/// See [`Client::timestamps`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ItemTimestamps {
+    /// When the item was added.
+    pub added: Timestamp,
+    /// When an update was last sent for the item itself.
+    /// This is the time it was added until it is first updated.
+    pub item_updated: Timestamp,
+    /// When an update was last sent for the item's menu,
+    /// if there has been one.
+    pub menu_updated: Option<Timestamp>,
+}
 
-            let flags = [zbus::fdo::RequestNameFlags::DoNotQueue];
-            match connection
-                .request_name_with_flags(&wellknown, flags.into_iter().collect())
-                .await?
-            {
-                PrimaryOwner => break wellknown,
-                Exists | AlreadyOwner => {}
-                InQueue => unreachable!(
-                    "request_name_with_flags returned InQueue even though we specified DoNotQueue"
-                ),
-            };
-        };
+impl ItemTimestamps {
+    fn new() -> Self {
+        let now = Timestamp::now();
 
-        debug!("wellknown: {wellknown}");
-        watcher_proxy
-            .register_status_notifier_host(&wellknown)
-            .await?;
+        Self {
+            added: now,
+            item_updated: now,
+            menu_updated: None,
+        }
+    }
 
-        let items = Arc::new(Mutex::new(HashMap::new()));
+    /// Gets the time of the most recent update to either the item or its menu.
+    #[must_use]
+    pub fn last_updated(&self) -> Timestamp {
+        match self.menu_updated {
+            Some(menu_updated) if menu_updated.instant > self.item_updated.instant => menu_updated,
+            _ => self.item_updated,
+        }
+    }
+}
 
-        // handle new items
-        {
-            let connection = connection.clone();
-            let tx = tx.clone();
-            let items = items.clone();
+type State = HashMap<String, ItemState>;
 
-            let mut stream = watcher_proxy
-                .receive_status_notifier_item_registered()
-                .await?;
+/// The latest copy of an item and its menu, if present.
+pub type ItemState = (StatusNotifierItem, Option<TrayMenu>);
 
-            spawn(async move {
-                while let Some(item) = stream.next().await {
-                    let address = item.args().map(|args| args.service);
-
-                    if let Ok(address) = address {
-                        debug!("received new item: {address}");
-                        if let Err(err) = Self::handle_item(
-                            address,
-                            connection.clone(),
-                            tx.clone(),
-                            items.clone(),
-                        )
-                        .await
-                        {
-                            error!("{err}");
-                            break;
-                        }
-                    }
-                }
+const PROPERTIES_INTERFACE: &str = "org.kde.StatusNotifierItem";
 
-                Ok::<(), Error>(())
-            });
-        }
+/// The item signals which announce a property change.
+/// See [`Client::get_update_event`].
+const ITEM_SIGNALS: &[&str] = &[
+    "NewAttentionIcon",
+    "NewIcon",
+    "NewOverlayIcon",
+    "NewStatus",
+    "NewTitle",
+    "NewToolTip",
+    // Ayatana extensions sent by libappindicator items
+    "NewIconThemePath",
+    "XAyatanaNewLabel",
+    // not part of the spec, but sent by some items which change their menu
+    "NewMenu",
+];
 
-        // then lastly get all items
-        // it can take so long to fetch all items that we have to do this last,
-        // otherwise some incoming items get missed
-        {
-            let connection = connection.clone();
-            let tx = tx.clone();
-            let items = items.clone();
-
-            spawn(async move {
-                let initial_items = watcher_proxy.registered_status_notifier_items().await?;
-                debug!("initial items: {initial_items:?}");
-
-                for item in initial_items {
-                    if let Err(err) =
-                        Self::handle_item(&item, connection.clone(), tx.clone(), items.clone())
-                            .await
-                    {
-                        error!("{err}");
-                    }
-                }
+/// How many of the items present at startup are fetched at once.
+const INITIAL_ITEM_CONCURRENCY: usize = 8;
 
-                Ok::<(), Error>(())
-            });
-        }
+/// How many registrations with the embedded watcher are held
+/// while the client is busy handling earlier ones.
+const WATCHER_EVENT_CAPACITY: usize = 64;
 
-        // Handle other watchers unregistering and this one taking over
-        // It is necessary to clear all items as our watcher will then re-send them all
-        {
-            let tx = tx.clone();
-            let items = items.clone();
-
-            let dbus_proxy = DBusProxy::new(&connection).await?;
-
-            let mut stream = dbus_proxy.receive_name_acquired().await?;
-
-            spawn(async move {
-                while let Some(thing) = stream.next().await {
-                    let body = thing.args()?;
-                    if body.name == names::WATCHER_BUS {
-                        let mut items = items.lock().expect("mutex lock should succeed");
-                        let keys = items.keys().cloned().collect::<Vec<_>>();
-                        for address in keys {
-                            items.remove(&address);
-                            tx.send(Event::Remove(address))?;
-                        }
-                    }
-                }
+/// Client for watching the tray.
+///
+/// The client is a cheap handle to shared state,
+/// so can be cloned to use it from several tasks.
+/// It stops once every clone has been dropped,
+/// or [`Client::shutdown`] is called on any of them.
+#[derive(Debug, Clone)]
+pub struct Client {
+    ctx: Context,
+    lifecycle: Arc<Lifecycle>,
 
-                Ok::<(), Error>(())
-            });
-        }
+    activations: Arc<ActivationQueue>,
+    /// The index of installed desktop files,
+    /// loaded the first time it is needed.
+    desktop_entries: Arc<tokio::sync::Mutex<Option<DesktopEntries>>>,
+    commands: Arc<OnceLock<CommandSender>>,
+}
 
-        debug!("tray client initialized");
+/// What must be cleaned up once the client stops,
+/// which happens when the last clone of it is dropped.
+#[derive(Debug)]
+struct Lifecycle {
+    ctx: Context,
+    _rx: broadcast::Receiver<Event>,
+    /// The well-known name registered as a host.
+    /// This is `None` if the name could not be requested from inside a sandbox.
+    host_name: Mutex<Option<WellKnownName<'static>>>,
+    /// Whether the client attached the embedded watcher,
+    /// rather than relying on one attached by the application or another process.
+    is_embedded: bool,
+    /// Whether the host name and watcher have been released from the bus.
+    released: AtomicBool,
+}
 
-        Ok(Self {
-            connection,
-            tx,
-            _rx: rx,
-            items,
-        })
+/// State shared between the client and the tasks watching each item.
+#[derive(Debug, Clone)]
+struct Context {
+    connection: Connection,
+    tx: broadcast::Sender<Event>,
+    stamped_tx: broadcast::Sender<StampedEvent>,
+    /// Subscribers with their own queues.
+    /// See [`Client::subscribe_queued`].
+    queued: Arc<QueuedSubscribers>,
+    items: Arc<Mutex<State>>,
+    /// When each item in `items` was last changed.
+    timestamps: Arc<Mutex<HashMap<String, ItemTimestamps>>>,
+    /// Incremented on every change to the state.
+    /// When changing `items`, this must be incremented while holding its lock.
+    generation: Arc<AtomicU64>,
+    options: Arc<RwLock<ClientOptions>>,
+    batcher: Arc<UpdateBatcher>,
+    debouncer: Arc<UpdateDebouncer>,
+    latencies: Arc<LatencyTracker>,
+    /// Which items' pixmaps were used least recently,
+    /// for evicting them when over [`ClientOptions::pixmap_budget`].
+    pixmaps: Arc<PixmapCache>,
+    /// The sandbox this process is running in, if any.
+    sandbox: Option<Sandbox>,
+    /// Background tasks listening for changes,
+    /// which are stopped when the client shuts down.
+    tasks: Arc<Mutex<JoinSet<crate::error::Result<()>>>>,
+    /// Items which are in the middle of being added.
+    adding: Arc<Mutex<HashSet<String>>>,
+    /// The task watching each item's properties,
+    /// so that it stops once the item is removed.
+    item_tasks: Arc<Mutex<HashMap<String, AbortHandle>>>,
+    /// The task watching each item's menu,
+    /// so that it can be replaced if the item's menu changes.
+    menu_tasks: Arc<Mutex<HashMap<String, AbortHandle>>>,
+    /// Whether the initial items have been processed.
+    ready: Arc<watch::Sender<bool>>,
+    /// Channels publishing the latest state of individual items.
+    /// See [`Client::watch_item`].
+    item_watchers: Arc<Mutex<HashMap<String, watch::Sender<ItemState>>>>,
+    /// Notifies item and menu watchers when their process leaves the bus.
+    disconnects: Arc<DisconnectDispatcher>,
+    /// Notifies item watchers when their item unregisters from the watcher,
+    /// keyed by item rather than bus name.
+    unregistrations: Arc<DisconnectDispatcher>,
+    /// Receives registrations with the embedded watcher directly,
+    /// including from any it is replaced with when restarted.
+    watcher_events: broadcast::Sender<WatcherEvent>,
+}
+
+impl Context {
+    /// Gets a copy of the options currently in use.
+    fn options(&self) -> ClientOptions {
+        self.options.read().expect("lock should succeed").clone()
     }
 
-    /// Processes an incoming item to send the initial add event,
-    /// then set up listeners for it and its menu.
-    async fn handle_item(
-        address: &str,
-        connection: Connection,
-        tx: broadcast::Sender<Event>,
-        items: Arc<Mutex<State>>,
-    ) -> crate::error::Result<()> {
-        let (destination, path) = parse_address(address);
+    /// Spawns a background task which is stopped when the client shuts down,
+    /// first cleaning up any which have finished
+    /// so that they do not build up as items come and go.
+    ///
+    /// The task is given its own copy of the context.
+    fn spawn<F>(&self, task: impl FnOnce(Self) -> F) -> AbortHandle
+    where
+        F: Future<Output = crate::error::Result<()>> + Send + 'static,
+    {
+        let task = task(self.clone());
 
-        let properties_proxy = PropertiesProxy::builder(&connection)
-            .destination(destination.to_string())?
-            .path(path.clone())?
-            .build()
-            .await?;
+        let mut tasks = self.tasks.lock().expect("mutex lock should succeed");
+        while tasks.try_join_next().is_some() {}
+        tasks.spawn(task)
+    }
 
-        let properties = Self::get_item_properties(destination, &path, &properties_proxy).await?;
+    /// Stops the task watching an item's properties, if there is one.
+    fn stop_watching(&self, destination: &str) {
+        let task = self
+            .item_tasks
+            .lock()
+            .expect("mutex lock should succeed")
+            .remove(destination);
 
-        items
+        if let Some(task) = task {
+            task.abort();
+        }
+    }
+
+    /// Stops the task watching an item's menu, if any.
+    fn disconnect_menu(&self, destination: &str) {
+        let task = self
+            .menu_tasks
             .lock()
             .expect("mutex lock should succeed")
-            .insert(destination.into(), (properties.clone(), None));
+            .remove(destination);
 
-        tx.send(Event::Add(
-            destination.to_string(),
-            properties.clone().into(),
-        ))?;
+        if let Some(task) = task {
+            task.abort();
+        }
+    }
 
-        {
-            let connection = connection.clone();
-            let destination = destination.to_string();
-            let tx = tx.clone();
+    /// Takes all background tasks, so that they can be stopped.
+    fn take_tasks(&self) -> JoinSet<crate::error::Result<()>> {
+        std::mem::take(&mut *self.tasks.lock().expect("mutex lock should succeed"))
+    }
 
-            spawn(async move {
-                Self::watch_item_properties(&destination, &path, &connection, properties_proxy, tx)
-                    .await?;
+    /// Marks the initial items as processed,
+    /// sending [`Event::Ready`].
+    fn set_ready(&self) -> crate::error::Result<()> {
+        self.ready.send_replace(true);
 
-                debug!("Stopped watching {destination}{path}");
-                Ok::<(), Error>(())
-            });
-        }
+        let generation = self.generation.load(Ordering::SeqCst);
+        self.emit(generation, Event::Ready)
+    }
 
-        if let Some(menu) = properties.menu {
-            let destination = destination.to_string();
+    /// Drops all cached item and menu state.
+    fn clear(&self) {
+        let mut items = self.items.lock().expect("mutex lock should succeed");
+        items.clear();
+        self.next_generation();
 
-            tx.send(Event::Update(
-                destination.clone(),
-                UpdateEvent::MenuConnect(menu.clone()),
-            ))?;
+        self.item_watchers
+            .lock()
+            .expect("mutex lock should succeed")
+            .clear();
 
-            spawn(async move {
-                Self::watch_menu(destination, &menu, &connection, tx, items).await?;
-                Ok::<(), Error>(())
+        self.timestamps
+            .lock()
+            .expect("mutex lock should succeed")
+            .clear();
+
+        self.pixmaps.clear();
+    }
+
+    /// Increments the state generation, returning the new value.
+    fn next_generation(&self) -> u64 {
+        self.generation.fetch_add(1, Ordering::SeqCst) + 1
+    }
+
+    /// Sends an event to all subscribers.
+    fn emit(&self, generation: u64, event: Event) -> crate::error::Result<()> {
+        if self.stamped_tx.receiver_count() > 0 {
+            // this can only fail if all receivers dropped in the meantime
+            let _ = self.stamped_tx.send(StampedEvent {
+                generation,
+                event: event.clone(),
             });
         }
 
+        self.queued.send(&event);
+        self.tx.send(event)?;
         Ok(())
     }
 
-    /// Gets the properties for an SNI item.
-    async fn get_item_properties(
-        destination: &str,
-        path: &str,
-        properties_proxy: &PropertiesProxy<'_>,
-    ) -> crate::error::Result<StatusNotifierItem> {
-        let properties = properties_proxy
-            .get_all(
-                InterfaceName::from_static_str(PROPERTIES_INTERFACE)
-                    .expect("to be valid interface name"),
-            )
-            .await;
-
-        let properties = match properties {
-            Ok(properties) => properties,
-            Err(err) => {
-                error!("Error fetching properties from {destination}{path}: {err:?}");
-                return Err(err.into());
-            }
+    /// Sends an update event for an item,
+    /// batching it with other updates if enabled.
+    ///
+    /// Updates which change nothing are dropped
+    /// if enabled by [`ClientOptions::suppress_unchanged`],
+    /// and updates are held back if debounced by [`ClientOptions::debounce`].
+    fn send_update(&self, destination: &str, event: UpdateEvent) -> crate::error::Result<()> {
+        let Some(generation) = self.cache_update(destination, &event) else {
+            trace!("[{destination}] suppressing unchanged update: {event:?}");
+            return Ok(());
         };
 
-        StatusNotifierItem::try_from(DBusProps(properties))
+        let debounce = event.kind().and_then(|kind| {
+            let options = self.options.read().expect("lock should succeed");
+            options.debounce.get(&kind).map(|window| (kind, *window))
+        });
+
+        match debounce {
+            Some((kind, window)) => {
+                self.debounce_update(generation, destination, kind, window, event);
+                Ok(())
+            }
+            None => self.send_update_at(generation, destination, event),
+        }
     }
 
-    /// Watches an SNI item's properties,
-    /// sending an update event whenever they change.
-    async fn watch_item_properties(
+    /// Holds back an update until `window` has passed,
+    /// replacing it with any later update of the same kind for the item in the meantime.
+    fn debounce_update(
+        &self,
+        generation: u64,
         destination: &str,
-        path: &str,
-        connection: &Connection,
-        properties_proxy: PropertiesProxy<'_>,
-        tx: broadcast::Sender<Event>,
-    ) -> crate::error::Result<()> {
-        let notifier_item_proxy = StatusNotifierItemProxy::builder(connection)
-            .destination(destination)?
-            .path(path)?
-            .build()
-            .await?;
+        kind: UpdateKind,
+        window: Duration,
+        event: UpdateEvent,
+    ) {
+        if !self.debouncer.push(destination, kind, generation, event) {
+            return;
+        }
 
-        let dbus_proxy = DBusProxy::new(connection).await?;
+        let destination = destination.to_string();
+        self.spawn(|ctx| async move {
+            sleep(window).await;
+            if let Some((generation, event)) = ctx.debouncer.take(&destination, kind) {
+                if let Err(err) = ctx.send_update_at(generation, &destination, event) {
+                    error!("{err}");
+                }
+            }
 
-        let mut disconnect_stream = dbus_proxy.receive_name_owner_changed().await?;
-        let mut props_changed = notifier_item_proxy.receive_all_signals().await?;
+            Ok(())
+        });
+    }
 
-        loop {
-            tokio::select! {
-                Some(change) = props_changed.next() => {
-                    if let Some(event) = Self::get_update_event(change, &properties_proxy).await {
-                        debug!("[{destination}{path}] received property change: {event:?}");
-                        tx.send(Event::Update(destination.to_string(), event))?;
-                    }
-                }
-                Some(signal) = disconnect_stream.next() => {
-                    let args = signal.args()?;
-                    let old = args.old_owner();
-                    let new = args.new_owner();
+    /// Applies an update to the cached copy of an item,
+    /// returning the new state generation,
+    /// or `None` if the update changes nothing and should be suppressed.
+    fn cache_update(&self, destination: &str, event: &UpdateEvent) -> Option<u64> {
+        let suppress = event.kind().is_some_and(|kind| {
+            self.options
+                .read()
+                .expect("lock should succeed")
+                .suppress_unchanged
+                .contains(&kind)
+        });
 
-                    if let (Some(old), None) = (old.as_ref(), new.as_ref()) {
-                        if old == destination {
-                            debug!("[{destination}{path}] disconnected");
+        let mut items = self.items.lock().expect("mutex lock should succeed");
 
-                            let watcher_proxy = StatusNotifierWatcherProxy::new(connection)
-                                .await
-                                .expect("Failed to open StatusNotifierWatcherProxy");
+        if let Some((item, menu)) = items.get_mut(destination) {
+            if suppress && event.is_unchanged(item) {
+                return None;
+            }
 
-                            if let Err(error) = watcher_proxy.unregister_status_notifier_item(old).await {
-                                error!("{error:?}");
-                            }
+            event.apply_to(item);
+
+            match (event, menu.as_mut()) {
+                (UpdateEvent::MenuConnect(_) | UpdateEvent::MenuOrphaned, _) => *menu = None,
+                (UpdateEvent::MenuDiff(diffs), Some(menu)) => menu.apply_diff(diffs),
+                _ => {}
+            }
+        }
+
+        self.publish_item(&items, destination);
+
+        if matches!(
+            event,
+            UpdateEvent::Icon { .. }
+                | UpdateEvent::AttentionIcon { .. }
+                | UpdateEvent::OverlayIcon { .. }
+        ) {
+            if let Some((item, _)) = items.get(destination) {
+                self.pixmaps.touch(destination, item);
+            }
+            self.enforce_pixmap_budget(&mut items);
+        }
+
+        Some(self.next_generation())
+    }
+
+    /// Drops the pixmaps of the least recently used items from the state
+    /// until it is within [`ClientOptions::pixmap_budget`], if set.
+    ///
+    /// This must be called while holding the `items` lock.
+    /// Evictions are not published to item watchers,
+    /// which keep the pixmaps they were last sent.
+    fn enforce_pixmap_budget(&self, items: &mut State) {
+        let budget = self
+            .options
+            .read()
+            .expect("lock should succeed")
+            .pixmap_budget;
+
+        let Some(budget) = budget else {
+            return;
+        };
+
+        for destination in self.pixmaps.evict(budget) {
+            if let Some((item, _)) = items.get_mut(&destination) {
+                debug!(destination, "evicting icon pixmaps");
+                pixmap_cache::evict_pixmaps(item);
+            }
+        }
+    }
+
+    /// Sends the cached copy of an item to anything watching it,
+    /// closing their channels if the item was removed.
+    ///
+    /// This must be called while holding the `items` lock,
+    /// so that watchers see changes in order.
+    fn publish_item(&self, items: &State, destination: &str) {
+        let mut watchers = self
+            .item_watchers
+            .lock()
+            .expect("mutex lock should succeed");
+
+        let Some(watcher) = watchers.get(destination) else {
+            return;
+        };
 
-                            tx.send(Event::Remove(destination.to_string()))?;
-                            break Ok(());
+        match items.get(destination) {
+            Some(state) if watcher.receiver_count() > 0 => {
+                watcher.send_replace(state.clone());
+            }
+            _ => {
+                watchers.remove(destination);
+            }
+        }
+    }
+
+    /// Sends an update event for an item at a specific state generation,
+    /// batching it with other updates if enabled.
+    fn send_update_at(
+        &self,
+        generation: u64,
+        destination: &str,
+        event: UpdateEvent,
+    ) -> crate::error::Result<()> {
+        self.touch(destination, &event);
+
+        let batch_window = self
+            .options
+            .read()
+            .expect("lock should succeed")
+            .batch_window;
+
+        match batch_window {
+            Some(window) => {
+                if self.batcher.push(destination, generation, event) {
+                    let destination = destination.to_string();
+
+                    self.spawn(|ctx| async move {
+                        sleep(window).await;
+                        if let Err(err) = ctx.flush_updates(&destination) {
+                            error!("{err}");
                         }
-                    }
+
+                        Ok(())
+                    });
                 }
+
+                Ok(())
             }
+            None => self.emit(generation, Event::Update(destination.to_string(), event)),
         }
     }
 
-    /// Gets the update event for a `DBus` properties change message.
-    async fn get_update_event(
-        change: Arc<Message>,
-        properties_proxy: &PropertiesProxy<'_>,
-    ) -> Option<UpdateEvent> {
-        let member = change.member()?;
+    /// Logs an error with an item, and sends it as an [`Event::Error`].
+    fn report_error(&self, key: &str, error: Error) {
+        error!("[{key}] {error}");
 
-        let property_name = match member.as_str() {
-            "NewAttentionIcon" => "AttentionIconName",
-            "NewIcon" => "IconName",
-            "NewOverlayIcon" => "OverlayIconName",
-            "NewStatus" => "Status",
-            "NewTitle" => "Title",
-            "NewToolTip" => "ToolTip",
-            _ => &member.as_str()["New".len()..],
+        // there is nobody left to tell
+        if matches!(error, Error::EventSend(_)) {
+            return;
+        }
+
+        let generation = self.generation.load(Ordering::SeqCst);
+        let event = Event::Error {
+            address: key.to_string(),
+            error: Arc::new(error),
         };
 
-        let res = properties_proxy
-            .get(
-                InterfaceName::from_static_str(PROPERTIES_INTERFACE)
-                    .expect("to be valid interface name"),
-                property_name,
-            )
-            .await;
+        if let Err(err) = self.emit(generation, event) {
+            error!("{err}");
+        }
+    }
 
-        let property = match res {
-            Ok(property) => property,
-            Err(err) => {
-                error!("error fetching property '{property_name}': {err:?}");
-                return None;
+    /// Applies any configured pixmap corrections for an item to its pixmaps.
+    fn apply_pixmap_quirks(&self, destination: &str, pixmaps: &mut [IconPixmap]) {
+        let id = {
+            let items = self.items.lock().expect("mutex lock should succeed");
+            match items.get(destination) {
+                Some((item, _)) => item.id.clone(),
+                None => return,
             }
         };
 
-        debug!("received tray item update: {member} -> {property:?}");
+        item::apply_quirks(pixmaps, self.options().pixmap_quirks_for(&id));
+    }
 
-        use UpdateEvent::*;
-        match member.as_str() {
-            "NewAttentionIcon" => Some(AttentionIcon(property.to_string())),
-            "NewIcon" => Some(Icon(property.to_string())),
-            "NewOverlayIcon" => Some(OverlayIcon(property.to_string())),
-            "NewStatus" => Some(Status(
-                property
-                    .downcast_ref::<str>()
-                    .map(item::Status::from)
-                    .unwrap_or_default(),
-            )),
-            "NewTitle" => Some(Title(property.to_string())),
-            "NewToolTip" => Some(Tooltip(
-                property
-                    .downcast_ref::<Structure>()
-                    .map(crate::item::Tooltip::try_from)?
-                    .ok(),
-            )),
+    /// Records that an update event was sent for an item.
+    fn touch(&self, destination: &str, event: &UpdateEvent) {
+        let mut timestamps = self.timestamps.lock().expect("mutex lock should succeed");
+        let Some(timestamps) = timestamps.get_mut(destination) else {
+            return;
+        };
+
+        let now = Timestamp::now();
+        match event {
+            UpdateEvent::Menu(_)
+            | UpdateEvent::MenuDiff(_)
+            | UpdateEvent::MenuSubtree { .. }
+            | UpdateEvent::MenuConnect(_)
+            | UpdateEvent::MenuOrphaned => timestamps.menu_updated = Some(now),
+            _ => timestamps.item_updated = now,
+        }
+    }
+
+    /// Immediately sends any pending batched updates for an item.
+    fn flush_updates(&self, destination: &str) -> crate::error::Result<()> {
+        let mut batch = self.batcher.take(destination);
+        let destination = destination.to_string();
+
+        match batch.len() {
+            0 => Ok(()),
+            1 => {
+                let (generation, event) = batch.remove(0);
+                self.emit(generation, Event::Update(destination, event))
+            }
             _ => {
-                warn!("received unhandled update event: {member}");
-                None
+                let generation = batch.last().map_or(0, |(generation, _)| *generation);
+                let events = batch.into_iter().map(|(_, event)| event).collect();
+                self.emit(generation, Event::UpdateBatch(destination, events))
             }
         }
     }
 
-    /// Watches the `DBusMenu` associated with an SNI item.
+    /// Awaits a D-Bus call, reporting it to the call observer if one is set.
+    async fn observe<T, E: std::fmt::Display>(
+        &self,
+        destination: &str,
+        method: &str,
+        call: impl Future<Output = Result<T, E>>,
+    ) -> Result<T, E> {
+        let observer = self
+            .options
+            .read()
+            .expect("lock should succeed")
+            .call_observer
+            .clone();
+
+        observe(observer.as_ref(), destination, method, call).await
+    }
+
+    /// Awaits a call to an item, recording how long it took.
     ///
-    /// This gets the initial menu, sending an update event immediately.
-    /// Update events are then sent for any further updates
-    /// until the item is removed.
-    async fn watch_menu(
-        destination: String,
-        menu_path: &str,
-        connection: &Connection,
-        tx: broadcast::Sender<Event>,
-        items: Arc<Mutex<State>>,
-    ) -> crate::error::Result<()> {
-        let dbus_menu_proxy = DBusMenuProxy::builder(connection)
-            .destination(destination.as_str())?
-            .path(menu_path)?
-            .build()
-            .await?;
+    /// If the item becomes consistently slow, a [`Event::SlowItem`] is sent.
+    ///
+    /// Calls to items which are not in the state are not recorded,
+    /// so that items removed while a call is in flight are not tracked forever.
+    async fn timed<T>(&self, destination: &str, call: impl Future<Output = T>) -> T {
+        let start = Instant::now();
+        let res = call.await;
+        let elapsed = start.elapsed();
 
-        let menu = dbus_menu_proxy.get_layout(0, 10, &[]).await?;
-        let menu = TrayMenu::try_from(menu)?;
+        let threshold = self
+            .options
+            .read()
+            .expect("lock should succeed")
+            .slow_item_threshold;
 
-        if let Some((_, menu_cache)) = items
+        let is_known = self
+            .items
             .lock()
             .expect("mutex lock should succeed")
-            .get_mut(&destination)
-        {
+            .contains_key(destination);
+
+        if let Some(threshold) = threshold.filter(|_| is_known) {
+            if let Some(average) = self.latencies.record(destination, elapsed, threshold) {
+                warn!(
+                    destination,
+                    average_ms = average.as_millis(),
+                    "item is consistently slow to respond"
+                );
+
+                let generation = self.generation.load(Ordering::SeqCst);
+                if let Err(err) = self.emit(
+                    generation,
+                    Event::SlowItem(destination.to_string(), average),
+                ) {
+                    error!("{err}");
+                }
+            }
+        }
+
+        res
+    }
+
+    /// Stores the latest menu for an item in the state,
+    /// returning the new state generation.
+    fn cache_menu(&self, destination: &str, menu: &TrayMenu) -> u64 {
+        let mut items = self.items.lock().expect("mutex lock should succeed");
+
+        if let Some((_, menu_cache)) = items.get_mut(destination) {
             menu_cache.replace(menu.clone());
         } else {
             error!("could not find item in state");
         }
 
-        tx.send(Event::Update(
-            destination.to_string(),
-            UpdateEvent::Menu(menu),
-        ))?;
-
-        let mut layout_updated = dbus_menu_proxy.receive_layout_updated().await?;
-        let mut properties_updated = dbus_menu_proxy.receive_items_properties_updated().await?;
-
-        loop {
-            tokio::select!(
-                Some(_) = layout_updated.next() => {
-                    debug!("[{destination}{menu_path}] layout update");
+        self.publish_item(&items, destination);
+        self.next_generation()
+    }
 
-                    let get_layout = dbus_menu_proxy.get_layout(0, 10, &[]);
+    /// Gets the layout revision of the cached menu for an item.
+    fn menu_revision(&self, destination: &str) -> Option<u32> {
+        let items = self.items.lock().expect("mutex lock should succeed");
 
-                    let menu = match timeout(Duration::from_secs(1), get_layout).await {
-                        Ok(Ok(menu)) => {
-                            debug!("got new menu layout");
-                            menu
-                        }
-                        Ok(Err(err)) => {
-                            error!("error fetching layout: {err:?}");
-                            break;
-                        }
-                        Err(_) => {
-                            error!("Timeout getting layout");
-                            break;
-                        }
-                    };
+        items
+            .get(destination)
+            .and_then(|(_, menu)| menu.as_ref())
+            .map(|menu| menu.id)
+    }
 
-                    let menu = TrayMenu::try_from(menu)?;
+    /// Checks whether the cached menu for an item contains an item with the given id.
+    fn menu_contains(&self, destination: &str, id: i32) -> bool {
+        let items = self.items.lock().expect("mutex lock should succeed");
 
-                    if let Some((_, menu_cache)) = items
-                        .lock()
-                        .expect("mutex lock should succeed")
-                        .get_mut(&destination)
-                    {
-                        menu_cache.replace(menu.clone());
-                    } else {
-                        error!("could not find item in state");
-                    }
+        items
+            .get(destination)
+            .and_then(|(_, menu)| menu.as_ref())
+            .is_some_and(|menu| menu.find(id).is_some())
+    }
 
-                    debug!("sending new menu for '{destination}'");
-                    trace!("new menu for '{destination}': {menu:?}");
-                    tx.send(Event::Update(
-                        destination.to_string(),
-                        UpdateEvent::Menu(menu),
-                    ))?;
-                }
-                Some(change) = properties_updated.next() => {
-                    let update = change.body::<PropertiesUpdate>()?;
-                    let diffs = Vec::try_from(update)?;
+    /// Replaces part of the cached menu for an item,
+    /// returning the new state generation,
+    /// or `None` if the parent is not in the cached menu.
+    fn cache_menu_subtree(
+        &self,
+        destination: &str,
+        revision: u32,
+        parent_id: i32,
+        subtree: &Arc<Vec<MenuItem>>,
+    ) -> Option<u64> {
+        let mut items = self.items.lock().expect("mutex lock should succeed");
 
-                    tx.send(Event::Update(
-                        destination.to_string(),
-                        UpdateEvent::MenuDiff(diffs),
-                    ))?;
+        let menu = items
+            .get_mut(destination)
+            .and_then(|(_, menu)| menu.as_mut())?;
 
-                    // FIXME: Menu cache gonna be out of sync
-                }
-            );
+        if !menu.replace_submenu(parent_id, subtree.clone()) {
+            return None;
         }
 
-        Ok(())
+        menu.id = revision;
+        self.publish_item(&items, destination);
+        Some(self.next_generation())
     }
 
-    async fn get_notifier_item_proxy(
-        &self,
-        address: String,
-    ) -> crate::error::Result<StatusNotifierItemProxy<'_>> {
-        let proxy = StatusNotifierItemProxy::builder(&self.connection)
-            .destination(address)?
-            .path(ITEM_OBJECT)?
-            .build()
-            .await?;
-        Ok(proxy)
-    }
+    /// Adds an item to the state and sends its add event.
+    fn send_add(&self, destination: &str, item: StatusNotifierItem) -> crate::error::Result<()> {
+        let generation = {
+            let mut items = self.items.lock().expect("mutex lock should succeed");
+            items.insert(destination.into(), (item.clone(), None));
+            self.publish_item(&items, destination);
+            self.pixmaps.touch(destination, &item);
+            self.enforce_pixmap_budget(&mut items);
+            self.timestamps
+                .lock()
+                .expect("mutex lock should succeed")
+                .insert(destination.into(), ItemTimestamps::new());
+            self.next_generation()
+        };
 
-    async fn get_menu_proxy(
-        &self,
-        address: String,
-        menu_path: String,
-    ) -> crate::error::Result<DBusMenuProxy<'_>> {
-        let proxy = DBusMenuProxy::builder(&self.connection)
-            .destination(address)?
-            .path(menu_path)?
-            .build()
-            .await?;
-        Ok(proxy)
+        self.emit(generation, Event::Add(destination.to_string(), item.into()))
     }
 
-    /// Subscribes to the events broadcast channel,
-    /// returning a new receiver.
-    ///
-    /// Once the client is dropped, the receiver will close.
-    #[must_use]
-    pub fn subscribe(&self) -> broadcast::Receiver<Event> {
-        self.tx.subscribe()
-    }
+    /// Removes an item from the state and sends its remove event,
+    /// after sending any of its pending updates.
+    fn send_remove(&self, destination: &str) -> crate::error::Result<()> {
+        let generation = {
+            let mut items = self.items.lock().expect("mutex lock should succeed");
+            items.remove(destination);
+            self.publish_item(&items, destination);
+            self.timestamps
+                .lock()
+                .expect("mutex lock should succeed")
+                .remove(destination);
+            self.next_generation()
+        };
 
-    /// Gets all current items, including their menus if present.
-    #[must_use]
-    pub fn items(&self) -> Arc<Mutex<State>> {
-        self.items.clone()
+        self.latencies.remove(destination);
+        self.pixmaps.remove(destination);
+        self.stop_watching(destination);
+        self.disconnect_menu(destination);
+        for (generation, event) in self.debouncer.take_all(destination) {
+            self.send_update_at(generation, destination, event)?;
+        }
+        self.flush_updates(destination)?;
+        self.emit(generation, Event::Remove(destination.to_string()))
     }
+}
 
-    /// Sends an activate request for a menu item.
+impl Client {
+    /// Creates and initializes the client.
+    ///
+    /// The client will begin listening to items and menus and sending events immediately.
+    /// It is recommended that consumers immediately follow the call to `new`
+    /// with a call to [`Client::subscribe_with_state`] to get the state without missing any events.
+    ///
+    /// The host is registered under a generated name
+    /// in the format `org.freedesktop.StatusNotifierHost-{pid}-{n}`.
+    /// Use [`Client::new_with_name`] to choose the name instead.
     ///
     /// # Errors
     ///
-    /// The method will return an error if the connection to the `DBus` object fails,
-    /// or if sending the event fails for any reason.
+    /// If the initialization fails for any reason,
+    /// for example if unable to connect to the bus,
+    /// this method will return an error.
     ///
     /// # Panics
     ///
-    /// If the system time is somehow before the Unix epoch.
-    pub async fn activate(&self, req: ActivateRequest) -> crate::error::Result<()> {
-        macro_rules! timeout_event {
-            ($event:expr) => {
-                if timeout(Duration::from_secs(1), $event).await.is_err() {
-                    error!("Timed out sending activate event");
-                }
-            };
-        }
-        match req {
-            ActivateRequest::MenuItem {
-                address,
-                menu_path,
-                submenu_id,
-            } => {
-                let proxy = self.get_menu_proxy(address, menu_path).await?;
-                let timestamp = SystemTime::now()
-                    .duration_since(UNIX_EPOCH)
-                    .expect("time should flow forwards");
-
-                let event = proxy.event(
-                    submenu_id,
-                    "clicked",
-                    &Value::I32(0),
-                    timestamp.as_secs() as u32,
-                );
-
-                timeout_event!(event);
-            }
-            ActivateRequest::Default { address, x, y } => {
-                let proxy = self.get_notifier_item_proxy(address).await?;
-                let event = proxy.activate(x, y);
+    /// If the generated well-known name is invalid, the library will panic
+    /// as this indicates a major bug.
+    ///
+    /// Likewise, the spawned tasks may panic if they cannot get a `Mutex` lock.
+    pub async fn new() -> crate::error::Result<Self> {
+        Self::with_options(ClientOptions::default(), None).await
+    }
 
-                timeout_event!(event);
-            }
-            ActivateRequest::Secondary { address, x, y } => {
-                let proxy = self.get_notifier_item_proxy(address).await?;
-                let event = proxy.secondary_activate(x, y);
+    /// Creates and initializes the client,
+    /// registering the host under the well-known name `name`
+    /// rather than a generated one.
+    ///
+    /// The name must be unique on the session bus,
+    /// such as `org.freedesktop.StatusNotifierHost-myapp`.
+    /// See [`ClientBuilder::host_name`], and [`Client::new`] for details.
+    ///
+    /// # Errors
+    ///
+    /// If the name is invalid or already owned by another connection,
+    /// or the initialization fails for any other reason,
+    /// this method will return an error.
+    pub async fn new_with_name(name: &str) -> crate::error::Result<Self> {
+        Self::builder().host_name(name).build().await
+    }
 
-                timeout_event!(event);
-            }
-        }
+    /// Creates and initializes the client using an existing bus connection,
+    /// rather than opening a new one.
+    ///
+    /// See [`ClientBuilder::connection`] for how the connection is used,
+    /// and [`Client::new`] for details.
+    ///
+    /// # Errors
+    ///
+    /// If the initialization fails for any reason,
+    /// this method will return an error.
+    pub async fn new_with_connection(connection: Connection) -> crate::error::Result<Self> {
+        Self::builder().connection(connection).build().await
+    }
 
-        Ok(())
+    /// Creates a builder for configuring the client before initializing it.
+    #[must_use]
+    pub fn builder() -> ClientBuilder {
+        ClientBuilder::new()
     }
-}
 
-fn parse_address(address: &str) -> (&str, String) {
-    address
-        .split_once('/')
-        .map_or((address, String::from("/StatusNotifierItem")), |(d, p)| {
-            (d, format!("/{p}"))
-        })
-}
+    async fn with_options(
+        options: ClientOptions,
+        connection: Option<Connection>,
+    ) -> crate::error::Result<Self> {
+        let connection = match connection {
+            Some(connection) => connection,
+            None => Connection::session().await?,
+        };
+        let observer = options.call_observer.clone();
+        let (tx, rx) = broadcast::channel(options.event_capacity.max(1));
+        let (stamped_tx, _) = broadcast::channel(options.event_capacity.max(1));
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+        let sandbox = Sandbox::current();
+        if let Some(sandbox) = &sandbox {
+            debug!("running inside {sandbox}");
+        }
 
-    #[test]
-    fn parse_unnamed() {
-        let address = ":1.58/StatusNotifierItem";
-        let (destination, path) = parse_address(address);
+        // first start server...
+        // the bus proxy used by sandboxes may not allow owning the watcher name,
+        // in which case an existing watcher outside the sandbox is relied on instead.
+        // without a bus there are no names to own at all,
+        // so the peer is relied on to serve the watcher and its items itself
+        let is_bus = connection.is_bus();
+        let (watcher_events, _) = broadcast::channel(WATCHER_EVENT_CAPACITY);
+        let is_embedded = if !options.embedded_watcher {
+            debug!("embedded watcher disabled, relying on an existing watcher");
+            false
+        } else if is_bus {
+            let watcher = Self::create_watcher(&options, &watcher_events);
+            match watcher.attach_to(&connection).await {
+                Ok(()) => true,
+                Err(err) if sandbox.is_some() => {
+                    warn!("could not start embedded watcher from inside sandbox: {err}");
+                    false
+                }
+                Err(err) => return Err(err.into()),
+            }
+        } else {
+            debug!("connected to a peer, not starting embedded watcher");
+            false
+        };
 
-        assert_eq!(":1.58", destination);
-        assert_eq!("/StatusNotifierItem", path);
-    }
+        // ...then connect to it
+        let watcher_proxy = StatusNotifierWatcherProxy::new(&connection).await?;
 
-    #[test]
+        // register a host on the watcher to declare we want to watch items
+        // get a well-known name
+        let pid = std::process::id();
+        let mut i = 0;
+        let wellknown = loop {
+            if !options.request_host_name || !is_bus {
+                break None;
+            }
+
+            use zbus::fdo::RequestNameReply::*;
+
+            let wellknown: WellKnownName = match &options.host_name {
+                Some(name) => WellKnownName::try_from(name.clone()).map_err(zbus::Error::from)?,
+                None => {
+                    i += 1;
+                    format!("org.freedesktop.StatusNotifierHost-{pid}-{i}")
+                        .try_into()
+                        .expect("generated well-known name is invalid")
+                }
+            };
+
+            let flags = [zbus::fdo::RequestNameFlags::DoNotQueue];
+            let request_name =
+                connection.request_name_with_flags(&wellknown, flags.into_iter().collect());
+
+            match observe(
+                observer.as_ref(),
+                names::DBUS_BUS,
+                "RequestName",
+                request_name,
+            )
+            .await
+            {
+                Ok(PrimaryOwner) => break Some(wellknown),
+                // a name chosen by the caller must be the one used
+                Ok(Exists | AlreadyOwner) | Err(zbus::Error::NameTaken)
+                    if options.host_name.is_some() =>
+                {
+                    return Err(zbus::Error::NameTaken.into());
+                }
+                // zbus reports names owned by another connection as an error,
+                // such as those of other clients in the same process
+                Ok(Exists | AlreadyOwner) | Err(zbus::Error::NameTaken) => {}
+                Ok(InQueue) => unreachable!(
+                    "request_name_with_flags returned InQueue even though we specified DoNotQueue"
+                ),
+                // sandbox bus proxies typically only allow owning names matching the app id,
+                // but the watcher accepts a unique name just as well
+                Err(err) if sandbox.is_some() => {
+                    warn!("could not request host name from inside sandbox: {err}");
+                    break None;
+                }
+                Err(err) => return Err(err.into()),
+            };
+        };
+
+        // peers have no unique names,
+        // but the watcher still needs something to identify the host by
+        let host_service = match (&wellknown, connection.unique_name()) {
+            (Some(wellknown), _) => wellknown.to_string(),
+            (None, Some(unique_name)) => unique_name.to_string(),
+            (None, None) => format!("org.freedesktop.StatusNotifierHost-{pid}"),
+        };
+
+        debug!("host name: {host_service}");
+        observe(
+            observer.as_ref(),
+            names::WATCHER_BUS,
+            "RegisterStatusNotifierHost",
+            watcher_proxy.register_status_notifier_host(&host_service),
+        )
+        .await?;
+
+        let ctx = Context {
+            batcher: Arc::default(),
+            debouncer: Arc::default(),
+            latencies: Arc::default(),
+            pixmaps: Arc::default(),
+            connection,
+            tx,
+            stamped_tx,
+            queued: Arc::default(),
+            items: Arc::new(Mutex::new(HashMap::new())),
+            timestamps: Arc::default(),
+            generation: Arc::new(AtomicU64::new(0)),
+            options: Arc::new(RwLock::new(options)),
+            sandbox,
+            tasks: Arc::default(),
+            adding: Arc::default(),
+            item_tasks: Arc::default(),
+            menu_tasks: Arc::default(),
+            ready: Arc::new(watch::channel(false).0),
+            item_watchers: Arc::default(),
+            disconnects: Arc::default(),
+            unregistrations: Arc::default(),
+            watcher_events,
+        };
+
+        // route disconnects to items and menus,
+        // before any are handled so that none are missed
+        if is_bus {
+            let dbus_proxy = DBusProxy::new(&ctx.connection).await?;
+            let mut stream = dbus_proxy.receive_name_owner_changed().await?;
+
+            ctx.spawn(|ctx| async move {
+                while let Some(signal) = stream.next().await {
+                    let args = signal.args()?;
+                    if args.new_owner().is_none() {
+                        ctx.disconnects.disconnected(args.name());
+                    }
+                }
+
+                // the stream only ends once the connection closes
+                ctx.disconnects.close();
+                Ok::<(), Error>(())
+            });
+        }
+
+        // handle new and unregistered items.
+        // those registering with the embedded watcher are received from it directly,
+        // while its signals are still needed for any other watcher owning the name
+        {
+            let mut stream = watcher_proxy
+                .receive_status_notifier_item_registered()
+                .await?;
+            let mut unregistered = watcher_proxy
+                .receive_status_notifier_item_unregistered()
+                .await?;
+            let mut watcher_events = ctx.watcher_events.subscribe();
+
+            ctx.spawn(|ctx| async move {
+                loop {
+                    let address = tokio::select! {
+                        Some(item) = stream.next() => {
+                            if is_from_self(&item, &ctx.connection) {
+                                continue;
+                            }
+
+                            match item.args() {
+                                Ok(args) => args.service.to_string(),
+                                Err(_) => continue,
+                            }
+                        }
+                        Some(item) = unregistered.next() => {
+                            if !is_from_self(&item, &ctx.connection) {
+                                if let Ok(args) = item.args() {
+                                    ctx.unregistrations.disconnected(&normalize_address(args.service));
+                                }
+                            }
+
+                            continue;
+                        }
+                        event = watcher_events.recv() => match event {
+                            Ok(WatcherEvent::ItemRegistered(address)) => address,
+                            Ok(WatcherEvent::ItemUnregistered(address)) => {
+                                ctx.unregistrations.disconnected(&normalize_address(&address));
+                                continue;
+                            }
+                            Ok(_) => continue,
+                            Err(broadcast::error::RecvError::Lagged(n)) => {
+                                // their signals are ignored as coming from this client,
+                                // so the missed items are picked up from the watcher instead
+                                warn!("missed {n} registrations from embedded watcher, fetching all items");
+                                if let Err(err) = Self::handle_registered_items(&ctx).await {
+                                    error!("failed to fetch registered items: {err}");
+                                }
+                                continue;
+                            }
+                            Err(broadcast::error::RecvError::Closed) => break,
+                        },
+                        else => break,
+                    };
+
+                    ctx.queued.wait_for_room().await;
+                    debug!("received new item: {address}");
+                    Self::spawn_handle_item(&ctx, address);
+                }
+
+                Ok::<(), Error>(())
+            });
+        }
+
+        // then lastly get all items
+        // it can take so long to fetch all items that we have to do this last,
+        // otherwise some incoming items get missed
+        ctx.spawn(|ctx| async move {
+            let initial_items = ctx
+                .observe(
+                    names::WATCHER_BUS,
+                    "RegisteredStatusNotifierItems",
+                    watcher_proxy.registered_status_notifier_items(),
+                )
+                .await;
+
+            // consumers waiting for the initial items must not wait forever
+            // if they cannot be fetched
+            let initial_items = match initial_items {
+                Ok(items) => items,
+                Err(err) => {
+                    error!("failed to get initial items: {err}");
+                    Vec::new()
+                }
+            };
+
+            debug!("initial items: {initial_items:?}");
+
+            // handle items concurrently so that one slow item does not hold up the rest.
+            // each item's events are still sent in order, as they come from a single task
+            stream::iter(initial_items)
+                .map(|item| {
+                    let ctx = ctx.clone();
+                    async move {
+                        if let Err(err) = Self::handle_item(&item, ctx.clone()).await {
+                            ctx.report_error(&normalize_address(&item), err);
+                        }
+                    }
+                })
+                .buffer_unordered(INITIAL_ITEM_CONCURRENCY)
+                .collect::<Vec<_>>()
+                .await;
+
+            ctx.set_ready()
+        });
+
+        // follow the watcher name to whichever process takes it over,
+        // such as the embedded watcher queued behind another which exits,
+        // so that the host is registered with it and its items are picked up
+        if is_bus {
+            let dbus_proxy = DBusProxy::new(&ctx.connection).await?;
+            let mut stream = dbus_proxy
+                .receive_name_owner_changed_with_args(&[(0, names::WATCHER_BUS)])
+                .await?;
+            let host_service = host_service.clone();
+
+            ctx.spawn(|ctx| async move {
+                while let Some(signal) = stream.next().await {
+                    let args = signal.args()?;
+                    let Some(owner) = args.new_owner().as_ref() else {
+                        warn!("watcher left the bus, waiting for another to take over");
+                        continue;
+                    };
+
+                    // restarting the embedded watcher takes the free name on our own connection,
+                    // and registers the host with it already.
+                    // a queued embedded watcher taking over instead replaces the previous owner
+                    let is_restart = args.old_owner().is_none()
+                        && ctx
+                            .connection
+                            .unique_name()
+                            .is_some_and(|name| **name == *owner);
+                    if is_restart {
+                        debug!("embedded watcher restarted");
+                        continue;
+                    }
+
+                    debug!("watcher changed to {owner}");
+                    if let Err(err) = Self::follow_watcher(&ctx, &host_service).await {
+                        error!("failed to switch to new watcher: {err}");
+                    }
+                }
+
+                Ok::<(), Error>(())
+            });
+        }
+
+        if is_embedded {
+            ctx.spawn(|ctx| async move {
+                Self::supervise_watcher(&ctx, &host_service).await;
+                Ok(())
+            });
+        }
+
+        debug!("tray client initialized");
+
+        Ok(Self {
+            activations: Arc::new(ActivationQueue::new(ctx.clone())),
+            lifecycle: Arc::new(Lifecycle {
+                ctx: ctx.clone(),
+                _rx: rx,
+                host_name: Mutex::new(wellknown),
+                is_embedded,
+                released: AtomicBool::new(false),
+            }),
+            ctx,
+            desktop_entries: Arc::default(),
+            commands: Arc::default(),
+        })
+    }
+
+    /// Periodically checks that the embedded watcher is still attached,
+    /// restarting it and re-registering the host if not.
+    async fn supervise_watcher(ctx: &Context, host_name: &str) {
+        loop {
+            let interval = ctx
+                .options
+                .read()
+                .expect("lock should succeed")
+                .watcher_check_interval;
+
+            let Some(interval) = interval else {
+                // check again later in case the options change
+                sleep(Duration::from_secs(5)).await;
+                continue;
+            };
+
+            sleep(interval).await;
+
+            if StatusNotifierWatcher::is_attached(&ctx.connection).await {
+                continue;
+            }
+
+            warn!("embedded watcher detached, restarting");
+            if let Err(err) = Self::restart_watcher(ctx, host_name).await {
+                error!("failed to restart watcher: {err}");
+                continue;
+            }
+
+            let generation = ctx.generation.load(Ordering::SeqCst);
+            if let Err(err) = ctx.emit(generation, Event::WatcherRestarted) {
+                error!("{err}");
+            }
+        }
+    }
+
+    fn create_watcher(
+        options: &ClientOptions,
+        events: &broadcast::Sender<WatcherEvent>,
+    ) -> StatusNotifierWatcher {
+        let watcher = match &options.admission_policy {
+            Some(policy) => StatusNotifierWatcher::with_policy(policy.clone()),
+            None => StatusNotifierWatcher::new(),
+        };
+
+        watcher
+            .freedesktop_alias(options.watcher_freedesktop_alias)
+            .events_to(events.clone())
+    }
+
+    /// Registers the host with a watcher which has just taken over,
+    /// then replaces all items with those registered with it.
+    async fn follow_watcher(ctx: &Context, host_name: &str) -> crate::error::Result<()> {
+        // items were registered with the previous watcher,
+        // and register again with the new one
+        let keys = ctx
+            .items
+            .lock()
+            .expect("mutex lock should succeed")
+            .keys()
+            .cloned()
+            .collect::<Vec<_>>();
+
+        for address in keys {
+            ctx.send_remove(&address)?;
+        }
+
+        let watcher_proxy = StatusNotifierWatcherProxy::new(&ctx.connection).await?;
+        ctx.observe(
+            names::WATCHER_BUS,
+            "RegisterStatusNotifierHost",
+            watcher_proxy.register_status_notifier_host(host_name),
+        )
+        .await?;
+
+        // items already registered with the new watcher are not announced again
+        let items = ctx
+            .observe(
+                names::WATCHER_BUS,
+                "RegisteredStatusNotifierItems",
+                watcher_proxy.registered_status_notifier_items(),
+            )
+            .await?;
+
+        for address in items {
+            if let Err(err) = Self::handle_item(&address, ctx.clone()).await {
+                ctx.report_error(&normalize_address(&address), err);
+            }
+        }
+
+        let generation = ctx.generation.load(Ordering::SeqCst);
+        ctx.emit(generation, Event::WatcherChanged)
+    }
+
+    async fn restart_watcher(ctx: &Context, host_name: &str) -> crate::error::Result<()> {
+        // releasing the names makes items notice the watcher going away,
+        // so they register again once it is back
+        StatusNotifierWatcher::detach_from(&ctx.connection).await?;
+        Self::create_watcher(&ctx.options(), &ctx.watcher_events)
+            .attach_to(&ctx.connection)
+            .await?;
+
+        let watcher_proxy = StatusNotifierWatcherProxy::new(&ctx.connection).await?;
+        ctx.observe(
+            names::WATCHER_BUS,
+            "RegisterStatusNotifierHost",
+            watcher_proxy.register_status_notifier_host(host_name),
+        )
+        .await?;
+
+        Ok(())
+    }
+
+    /// Handles an item in the background,
+    /// so that one which is slow to respond does not hold up others.
+    fn spawn_handle_item(ctx: &Context, address: String) {
+        ctx.spawn(|ctx| async move {
+            // an item failing, for example by exiting straight away,
+            // must not stop further items being handled
+            if let Err(err) = Self::handle_item(&address, ctx.clone()).await {
+                ctx.report_error(&normalize_address(&address), err);
+            }
+
+            Ok(())
+        });
+    }
+
+    /// Handles every item registered with the watcher,
+    /// skipping those which have already been added.
+    async fn handle_registered_items(ctx: &Context) -> crate::error::Result<()> {
+        // a cached copy of the property may not have caught up with the latest registrations
+        let watcher_proxy = StatusNotifierWatcherProxy::builder(&ctx.connection)
+            .cache_properties(CacheProperties::No)
+            .build()
+            .await?;
+
+        let items = ctx
+            .observe(
+                names::WATCHER_BUS,
+                "RegisteredStatusNotifierItems",
+                watcher_proxy.registered_status_notifier_items(),
+            )
+            .await?;
+
+        for address in items {
+            Self::spawn_handle_item(ctx, address);
+        }
+
+        Ok(())
+    }
+
+    /// Processes an incoming item to send the initial add event,
+    /// then set up listeners for it and its menu.
+    ///
+    /// Items which have already been added, or are being added, are skipped,
+    /// as the same item can be announced more than once,
+    /// for example by both the initial list of items and a registration signal.
+    async fn handle_item(address: &str, ctx: Context) -> crate::error::Result<()> {
+        let (destination, path) = parse_address(address);
+        let key = item_key(destination, &path);
+
+        {
+            let items = ctx.items.lock().expect("mutex lock should succeed");
+            let mut adding = ctx.adding.lock().expect("mutex lock should succeed");
+            if items.contains_key(&key) || !adding.insert(key.clone()) {
+                debug!("already handling item {key}");
+                return Ok(());
+            }
+        }
+
+        let res = Self::add_item(destination, path, key.clone(), ctx.clone()).await;
+        ctx.adding
+            .lock()
+            .expect("mutex lock should succeed")
+            .remove(&key);
+
+        res
+    }
+
+    async fn add_item(
+        destination: &str,
+        path: String,
+        key: String,
+        ctx: Context,
+    ) -> crate::error::Result<()> {
+        let properties_proxy = PropertiesProxy::builder(&ctx.connection)
+            .destination(destination.to_string())?
+            .path(path.clone())?
+            .build()
+            .await?;
+
+        let (mut properties, invalid) = ctx
+            .timed(
+                &key,
+                Self::get_item_properties(&ctx, destination, &properties_proxy),
+            )
+            .await?;
+
+        if ctx.options().is_ignored(&properties.id) {
+            debug!("ignoring item {destination} with id '{}'", properties.id);
+            return Ok(());
+        }
+
+        properties.apply_pixmap_quirks(ctx.options().pixmap_quirks_for(&properties.id));
+
+        if let Some(icon_theme_path) = properties.icon_theme_path.take() {
+            properties.icon_theme_path =
+                Some(Self::host_icon_theme_path(&ctx, destination, icon_theme_path).await);
+        }
+
+        ctx.send_add(&key, properties.clone())?;
+
+        if !invalid.is_empty() {
+            ctx.report_error(&key, Error::InvalidProperties(invalid));
+        }
+
+        let task = {
+            let destination = destination.to_string();
+            let key = key.clone();
+
+            ctx.spawn(|ctx| async move {
+                if let Err(err) =
+                    Self::watch_item_properties(&destination, &path, &key, &ctx, properties_proxy)
+                        .await
+                {
+                    ctx.report_error(&key, err);
+                }
+
+                debug!("Stopped watching {destination}{path}");
+                Ok::<(), Error>(())
+            })
+        };
+
+        // an item registering twice must not be watched twice
+        ctx.stop_watching(&key);
+        ctx.item_tasks
+            .lock()
+            .expect("mutex lock should succeed")
+            .insert(key.clone(), task);
+
+        if let Some(menu) = properties.menu {
+            Self::connect_menu(&ctx, destination, &key, menu)?;
+        }
+
+        Ok(())
+    }
+
+    /// Starts watching an item's menu,
+    /// replacing the task watching its previous menu if there was one.
+    fn connect_menu(
+        ctx: &Context,
+        destination: &str,
+        key: &str,
+        menu: String,
+    ) -> crate::error::Result<()> {
+        ctx.disconnect_menu(key);
+        ctx.send_update(key, UpdateEvent::MenuConnect(menu.clone()))?;
+
+        let task = {
+            let destination = destination.to_string();
+            let key = key.to_string();
+
+            ctx.spawn(|ctx| async move {
+                if let Err(err) = Self::watch_menu(destination, key.clone(), &menu, &ctx).await {
+                    ctx.report_error(&key, err);
+                }
+
+                Ok(())
+            })
+        };
+
+        ctx.menu_tasks
+            .lock()
+            .expect("mutex lock should succeed")
+            .insert(key.to_string(), task);
+
+        Ok(())
+    }
+
+    /// Fetches an item's `Menu` property after it reports a change,
+    /// reconnecting to the menu if it now points somewhere else.
+    async fn update_menu_path(
+        ctx: &Context,
+        destination: &str,
+        key: &str,
+        properties_proxy: &PropertiesProxy<'_>,
+    ) -> crate::error::Result<()> {
+        let res = Self::get_property(ctx, destination, "Menu", properties_proxy).await;
+
+        let menu = match res {
+            Ok(property) => property
+                .downcast_ref::<ObjectPath>()
+                .map(ToString::to_string),
+            Err(Error::ZBusFdo(zbus::fdo::Error::UnknownProperty(_))) => None,
+            Err(err) => {
+                debug!("error fetching property 'Menu'");
+                ctx.report_error(key, err);
+                return Ok(());
+            }
+        };
+
+        let current = ctx
+            .items
+            .lock()
+            .expect("mutex lock should succeed")
+            .get(key)
+            .and_then(|(item, _)| item.menu.clone());
+
+        if menu == current {
+            return Ok(());
+        }
+
+        debug!("[{key}] menu changed from {current:?} to {menu:?}");
+
+        match menu {
+            Some(menu) => Self::connect_menu(ctx, destination, key, menu),
+            None => {
+                ctx.disconnect_menu(key);
+                ctx.send_update(key, UpdateEvent::MenuOrphaned)
+            }
+        }
+    }
+
+    /// Gets the properties for an SNI item,
+    /// along with the names of any invalid properties skipped
+    /// if [`ClientOptions::lossy_parsing`] is enabled.
+    async fn get_item_properties(
+        ctx: &Context,
+        destination: &str,
+        properties_proxy: &PropertiesProxy<'_>,
+    ) -> crate::error::Result<(StatusNotifierItem, Vec<&'static str>)> {
+        let get_all = properties_proxy.get_all(
+            InterfaceName::from_static_str(PROPERTIES_INTERFACE)
+                .expect("to be valid interface name"),
+        );
+        let get_all = call_with_timeout(get_all, ctx.options().property_timeout);
+
+        let properties = ctx.observe(destination, "GetAll", get_all).await?;
+
+        let properties = DBusProps(properties);
+
+        if ctx.options().lossy_parsing {
+            StatusNotifierItem::try_from_lossy(&properties)
+        } else {
+            StatusNotifierItem::try_from(properties).map(|item| (item, Vec::new()))
+        }
+    }
+
+    /// Translates the icon theme path of a sandboxed item
+    /// into a path which can be read from the host.
+    ///
+    /// Paths are left untouched if the item is not sandboxed,
+    /// or if this process is sandboxed and so cannot see other processes.
+    async fn host_icon_theme_path(ctx: &Context, destination: &str, path: String) -> String {
+        if ctx.sandbox.is_some() || path.is_empty() || !ctx.connection.is_bus() {
+            return path;
+        }
+
+        let Ok(name) = BusName::try_from(destination) else {
+            return path;
+        };
+
+        let pid = match DBusProxy::new(&ctx.connection).await {
+            Ok(dbus_proxy) => ctx
+                .observe(
+                    names::DBUS_BUS,
+                    "GetConnectionUnixProcessID",
+                    dbus_proxy.get_connection_unix_process_id(name),
+                )
+                .await
+                .ok(),
+            Err(_) => None,
+        };
+
+        let Some(pid) = pid else {
+            return path;
+        };
+
+        let Some(sandbox) = Sandbox::of_process(pid) else {
+            return path;
+        };
+        let translated = sandbox.translate_path(pid, &path);
+        debug!(
+            "[{destination}] translated icon theme path from {sandbox}: {path} -> {translated:?}"
+        );
+
+        translated.to_string_lossy().into_owned()
+    }
+
+    /// Watches an SNI item's properties,
+    /// sending an update event whenever they change.
+    ///
+    /// The item is stored in the state under `key`.
+    async fn watch_item_properties(
+        destination: &str,
+        path: &str,
+        key: &str,
+        ctx: &Context,
+        properties_proxy: PropertiesProxy<'_>,
+    ) -> crate::error::Result<()> {
+        let connection = &ctx.connection;
+        let notifier_item_proxy = StatusNotifierItemProxy::builder(connection)
+            .destination(destination)?
+            .path(path)?
+            .build()
+            .await?;
+
+        let dbus_proxy = DBusProxy::new(connection).await?;
+
+        // watch before resolving the owner
+        // so that an item exiting in between is not missed
+        let mut disconnected = ctx.disconnects.watch(destination);
+        let mut unregistered = ctx.unregistrations.watch(key);
+
+        let owner = match resolve_owner(ctx, &dbus_proxy, destination).await {
+            Ok(owner) => owner,
+            Err(err) => {
+                debug!("[{destination}{path}] disconnected before it could be watched: {err}");
+                ctx.send_remove(key)?;
+                return Ok(());
+            }
+        };
+
+        // subscribing to each signal separately means the bus only sends the ones we handle
+        let mut streams = Vec::with_capacity(ITEM_SIGNALS.len());
+        for signal in ITEM_SIGNALS {
+            streams.push(notifier_item_proxy.receive_signal(*signal).await?);
+        }
+        let mut props_changed = select_all(streams);
+
+        // the spec has no signals for these properties,
+        // so items which change them can only be noticed this way
+        let mut properties_changed = properties_proxy.receive_properties_changed().await?;
+
+        let mut animation = AnimationDetector::default();
+
+        loop {
+            ctx.queued.wait_for_room().await;
+            let animation_deadline = animation.deadline().map(tokio::time::Instant::from_std);
+
+            tokio::select! {
+                Some(change) = props_changed.next() => {
+                    if !is_from_owner(&change, owner.as_deref()) {
+                        continue;
+                    }
+
+                    if change.member().is_some_and(|member| member == "NewMenu") {
+                        Self::update_menu_path(ctx, destination, key, &properties_proxy).await?;
+                        continue;
+                    }
+
+                    let is_new_icon = change.member().is_some_and(|member| member == "NewIcon");
+                    let animation_threshold = ctx.options().animation_threshold;
+
+                    if let Some(threshold) = animation_threshold.filter(|_| is_new_icon) {
+                        if animation.tick(Instant::now(), threshold) {
+                            let mut frame = ctx.timed(key, Self::get_icon_frame(ctx, destination, &properties_proxy)).await;
+                            if let Some(pixmap) = &mut frame.pixmap {
+                                ctx.apply_pixmap_quirks(key, pixmap);
+                            }
+
+                            // the state keeps the current frame without an update being sent for it
+                            ctx.cache_update(key, &UpdateEvent::Icon { icon_name: frame.name.clone(), icon_pixmap: frame.pixmap.clone() });
+
+                            if let Some(icon) = animation.push_frame(frame) {
+                                debug!("[{destination}{path}] detected icon animation with {} frames", icon.frames.len());
+
+                                let generation = ctx.generation.load(Ordering::SeqCst);
+                                ctx.emit(generation, Event::AnimatedIcon(key.to_string(), icon))?;
+                            }
+
+                            continue;
+                        }
+                    }
+
+                    if let Some(mut event) = ctx.timed(key, Self::get_update_event(ctx, destination, key, change, &properties_proxy)).await {
+                        match &mut event {
+                            UpdateEvent::Tooltip(Some(tooltip)) => ctx.apply_pixmap_quirks(key, &mut tooltip.icon_data),
+                            UpdateEvent::Icon { icon_pixmap: Some(pixmap), .. }
+                            | UpdateEvent::AttentionIcon { icon_pixmap: Some(pixmap), .. }
+                            | UpdateEvent::OverlayIcon { icon_pixmap: Some(pixmap), .. } => ctx.apply_pixmap_quirks(key, pixmap),
+                            _ => {}
+                        }
+
+                        debug!("[{destination}{path}] received property change: {event:?}");
+                        ctx.send_update(key, event)?;
+                    }
+                }
+                Some(change) = properties_changed.next() => {
+                    if !is_from_owner(&change, owner.as_deref()) {
+                        continue;
+                    }
+
+                    let Ok(args) = change.args() else {
+                        continue;
+                    };
+
+                    if args.interface_name() != PROPERTIES_INTERFACE {
+                        continue;
+                    }
+
+                    let is_changed = |name| {
+                        args.changed_properties().contains_key(name)
+                            || args.invalidated_properties().contains(&name)
+                    };
+
+                    if is_changed("Menu") {
+                        Self::update_menu_path(ctx, destination, key, &properties_proxy).await?;
+                    }
+
+                    if is_changed("XAyatanaOrderingIndex") {
+                        let index = match args.changed_properties().get("XAyatanaOrderingIndex") {
+                            Some(index) => index.downcast_ref::<u32>().copied(),
+                            None => Self::get_property(ctx, destination, "XAyatanaOrderingIndex", &properties_proxy)
+                                .await
+                                .ok()
+                                .and_then(|index| index.downcast_ref::<u32>().copied()),
+                        };
+
+                        ctx.send_update(key, UpdateEvent::OrderingIndex(index))?;
+                    }
+                }
+                () = sleep_until(animation_deadline.unwrap_or_else(tokio::time::Instant::now)), if animation_deadline.is_some() => {
+                    if animation.finish() {
+                        debug!("[{destination}{path}] icon animation stopped");
+
+                        let mut frame = ctx.timed(key, Self::get_icon_frame(ctx, destination, &properties_proxy)).await;
+                        if let Some(pixmap) = &mut frame.pixmap {
+                            ctx.apply_pixmap_quirks(key, pixmap);
+                        }
+
+                        ctx.send_update(key, UpdateEvent::Icon { icon_name: frame.name, icon_pixmap: frame.pixmap })?;
+                    }
+                }
+                gone = unregistered.wait() => {
+                    // the item may stay on the bus after releasing the name it registered with
+                    if gone {
+                        debug!("[{destination}{path}] unregistered");
+                        ctx.send_remove(key)?;
+                    }
+
+                    break Ok(());
+                }
+                gone = disconnected.wait() => {
+                    // the dispatcher only closes once the connection closes,
+                    // which happens while the client is shutting down
+                    if !gone {
+                        break Ok(());
+                    }
+
+                    debug!("[{destination}{path}] disconnected");
+
+                    let watcher_proxy = StatusNotifierWatcherProxy::new(connection)
+                        .await
+                        .expect("Failed to open StatusNotifierWatcherProxy");
+
+                    let address = format!("{destination}{path}");
+                    let unregister = watcher_proxy.unregister_status_notifier_item(&address);
+                    if let Err(error) = ctx.observe(names::WATCHER_BUS, "UnregisterStatusNotifierItem", unregister).await {
+                        error!("{error:?}");
+                    }
+
+                    ctx.send_remove(key)?;
+                    break Ok(());
+                }
+            }
+        }
+    }
+
+    /// Gets the current icon name and pixmap of an item.
+    async fn get_icon_frame(
+        ctx: &Context,
+        destination: &str,
+        properties_proxy: &PropertiesProxy<'_>,
+    ) -> IconFrame {
+        let interface = InterfaceName::from_static_str(PROPERTIES_INTERFACE)
+            .expect("to be valid interface name");
+        let timeout = ctx.options().property_timeout;
+
+        let name = ctx
+            .observe(
+                destination,
+                "Get",
+                call_with_timeout(properties_proxy.get(interface.clone(), "IconName"), timeout),
+            )
+            .await
+            .ok()
+            .and_then(|name| name.downcast_ref::<str>().map(ToString::to_string))
+            .filter(|name| !name.is_empty());
+
+        let pixmap = Self::get_icon_pixmap(ctx, destination, "IconPixmap", properties_proxy).await;
+
+        IconFrame { name, pixmap }
+    }
+
+    /// Gets one of an item's icon pixmap properties,
+    /// or `None` if the item does not provide it.
+    async fn get_icon_pixmap(
+        ctx: &Context,
+        destination: &str,
+        property_name: &str,
+        properties_proxy: &PropertiesProxy<'_>,
+    ) -> Option<Vec<IconPixmap>> {
+        let interface = InterfaceName::from_static_str(PROPERTIES_INTERFACE)
+            .expect("to be valid interface name");
+        let get = properties_proxy.get(interface, property_name);
+
+        ctx.observe(
+            destination,
+            "Get",
+            call_with_timeout(get, ctx.options().property_timeout),
+        )
+        .await
+        .ok()
+        .and_then(|pixmap| {
+            pixmap
+                .downcast_ref::<Array>()
+                .and_then(|array| IconPixmap::from_array(array).ok())
+        })
+        .filter(|pixmap| !pixmap.is_empty())
+    }
+
+    /// Gets a single property of an item,
+    /// retrying according to [`ClientOptions::fetch_retry`].
+    async fn get_property(
+        ctx: &Context,
+        destination: &str,
+        property_name: &str,
+        properties_proxy: &PropertiesProxy<'_>,
+    ) -> crate::error::Result<OwnedValue> {
+        let options = ctx.options();
+        retry(&options.fetch_retry, || {
+            let get = properties_proxy.get(
+                InterfaceName::from_static_str(PROPERTIES_INTERFACE)
+                    .expect("to be valid interface name"),
+                property_name,
+            );
+            let get = call_with_timeout(get, options.property_timeout);
+            ctx.observe(destination, "Get", get)
+        })
+        .await
+    }
+
+    /// Gets the update event for a `DBus` properties change message.
+    async fn get_update_event(
+        ctx: &Context,
+        destination: &str,
+        key: &str,
+        change: Arc<Message>,
+        properties_proxy: &PropertiesProxy<'_>,
+    ) -> Option<UpdateEvent> {
+        let member = change.member()?;
+
+        // unlike the other signals, this carries the new values
+        if member.as_str() == "XAyatanaNewLabel" {
+            return match change.body::<(String, String)>() {
+                Ok((label, guide)) => Some(UpdateEvent::Label {
+                    label: Some(label).filter(|label| !label.is_empty()),
+                    guide: Some(guide).filter(|guide| !guide.is_empty()),
+                }),
+                Err(err) => {
+                    warn!("received invalid label update: {err}");
+                    None
+                }
+            };
+        }
+
+        let property_name = match member.as_str() {
+            "NewAttentionIcon" => "AttentionIconName",
+            "NewIcon" => "IconName",
+            "NewOverlayIcon" => "OverlayIconName",
+            "NewStatus" => "Status",
+            "NewTitle" => "Title",
+            "NewToolTip" => "ToolTip",
+            _ => &member.as_str()["New".len()..],
+        };
+
+        let res = Self::get_property(ctx, destination, property_name, properties_proxy).await;
+
+        let property = match res {
+            Ok(property) => property,
+            Err(err) => {
+                debug!("error fetching property '{property_name}'");
+                ctx.report_error(key, err);
+                return None;
+            }
+        };
+
+        debug!("received tray item update: {member} -> {property:?}");
+
+        // items which only provide pixmaps are otherwise never seen to change their icon
+        let icon_pixmap = match member.as_str() {
+            "NewAttentionIcon" => Some("AttentionIconPixmap"),
+            "NewIcon" => Some("IconPixmap"),
+            "NewOverlayIcon" => Some("OverlayIconPixmap"),
+            _ => None,
+        };
+        let icon_pixmap = match icon_pixmap {
+            Some(pixmap_name) => {
+                Self::get_icon_pixmap(ctx, destination, pixmap_name, properties_proxy).await
+            }
+            None => None,
+        };
+
+        use UpdateEvent::*;
+        match member.as_str() {
+            "NewAttentionIcon" => Some(AttentionIcon {
+                icon_name: property.to_string(),
+                icon_pixmap,
+            }),
+            "NewIcon" => Some(Icon {
+                icon_name: property.to_string(),
+                icon_pixmap,
+            }),
+            "NewOverlayIcon" => Some(OverlayIcon {
+                icon_name: property.to_string(),
+                icon_pixmap,
+            }),
+            "NewStatus" => Some(Status(
+                property
+                    .downcast_ref::<str>()
+                    .map(item::Status::from)
+                    .unwrap_or_default(),
+            )),
+            "NewTitle" => Some(Title(property.to_string())),
+            "NewIconThemePath" => {
+                let path = match property.to_string() {
+                    Some(path) => Some(Self::host_icon_theme_path(ctx, destination, path).await),
+                    None => None,
+                };
+                Some(IconThemePath(path))
+            }
+            "NewToolTip" => Some(Tooltip(
+                property
+                    .downcast_ref::<Structure>()
+                    .map(crate::item::Tooltip::try_from)?
+                    .ok(),
+            )),
+            _ => {
+                warn!("received unhandled update event: {member}");
+                None
+            }
+        }
+    }
+
+    /// Watches the `DBusMenu` associated with an SNI item.
+    ///
+    /// This gets the initial menu, sending an update event immediately.
+    /// Update events are then sent for any further updates
+    /// until the item is removed.
+    async fn watch_menu(
+        destination: String,
+        key: String,
+        menu_path: &str,
+        ctx: &Context,
+    ) -> crate::error::Result<()> {
+        let dbus_menu_proxy = DBusMenuProxy::builder(&ctx.connection)
+            .destination(destination.as_str())?
+            .path(menu_path)?
+            .build()
+            .await?;
+
+        let options = ctx.options();
+        let properties = options.menu_property_names();
+        let get_layout = call_with_timeout(
+            dbus_menu_proxy.get_layout(0, options.menu_depth, &properties),
+            options.menu_probe_timeout,
+        );
+
+        let menu = match ctx
+            .timed(&key, ctx.observe(&destination, "GetLayout", get_layout))
+            .await
+        {
+            Ok(menu) => menu,
+            Err(Error::ZBus(err)) if is_missing_object(&err) => {
+                warn!("[{destination}{menu_path}] menu does not exist: {err}");
+                ctx.send_update(&key, UpdateEvent::MenuOrphaned)?;
+                return Ok(());
+            }
+            Err(Error::Timeout) => {
+                warn!("[{destination}{menu_path}] menu did not respond");
+                ctx.send_update(&key, UpdateEvent::MenuOrphaned)?;
+                return Ok(());
+            }
+            Err(err) => return Err(err),
+        };
+
+        // this is optional, and only read once as it is not expected to change
+        let get_icon_theme_path =
+            call_with_timeout(dbus_menu_proxy.icon_theme_path(), options.property_timeout);
+        let mut icon_theme_path = Vec::new();
+        for path in ctx
+            .observe(&destination, "Get", get_icon_theme_path)
+            .await
+            .unwrap_or_default()
+        {
+            icon_theme_path.push(Self::host_icon_theme_path(ctx, &destination, path).await);
+        }
+
+        let mut menu = TrayMenu::try_from(menu)?;
+        menu.icon_theme_path.clone_from(&icon_theme_path);
+
+        let generation = ctx.cache_menu(&key, &menu);
+        ctx.send_update_at(generation, &key, UpdateEvent::Menu(menu.into()))?;
+
+        let dbus_proxy = DBusProxy::new(&ctx.connection).await?;
+
+        // the item's watcher takes care of removing it,
+        // but this still needs to stop once it is gone
+        let mut disconnected = ctx.disconnects.watch(&destination);
+
+        let Ok(owner) = resolve_owner(ctx, &dbus_proxy, &destination).await else {
+            debug!("[{destination}{menu_path}] disconnected before menu could be watched");
+            return Ok(());
+        };
+
+        let mut layout_updated = dbus_menu_proxy.receive_layout_updated().await?;
+        let mut properties_updated = dbus_menu_proxy.receive_items_properties_updated().await?;
+
+        loop {
+            ctx.queued.wait_for_room().await;
+
+            tokio::select!(
+                Some(signal) = layout_updated.next() => {
+                    if !is_from_owner(&signal, owner.as_deref()) {
+                        continue;
+                    }
+
+                    let (revision, parent) = signal
+                        .args()
+                        .map_or((0, 0), |args| (args.revision, args.parent));
+
+                    // signals can arrive after the layout they announce was already fetched,
+                    // for example when several are sent in quick succession.
+                    // a revision of 0 is sent by some items for every change, so cannot be trusted
+                    if revision != 0 && ctx.menu_revision(&key).is_some_and(|cached| cached >= revision) {
+                        trace!("[{destination}{menu_path}] already have layout revision {revision}");
+                        continue;
+                    }
+
+                    let parent = match ctx.options().layout_coalesce_window {
+                        Some(window) => Self::coalesce_layout_updates(&mut layout_updated, owner.as_deref(), parent, window).await,
+                        None => parent,
+                    };
+
+                    // only fetch the changed branch if we already have the rest of the menu
+                    let parent = if parent != 0 && ctx.menu_contains(&key, parent) {
+                        parent
+                    } else {
+                        0
+                    };
+
+                    debug!("[{destination}{menu_path}] layout update (parent: {parent})");
+
+                    let options = ctx.options();
+                    let properties = options.menu_property_names();
+                    let get_layout = retry(&options.fetch_retry, || {
+                        let get_layout = call_with_timeout(
+                            dbus_menu_proxy.get_layout(parent, options.menu_depth, &properties),
+                            options.layout_timeout,
+                        );
+                        ctx.timed(&key, ctx.observe(&destination, "GetLayout", get_layout))
+                    });
+
+                    let menu = match get_layout.await {
+                        Ok(menu) => {
+                            debug!("got new menu layout");
+                            menu
+                        }
+                        Err(err) => {
+                            error!("[{destination}{menu_path}] giving up on menu after failing to fetch layout: {err:?}");
+
+                            let generation = ctx.generation.load(Ordering::SeqCst);
+                            ctx.emit(generation, Event::MenuAbandoned(key.clone(), err.to_string()))?;
+                            break;
+                        }
+                    };
+
+                    let mut menu = TrayMenu::try_from(menu)?;
+                    menu.icon_theme_path.clone_from(&icon_theme_path);
+
+                    if parent != 0 {
+                        let subtree = menu.submenus;
+
+                        match ctx.cache_menu_subtree(&key, menu.id, parent, &subtree) {
+                            Some(generation) => {
+                                debug!("sending menu subtree {parent} for '{key}'");
+                                ctx.send_update_at(
+                                    generation,
+                                    &key,
+                                    UpdateEvent::MenuSubtree { parent_id: parent, subtree },
+                                )?;
+                            }
+                            None => warn!("[{destination}{menu_path}] menu item {parent} disappeared from cache"),
+                        }
+
+                        continue;
+                    }
+
+                    let generation = ctx.cache_menu(&key, &menu);
+
+                    debug!("sending new menu for '{key}'");
+                    trace!("new menu for '{key}': {menu:?}");
+                    ctx.send_update_at(generation, &key, UpdateEvent::Menu(menu.into()))?;
+                }
+                Some(change) = properties_updated.next() => {
+                    if !is_from_owner(&change, owner.as_deref()) {
+                        continue;
+                    }
+
+                    let update = change.body::<PropertiesUpdate>()?;
+                    let diffs = Vec::try_from(update)?;
+
+                    ctx.send_update(&key, UpdateEvent::MenuDiff(diffs))?;
+                }
+                gone = disconnected.wait() => {
+                    if gone {
+                        debug!("[{destination}{menu_path}] disconnected");
+                    }
+                    break;
+                }
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Waits for `window` to pass, absorbing any further layout updates from the menu's owner,
+    /// so that a burst of them results in a single fetch.
+    ///
+    /// Returns the parent whose branch should be fetched,
+    /// which is the root unless every update was for the same branch.
+    async fn coalesce_layout_updates(
+        layout_updated: &mut LayoutUpdatedStream<'_>,
+        owner: Option<&UniqueName<'_>>,
+        mut parent: i32,
+        window: Duration,
+    ) -> i32 {
+        let deadline = tokio::time::Instant::now() + window;
+
+        while let Ok(Some(signal)) = timeout_at(deadline, layout_updated.next()).await {
+            if !is_from_owner(&signal, owner) {
+                continue;
+            }
+
+            if signal.args().map_or(0, |args| args.parent) != parent {
+                parent = 0;
+            }
+        }
+
+        parent
+    }
+
+    /// Subscribes to the events broadcast channel,
+    /// returning a new receiver.
+    ///
+    /// Once the client is dropped, the receiver will close.
+    #[must_use]
+    pub fn subscribe(&self) -> broadcast::Receiver<Event> {
+        self.ctx.tx.subscribe()
+    }
+
+    /// Subscribes to the events broadcast channel,
+    /// returning a receiver which recovers from falling behind.
+    ///
+    /// A receiver which does not keep up with events misses some of them.
+    /// Rather than reporting this as an error,
+    /// the returned receiver sends an [`Event::Resync`] holding the current state.
+    /// See [`ClientOptions::event_capacity`] for how far behind a receiver can fall.
+    ///
+    /// Once the client is dropped, the receiver will close.
+    #[must_use]
+    pub fn subscribe_resync(&self) -> ResyncReceiver {
+        ResyncReceiver::new(
+            self.subscribe(),
+            self.ctx.items.clone(),
+            self.ctx.timestamps.clone(),
+            self.ctx.generation.clone(),
+            self.ctx.pixmaps.clone(),
+        )
+    }
+
+    /// Registers a handler which is called with every event,
+    /// as an alternative to receiving them from a channel.
+    ///
+    /// Handlers run on a background task of the client,
+    /// receiving events in order.
+    /// A slow handler does not hold up the client,
+    /// but if it falls behind it is called with an [`Event::Resync`],
+    /// as with [`Client::subscribe_resync`].
+    ///
+    /// The handler is called until it is unregistered using the returned handle,
+    /// or the client shuts down.
+    pub fn on_event(&self, mut handler: impl FnMut(&Event) + Send + 'static) -> HandlerHandle {
+        let mut rx = self.subscribe_resync();
+
+        let task = self.ctx.spawn(|_| async move {
+            while let Ok(event) = rx.recv().await {
+                handler(&event);
+            }
+
+            Ok(())
+        });
+
+        HandlerHandle::new(task)
+    }
+
+    /// Subscribes to events through a queue dedicated to the new receiver,
+    /// rather than the broadcast channel shared by other subscribers.
+    ///
+    /// Up to `capacity` events are held for the receiver,
+    /// after which `policy` decides what happens.
+    /// With [`OverflowPolicy::Block`], no events are lost,
+    /// which suits consumers which must see every `Remove`.
+    ///
+    /// Once the client is dropped, the receiver will close.
+    #[must_use]
+    pub fn subscribe_queued(&self, capacity: usize, policy: OverflowPolicy) -> QueuedReceiver {
+        self.ctx.queued.subscribe(capacity, policy)
+    }
+
+    /// Subscribes to events for items matching `filter`,
+    /// returning a new receiver.
+    ///
+    /// Items are checked against the filter as they change,
+    /// so an item which starts matching is sent as an `Add`,
+    /// and one which stops matching is sent as a `Remove`.
+    /// Events which are not tied to an item are always sent.
+    ///
+    /// Once the client is dropped, the receiver will close.
+    #[must_use]
+    pub fn subscribe_filtered(&self, filter: Filter) -> FilteredReceiver {
+        let (snapshot, rx) = self.subscribe_with_state();
+        FilteredReceiver::new(rx, filter, snapshot.items)
+    }
+
+    /// Subscribes to events tagged with the state generation they bring the consumer up to,
+    /// returning a new receiver.
+    ///
+    /// Comparing the generation of an event against that of a [`Snapshot`]
+    /// tells whether the event is already reflected in the snapshot.
+    #[must_use]
+    pub fn subscribe_stamped(&self) -> broadcast::Receiver<StampedEvent> {
+        self.ctx.stamped_tx.subscribe()
+    }
+
+    /// Checks whether all items registered with the watcher when the client started have been processed.
+    ///
+    /// See [`Event::Ready`].
+    #[must_use]
+    pub fn is_ready(&self) -> bool {
+        *self.ctx.ready.borrow()
+    }
+
+    /// Waits until all items registered with the watcher when the client started have been processed,
+    /// returning immediately if this has already happened.
+    ///
+    /// Once this returns, [`Client::items`] contains every item
+    /// which was on the bus at startup and is still there,
+    /// so a complete tray can be rendered.
+    ///
+    /// See [`Event::Ready`].
+    pub async fn wait_ready(&self) {
+        let mut ready = self.ctx.ready.subscribe();
+
+        // the sender lives as long as the client, so this cannot fail
+        let _ = ready.wait_for(|ready| *ready).await;
+    }
+
+    /// Gets the current state generation.
+    ///
+    /// This increases every time an item or menu changes.
+    #[must_use]
+    pub fn generation(&self) -> u64 {
+        self.ctx.generation.load(Ordering::SeqCst)
+    }
+
+    /// Takes a consistent copy of all current items and their menus,
+    /// along with the state generation it was taken at.
+    ///
+    /// # Panics
+    ///
+    /// If the state mutex is poisoned.
+    #[must_use]
+    pub fn snapshot(&self) -> Snapshot {
+        self.snapshot_with(|| ()).0
+    }
+
+    /// Takes a snapshot of the current state and subscribes to the events broadcast channel,
+    /// as a single atomic operation.
+    ///
+    /// The receiver is guaranteed to see every change made after the snapshot was taken,
+    /// so applying its events on top of the snapshot always results in the live state.
+    /// This should be preferred to calling [`Client::subscribe`] followed by [`Client::items`].
+    ///
+    /// A change made immediately before the snapshot may still have its event delivered,
+    /// so consumers should treat an `Add` for a known item as a replacement
+    /// and a `Remove` for an unknown item as a no-op.
+    ///
+    /// # Panics
+    ///
+    /// If the state mutex is poisoned.
+    #[must_use]
+    pub fn subscribe_with_state(&self) -> (Snapshot, broadcast::Receiver<Event>) {
+        self.snapshot_with(|| self.ctx.tx.subscribe())
+    }
+
+    /// Takes a snapshot of the current state,
+    /// running `f` while the state is locked.
+    ///
+    /// Changes to the state are made under the same lock before their events are sent,
+    /// so subscribing inside `f` cannot miss a change that is not in the snapshot.
+    fn snapshot_with<T>(&self, f: impl FnOnce() -> T) -> (Snapshot, T) {
+        snapshot_with(
+            &self.ctx.items,
+            &self.ctx.timestamps,
+            &self.ctx.generation,
+            &self.ctx.pixmaps,
+            f,
+        )
+    }
+
+    /// Gets when an item and its menu were last updated.
+    ///
+    /// This can be used to show staleness indicators,
+    /// order items by recent activity, or debug items which appear frozen.
+    ///
+    /// Returns `None` if the item does not exist.
+    ///
+    /// # Panics
+    ///
+    /// If the timestamps mutex is poisoned.
+    #[must_use]
+    pub fn timestamps(&self, address: &str) -> Option<ItemTimestamps> {
+        self.ctx
+            .timestamps
+            .lock()
+            .expect("mutex lock should succeed")
+            .get(&normalize_address(address))
+            .copied()
+    }
+
+    /// Gets all current items, including their menus if present.
+    ///
+    /// The icon pixmaps of items may have been dropped
+    /// to stay within [`ClientOptions::pixmap_budget`].
+    /// Use [`Client::snapshot`] to tell which.
+    #[must_use]
+    pub fn items(&self) -> Arc<Mutex<State>> {
+        self.ctx.items.clone()
+    }
+
+    /// Gets the cached copy of an item,
+    /// first fetching its icon pixmaps again if they were evicted
+    /// to stay within [`ClientOptions::pixmap_budget`].
+    ///
+    /// This marks the item's pixmaps as recently used,
+    /// so that they are the last to be evicted.
+    /// Returns `None` if the item is not known.
+    ///
+    /// # Errors
+    ///
+    /// The method will return an error if the evicted pixmaps cannot be fetched,
+    /// for example if the item has already disconnected.
+    ///
+    /// # Panics
+    ///
+    /// If the state mutex is poisoned.
+    pub async fn item_with_pixmaps(
+        &self,
+        address: &str,
+    ) -> crate::error::Result<Option<StatusNotifierItem>> {
+        let key = normalize_address(address);
+
+        let fetched = if self.ctx.pixmaps.is_evicted(&key) {
+            let (destination, path) = parse_address(address);
+            let properties_proxy = PropertiesProxy::builder(&self.ctx.connection)
+                .destination(destination.to_string())?
+                .path(path)?
+                .build()
+                .await?;
+
+            let (mut properties, _) = self
+                .ctx
+                .timed(
+                    &key,
+                    Self::get_item_properties(&self.ctx, destination, &properties_proxy),
+                )
+                .await?;
+
+            properties.apply_pixmap_quirks(self.ctx.options().pixmap_quirks_for(&properties.id));
+            Some(properties)
+        } else {
+            None
+        };
+
+        let mut items = self.ctx.items.lock().expect("mutex lock should succeed");
+        let Some((item, _)) = items.get_mut(&key) else {
+            return Ok(None);
+        };
+
+        if let Some(fetched) = fetched {
+            item.icon_pixmap = fetched.icon_pixmap;
+            item.overlay_icon_pixmap = fetched.overlay_icon_pixmap;
+            item.attention_icon_pixmap = fetched.attention_icon_pixmap;
+            self.ctx.pixmaps.restore(&key, item);
+        } else {
+            self.ctx.pixmaps.touch(&key, item);
+        }
+
+        // taken before enforcing the budget,
+        // in case this item alone is over it
+        let item = item.clone();
+        self.ctx.enforce_pixmap_budget(&mut items);
+
+        Ok(Some(item))
+    }
+
+    /// Watches the latest state of a single item and its menu,
+    /// returning `None` if the item is not known.
+    ///
+    /// The channel always holds the current state,
+    /// so intermediate changes made between reads are skipped.
+    /// This suits UI frameworks which render from the latest state
+    /// rather than consuming a stream of events.
+    ///
+    /// The channel closes when the item is removed.
+    ///
+    /// # Panics
+    ///
+    /// If the state mutex is poisoned.
+    #[must_use]
+    pub fn watch_item(&self, address: &str) -> Option<watch::Receiver<ItemState>> {
+        let key = normalize_address(address);
+
+        let items = self.ctx.items.lock().expect("mutex lock should succeed");
+        let state = items.get(&key)?;
+
+        let mut watchers = self
+            .ctx
+            .item_watchers
+            .lock()
+            .expect("mutex lock should succeed");
+
+        let watcher = watchers
+            .entry(key)
+            .or_insert_with(|| watch::channel(state.clone()).0);
+
+        Some(watcher.subscribe())
+    }
+
+    /// Gets the desktop entry for the application behind an item,
+    /// matched using its `Id` and `Title`.
+    ///
+    /// This allows displaying proper application names,
+    /// and falling back to the application icon if the item has none.
+    ///
+    /// Installed desktop files are indexed on a blocking thread the first time this is called,
+    /// and the index is kept until [`Client::reload_desktop_entries`] is called.
+    /// See [`DesktopEntries::find`] for how entries are matched.
+    ///
+    /// # Panics
+    ///
+    /// If the state mutex is poisoned.
+    pub async fn desktop_entry(&self, address: &str) -> Option<DesktopEntry> {
+        let (id, title) = {
+            let items = self.ctx.items.lock().expect("mutex lock should succeed");
+            let (item, _) = items.get(&normalize_address(address))?;
+            (item.id.clone(), item.title.clone())
+        };
+
+        // held while loading, so that concurrent calls share a single scan
+        let mut entries = self.desktop_entries.lock().await;
+        if entries.is_none() {
+            *entries = Some(load_desktop_entries().await);
+        }
+
+        entries.as_ref()?.find(&id, title.as_deref()).cloned()
+    }
+
+    /// Indexes the installed desktop files again,
+    /// so that applications installed or removed since they were last indexed are picked up.
+    ///
+    /// Files are read on a blocking thread.
+    /// See [`Client::desktop_entry`].
+    pub async fn reload_desktop_entries(&self) {
+        let entries = load_desktop_entries().await;
+        *self.desktop_entries.lock().await = Some(entries);
+    }
+
+    /// Gets information about the process which owns an item's bus connection.
+    ///
+    /// This is useful for identifying unknown icons,
+    /// or for offering actions such as killing the application.
+    ///
+    /// # Errors
+    ///
+    /// The method will return an error if the bus cannot be queried,
+    /// for example if the item has already disconnected.
+    pub async fn item_process(&self, address: &str) -> crate::error::Result<ProcessInfo> {
+        let (destination, _) = parse_address(address);
+        let destination = BusName::try_from(destination).map_err(zbus::Error::from)?;
+
+        let dbus_proxy = DBusProxy::new(&self.ctx.connection).await?;
+
+        let pid = self
+            .ctx
+            .observe(
+                names::DBUS_BUS,
+                "GetConnectionUnixProcessID",
+                dbus_proxy.get_connection_unix_process_id(destination.clone()),
+            )
+            .await?;
+        let uid = self
+            .ctx
+            .observe(
+                names::DBUS_BUS,
+                "GetConnectionUnixUser",
+                dbus_proxy.get_connection_unix_user(destination),
+            )
+            .await?;
+        let exe = std::fs::read_link(format!("/proc/{pid}/exe")).ok();
+        let sandbox = Sandbox::of_process(pid);
+
+        Ok(ProcessInfo {
+            pid,
+            uid,
+            exe,
+            sandbox,
+        })
+    }
+
+    /// Shuts the client down, waiting for this to complete.
+    ///
+    /// This stops watching all items and menus,
+    /// drops the cached state,
+    /// and releases the host name and embedded watcher from the bus.
+    /// No further events are sent to subscribers.
+    ///
+    /// This also happens when the last clone of the client is dropped,
+    /// but the bus is then cleaned up in the background.
+    /// `shutdown` allows waiting for it and handling any errors,
+    /// for example in tests which repeatedly create clients.
+    ///
+    /// The client is stopped for every clone of it.
+    /// Activations still waiting to be sent fail with [`Error::ClientStopped`].
+    ///
+    /// # Errors
+    ///
+    /// The method will return an error if releasing the names fails.
+    ///
+    /// # Panics
+    ///
+    /// If the state mutex is poisoned.
+    pub async fn shutdown(self) -> crate::error::Result<()> {
+        self.activations.close();
+        self.ctx.take_tasks().shutdown().await;
+        self.ctx.queued.close();
+        self.ctx.clear();
+
+        self.lifecycle.release().await
+    }
+
+    /// Releases the host name and removes the embedded watcher from the bus,
+    /// waiting for this to complete.
+    ///
+    /// # Errors
+    ///
+    /// The method will return an error if releasing the names fails.
+    #[deprecated(note = "use `Client::shutdown`, which also stops all background tasks")]
+    pub async fn close(self) -> crate::error::Result<()> {
+        self.shutdown().await
+    }
+
+    /// Gets information about the watcher currently owning the `org.kde.StatusNotifierWatcher` name,
+    /// including whether it is the watcher embedded in this client.
+    ///
+    /// This can be used to warn users when another tray implementation is competing for the name.
+    ///
+    /// # Errors
+    ///
+    /// The method will return an error if the bus cannot be queried.
+    pub async fn watcher_info(&self) -> crate::error::Result<WatcherInfo> {
+        let dbus_proxy = DBusProxy::new(&self.ctx.connection).await?;
+
+        let get_name_owner = dbus_proxy.get_name_owner(
+            BusName::from_static_str(names::WATCHER_BUS).expect("to be valid bus name"),
+        );
+
+        let owner = match self
+            .ctx
+            .observe(names::DBUS_BUS, "GetNameOwner", get_name_owner)
+            .await
+        {
+            Ok(owner) => Some(owner.to_string()),
+            Err(zbus::fdo::Error::NameHasNoOwner(_)) => None,
+            Err(err) => return Err(err.into()),
+        };
+
+        let is_embedded = owner.is_some()
+            && owner.as_deref() == self.ctx.connection.unique_name().map(|name| name.as_str());
+
+        Ok(WatcherInfo { is_embedded, owner })
+    }
+
+    /// Gets a copy of the options currently in use.
+    ///
+    /// # Panics
+    ///
+    /// If the options lock is poisoned.
+    #[must_use]
+    pub fn options(&self) -> ClientOptions {
+        self.ctx.options()
+    }
+
+    /// Replaces the options used by the client.
+    ///
+    /// The new options take effect for all existing items from the next time each is used,
+    /// without needing to recreate the client.
+    /// Items which are now ignored are removed,
+    /// and those which no longer are are added.
+    ///
+    /// # Errors
+    ///
+    /// If an option which can only be set when the client is created is changed,
+    /// such as [`ClientOptions::event_capacity`].
+    /// The options in use are left unchanged.
+    ///
+    /// # Panics
+    ///
+    /// If the options lock is poisoned.
+    pub fn set_options(&self, options: ClientOptions) -> crate::error::Result<()> {
+        let ignore_changed = {
+            let mut current = self.ctx.options.write().expect("lock should succeed");
+            current.check_runtime_change(&options)?;
+
+            let ignore_changed = current.ignore_ids != options.ignore_ids;
+            *current = options;
+            ignore_changed
+        };
+
+        if ignore_changed {
+            self.apply_ignore_ids()?;
+        }
+
+        Ok(())
+    }
+
+    /// Removes items which are now ignored,
+    /// and fetches those which were previously ignored from the watcher.
+    fn apply_ignore_ids(&self) -> crate::error::Result<()> {
+        let options = self.ctx.options();
+        let ignored = self
+            .ctx
+            .items
+            .lock()
+            .expect("mutex lock should succeed")
+            .iter()
+            .filter(|(_, (item, _))| options.is_ignored(&item.id))
+            .map(|(address, _)| address.clone())
+            .collect::<Vec<_>>();
+
+        for address in ignored {
+            debug!("removing newly ignored item {address}");
+            self.ctx.send_remove(&address)?;
+        }
+
+        // items which are already present are skipped
+        self.ctx.spawn(|ctx| async move {
+            if let Err(err) = Self::handle_registered_items(&ctx).await {
+                error!("failed to fetch registered items: {err}");
+            }
+
+            Ok(())
+        });
+
+        Ok(())
+    }
+
+    /// Sends an activate request for a menu item.
+    ///
+    /// The method waits for the item to reply, so any error it returns is surfaced to the caller.
+    /// If the activation fails, an [`Event::ActivationFailed`] is also emitted,
+    /// allowing UIs to tell the user that a click did nothing.
+    ///
+    /// Requests are queued and sent one at a time per item,
+    /// so rapid clicks cannot interleave calls to the same application.
+    /// A request identical to one still waiting in the queue is merged into it
+    /// and returns immediately, unless disabled with [`ClientOptions::coalesce_activations`].
+    ///
+    /// # Errors
+    ///
+    /// The method will return an error if the connection to the `DBus` object fails,
+    /// if too many requests are already queued for the item,
+    /// or if sending the event fails for any reason.
+    ///
+    /// If the item itself fails to handle the request,
+    /// an [`Error::Activation`] describes why,
+    /// so that UIs can respond appropriately, for example by disabling a menu entry
+    /// on [`ActivationError::MethodUnsupported`].
+    ///
+    /// # Panics
+    ///
+    /// If the system time is somehow before the Unix epoch.
+    pub async fn activate(&self, req: ActivateRequest) -> crate::error::Result<()> {
+        let address = req.address().to_string();
+        let reply = self.activations.push(req);
+        self.finish_activation(address, reply).await
+    }
+
+    /// Waits for the reply to a request pushed to the activation queue,
+    /// sending an [`Event::ActivationFailed`] if it failed.
+    async fn finish_activation(
+        &self,
+        address: String,
+        reply: crate::error::Result<Option<oneshot::Receiver<crate::error::Result<()>>>>,
+    ) -> crate::error::Result<()> {
+        let res = match reply {
+            Ok(Some(reply)) => queue::recv_reply(reply).await,
+            Ok(None) => Ok(()),
+            Err(err) => Err(err),
+        };
+        if let Err(err) = &res {
+            error!("failed to activate '{address}': {err}");
+            let generation = self.ctx.generation.load(Ordering::SeqCst);
+            self.ctx.emit(
+                generation,
+                Event::ActivationFailed(address, err.to_string()),
+            )?;
+        }
+
+        res
+    }
+
+    /// Searches the cached menus of all items for entries whose label contains `query`,
+    /// returning the address of the item each result belongs to.
+    ///
+    /// Results for each item are in menu order, but items are in no particular order.
+    /// Results can be clicked using [`Client::menu`] and [`MenuHandle::click`].
+    /// See [`TrayMenu::search`] for details on matching.
+    ///
+    /// # Panics
+    ///
+    /// If the state mutex is poisoned.
+    #[must_use]
+    pub fn search_menus(&self, query: &str) -> Vec<(String, MenuSearchResult)> {
+        let items = self.ctx.items.lock().expect("mutex lock should succeed");
+
+        items
+            .iter()
+            .filter_map(|(address, (_, menu))| Some((address, menu.as_ref()?)))
+            .flat_map(|(address, menu)| {
+                menu.search(query)
+                    .into_iter()
+                    .map(|result| (address.clone(), result))
+            })
+            .collect()
+    }
+
+    /// Gets a handle to the menu of an item,
+    /// for sending menu operations to it.
+    ///
+    /// Returns `None` if the item does not exist or does not have a menu.
+    ///
+    /// # Panics
+    ///
+    /// If the state mutex is poisoned.
+    #[must_use]
+    pub fn menu(&self, address: &str) -> Option<MenuHandle<'_>> {
+        let address = normalize_address(address);
+
+        let menu_path = {
+            let items = self.ctx.items.lock().expect("mutex lock should succeed");
+            items.get(&address)?.0.menu.clone()?
+        };
+
+        Some(MenuHandle::new(self, address, menu_path))
+    }
+
+    /// Clicks the menu item with the given ID in an item's menu,
+    /// looking up the path of the menu from the item's cached properties.
+    ///
+    /// This is a shorthand for [`Client::menu`] followed by [`MenuHandle::click`].
+    ///
+    /// # Errors
+    ///
+    /// If the item is not known, [`ActivationError::ServiceGone`] is returned,
+    /// and if it has no menu, [`ActivationError::MethodUnsupported`].
+    /// Otherwise, see [`Client::activate`].
+    ///
+    /// # Panics
+    ///
+    /// If the state mutex is poisoned.
+    pub async fn activate_menu_item(&self, address: &str, id: i32) -> crate::error::Result<()> {
+        let menu = self.menu_or_error(address)?;
+        menu.click(id).await
+    }
+
+    /// Gets a handle to the menu of an item,
+    /// describing why it is missing otherwise.
+    fn menu_or_error(&self, address: &str) -> crate::error::Result<MenuHandle<'_>> {
+        self.menu(address).ok_or_else(|| {
+            let is_known = self
+                .ctx
+                .items
+                .lock()
+                .expect("mutex lock should succeed")
+                .contains_key(&normalize_address(address));
+
+            if is_known {
+                ActivationError::MethodUnsupported
+            } else {
+                ActivationError::ServiceGone
+            }
+            .into()
+        })
+    }
+
+    /// Sends several events to the menu at `menu_path` of an item in a single call,
+    /// for example closing one submenu and opening another.
+    ///
+    /// Returns the IDs of the menu items which could not be found.
+    /// Events for the other items are still sent.
+    ///
+    /// This is sent through the activation queue, as with [`Client::activate`],
+    /// but is never merged with other requests.
+    ///
+    /// # Errors
+    ///
+    /// See [`MenuHandle::event_group`].
+    pub async fn event_group(
+        &self,
+        address: &str,
+        menu_path: &str,
+        events: &[(i32, MenuEvent)],
+    ) -> crate::error::Result<Vec<i32>> {
+        MenuHandle::new(self, normalize_address(address), menu_path.to_string())
+            .event_group(events)
+            .await
+    }
+
+    /// Waits for an item to appear, returning its address and properties.
+    ///
+    /// `id_or_address` is matched against both the item's ID and its address.
+    /// Items which already exist are matched straight away.
+    ///
+    /// # Errors
+    ///
+    /// If no matching item appears within `timeout`,
+    /// or the client stops sending events.
+    ///
+    /// # Panics
+    ///
+    /// If the state mutex is poisoned.
+    pub async fn wait_for_item(
+        &self,
+        id_or_address: &str,
+        timeout: Duration,
+    ) -> crate::error::Result<(String, StatusNotifierItem)> {
+        let key = normalize_address(id_or_address);
+        let matches =
+            |address: &str, item: &StatusNotifierItem| address == key || item.id == id_or_address;
+
+        let find_existing = || {
+            let items = self.ctx.items.lock().expect("mutex lock should succeed");
+            items
+                .iter()
+                .find(|(address, (item, _))| matches(address, item))
+                .map(|(address, (item, _))| (address.clone(), item.clone()))
+        };
+
+        // subscribe first so that an item added in between is not missed
+        let mut rx = self.subscribe();
+
+        if let Some(found) = find_existing() {
+            return Ok(found);
+        }
+
+        let wait = async {
+            loop {
+                match rx.recv().await {
+                    Ok(Event::Add(address, item)) if matches(&address, &item) => {
+                        return Ok((address, Arc::unwrap_or_clone(item)));
+                    }
+                    Ok(_) => {}
+                    Err(broadcast::error::RecvError::Lagged(_)) => {
+                        if let Some(found) = find_existing() {
+                            return Ok(found);
+                        }
+                    }
+                    Err(broadcast::error::RecvError::Closed) => {
+                        return Err(Error::ClientStopped);
+                    }
+                }
+            }
+        };
+
+        tokio::time::timeout(timeout, wait)
+            .await
+            .unwrap_or(Err(Error::Timeout))
+    }
+
+    /// Gets a [`Simulator`](simulate::Simulator) for injecting fake items into this client.
+    #[must_use]
+    pub fn simulate(&self) -> simulate::Simulator<'_> {
+        simulate::Simulator::new(self)
+    }
+
+    /// Searches the cached menu of an item for an entry with the given keyboard shortcut,
+    /// and activates it if found.
+    ///
+    /// This allows bars to pass global hotkeys through to tray menus.
+    /// Only single key press shortcuts are matched;
+    /// see [`TrayMenu::find_by_shortcut`] for details.
+    ///
+    /// Returns whether a matching menu item was found.
+    ///
+    /// # Errors
+    ///
+    /// The method will return an error if activating the menu item fails.
+    /// See [`Client::activate`].
+    ///
+    /// # Panics
+    ///
+    /// If the state mutex is poisoned.
+    pub async fn activate_shortcut(
+        &self,
+        address: &str,
+        modifiers: &[Modifier],
+        key: &str,
+    ) -> crate::error::Result<bool> {
+        match self.shortcut_request(address, modifiers, key) {
+            Some(request) => {
+                self.activate(request).await?;
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    /// Looks up the menu item of an item with the given keyboard shortcut,
+    /// returning the request which activates it.
+    fn shortcut_request(
+        &self,
+        address: &str,
+        modifiers: &[Modifier],
+        key: &str,
+    ) -> Option<ActivateRequest> {
+        let items = self.ctx.items.lock().expect("mutex lock should succeed");
+        find_shortcut(&items, address, modifiers, key)
+    }
+
+    /// Gets a sender for commands to the client.
+    ///
+    /// Unlike the client, the sender does not need an async context to send commands,
+    /// so can be moved into GUI callbacks to trigger activations from them.
+    /// Commands to the same item are sent to it in the order they are sent,
+    /// through the same queue as [`Client::activate`].
+    #[must_use]
+    pub fn command_sender(&self) -> CommandSender {
+        self.commands.get_or_init(|| spawn_processor(self)).clone()
+    }
+
+    /// Asks an item to show its own context menu at the given screen coordinates.
+    ///
+    /// This is a shorthand for sending an [`ActivateRequest::ContextMenu`].
+    /// Items which export a menu, found in [`StatusNotifierItem::menu`],
+    /// usually expect the host to show that instead.
+    ///
+    /// # Errors
+    ///
+    /// See [`Client::activate`].
+    pub async fn context_menu(&self, address: &str, x: i32, y: i32) -> crate::error::Result<()> {
+        self.activate(ActivateRequest::ContextMenu {
+            address: address.to_string(),
+            x,
+            y,
+        })
+        .await
+    }
+}
+
+impl Lifecycle {
+    /// Takes the host name to release,
+    /// returning `None` if the client has already been released.
+    fn take_release(&self) -> Option<Option<WellKnownName<'static>>> {
+        if self.released.swap(true, Ordering::SeqCst) {
+            return None;
+        }
+
+        Some(
+            self.host_name
+                .lock()
+                .expect("mutex lock should succeed")
+                .take(),
+        )
+    }
+
+    /// Releases the host name and embedded watcher from the bus,
+    /// unless this has already been done.
+    async fn release(&self) -> crate::error::Result<()> {
+        match self.take_release() {
+            Some(host_name) => {
+                release_bus_state(&self.ctx.connection, host_name, self.is_embedded).await
+            }
+            None => Ok(()),
+        }
+    }
+}
+
+impl Drop for Lifecycle {
+    fn drop(&mut self) {
+        // the tasks each hold a copy of the context,
+        // so are not stopped by the client going away on its own
+        drop(self.ctx.take_tasks());
+        self.ctx.queued.close();
+
+        let Some(host_name) = self.take_release() else {
+            return;
+        };
+
+        // cleanup requires talking to the bus,
+        // which is only possible while the runtime is still around
+        if let Ok(handle) = tokio::runtime::Handle::try_current() {
+            let connection = self.ctx.connection.clone();
+            let is_embedded = self.is_embedded;
+            handle.spawn(async move {
+                if let Err(err) = release_bus_state(&connection, host_name, is_embedded).await {
+                    error!("failed to clean up client: {err}");
+                }
+            });
+        }
+    }
+}
+
+/// Releases the host name and removes the embedded watcher, if the client attached it,
+/// so that the connection no longer holds any tray state on the bus.
+///
+/// A watcher attached to a shared connection by the application itself is left running.
+async fn release_bus_state(
+    connection: &Connection,
+    host_name: Option<WellKnownName<'static>>,
+    is_embedded: bool,
+) -> crate::error::Result<()> {
+    if let Some(host_name) = host_name {
+        debug!("releasing {host_name}");
+        connection.release_name(host_name).await?;
+    }
+
+    // there is no embedded watcher inside some sandboxes, or on a connection to a peer
+    if is_embedded && StatusNotifierWatcher::is_attached(connection).await {
+        StatusNotifierWatcher::detach_from(connection).await?;
+    }
+
+    Ok(())
+}
+
+/// Sends an activate request to an item,
+/// waiting for it to reply.
+async fn send_activate(
+    ctx: &Context,
+    req: ActivateRequest,
+    timeout: Duration,
+) -> crate::error::Result<()> {
+    let connection = &ctx.connection;
+    let destination = parse_address(req.address()).0.to_string();
+
+    let res = match req {
+        ActivateRequest::MenuItem {
+            menu_path,
+            submenu_id,
+            ..
+        } => {
+            let proxy = DBusMenuProxy::builder(connection)
+                .destination(destination.as_str())?
+                .path(menu_path)?
+                .build()
+                .await?;
+
+            let event = proxy.event(submenu_id, "clicked", &Value::I32(0), event_timestamp());
+
+            let event = call_with_timeout(event, timeout);
+            ctx.observe(&destination, "Event", event).await
+        }
+        ActivateRequest::Default {
+            address,
+            x,
+            y,
+            token,
+        } => {
+            let proxy = get_notifier_item_proxy(connection, address).await?;
+            provide_activation_token(ctx, &proxy, token, timeout).await;
+
+            let activate = call_with_timeout(proxy.activate(x, y), timeout);
+            ctx.observe(&destination, "Activate", activate).await
+        }
+        ActivateRequest::Secondary {
+            address,
+            x,
+            y,
+            token,
+        } => {
+            let proxy = get_notifier_item_proxy(connection, address).await?;
+            provide_activation_token(ctx, &proxy, token, timeout).await;
+
+            let activate = call_with_timeout(proxy.secondary_activate(x, y), timeout);
+            ctx.observe(&destination, "SecondaryActivate", activate)
+                .await
+        }
+        ActivateRequest::ContextMenu { address, x, y } => {
+            let proxy = get_notifier_item_proxy(connection, address).await?;
+            let context_menu = call_with_timeout(proxy.context_menu(x, y), timeout);
+            ctx.observe(&destination, "ContextMenu", context_menu).await
+        }
+        ActivateRequest::Scroll {
+            address,
+            delta,
+            orientation,
+        } => {
+            let proxy = get_notifier_item_proxy(connection, address).await?;
+            let scroll = call_with_timeout(proxy.scroll(delta, orientation.as_str()), timeout);
+            ctx.observe(&destination, "Scroll", scroll).await
+        }
+    };
+
+    res.map_err(classify_activation_error)
+}
+
+/// Sends an XDG activation token to an item ahead of activating it,
+/// so that it can focus the window it shows.
+///
+/// This is best effort, as many items do not support receiving a token,
+/// so failures are only logged.
+async fn provide_activation_token(
+    ctx: &Context,
+    proxy: &StatusNotifierItemProxy<'_>,
+    token: Option<String>,
+    timeout: Duration,
+) {
+    let Some(token) = token else {
+        return;
+    };
+
+    let destination = proxy.destination().to_string();
+    let provide = call_with_timeout(proxy.provide_xdg_activation_token(&token), timeout);
+
+    if let Err(err) = ctx
+        .observe(&destination, "ProvideXdgActivationToken", provide)
+        .await
+    {
+        debug!("[{destination}] could not provide activation token: {err}");
+    }
+}
+
+/// Gets the timestamp sent with menu events, in seconds since the Unix epoch.
+///
+/// # Panics
+///
+/// If the system time is somehow before the Unix epoch.
+fn event_timestamp() -> u32 {
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("time should flow forwards");
+
+    timestamp.as_secs() as u32
+}
+
+async fn get_notifier_item_proxy(
+    connection: &Connection,
+    address: String,
+) -> crate::error::Result<StatusNotifierItemProxy<'_>> {
+    let (destination, path) = parse_address(&address);
+    let proxy = StatusNotifierItemProxy::builder(connection)
+        .destination(destination.to_string())?
+        .path(path)?
+        .build()
+        .await?;
+    Ok(proxy)
+}
+
+/// Awaits the reply to a call,
+/// giving up if the peer does not respond in time.
+async fn call_with_timeout<T, E>(
+    call: impl Future<Output = Result<T, E>>,
+    duration: Duration,
+) -> crate::error::Result<T>
+where
+    Error: From<E>,
+{
+    match timeout(duration, call).await {
+        Ok(res) => Ok(res?),
+        Err(_) => Err(Error::Timeout),
+    }
+}
+
+/// Converts the error from a failed activation into an [`ActivationError`]
+/// describing why it failed, where possible.
+///
+/// Errors which did not come from the item, such as an invalid address,
+/// are returned unchanged.
+fn classify_activation_error(err: Error) -> Error {
+    let (name, description) = match &err {
+        Error::Timeout => return ActivationError::Timeout.into(),
+        Error::ZBus(zbus::Error::MethodError(name, description, _)) => {
+            (name.to_string(), description.clone())
+        }
+        Error::ZBus(zbus::Error::FDO(err)) => (
+            zbus::DBusError::name(&**err).to_string(),
+            zbus::DBusError::description(&**err).map(ToString::to_string),
+        ),
+        _ => return err,
+    };
+
+    let error = match name.as_str() {
+        "org.freedesktop.DBus.Error.ServiceUnknown"
+        | "org.freedesktop.DBus.Error.NameHasNoOwner"
+        | "org.freedesktop.DBus.Error.Disconnected" => ActivationError::ServiceGone,
+        "org.freedesktop.DBus.Error.UnknownObject"
+        | "org.freedesktop.DBus.Error.UnknownMethod"
+        | "org.freedesktop.DBus.Error.UnknownInterface"
+        | "org.freedesktop.DBus.Error.NotSupported" => ActivationError::MethodUnsupported,
+        "org.freedesktop.DBus.Error.NoReply"
+        | "org.freedesktop.DBus.Error.Timeout"
+        | "org.freedesktop.DBus.Error.TimedOut" => ActivationError::Timeout,
+        _ => ActivationError::Rejected(match description {
+            Some(description) => format!("{name}: {description}"),
+            None => name,
+        }),
+    };
+
+    error.into()
+}
+
+/// Checks whether an error indicates that the object being called does not exist,
+/// rather than some other failure.
+fn is_missing_object(err: &zbus::Error) -> bool {
+    const MISSING: [&str; 4] = [
+        "org.freedesktop.DBus.Error.ServiceUnknown",
+        "org.freedesktop.DBus.Error.UnknownObject",
+        "org.freedesktop.DBus.Error.UnknownInterface",
+        "org.freedesktop.DBus.Error.UnknownMethod",
+    ];
+
+    match err {
+        zbus::Error::MethodError(name, _, _) => MISSING.contains(&name.as_str()),
+        zbus::Error::FDO(err) => matches!(
+            **err,
+            zbus::fdo::Error::ServiceUnknown(_)
+                | zbus::fdo::Error::UnknownObject(_)
+                | zbus::fdo::Error::UnknownInterface(_)
+                | zbus::fdo::Error::UnknownMethod(_)
+        ),
+        _ => false,
+    }
+}
+
+/// Gets the unique name of the peer currently owning a bus name.
+///
+/// Unique names are resolved too, which fails if the peer has already disconnected.
+/// Connections to a single peer have no owners to resolve, so return `None`.
+async fn resolve_owner(
+    ctx: &Context,
+    dbus_proxy: &DBusProxy<'_>,
+    destination: &str,
+) -> crate::error::Result<Option<OwnedUniqueName>> {
+    if !ctx.connection.is_bus() {
+        return Ok(None);
+    }
+
+    let name = BusName::try_from(destination).map_err(zbus::Error::from)?;
+    let owner = ctx
+        .observe(
+            names::DBUS_BUS,
+            "GetNameOwner",
+            dbus_proxy.get_name_owner(name),
+        )
+        .await?;
+
+    Ok(Some(owner))
+}
+
+/// Checks whether a signal was sent by the expected peer.
+///
+/// Signals from any other peer are logged and should be ignored,
+/// so that they cannot spoof updates into the tray state.
+/// Without an owner, the connection is to a single peer which sent every signal.
+fn is_from_owner(message: &Message, owner: Option<&UniqueName<'_>>) -> bool {
+    let Some(owner) = owner else {
+        return true;
+    };
+
+    let sender = message
+        .header()
+        .ok()
+        .and_then(|header| header.sender().ok().flatten().map(UniqueName::to_owned));
+
+    let is_owner = sender.as_ref() == Some(owner);
+    if !is_owner {
+        warn!("ignoring signal from unexpected sender {sender:?}, expected {owner}");
+    }
+
+    is_owner
+}
+
+/// Checks whether a signal was sent from the client's own connection,
+/// such as by the embedded watcher.
+fn is_from_self(message: &Message, connection: &Connection) -> bool {
+    let sender = message
+        .header()
+        .ok()
+        .and_then(|header| header.sender().ok().flatten().map(UniqueName::to_owned));
+
+    match (sender, connection.unique_name()) {
+        (Some(sender), Some(name)) => sender == **name,
+        _ => false,
+    }
+}
+
+/// Indexes the installed desktop files on a blocking thread.
+async fn load_desktop_entries() -> DesktopEntries {
+    tokio::task::spawn_blocking(DesktopEntries::load)
+        .await
+        .unwrap_or_else(|err| {
+            error!("failed to load desktop entries: {err}");
+            DesktopEntries::default()
+        })
+}
+
+fn parse_address(address: &str) -> (&str, String) {
+    address
+        .split_once('/')
+        .map_or((address, String::from(ITEM_OBJECT)), |(d, p)| {
+            (d, format!("/{p}"))
+        })
+}
+
+/// Takes a snapshot of the state,
+/// running `f` while it is locked.
+/// See [`Client::snapshot_with`].
+fn snapshot_with<T>(
+    items: &Mutex<State>,
+    timestamps: &Mutex<HashMap<String, ItemTimestamps>>,
+    generation: &AtomicU64,
+    pixmaps: &PixmapCache,
+    f: impl FnOnce() -> T,
+) -> (Snapshot, T) {
+    let items = items.lock().expect("mutex lock should succeed");
+    let timestamps = timestamps.lock().expect("mutex lock should succeed");
+
+    let value = f();
+
+    let snapshot = Snapshot {
+        generation: generation.load(Ordering::SeqCst),
+        items: items.clone(),
+        timestamps: timestamps.clone(),
+        // evictions are made while holding the state lock,
+        // so this matches the copied items
+        evicted: pixmaps.evicted(),
+    };
+
+    (snapshot, value)
+}
+
+/// Gets the address an item is stored under in the state.
+///
+/// Items at the standard object path are addressed by their bus name alone,
+/// while items at any other path include it,
+/// so that a process exposing several items keeps them apart.
+fn item_key(destination: &str, path: &str) -> String {
+    if path == ITEM_OBJECT {
+        destination.to_string()
+    } else {
+        format!("{destination}{path}")
+    }
+}
+
+/// Converts an address in any accepted form
+/// into the address the item is stored under in the state.
+fn normalize_address(address: &str) -> String {
+    let (destination, path) = parse_address(address);
+    item_key(destination, &path)
+}
+
+/// Looks up the menu item of an item in the state with the given keyboard shortcut,
+/// returning the request which activates it.
+fn find_shortcut(
+    items: &State,
+    address: &str,
+    modifiers: &[Modifier],
+    key: &str,
+) -> Option<ActivateRequest> {
+    let address = normalize_address(address);
+
+    items.get(&address).and_then(|(item, menu)| {
+        let menu_path = item.menu.clone()?;
+        let submenu_id = menu.as_ref()?.find_by_shortcut(modifiers, key)?.id;
+
+        Some(ActivateRequest::MenuItem {
+            address,
+            menu_path,
+            submenu_id,
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_unnamed() {
+        let address = ":1.58/StatusNotifierItem";
+        let (destination, path) = parse_address(address);
+
+        assert_eq!(":1.58", destination);
+        assert_eq!("/StatusNotifierItem", path);
+    }
+
+    #[test]
     fn parse_named() {
         let address = ":1.72/org/ayatana/NotificationItem/dropbox_client_1398";
         let (destination, path) = parse_address(address);
@@ -671,4 +3844,703 @@ mod tests {
         assert_eq!(":1.72", destination);
         assert_eq!("/org/ayatana/NotificationItem/dropbox_client_1398", path);
     }
+
+    #[test]
+    fn normalizes_addresses() {
+        assert_eq!(":1.58", normalize_address(":1.58"));
+        assert_eq!(":1.58", normalize_address(":1.58/StatusNotifierItem"));
+        assert_eq!(
+            ":1.72/org/ayatana/NotificationItem/a",
+            normalize_address(":1.72/org/ayatana/NotificationItem/a")
+        );
+
+        let quit = MenuItem {
+            id: 3,
+            enabled: true,
+            visible: true,
+            shortcut: Some(vec![vec![String::from("Control"), String::from("Q")]]),
+            ..Default::default()
+        };
+        let item = StatusNotifierItem {
+            menu: Some(String::from("/Menu")),
+            ..Default::default()
+        };
+        let items = State::from([(
+            String::from(":1.58"),
+            (item, Some(TrayMenu::new(0, vec![quit]))),
+        )]);
+
+        for address in [":1.58", ":1.58/StatusNotifierItem"] {
+            assert_eq!(
+                find_shortcut(&items, address, &[Modifier::Control], "q"),
+                Some(ActivateRequest::MenuItem {
+                    address: String::from(":1.58"),
+                    menu_path: String::from("/Menu"),
+                    submenu_id: 3,
+                })
+            );
+        }
+    }
+
+    #[test]
+    fn flags_evicted_items_in_snapshots() {
+        let item = StatusNotifierItem {
+            icon_pixmap: Some(vec![IconPixmap {
+                width: 1,
+                height: 1,
+                pixels: vec![0; 100],
+            }]),
+            ..Default::default()
+        };
+
+        let pixmaps = PixmapCache::default();
+        pixmaps.touch(":1.1", &item);
+        pixmaps.touch(":1.2", &item);
+
+        let mut state = State::from([
+            (String::from(":1.1"), (item.clone(), None)),
+            (String::from(":1.2"), (item, None)),
+        ]);
+        for destination in pixmaps.evict(100) {
+            let (item, _) = state.get_mut(&destination).expect("item should exist");
+            pixmap_cache::evict_pixmaps(item);
+        }
+
+        let (snapshot, ()) = snapshot_with(
+            &Mutex::new(state),
+            &Mutex::default(),
+            &AtomicU64::default(),
+            &pixmaps,
+            || (),
+        );
+
+        assert_eq!(snapshot.evicted, HashSet::from([String::from(":1.1")]));
+        assert!(snapshot.items[":1.1"].0.icon_pixmap.is_none());
+        assert!(snapshot.items[":1.2"].0.icon_pixmap.is_some());
+    }
+
+    #[test]
+    fn classifies_activation_errors() {
+        let classify = |err: zbus::fdo::Error| match classify_activation_error(Error::ZBus(
+            zbus::Error::FDO(Box::new(err)),
+        )) {
+            Error::Activation(err) => err,
+            err => panic!("unexpected error: {err}"),
+        };
+
+        assert_eq!(
+            classify(zbus::fdo::Error::ServiceUnknown(String::new())),
+            ActivationError::ServiceGone
+        );
+        assert_eq!(
+            classify(zbus::fdo::Error::UnknownMethod(String::new())),
+            ActivationError::MethodUnsupported
+        );
+        assert_eq!(
+            classify(zbus::fdo::Error::AccessDenied(String::from("nope"))),
+            ActivationError::Rejected(String::from(
+                "org.freedesktop.DBus.Error.AccessDenied: nope"
+            ))
+        );
+        assert!(matches!(
+            classify_activation_error(Error::Timeout),
+            Error::Activation(ActivationError::Timeout)
+        ));
+    }
+
+    #[test]
+    fn detects_unchanged_updates() {
+        let item = StatusNotifierItem {
+            title: Some(String::from("App")),
+            icon_name: Some(String::from("app")),
+            ..Default::default()
+        };
+
+        assert!(UpdateEvent::Title(Some(String::from("App"))).is_unchanged(&item));
+        assert!(!UpdateEvent::Title(None).is_unchanged(&item));
+        assert!(UpdateEvent::Icon {
+            icon_name: Some(String::from("app")),
+            icon_pixmap: None,
+        }
+        .is_unchanged(&item));
+        assert!(!UpdateEvent::Icon {
+            icon_name: Some(String::from("app")),
+            icon_pixmap: Some(Vec::new()),
+        }
+        .is_unchanged(&item));
+        assert!(!UpdateEvent::MenuOrphaned.is_unchanged(&item));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn keeps_items_when_watcher_restarts() {
+        use crate::test_bus::{MockItem, TestBus};
+
+        let bus = TestBus::start();
+        let client = bus
+            .builder()
+            .watcher_check_interval(Some(Duration::from_millis(50)))
+            .build()
+            .await
+            .expect("client should start");
+
+        let _item = bus.serve_item(MockItem::new("restart")).await;
+        let (address, _) = client
+            .wait_for_item("restart", Duration::from_secs(5))
+            .await
+            .expect("item should appear");
+
+        let mut rx = client.subscribe();
+        StatusNotifierWatcher::detach_from(&client.ctx.connection)
+            .await
+            .expect("watcher should detach");
+
+        let mut events = Vec::new();
+        let collect = async {
+            loop {
+                let event = rx.recv().await.expect("client should send events");
+                events.push(event);
+            }
+        };
+        let _ = timeout(Duration::from_millis(500), collect).await;
+
+        assert!(events
+            .iter()
+            .any(|event| matches!(event, Event::WatcherRestarted)));
+        assert!(!events
+            .iter()
+            .any(|event| matches!(event, Event::Remove(_) | Event::WatcherChanged)));
+        assert!(client
+            .items()
+            .lock()
+            .expect("mutex lock should succeed")
+            .contains_key(&address));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn receives_registrations_from_embedded_watcher() {
+        use crate::test_bus::{recv_until, MockItem, TestBus};
+
+        let bus = TestBus::start();
+        let client = bus
+            .builder()
+            .watcher_check_interval(Some(Duration::from_millis(50)))
+            .build()
+            .await
+            .expect("client should start");
+
+        let mut registrations = client.ctx.watcher_events.subscribe();
+        let mut rx = client.subscribe();
+        let item = bus.serve_item(MockItem::new("direct")).await;
+        let name = item.unique_name().expect("connection should be on a bus");
+
+        let registered = timeout(Duration::from_secs(5), registrations.recv())
+            .await
+            .expect("registration should be received")
+            .expect("watcher should be running");
+        assert_eq!(
+            registered,
+            WatcherEvent::ItemRegistered(format!("{name}/StatusNotifierItem"))
+        );
+
+        // the watcher's own signal for the registration is ignored,
+        // so the item is only added once
+        recv_until(&mut rx, |event| {
+            matches!(event, Event::Add(address, _) if address == name.as_str()).then_some(())
+        })
+        .await;
+        let added_again = timeout(Duration::from_millis(300), async {
+            recv_until(&mut rx, |event| {
+                matches!(event, Event::Add(address, _) if address == name.as_str()).then_some(())
+            })
+            .await;
+        })
+        .await;
+        assert!(added_again.is_err());
+
+        // registrations keep arriving from the watcher it is restarted with
+        StatusNotifierWatcher::detach_from(&client.ctx.connection)
+            .await
+            .expect("watcher should detach");
+        recv_until(&mut rx, |event| {
+            matches!(event, Event::WatcherRestarted).then_some(())
+        })
+        .await;
+
+        let item = bus.serve_item(MockItem::new("restarted")).await;
+        let name = item.unique_name().expect("connection should be on a bus");
+        let expected = WatcherEvent::ItemRegistered(format!("{name}/StatusNotifierItem"));
+        timeout(Duration::from_secs(5), async {
+            while registrations
+                .recv()
+                .await
+                .expect("watcher should be running")
+                != expected
+            {}
+        })
+        .await
+        .expect("registration should be received");
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn removes_items_releasing_their_name() {
+        use crate::test_bus::{recv_until, MockItem, TestBus};
+
+        let bus = TestBus::start();
+        let client = bus.client().await;
+
+        let mut registrations = client.ctx.watcher_events.subscribe();
+        let name = "org.kde.StatusNotifierItem-1-1";
+        let item = bus.serve_named_item(MockItem::new("named"), name).await;
+        let (address, _) = client
+            .wait_for_item("named", Duration::from_secs(5))
+            .await
+            .expect("item should appear");
+
+        // the item stays on the bus, but no longer has the name it registered with
+        let mut rx = client.subscribe();
+        item.release_name(name)
+            .await
+            .expect("name should be released");
+
+        recv_until(&mut rx, |event| {
+            matches!(&event, Event::Remove(removed) if *removed == address).then_some(())
+        })
+        .await;
+
+        let unregistered = WatcherEvent::ItemUnregistered(format!("{address}/StatusNotifierItem"));
+        timeout(Duration::from_secs(5), async {
+            while registrations
+                .recv()
+                .await
+                .expect("watcher should be running")
+                != unregistered
+            {}
+        })
+        .await
+        .expect("item should unregister from the watcher");
+        assert!(!client
+            .items()
+            .lock()
+            .expect("mutex lock should succeed")
+            .contains_key(&address));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn follows_watcher_takeover() {
+        use crate::test_bus::{recv_until, serve_item_at, MockItem, TestBus};
+
+        let bus = TestBus::start();
+        let first = StatusNotifierWatcher::new()
+            .run_on(bus.connect().await)
+            .await
+            .expect("watcher should start");
+        let client = bus
+            .builder()
+            .embedded_watcher(false)
+            .build()
+            .await
+            .expect("client should start");
+
+        let _old_item = bus.serve_item(MockItem::new("old")).await;
+        let (old_address, _) = client
+            .wait_for_item("old", Duration::from_secs(5))
+            .await
+            .expect("item should appear");
+
+        // the second watcher queues behind the first,
+        // and has an item registered with it directly
+        let second = StatusNotifierWatcher::new()
+            .run_on(bus.connect().await)
+            .await
+            .expect("watcher should start");
+        let watcher_name = second
+            .connection()
+            .unique_name()
+            .expect("connection should be on a bus")
+            .to_string();
+        let new_item = bus.connect().await;
+        serve_item_at(&new_item, MockItem::new("new")).await;
+        let new_address = new_item
+            .unique_name()
+            .expect("connection should be on a bus")
+            .to_string();
+        new_item
+            .call_method(
+                Some(watcher_name.as_str()),
+                names::WATCHER_OBJECT,
+                Some(names::WATCHER_BUS),
+                "RegisterStatusNotifierItem",
+                &(new_address.as_str(),),
+            )
+            .await
+            .expect("item should register");
+
+        let mut rx = client.subscribe();
+        first.stop().await.expect("watcher should stop");
+
+        let mut removed = false;
+        let mut added = false;
+        recv_until(&mut rx, |event| {
+            match event {
+                Event::Remove(address) => removed |= address == old_address,
+                Event::Add(address, _) => added |= address == new_address,
+                Event::WatcherChanged => return Some(()),
+                _ => {}
+            }
+            None
+        })
+        .await;
+        assert!(removed && added);
+
+        let items = client.items();
+        let items = items.lock().expect("mutex lock should succeed");
+        assert!(items.contains_key(&new_address));
+        assert!(!items.contains_key(&old_address));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn registers_host_under_chosen_name() {
+        use crate::test_bus::TestBus;
+
+        let bus = TestBus::start();
+        let name = "org.freedesktop.StatusNotifierHost-test";
+        let client = bus
+            .builder()
+            .host_name(name)
+            .build()
+            .await
+            .expect("client should start");
+
+        let dbus = DBusProxy::new(&client.ctx.connection)
+            .await
+            .expect("proxy should be created");
+        let owner = dbus
+            .get_name_owner(name.try_into().expect("name should be valid"))
+            .await
+            .expect("name should be owned");
+        assert_eq!(client.ctx.connection.unique_name(), Some(&owner));
+
+        let watcher = StatusNotifierWatcherProxy::new(&client.ctx.connection)
+            .await
+            .expect("proxy should be created");
+        assert!(watcher
+            .is_status_notifier_host_registered()
+            .await
+            .expect("property should be read"));
+
+        // a chosen name is never replaced with a generated one
+        let taken = bus.builder().host_name(name).build().await;
+        assert!(matches!(taken, Err(Error::ZBus(zbus::Error::NameTaken))));
+
+        let invalid = bus.builder().host_name("not a name").build().await;
+        assert!(matches!(invalid, Err(Error::ZBus(zbus::Error::Names(_)))));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn keeps_application_watcher_on_shared_connection() {
+        use crate::test_bus::TestBus;
+
+        let bus = TestBus::start();
+        let connection = bus.connect().await;
+        StatusNotifierWatcher::new()
+            .attach_to(&connection)
+            .await
+            .expect("watcher should attach");
+
+        let client = bus
+            .builder()
+            .connection(connection.clone())
+            .embedded_watcher(false)
+            .build()
+            .await
+            .expect("client should start");
+        client.shutdown().await.expect("client should shut down");
+        assert!(StatusNotifierWatcher::is_attached(&connection).await);
+
+        // dropping cleans up in the background
+        let client = bus
+            .builder()
+            .connection(connection.clone())
+            .embedded_watcher(false)
+            .build()
+            .await
+            .expect("client should start");
+        drop(client);
+        sleep(Duration::from_millis(200)).await;
+        assert!(StatusNotifierWatcher::is_attached(&connection).await);
+
+        // a watcher the client attached itself is still removed
+        StatusNotifierWatcher::detach_from(&connection)
+            .await
+            .expect("watcher should detach");
+        let client = bus
+            .builder()
+            .connection(connection.clone())
+            .build()
+            .await
+            .expect("client should start");
+        assert!(StatusNotifierWatcher::is_attached(&connection).await);
+        client.shutdown().await.expect("client should shut down");
+        assert!(!StatusNotifierWatcher::is_attached(&connection).await);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn keeps_icon_current_while_animating() {
+        use crate::test_bus::{recv_until, set_icon, wait_until_watched, MockItem, TestBus};
+
+        assert!(ClientOptions::default().animation_threshold.is_none());
+
+        let bus = TestBus::start();
+        let client = bus
+            .builder()
+            .animation_threshold(Some(Duration::from_millis(250)))
+            .build()
+            .await
+            .expect("client should start");
+
+        let mut rx = client.subscribe();
+        let item = bus.serve_item(MockItem::new("animated")).await;
+        let (address, _) = client
+            .wait_for_item("animated", Duration::from_secs(5))
+            .await
+            .expect("item should appear");
+        wait_until_watched(&item, &mut rx).await;
+
+        let icon_name = || {
+            client
+                .items()
+                .lock()
+                .expect("mutex lock should succeed")
+                .get(&address)
+                .and_then(|(item, _)| item.icon_name.clone())
+        };
+
+        // a meter-style icon which never repeats a frame
+        let mut icon_updates = 0;
+        for i in 0..10 {
+            let name = format!("level-{i}");
+            set_icon(&item, &name).await;
+            sleep(Duration::from_millis(50)).await;
+
+            while let Ok(event) = rx.try_recv() {
+                if matches!(event, Event::Update(_, UpdateEvent::Icon { .. })) {
+                    icon_updates += 1;
+                }
+            }
+
+            // updates are only held back once the animation is detected
+            if i > 5 {
+                assert_eq!(icon_name().as_deref(), Some(name.as_str()));
+            }
+        }
+        assert!(icon_updates < 10);
+
+        // a single update with the final icon is sent once it stops
+        let final_icon = recv_until(&mut rx, |event| match event {
+            Event::Update(_, UpdateEvent::Icon { icon_name, .. }) => Some(icon_name),
+            _ => None,
+        })
+        .await;
+        assert_eq!(final_icon.as_deref(), Some("level-9"));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn fetches_registrations_missed_from_embedded_watcher() {
+        use crate::test_bus::{MockItem, TestBus};
+
+        let bus = TestBus::start();
+        let client = bus.client().await;
+        client.wait_ready().await;
+
+        let send = |address: String| {
+            client
+                .ctx
+                .watcher_events
+                .send(WatcherEvent::ItemRegistered(address))
+                .expect("send should succeed");
+        };
+
+        // the error for an item which does not exist fills the queue,
+        // which then blocks the client from handling further registrations
+        let queued = client.subscribe_queued(1, OverflowPolicy::Block);
+        send(String::from(":1.900/StatusNotifierItem"));
+        sleep(Duration::from_millis(200)).await;
+        send(String::from(":1.901/StatusNotifierItem"));
+        sleep(Duration::from_millis(100)).await;
+
+        // the registration is pushed out of the channel while the client is blocked
+        let _missed = bus.serve_item(MockItem::new("missed")).await;
+        for i in 0..WATCHER_EVENT_CAPACITY {
+            send(format!(":1.{}/StatusNotifierItem", 902 + i));
+        }
+
+        drop(queued);
+        client
+            .wait_for_item("missed", Duration::from_secs(5))
+            .await
+            .expect("item should appear");
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn skips_ignored_items() {
+        use crate::test_bus::{MockItem, TestBus};
+
+        let bus = TestBus::start();
+        let client = bus
+            .builder()
+            .ignore_ids(["Ignored"])
+            .build()
+            .await
+            .expect("client should start");
+        let mut rx = client.subscribe();
+
+        // the ignored item registers first,
+        // so it has been handled by the time the other one is added
+        let _ignored = bus.serve_item(MockItem::new("ignored")).await;
+        let _kept = bus.serve_item(MockItem::new("kept")).await;
+        client
+            .wait_for_item("kept", Duration::from_secs(5))
+            .await
+            .expect("item should appear");
+        sleep(Duration::from_millis(200)).await;
+
+        while let Ok(event) = rx.try_recv() {
+            if let Event::Add(_, item) = event {
+                assert_eq!(item.id, "kept", "ignored item should not be added");
+            }
+        }
+        assert_eq!(
+            client
+                .items()
+                .lock()
+                .expect("mutex lock should succeed")
+                .len(),
+            1
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn applies_ignored_ids_to_running_client() {
+        use crate::test_bus::{recv_until, MockItem, TestBus};
+
+        let bus = TestBus::start();
+        let client = bus.client().await;
+
+        let _kept = bus.serve_item(MockItem::new("kept")).await;
+        let _ignored = bus.serve_item(MockItem::new("ignored")).await;
+        let (ignored, _) = client
+            .wait_for_item("ignored", Duration::from_secs(5))
+            .await
+            .expect("item should appear");
+        client
+            .wait_for_item("kept", Duration::from_secs(5))
+            .await
+            .expect("item should appear");
+
+        let mut rx = client.subscribe();
+        let mut options = client.options();
+        options.ignore_ids = vec![String::from("IGNORED")];
+        client
+            .set_options(options.clone())
+            .expect("options should be set");
+
+        recv_until(&mut rx, |event| {
+            matches!(&event, Event::Remove(address) if *address == ignored).then_some(())
+        })
+        .await;
+        assert_eq!(
+            client
+                .items()
+                .lock()
+                .expect("mutex lock should succeed")
+                .len(),
+            1
+        );
+
+        options.ignore_ids.clear();
+        client.set_options(options).expect("options should be set");
+        client
+            .wait_for_item("ignored", Duration::from_secs(5))
+            .await
+            .expect("item should be added again");
+
+        // creation-only options are rejected, leaving the options in use unchanged
+        let mut options = client.options();
+        options.event_capacity += 1;
+        assert!(matches!(
+            client.set_options(options),
+            Err(Error::CreationOnlyOption("event_capacity"))
+        ));
+        assert_eq!(
+            client.options().event_capacity,
+            ClientOptions::default().event_capacity
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn ignores_signals_from_other_senders() {
+        use crate::test_bus::{recv_until, set_title, wait_until_watched, MockItem, TestBus};
+
+        let bus = TestBus::start();
+        let client = bus.client().await;
+
+        let mut rx = client.subscribe();
+        let item = bus.serve_item(MockItem::new("spoofed")).await;
+        client
+            .wait_for_item("spoofed", Duration::from_secs(5))
+            .await
+            .expect("item should appear");
+        wait_until_watched(&item, &mut rx).await;
+        // give the menu time to be watched too
+        sleep(Duration::from_millis(200)).await;
+        while rx.try_recv().is_ok() {}
+
+        // another connection sends the item's signals on its paths,
+        // both broadcast and directly to the host, which bypasses match rules
+        let other = bus.connect().await;
+        let host = client.ctx.connection.unique_name().cloned();
+        for destination in [None, host] {
+            other
+                .emit_signal(
+                    destination.clone(),
+                    ITEM_OBJECT,
+                    "org.kde.StatusNotifierItem",
+                    "NewTitle",
+                    &(),
+                )
+                .await
+                .expect("signal should be sent");
+            other
+                .emit_signal(
+                    destination,
+                    "/Menu",
+                    "com.canonical.dbusmenu",
+                    "LayoutUpdated",
+                    &(2u32, 0i32),
+                )
+                .await
+                .expect("signal should be sent");
+        }
+
+        // the item's own signal still arrives after the spoofed ones
+        set_title(&item, "Real").await;
+        recv_until(&mut rx, |event| match event {
+            Event::Update(_, UpdateEvent::Title(Some(title))) if title == "Real" => Some(()),
+            Event::Update(_, update) => panic!("unexpected update: {update:?}"),
+            Event::UpdateBatch(_, updates) => panic!("unexpected updates: {updates:?}"),
+            _ => None,
+        })
+        .await;
+
+        let spoofed = timeout(Duration::from_millis(500), async {
+            loop {
+                match rx.recv().await.expect("client should be running") {
+                    Event::Update(..) | Event::UpdateBatch(..) => return,
+                    _ => {}
+                }
+            }
+        })
+        .await;
+        assert!(spoofed.is_err());
+    }
 }
@@ -0,0 +1,152 @@
+use crate::item::{AnimatedIcon, IconFrame};
+use std::time::{Duration, Instant};
+
+/// Number of consecutive rapid icon changes
+/// before an item is considered to be animating.
+const MIN_CHANGES: u32 = 4;
+
+/// Maximum number of frames collected for a single animation.
+/// Animations which do not loop within this many frames
+/// are sent in chunks of this size.
+const MAX_FRAMES: usize = 64;
+
+/// Detects items animating their icon by changing it rapidly,
+/// collecting the frames into an [`AnimatedIcon`].
+///
+/// One detector is used per item.
+#[derive(Debug, Default)]
+pub(crate) struct AnimationDetector {
+    last_change: Option<Instant>,
+    threshold: Duration,
+    rapid_changes: u32,
+    total_interval: Duration,
+    /// Frames collected for the current loop of the animation.
+    frames: Vec<IconFrame>,
+    /// The last animation sent.
+    current: Option<AnimatedIcon>,
+}
+
+impl AnimationDetector {
+    /// Records an icon change,
+    /// returning whether the item is currently animating.
+    ///
+    /// Changes less than `threshold` apart count towards an animation.
+    pub fn tick(&mut self, now: Instant, threshold: Duration) -> bool {
+        let interval = self
+            .last_change
+            .map(|last| now.saturating_duration_since(last));
+        self.last_change = Some(now);
+        self.threshold = threshold;
+
+        match interval {
+            Some(interval) if interval < threshold => {
+                self.rapid_changes = self.rapid_changes.saturating_add(1);
+                self.total_interval += interval;
+            }
+            _ => self.reset(),
+        }
+
+        self.is_animating()
+    }
+
+    /// Adds a frame to the current animation,
+    /// returning the animation once a full loop has been collected
+    /// if it differs from the last one returned.
+    pub fn push_frame(&mut self, frame: IconFrame) -> Option<AnimatedIcon> {
+        let looped = self.frames.len() > 1 && self.frames.first() == Some(&frame);
+
+        if !looped {
+            self.frames.push(frame);
+            if self.frames.len() < MAX_FRAMES {
+                return None;
+            }
+        }
+
+        let frames = std::mem::take(&mut self.frames);
+        if looped {
+            self.frames.push(frames[0].clone());
+        }
+
+        if self
+            .current
+            .as_ref()
+            .is_some_and(|current| current.frames == frames)
+        {
+            return None;
+        }
+
+        let animation = AnimatedIcon {
+            frames,
+            interval: self.total_interval / self.rapid_changes.max(1),
+        };
+
+        self.current = Some(animation.clone());
+        Some(animation)
+    }
+
+    /// Gets the time at which the animation should be considered stopped
+    /// if no further changes arrive.
+    pub fn deadline(&self) -> Option<Instant> {
+        self.last_change
+            .filter(|_| self.is_animating())
+            .map(|last| last + self.threshold * 2)
+    }
+
+    /// Ends the current animation,
+    /// returning whether the item was animating.
+    pub fn finish(&mut self) -> bool {
+        let was_animating = self.is_animating();
+        self.reset();
+        self.last_change = None;
+        was_animating
+    }
+
+    fn is_animating(&self) -> bool {
+        self.rapid_changes >= MIN_CHANGES
+    }
+
+    fn reset(&mut self) {
+        self.rapid_changes = 0;
+        self.total_interval = Duration::ZERO;
+        self.frames.clear();
+        self.current = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(name: &str) -> IconFrame {
+        IconFrame {
+            name: Some(name.to_string()),
+            pixmap: None,
+        }
+    }
+
+    #[test]
+    fn collects_looping_frames() {
+        let mut detector = AnimationDetector::default();
+        let threshold = Duration::from_millis(250);
+        let start = Instant::now();
+
+        let mut animation = None;
+        for (i, name) in ["a", "b", "c", "a", "b", "c", "a", "b"].iter().enumerate() {
+            let now = start + Duration::from_millis(100) * i as u32;
+            if detector.tick(now, threshold) {
+                if let Some(anim) = detector.push_frame(frame(name)) {
+                    assert!(animation.is_none(), "animation should only be sent once");
+                    animation = Some(anim);
+                }
+            }
+        }
+
+        let animation = animation.expect("animation to be detected");
+        assert_eq!(animation.frames, [frame("b"), frame("c"), frame("a")]);
+        assert_eq!(animation.interval, Duration::from_millis(100));
+
+        assert!(detector.deadline().is_some());
+        assert!(detector.finish());
+        assert!(detector.deadline().is_none());
+    }
+}
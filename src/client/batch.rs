@@ -0,0 +1,94 @@
+use super::UpdateEvent;
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+/// Collects updates for each item which arrive close together,
+/// so they can be sent as a single [`super::Event::UpdateBatch`].
+///
+/// Each update is stored alongside the state generation it was received at.
+#[derive(Debug, Default)]
+pub(crate) struct UpdateBatcher {
+    pending: Mutex<HashMap<String, Vec<(u64, UpdateEvent)>>>,
+}
+
+impl UpdateBatcher {
+    /// Adds an update to the item's pending batch.
+    ///
+    /// Returns whether this is the first update in the batch,
+    /// in which case the caller is responsible for scheduling it to be sent.
+    pub fn push(&self, address: &str, generation: u64, event: UpdateEvent) -> bool {
+        let mut pending = self.pending.lock().expect("mutex lock should succeed");
+        let batch = pending.entry(address.to_string()).or_default();
+        batch.push((generation, event));
+        batch.len() == 1
+    }
+
+    /// Takes all pending updates for the item.
+    pub fn take(&self, address: &str) -> Vec<(u64, UpdateEvent)> {
+        self.pending
+            .lock()
+            .expect("mutex lock should succeed")
+            .remove(address)
+            .unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::Event;
+    use crate::item::Status;
+    use crate::test_bus::{
+        recv_until, set_status, set_title, wait_until_watched, MockItem, TestBus,
+    };
+    use std::time::Duration;
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn sends_updates_arriving_together_as_batch() {
+        let bus = TestBus::start();
+        let client = bus
+            .builder()
+            .batch_window(Duration::from_millis(200))
+            .build()
+            .await
+            .expect("client should start");
+
+        let item = bus.serve_item(MockItem::new("batch")).await;
+        client
+            .wait_for_item("batch", Duration::from_secs(5))
+            .await
+            .expect("item should appear");
+
+        let mut rx = client.subscribe();
+        wait_until_watched(&item, &mut rx).await;
+
+        set_title(&item, "Batch").await;
+        set_status(&item, "Passive").await;
+
+        // other updates may arrive in the meantime,
+        // so only the changes made here are checked
+        let is_change = |update: &UpdateEvent| match update {
+            UpdateEvent::Title(title) => title.as_deref() == Some("Batch"),
+            UpdateEvent::Status(status) => *status == Status::Passive,
+            _ => false,
+        };
+
+        let updates = recv_until(&mut rx, |event| match event {
+            Event::UpdateBatch(_, updates) if updates.iter().any(is_change) => Some(updates),
+            Event::Update(_, update) if is_change(&update) => {
+                panic!("update should be batched: {update:?}")
+            }
+            _ => None,
+        })
+        .await;
+
+        let updates: Vec<_> = updates.into_iter().filter(is_change).collect();
+        assert!(
+            matches!(
+                updates.as_slice(),
+                [UpdateEvent::Title(_), UpdateEvent::Status(_)]
+            ),
+            "unexpected updates: {updates:?}"
+        );
+    }
+}
@@ -0,0 +1,305 @@
+use super::queue::recv_reply;
+use super::{ActivateRequest, ActivationQueue, Client, Context, Lifecycle, MenuEvent};
+use crate::desktop::DesktopEntries;
+use crate::error::{Error, Result};
+use crate::menu::Modifier;
+use std::sync::{Arc, OnceLock, Weak};
+use tokio::sync::mpsc;
+use tracing::{trace, warn};
+
+/// A command sent to a client through a [`CommandSender`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Command {
+    /// Sends an activation request.
+    /// See [`Client::activate`].
+    Activate(ActivateRequest),
+    /// Clicks the menu item with the given ID in an item's menu.
+    /// See [`Client::activate_menu_item`].
+    ActivateMenuItem { address: String, id: i32 },
+    /// Activates the menu item with the given keyboard shortcut in an item's menu, if any.
+    /// See [`Client::activate_shortcut`].
+    ActivateShortcut {
+        address: String,
+        modifiers: Vec<Modifier>,
+        key: String,
+    },
+    /// Sends an event for the menu item with the given ID in an item's menu.
+    /// Clicks are handled as with [`Command::ActivateMenuItem`].
+    /// See [`MenuHandle`](super::MenuHandle).
+    MenuEvent {
+        address: String,
+        id: i32,
+        event: MenuEvent,
+    },
+}
+
+impl Command {
+    /// Gets the address of the item the command is sent to.
+    #[must_use]
+    pub fn address(&self) -> &str {
+        match self {
+            Self::Activate(request) => request.address(),
+            Self::ActivateMenuItem { address, .. }
+            | Self::ActivateShortcut { address, .. }
+            | Self::MenuEvent { address, .. } => address,
+        }
+    }
+}
+
+impl From<ActivateRequest> for Command {
+    fn from(request: ActivateRequest) -> Self {
+        Self::Activate(request)
+    }
+}
+
+/// A handle for sending [`Command`]s to a client,
+/// created using [`Client::command_sender`].
+///
+/// This is cheap to clone and does not keep the client running.
+/// As sending does not wait for commands to complete,
+/// failed activations are only reported through [`Event::ActivationFailed`](super::Event::ActivationFailed).
+#[derive(Debug, Clone)]
+pub struct CommandSender {
+    tx: mpsc::UnboundedSender<Command>,
+}
+
+impl CommandSender {
+    /// Sends a command to the client.
+    ///
+    /// # Errors
+    ///
+    /// If the client has stopped.
+    pub fn send(&self, command: impl Into<Command>) -> Result<()> {
+        self.tx
+            .send(command.into())
+            .map_err(|_| Error::ClientStopped)
+    }
+
+    /// Sends an activation request to the client.
+    /// This is a shorthand for sending a [`Command::Activate`].
+    ///
+    /// # Errors
+    ///
+    /// If the client has stopped.
+    pub fn activate(&self, request: ActivateRequest) -> Result<()> {
+        self.send(request)
+    }
+
+    /// Checks whether the client has stopped processing commands.
+    #[must_use]
+    pub fn is_closed(&self) -> bool {
+        self.tx.is_closed()
+    }
+}
+
+/// A handle to a client which does not keep it running,
+/// so that the task processing commands stops along with the client.
+#[derive(Debug)]
+struct WeakClient {
+    ctx: Context,
+    lifecycle: Weak<Lifecycle>,
+    activations: Arc<ActivationQueue>,
+    desktop_entries: Arc<tokio::sync::Mutex<Option<DesktopEntries>>>,
+    commands: Arc<OnceLock<CommandSender>>,
+}
+
+impl WeakClient {
+    fn new(client: &Client) -> Self {
+        Self {
+            ctx: client.ctx.clone(),
+            lifecycle: Arc::downgrade(&client.lifecycle),
+            activations: client.activations.clone(),
+            desktop_entries: client.desktop_entries.clone(),
+            commands: client.commands.clone(),
+        }
+    }
+
+    fn upgrade(&self) -> Option<Client> {
+        Some(Client {
+            ctx: self.ctx.clone(),
+            lifecycle: self.lifecycle.upgrade()?,
+            activations: self.activations.clone(),
+            desktop_entries: self.desktop_entries.clone(),
+            commands: self.commands.clone(),
+        })
+    }
+}
+
+/// Starts the task processing commands for a client,
+/// returning the sender for it.
+pub(super) fn spawn_processor(client: &Client) -> CommandSender {
+    let (tx, mut rx) = mpsc::unbounded_channel();
+    let weak = WeakClient::new(client);
+
+    client.ctx.spawn(|ctx| async move {
+        while let Some(command) = rx.recv().await {
+            let Some(client) = weak.upgrade() else {
+                break;
+            };
+
+            trace!("processing command: {command:?}");
+            process(&ctx, client, command);
+        }
+
+        Ok(())
+    });
+
+    CommandSender { tx }
+}
+
+/// Processes a single command.
+///
+/// Activation requests and menu events are queued straight away,
+/// so that they are sent in the order the commands were.
+fn process(ctx: &Context, client: Client, command: Command) {
+    let address = command.address().to_string();
+
+    let request = match command {
+        Command::Activate(request) => Ok(request),
+        Command::ActivateMenuItem { id, .. }
+        | Command::MenuEvent {
+            id,
+            event: MenuEvent::Clicked,
+            ..
+        } => client
+            .menu_or_error(&address)
+            .map(|menu| ActivateRequest::MenuItem {
+                address: menu.address().to_string(),
+                menu_path: menu.menu_path().to_string(),
+                submenu_id: id,
+            }),
+        Command::ActivateShortcut { modifiers, key, .. } => {
+            let Some(request) = client.shortcut_request(&address, &modifiers, &key) else {
+                trace!("no menu item with shortcut '{key}' for '{address}'");
+                return;
+            };
+
+            Ok(request)
+        }
+        Command::MenuEvent { id, event, .. } => {
+            let reply = client
+                .menu_or_error(&address)
+                .and_then(|menu| menu.push_event(id, event));
+
+            ctx.spawn(|_| async move {
+                let res = match reply {
+                    Ok(reply) => recv_reply(reply).await,
+                    Err(err) => Err(err),
+                };
+
+                if let Err(err) = res {
+                    warn!("failed to send menu event to '{address}': {err}");
+                }
+
+                Ok(())
+            });
+
+            return;
+        }
+    };
+
+    let reply = request.and_then(|request| client.activations.push(request));
+    ctx.spawn(|_| async move {
+        // failures are logged and sent as events
+        let _ = client.finish_activation(address, reply).await;
+        Ok(())
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_bus::{wait_for_call, MockItem, TestBus};
+    use std::time::Duration;
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn sends_commands_in_order() {
+        let bus = TestBus::start();
+        let client = bus.client().await;
+
+        let item = MockItem::new("commands").delay(Duration::from_millis(50));
+        let calls = item.calls();
+        let _item = bus.serve_item(item).await;
+        let (address, _) = client
+            .wait_for_item("commands", Duration::from_secs(5))
+            .await
+            .expect("item should appear");
+
+        let sender = client.command_sender();
+        let commands = [
+            Command::ActivateMenuItem {
+                address: address.clone(),
+                id: 1,
+            },
+            Command::MenuEvent {
+                address: address.clone(),
+                id: 2,
+                event: MenuEvent::Hovered,
+            },
+            Command::Activate(ActivateRequest::Default {
+                address: address.clone(),
+                x: 3,
+                y: 0,
+                token: None,
+            }),
+            Command::MenuEvent {
+                address: address.clone(),
+                id: 4,
+                event: MenuEvent::Opened,
+            },
+        ];
+
+        // sent from a thread without a runtime, as from a GUI callback
+        std::thread::spawn(move || {
+            for command in commands {
+                sender.send(command).expect("client should be running");
+            }
+        })
+        .join()
+        .expect("thread should not panic");
+
+        wait_for_call(&calls, "end Event 4 opened").await;
+        assert_eq!(
+            *calls.lock().expect("mutex lock should succeed"),
+            [
+                "start Event 1 clicked",
+                "end Event 1 clicked",
+                "start Event 2 hovered",
+                "end Event 2 hovered",
+                "start Activate 3",
+                "end Activate 3",
+                "start Event 4 opened",
+                "end Event 4 opened",
+            ]
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn fails_to_send_once_client_is_dropped() {
+        let bus = TestBus::start();
+        let client = bus.client().await;
+
+        let sender = client.command_sender();
+        assert!(!sender.is_closed());
+
+        // the sender does not keep the client running
+        drop(client);
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while !sender.is_closed() {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("sender should close");
+
+        let request = ActivateRequest::ContextMenu {
+            address: String::from(":1.1"),
+            x: 0,
+            y: 0,
+        };
+        assert!(matches!(
+            sender.activate(request),
+            Err(Error::ClientStopped)
+        ));
+    }
+}
@@ -0,0 +1,134 @@
+use super::{UpdateEvent, UpdateKind};
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+/// Holds back the latest update of each kind for each item
+/// while a burst of updates is arriving,
+/// so that only the last of the burst is sent.
+///
+/// Each update is stored alongside the state generation it was received at.
+#[derive(Debug, Default)]
+pub(crate) struct UpdateDebouncer {
+    pending: Mutex<HashMap<(String, UpdateKind), (u64, UpdateEvent)>>,
+}
+
+impl UpdateDebouncer {
+    /// Replaces the item's pending update of the same kind.
+    ///
+    /// Returns whether there was no pending update,
+    /// in which case the caller is responsible for scheduling it to be sent.
+    pub fn push(
+        &self,
+        address: &str,
+        kind: UpdateKind,
+        generation: u64,
+        event: UpdateEvent,
+    ) -> bool {
+        self.pending
+            .lock()
+            .expect("mutex lock should succeed")
+            .insert((address.to_string(), kind), (generation, event))
+            .is_none()
+    }
+
+    /// Takes the item's pending update of the given kind.
+    pub fn take(&self, address: &str, kind: UpdateKind) -> Option<(u64, UpdateEvent)> {
+        self.pending
+            .lock()
+            .expect("mutex lock should succeed")
+            .remove(&(address.to_string(), kind))
+    }
+
+    /// Takes all of the item's pending updates, in the order they were received.
+    pub fn take_all(&self, address: &str) -> Vec<(u64, UpdateEvent)> {
+        let mut pending = self.pending.lock().expect("mutex lock should succeed");
+
+        let kinds: Vec<_> = pending
+            .keys()
+            .filter(|(pending_address, _)| pending_address == address)
+            .cloned()
+            .collect();
+
+        let mut updates: Vec<_> = kinds
+            .into_iter()
+            .filter_map(|key| pending.remove(&key))
+            .collect();
+
+        updates.sort_unstable_by_key(|(generation, _)| *generation);
+        updates
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::Event;
+    use crate::item::Status;
+    use crate::test_bus::{
+        recv_until, set_status, set_title, wait_until_watched, MockItem, TestBus,
+    };
+    use std::time::Duration;
+    use tokio::time::{sleep, timeout};
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn sends_last_update_of_burst() {
+        let bus = TestBus::start();
+        let client = bus
+            .builder()
+            .debounce(UpdateKind::Title, Duration::from_millis(300))
+            .build()
+            .await
+            .expect("client should start");
+
+        let item = bus.serve_item(MockItem::new("debounce")).await;
+        let (address, _) = client
+            .wait_for_item("debounce", Duration::from_secs(5))
+            .await
+            .expect("item should appear");
+
+        let mut rx = client.subscribe();
+        wait_until_watched(&item, &mut rx).await;
+
+        for title in ["One", "Two", "Three"] {
+            set_title(&item, title).await;
+            sleep(Duration::from_millis(20)).await;
+        }
+        set_status(&item, "Passive").await;
+
+        // other kinds are not held back
+        recv_until(&mut rx, |event| match event {
+            Event::Update(_, UpdateEvent::Status(Status::Passive)) => Some(()),
+            Event::Update(_, UpdateEvent::Title(title)) => {
+                panic!("title should be held back: {title:?}")
+            }
+            _ => None,
+        })
+        .await;
+
+        // the state is updated straight away
+        let title = client
+            .items()
+            .lock()
+            .expect("mutex lock should succeed")
+            .get(&address)
+            .and_then(|(item, _)| item.title.clone());
+        assert_eq!(title.as_deref(), Some("Three"));
+
+        let title = recv_until(&mut rx, |event| match event {
+            Event::Update(_, UpdateEvent::Title(title)) => Some(title),
+            _ => None,
+        })
+        .await;
+        assert_eq!(title.as_deref(), Some("Three"));
+
+        let another = timeout(Duration::from_millis(500), async {
+            loop {
+                if let Ok(Event::Update(_, UpdateEvent::Title(title))) = rx.recv().await {
+                    return title;
+                }
+            }
+        })
+        .await;
+        assert!(another.is_err(), "only one title should be sent");
+    }
+}
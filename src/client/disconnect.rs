@@ -0,0 +1,95 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use tokio::sync::watch;
+
+/// Routes bus name disconnects to the tasks watching items and menus,
+/// so that a single `NameOwnerChanged` subscription serves every item
+/// rather than each adding its own match rule.
+#[derive(Debug, Default)]
+pub(crate) struct DisconnectDispatcher {
+    names: Mutex<HashMap<String, watch::Sender<bool>>>,
+}
+
+impl DisconnectDispatcher {
+    /// Starts watching for `name` leaving the bus.
+    ///
+    /// This must be called before checking that the name is still on the bus,
+    /// so that it leaving in between is not missed.
+    pub fn watch(&self, name: &str) -> Disconnected {
+        let mut names = self.names.lock().expect("mutex lock should succeed");
+
+        // clean up after names whose watchers stopped before they disconnected
+        names.retain(|_, tx| tx.receiver_count() > 0);
+
+        let rx = names
+            .entry(name.to_string())
+            .or_insert_with(|| watch::channel(false).0)
+            .subscribe();
+
+        Disconnected(rx)
+    }
+
+    /// Notifies everything watching `name` that it has left the bus.
+    pub fn disconnected(&self, name: &str) {
+        let tx = self
+            .names
+            .lock()
+            .expect("mutex lock should succeed")
+            .remove(name);
+
+        if let Some(tx) = tx {
+            tx.send_replace(true);
+        }
+    }
+
+    /// Stops notifying watchers,
+    /// which happens once the connection closes.
+    pub fn close(&self) {
+        self.names
+            .lock()
+            .expect("mutex lock should succeed")
+            .clear();
+    }
+}
+
+/// Completes once a bus name has left the bus.
+///
+/// See [`DisconnectDispatcher::watch`].
+#[derive(Debug)]
+pub(crate) struct Disconnected(watch::Receiver<bool>);
+
+impl Disconnected {
+    /// Waits for the name to leave the bus,
+    /// returning `false` instead if the dispatcher was closed first.
+    ///
+    /// This is cancel safe, so can be used in a `select!` loop.
+    pub async fn wait(&mut self) -> bool {
+        self.0.wait_for(|gone| *gone).await.is_ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use tokio::time::timeout;
+
+    #[tokio::test]
+    async fn notifies_every_watcher() {
+        let dispatcher = DisconnectDispatcher::default();
+
+        let mut item = dispatcher.watch(":1.5");
+        let mut menu = dispatcher.watch(":1.5");
+        let mut other = dispatcher.watch(":1.6");
+
+        dispatcher.disconnected(":1.5");
+
+        let wait = Duration::from_millis(100);
+        assert_eq!(timeout(wait, item.wait()).await, Ok(true));
+        assert_eq!(timeout(wait, menu.wait()).await, Ok(true));
+        assert!(timeout(wait, other.wait()).await.is_err());
+
+        dispatcher.close();
+        assert_eq!(timeout(wait, other.wait()).await, Ok(false));
+    }
+}
@@ -0,0 +1,285 @@
+use super::{Event, State, UpdateEvent};
+use crate::item::{Category, Status, StatusNotifierItem};
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use tokio::sync::broadcast::{self, error::RecvError};
+
+type Predicate = Arc<dyn Fn(&StatusNotifierItem) -> bool + Send + Sync>;
+
+/// A set of conditions an item must meet
+/// for its events to be sent to a [`FilteredReceiver`].
+///
+/// Each kind of condition is optional.
+/// Setting the same kind more than once matches items meeting any of them,
+/// and items must meet every kind of condition set.
+///
+/// # Example
+///
+/// ```
+/// use system_tray::client::Filter;
+/// use system_tray::item::{Category, Status};
+///
+/// let filter = Filter::new()
+///     .category(Category::Communications)
+///     .status(Status::Active)
+///     .status(Status::NeedsAttention);
+/// ```
+#[derive(Clone, Default)]
+pub struct Filter {
+    categories: Vec<Category>,
+    statuses: Vec<Status>,
+    id_prefixes: Vec<String>,
+    predicates: Vec<Predicate>,
+}
+
+impl std::fmt::Debug for Filter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Filter")
+            .field("categories", &self.categories)
+            .field("statuses", &self.statuses)
+            .field("id_prefixes", &self.id_prefixes)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Filter {
+    /// Creates a filter which matches every item.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Matches items in `category`.
+    #[must_use]
+    pub fn category(mut self, category: Category) -> Self {
+        self.categories.push(category);
+        self
+    }
+
+    /// Matches items with `status`.
+    #[must_use]
+    pub fn status(mut self, status: Status) -> Self {
+        self.statuses.push(status);
+        self
+    }
+
+    /// Matches items whose id starts with `prefix`.
+    #[must_use]
+    pub fn id_prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.id_prefixes.push(prefix.into());
+        self
+    }
+
+    /// Matches items for which `predicate` returns `true`.
+    ///
+    /// Unlike other conditions, every predicate must return `true`.
+    #[must_use]
+    pub fn matching(
+        mut self,
+        predicate: impl Fn(&StatusNotifierItem) -> bool + Send + Sync + 'static,
+    ) -> Self {
+        self.predicates.push(Arc::new(predicate));
+        self
+    }
+
+    /// Checks whether an item meets the filter's conditions.
+    #[must_use]
+    pub fn matches(&self, item: &StatusNotifierItem) -> bool {
+        (self.categories.is_empty() || self.categories.contains(&item.category))
+            && (self.statuses.is_empty() || self.statuses.contains(&item.status))
+            && (self.id_prefixes.is_empty()
+                || self
+                    .id_prefixes
+                    .iter()
+                    .any(|prefix| item.id.starts_with(prefix.as_str())))
+            && self.predicates.iter().all(|predicate| predicate(item))
+    }
+}
+
+/// A receiver for events of items matching a [`Filter`].
+///
+/// See [`Client::subscribe_filtered`](super::Client::subscribe_filtered).
+#[derive(Debug)]
+pub struct FilteredReceiver {
+    rx: broadcast::Receiver<Event>,
+    filter: Filter,
+    /// The latest known state of every item, matching or not,
+    /// so that updates can be checked against the filter.
+    items: HashMap<String, StatusNotifierItem>,
+    /// The items which currently match.
+    matched: HashSet<String>,
+}
+
+impl FilteredReceiver {
+    pub(super) fn new(rx: broadcast::Receiver<Event>, filter: Filter, state: State) -> Self {
+        let mut receiver = Self {
+            rx,
+            filter,
+            items: HashMap::new(),
+            matched: HashSet::new(),
+        };
+
+        receiver.reset(&state);
+        receiver
+    }
+
+    /// Replaces the known state of every item.
+    fn reset(&mut self, state: &State) {
+        self.items = state
+            .iter()
+            .map(|(destination, (item, _))| (destination.clone(), item.clone()))
+            .collect();
+
+        self.matched = self
+            .items
+            .iter()
+            .filter(|(_, item)| self.filter.matches(item))
+            .map(|(destination, _)| destination.clone())
+            .collect();
+    }
+
+    /// Receives the next event for a matching item.
+    ///
+    /// # Errors
+    ///
+    /// As [`broadcast::Receiver::recv`].
+    /// After [`RecvError::Lagged`], the receiver may briefly have an outdated view
+    /// of which items match until they next change.
+    pub async fn recv(&mut self) -> Result<Event, RecvError> {
+        loop {
+            let event = self.rx.recv().await?;
+            if let Some(event) = self.handle(event) {
+                return Ok(event);
+            }
+        }
+    }
+
+    /// Checks an event against the filter,
+    /// returning the event to send, if any.
+    fn handle(&mut self, event: Event) -> Option<Event> {
+        match event {
+            Event::Add(destination, item) => {
+                self.items.insert(destination.clone(), (*item).clone());
+                let event = Event::Add(destination.clone(), item);
+                self.reconcile(destination, event)
+            }
+            Event::Update(ref destination, ref update) => {
+                let destination = destination.clone();
+                self.apply(&destination, std::slice::from_ref(update));
+                self.reconcile(destination, event)
+            }
+            Event::UpdateBatch(ref destination, ref updates) => {
+                let destination = destination.clone();
+                self.apply(&destination, updates);
+                self.reconcile(destination, event)
+            }
+            Event::Remove(destination) => {
+                self.items.remove(&destination);
+                self.matched
+                    .remove(&destination)
+                    .then_some(Event::Remove(destination))
+            }
+            Event::SlowItem(ref destination, _)
+            | Event::AnimatedIcon(ref destination, _)
+            | Event::ActivationFailed(ref destination, _)
+            | Event::MenuAbandoned(ref destination, _) => {
+                self.matched.contains(destination).then_some(event)
+            }
+            // errors may be for items which failed before being added,
+            // so it is not known whether they match
+            Event::Error { ref address, .. } => (self.matched.contains(address)
+                || !self.items.contains_key(address))
+            .then_some(event),
+            Event::Resync(snapshot) => {
+                self.reset(&snapshot.items);
+
+                let mut snapshot = (*snapshot).clone();
+                snapshot
+                    .items
+                    .retain(|destination, _| self.matched.contains(destination));
+                Some(Event::Resync(Arc::new(snapshot)))
+            }
+            Event::WatcherRestarted | Event::WatcherChanged | Event::Ready => Some(event),
+        }
+    }
+
+    fn apply(&mut self, destination: &str, updates: &[UpdateEvent]) {
+        if let Some(item) = self.items.get_mut(destination) {
+            for update in updates {
+                update.apply_to(item);
+            }
+        }
+    }
+
+    /// Updates whether an item matches after `event` changed it,
+    /// translating the event if the item started or stopped matching.
+    fn reconcile(&mut self, destination: String, event: Event) -> Option<Event> {
+        let is_match = self
+            .items
+            .get(&destination)
+            .is_some_and(|item| self.filter.matches(item));
+
+        match (self.matched.contains(&destination), is_match) {
+            (true, true) => Some(event),
+            (true, false) => {
+                self.matched.remove(&destination);
+                Some(Event::Remove(destination))
+            }
+            (false, true) => {
+                let item = self.items.get(&destination)?.clone();
+                self.matched.insert(destination.clone());
+                Some(Event::Add(destination, Arc::new(item)))
+            }
+            (false, false) => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn follows_status_changes() {
+        let (tx, rx) = broadcast::channel(8);
+        drop(tx);
+
+        let mut receiver =
+            FilteredReceiver::new(rx, Filter::new().status(Status::Active), State::new());
+
+        let passive = StatusNotifierItem {
+            id: String::from("app"),
+            status: Status::Passive,
+            ..Default::default()
+        };
+
+        assert!(receiver
+            .handle(Event::Add(":1.1".into(), Arc::new(passive)))
+            .is_none());
+
+        let shown = receiver.handle(Event::Update(
+            ":1.1".into(),
+            UpdateEvent::Status(Status::Active),
+        ));
+        assert!(
+            matches!(shown, Some(Event::Add(ref dest, ref item)) if dest == ":1.1" && item.status == Status::Active)
+        );
+
+        let updated = receiver.handle(Event::Update(
+            ":1.1".into(),
+            UpdateEvent::Title(Some("App".into())),
+        ));
+        assert!(matches!(
+            updated,
+            Some(Event::Update(_, UpdateEvent::Title(_)))
+        ));
+
+        let hidden = receiver.handle(Event::Update(
+            ":1.1".into(),
+            UpdateEvent::Status(Status::Passive),
+        ));
+        assert!(matches!(hidden, Some(Event::Remove(ref dest)) if dest == ":1.1"));
+
+        assert!(receiver.handle(Event::Remove(":1.1".into())).is_none());
+    }
+}
@@ -0,0 +1,30 @@
+use tokio::task::AbortHandle;
+
+/// A handler registered using [`Client::on_event`](super::Client::on_event).
+///
+/// Dropping this does not unregister the handler,
+/// which keeps running until [`unregister`](Self::unregister) is called
+/// or the client shuts down.
+#[derive(Debug)]
+pub struct HandlerHandle {
+    task: AbortHandle,
+}
+
+impl HandlerHandle {
+    pub(super) fn new(task: AbortHandle) -> Self {
+        Self { task }
+    }
+
+    /// Stops calling the handler.
+    ///
+    /// A call already in progress is allowed to finish.
+    pub fn unregister(self) {
+        self.task.abort();
+    }
+
+    /// Checks whether the handler is still being called for new events.
+    #[must_use]
+    pub fn is_registered(&self) -> bool {
+        !self.task.is_finished()
+    }
+}
@@ -0,0 +1,129 @@
+use std::fmt::Display;
+use std::future::Future;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// A completed D-Bus call made by the client,
+/// passed to a [`CallObserver`].
+#[derive(Debug, Clone, Copy)]
+pub struct CallInfo<'a> {
+    /// The name of the method called, such as `GetLayout`.
+    pub method: &'a str,
+    /// The bus name the call was sent to.
+    pub destination: &'a str,
+    /// How long the call took, including any time spent waiting for a reply.
+    pub duration: Duration,
+    /// The error the call failed with, or `None` if it succeeded.
+    ///
+    /// Calls which time out are reported with a timeout error.
+    pub error: Option<&'a str>,
+}
+
+impl CallInfo<'_> {
+    /// Checks whether the call succeeded.
+    #[must_use]
+    pub fn is_ok(&self) -> bool {
+        self.error.is_none()
+    }
+}
+
+/// A callback invoked after every D-Bus call the client makes,
+/// for profiling or tracing.
+///
+/// The callback runs on the task which made the call,
+/// so should return quickly.
+#[derive(Clone)]
+pub struct CallObserver(Arc<dyn Fn(&CallInfo<'_>) + Send + Sync>);
+
+impl CallObserver {
+    /// Creates an observer from a callback.
+    pub fn new(observer: impl Fn(&CallInfo<'_>) + Send + Sync + 'static) -> Self {
+        Self(Arc::new(observer))
+    }
+
+    /// Reports a completed call to the observer.
+    pub fn notify(&self, info: &CallInfo<'_>) {
+        (self.0)(info);
+    }
+}
+
+impl std::fmt::Debug for CallObserver {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("CallObserver").finish_non_exhaustive()
+    }
+}
+
+/// Awaits a call, reporting it to `observer` once it completes.
+///
+/// Without an observer, the call is awaited as normal.
+pub(super) async fn observe<T, E: Display>(
+    observer: Option<&CallObserver>,
+    destination: &str,
+    method: &str,
+    call: impl Future<Output = Result<T, E>>,
+) -> Result<T, E> {
+    let Some(observer) = observer else {
+        return call.await;
+    };
+
+    let start = Instant::now();
+    let res = call.await;
+    let duration = start.elapsed();
+
+    let error = res.as_ref().err().map(ToString::to_string);
+    observer.notify(&CallInfo {
+        method,
+        destination,
+        duration,
+        error: error.as_deref(),
+    });
+
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    #[tokio::test]
+    async fn reports_calls() {
+        let calls = Arc::new(Mutex::new(Vec::new()));
+
+        let observer = {
+            let calls = calls.clone();
+            CallObserver::new(move |info| {
+                calls.lock().expect("mutex lock should succeed").push((
+                    info.method.to_string(),
+                    info.destination.to_string(),
+                    info.error.map(ToString::to_string),
+                ));
+            })
+        };
+
+        let ok = observe(Some(&observer), ":1.1", "Activate", async {
+            Ok::<_, String>(1)
+        })
+        .await;
+        assert_eq!(ok, Ok(1));
+
+        let err = observe(Some(&observer), ":1.2", "GetLayout", async {
+            Err::<(), _>("no reply")
+        })
+        .await;
+        assert!(err.is_err());
+
+        let calls = calls.lock().expect("mutex lock should succeed");
+        assert_eq!(
+            *calls,
+            [
+                (String::from("Activate"), String::from(":1.1"), None),
+                (
+                    String::from("GetLayout"),
+                    String::from(":1.2"),
+                    Some(String::from("no reply"))
+                ),
+            ]
+        );
+    }
+}
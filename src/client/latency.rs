@@ -0,0 +1,79 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// Weight given to each new sample in the moving average.
+const SMOOTHING: f64 = 0.3;
+
+/// Minimum number of samples before an item can be considered slow,
+/// so that a single slow call does not flag it.
+const MIN_SAMPLES: u32 = 3;
+
+#[derive(Debug, Default)]
+struct Stats {
+    samples: u32,
+    average: f64,
+    is_slow: bool,
+}
+
+/// Tracks a moving average of call latencies per destination.
+#[derive(Debug, Default)]
+pub(crate) struct LatencyTracker {
+    stats: Mutex<HashMap<String, Stats>>,
+}
+
+impl LatencyTracker {
+    /// Records the duration of a call to a destination.
+    ///
+    /// Returns the average latency if this caused the destination
+    /// to become consistently slower than `threshold`.
+    pub fn record(
+        &self,
+        destination: &str,
+        duration: Duration,
+        threshold: Duration,
+    ) -> Option<Duration> {
+        let mut stats = self.stats.lock().expect("mutex lock should succeed");
+        let stats = stats.entry(destination.to_string()).or_default();
+
+        let sample = duration.as_secs_f64();
+        stats.average = if stats.samples == 0 {
+            sample
+        } else {
+            SMOOTHING * sample + (1.0 - SMOOTHING) * stats.average
+        };
+        stats.samples = stats.samples.saturating_add(1);
+
+        let was_slow = stats.is_slow;
+        stats.is_slow = stats.samples >= MIN_SAMPLES && stats.average > threshold.as_secs_f64();
+
+        (stats.is_slow && !was_slow).then(|| Duration::from_secs_f64(stats.average))
+    }
+
+    /// Stops tracking a destination.
+    pub fn remove(&self, destination: &str) {
+        self.stats
+            .lock()
+            .expect("mutex lock should succeed")
+            .remove(destination);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn flags_consistently_slow() {
+        let tracker = LatencyTracker::default();
+        let threshold = Duration::from_millis(100);
+        let slow = Duration::from_millis(500);
+
+        assert!(tracker.record("a", slow, threshold).is_none());
+        assert!(tracker.record("a", slow, threshold).is_none());
+        assert!(tracker.record("a", slow, threshold).is_some());
+
+        // only reported once
+        assert!(tracker.record("a", slow, threshold).is_none());
+    }
+}
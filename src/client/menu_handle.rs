@@ -0,0 +1,286 @@
+use super::queue::recv_reply;
+use super::{
+    call_with_timeout, classify_activation_error, event_timestamp, parse_address, ActivateRequest,
+    Client, MenuEvent,
+};
+use crate::dbus::dbus_menu_proxy::DBusMenuProxy;
+use crate::menu::TrayMenu;
+use std::future::Future;
+use tokio::sync::oneshot;
+use zbus::zvariant::Value;
+
+/// A handle to the menu of a single item,
+/// for sending menu operations without passing its address and path around.
+///
+/// Every operation is sent through the activation queue, as with [`Client::activate`],
+/// so that calls to the same item never interleave.
+///
+/// Get one using [`Client::menu`].
+#[derive(Debug, Clone)]
+pub struct MenuHandle<'a> {
+    client: &'a Client,
+    address: String,
+    menu_path: String,
+}
+
+impl<'a> MenuHandle<'a> {
+    pub(super) fn new(client: &'a Client, address: String, menu_path: String) -> Self {
+        Self {
+            client,
+            address,
+            menu_path,
+        }
+    }
+
+    /// Gets the address of the item the menu belongs to.
+    #[must_use]
+    pub fn address(&self) -> &str {
+        &self.address
+    }
+
+    /// Gets the object path of the menu.
+    #[must_use]
+    pub fn menu_path(&self) -> &str {
+        &self.menu_path
+    }
+
+    /// Gets the latest layout of the menu,
+    /// or `None` if it has not been received yet.
+    ///
+    /// # Panics
+    ///
+    /// If the state mutex is poisoned.
+    #[must_use]
+    pub fn layout(&self) -> Option<TrayMenu> {
+        let items = self
+            .client
+            .ctx
+            .items
+            .lock()
+            .expect("mutex lock should succeed");
+        items.get(&self.address).and_then(|(_, menu)| menu.clone())
+    }
+
+    /// Tells the item that the submenu with the given ID is about to be shown,
+    /// so that it can update it first.
+    /// Use `0` for the root menu.
+    ///
+    /// Returns whether the item requested the menu to be updated.
+    /// The new layout arrives as a normal menu update.
+    ///
+    /// # Errors
+    ///
+    /// If too many requests are already queued for the item,
+    /// the call fails or the item does not reply in time.
+    /// Failures of the item itself are described by an [`Error::Activation`](crate::error::Error::Activation).
+    pub async fn about_to_show(&self, id: i32) -> crate::error::Result<bool> {
+        self.queue_call("AboutToShow", move |proxy| async move {
+            proxy.about_to_show(id).await
+        })
+        .await
+    }
+
+    /// Clicks the menu item with the given ID.
+    ///
+    /// Identical clicks waiting in the queue are merged, as with [`Client::activate`].
+    ///
+    /// # Errors
+    ///
+    /// See [`Client::activate`].
+    pub async fn click(&self, id: i32) -> crate::error::Result<()> {
+        self.client
+            .activate(ActivateRequest::MenuItem {
+                address: self.address.clone(),
+                menu_path: self.menu_path.clone(),
+                submenu_id: id,
+            })
+            .await
+    }
+
+    /// Tells the item that the pointer is hovering over the menu item with the given ID.
+    ///
+    /// # Errors
+    ///
+    /// If too many requests are already queued for the item,
+    /// the call fails or the item does not reply in time.
+    /// Failures of the item itself are described by an [`Error::Activation`](crate::error::Error::Activation).
+    pub async fn hover(&self, id: i32) -> crate::error::Result<()> {
+        self.send_event(id, MenuEvent::Hovered).await
+    }
+
+    /// Tells the item that the submenu with the given ID has been opened.
+    /// Use `0` for the root menu.
+    ///
+    /// Some items only fill in dynamic submenus once they are opened,
+    /// sending the new entries as a normal menu update.
+    ///
+    /// # Errors
+    ///
+    /// If too many requests are already queued for the item,
+    /// the call fails or the item does not reply in time.
+    /// Failures of the item itself are described by an [`Error::Activation`](crate::error::Error::Activation).
+    pub async fn open(&self, id: i32) -> crate::error::Result<()> {
+        self.send_event(id, MenuEvent::Opened).await
+    }
+
+    /// Tells the item that the submenu with the given ID has been closed.
+    /// Use `0` for the root menu.
+    ///
+    /// # Errors
+    ///
+    /// If too many requests are already queued for the item,
+    /// the call fails or the item does not reply in time.
+    /// Failures of the item itself are described by an [`Error::Activation`](crate::error::Error::Activation).
+    pub async fn close(&self, id: i32) -> crate::error::Result<()> {
+        self.send_event(id, MenuEvent::Closed).await
+    }
+
+    /// Sends several events in a single call,
+    /// returning the IDs of the menu items which could not be found.
+    ///
+    /// Events for the other items are still sent.
+    ///
+    /// # Errors
+    ///
+    /// If too many requests are already queued for the item,
+    /// the call fails or the item does not reply in time.
+    /// Failures of the item itself are described by an [`Error::Activation`](crate::error::Error::Activation).
+    pub async fn event_group(&self, events: &[(i32, MenuEvent)]) -> crate::error::Result<Vec<i32>> {
+        let timestamp = event_timestamp();
+        let events = events
+            .iter()
+            .map(|(id, event)| (*id, event.as_str(), Value::I32(0), timestamp))
+            .collect::<Vec<_>>();
+
+        self.queue_call("EventGroup", move |proxy| async move {
+            proxy.event_group(&events).await
+        })
+        .await
+    }
+
+    /// Sends an event for the menu item with the given ID.
+    async fn send_event(&self, id: i32, event: MenuEvent) -> crate::error::Result<()> {
+        recv_reply(self.push_event(id, event)?).await
+    }
+
+    /// Adds an event for the menu item with the given ID to the activation queue,
+    /// without waiting for it to be sent.
+    pub(super) fn push_event(
+        &self,
+        id: i32,
+        event: MenuEvent,
+    ) -> crate::error::Result<oneshot::Receiver<crate::error::Result<()>>> {
+        self.push_call("Event", move |proxy| async move {
+            proxy
+                .event(id, event.as_str(), &Value::I32(0), event_timestamp())
+                .await
+        })
+    }
+
+    /// Sends a call to the menu through the activation queue,
+    /// waiting for its result.
+    async fn queue_call<T, F>(
+        &self,
+        method: &'static str,
+        call: impl FnOnce(DBusMenuProxy<'static>) -> F + Send + 'static,
+    ) -> crate::error::Result<T>
+    where
+        T: Send + 'static,
+        F: Future<Output = zbus::Result<T>> + Send,
+    {
+        recv_reply(self.push_call(method, call)?).await
+    }
+
+    /// Adds a call to the menu to the activation queue,
+    /// returning a receiver for its result.
+    fn push_call<T, F>(
+        &self,
+        method: &'static str,
+        call: impl FnOnce(DBusMenuProxy<'static>) -> F + Send + 'static,
+    ) -> crate::error::Result<oneshot::Receiver<crate::error::Result<T>>>
+    where
+        T: Send + 'static,
+        F: Future<Output = zbus::Result<T>> + Send,
+    {
+        let ctx = self.client.ctx.clone();
+        let destination = self.destination().to_string();
+        let menu_path = self.menu_path.clone();
+        let timeout = self.timeout();
+
+        let job = async move {
+            let proxy = DBusMenuProxy::builder(&ctx.connection)
+                .destination(destination.clone())?
+                .path(menu_path)?
+                .build()
+                .await?;
+
+            let call = call_with_timeout(call(proxy), timeout);
+            ctx.observe(&destination, method, call)
+                .await
+                .map_err(classify_activation_error)
+        };
+
+        self.client.activations.push_call(&self.address, job)
+    }
+
+    /// Gets the bus name of the item, without its object path.
+    fn destination(&self) -> &str {
+        parse_address(&self.address).0
+    }
+
+    fn timeout(&self) -> std::time::Duration {
+        self.client.ctx.options().activation_timeout
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_bus::{wait_for_call, MockItem, TestBus};
+    use std::time::Duration;
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn queues_menu_calls_with_activations() {
+        let bus = TestBus::start();
+        let client = bus.client().await;
+
+        let item = MockItem::new("menu").delay(Duration::from_millis(100));
+        let calls = item.calls();
+        let _item = bus.serve_item(item).await;
+        let (address, _) = client
+            .wait_for_item("menu", Duration::from_secs(5))
+            .await
+            .expect("item should appear");
+
+        let click = tokio::spawn({
+            let client = client.clone();
+            let address = address.clone();
+            async move { client.activate_menu_item(&address, 1).await }
+        });
+        wait_for_call(&calls, "start Event 1 clicked").await;
+
+        let menu = client.menu(&address).expect("item should have a menu");
+        let (shown, grouped) = tokio::join!(
+            menu.about_to_show(0),
+            menu.event_group(&[(2, MenuEvent::Opened)])
+        );
+        shown.expect("call should succeed");
+        grouped.expect("call should succeed");
+        click
+            .await
+            .expect("task should not panic")
+            .expect("click should succeed");
+
+        assert_eq!(
+            *calls.lock().expect("mutex lock should succeed"),
+            [
+                "start Event 1 clicked",
+                "end Event 1 clicked",
+                "start AboutToShow 0",
+                "end AboutToShow 0",
+                "start EventGroup 2 opened",
+                "end EventGroup 2 opened",
+            ]
+        );
+    }
+}
@@ -0,0 +1,666 @@
+use super::{
+    AdmissionPolicy, CallInfo, CallObserver, Client, Registration, RetryPolicy, UpdateKind,
+};
+use crate::error::{Error, Result};
+use crate::item::PixmapQuirk;
+use std::collections::HashMap;
+use std::time::Duration;
+use zbus::{Connection, ConnectionBuilder};
+
+/// Options controlling the behaviour of a [`Client`].
+///
+/// Options can be changed on a running client using [`Client::set_options`].
+/// Changes apply to all existing items from the next time each option is used,
+/// except for those which can only be set when the client is created.
+#[derive(Debug, Clone)]
+pub struct ClientOptions {
+    /// How many events are held for each subscriber
+    /// before the oldest are dropped.
+    ///
+    /// Subscribers which fall further behind than this miss events.
+    /// This must be at least 1, and can only be set when the client is created.
+    pub event_capacity: usize,
+
+    /// Whether a well-known `org.freedesktop.StatusNotifierHost-*` name is requested for the host.
+    ///
+    /// If disabled, the host is registered with the watcher using the connection's unique name,
+    /// which avoids owning extra names on a connection shared with other services.
+    /// This can only be set when the client is created.
+    pub request_host_name: bool,
+
+    /// The well-known name to register the host under,
+    /// such as `org.freedesktop.StatusNotifierHost-myapp`.
+    ///
+    /// The client fails to start if the name is already owned.
+    /// If `None`, which is the default, a unique name is generated
+    /// in the format `org.freedesktop.StatusNotifierHost-{pid}-{n}`.
+    /// This has no effect if [`ClientOptions::request_host_name`] is disabled,
+    /// and can only be set when the client is created.
+    pub host_name: Option<String>,
+
+    /// How long to wait for an item to reply to an activation request
+    /// before giving up.
+    pub activation_timeout: Duration,
+
+    /// How long to wait for an item to reply when fetching its properties
+    /// before giving up.
+    ///
+    /// Items which do not reply in time when first seen are not added.
+    pub property_timeout: Duration,
+
+    /// How long to wait for the initial layout of an item's menu
+    /// before considering the menu orphaned.
+    pub menu_probe_timeout: Duration,
+
+    /// How long to wait for an item to reply when fetching an updated menu layout
+    /// before giving up.
+    pub layout_timeout: Duration,
+
+    /// How long to wait for further layout changes to a menu after being told of one,
+    /// so that a burst of them is fetched and sent as a single update.
+    ///
+    /// If `None`, the layout is fetched as soon as each change is announced.
+    pub layout_coalesce_window: Option<Duration>,
+
+    /// How many levels of submenus to fetch when getting a menu layout.
+    ///
+    /// Use `-1` to fetch all levels, however deep the menu is.
+    /// Menus deeper than this are truncated.
+    pub menu_depth: i32,
+
+    /// The menu item properties to fetch when getting a menu layout,
+    /// such as `label` or `icon-data`.
+    ///
+    /// If empty, which is the default, all properties are fetched.
+    /// Properties which are not fetched take their default values.
+    pub menu_properties: Vec<String>,
+
+    /// Whether items with invalid properties are still added,
+    /// with the invalid properties left at their defaults.
+    ///
+    /// Some items send properties such as `ToolTip` or `WindowId` with the wrong structure or type.
+    /// When this is enabled, an [`Event::Error`](super::Event::Error) naming the invalid properties
+    /// is sent after the item is added.
+    /// Otherwise, an invalid `ToolTip` prevents the item from being added at all.
+    pub lossy_parsing: bool,
+
+    /// How fetching an item's changed properties or menu layout is retried if it fails.
+    ///
+    /// If fetching a menu layout still fails,
+    /// the menu stops being watched and an [`Event::MenuAbandoned`](super::Event::MenuAbandoned) is sent.
+    pub fetch_retry: RetryPolicy,
+
+    /// The maximum number of activation requests
+    /// which can be waiting to be sent to a single item.
+    ///
+    /// Requests beyond this limit are rejected with [`crate::error::Error::QueueFull`].
+    pub activation_queue_depth: usize,
+
+    /// How long to wait for further updates to an item after receiving one,
+    /// so that they can be sent together as a single [`Event::UpdateBatch`](super::Event::UpdateBatch).
+    ///
+    /// If `None`, which is the default, updates are sent as individual [`Event::Update`](super::Event::Update) events.
+    pub batch_window: Option<Duration>,
+
+    /// The average call latency above which an item is considered slow,
+    /// causing an [`Event::SlowItem`](super::Event::SlowItem) to be sent.
+    ///
+    /// If `None`, latencies are not checked.
+    pub slow_item_threshold: Option<Duration>,
+
+    /// The time between icon changes below which an item is considered to be animating,
+    /// causing an [`Event::AnimatedIcon`](super::Event::AnimatedIcon) to be sent
+    /// instead of an update for every frame.
+    ///
+    /// If `None`, which is the default, icon changes are always sent as individual updates.
+    pub animation_threshold: Option<Duration>,
+
+    /// Whether to serve a `StatusNotifierWatcher` from the client.
+    ///
+    /// If disabled, the client only acts as a host,
+    /// relying on another process such as the desktop environment to act as the watcher,
+    /// and fails to start if there is none.
+    /// This can only be set when the client is created.
+    pub embedded_watcher: bool,
+
+    /// Whether the embedded watcher is also served as `org.freedesktop.StatusNotifierWatcher`,
+    /// for items and hosts which only look for that name.
+    ///
+    /// This can only be set when the client is created.
+    pub watcher_freedesktop_alias: bool,
+
+    /// How often to check that the embedded watcher is still attached to the bus,
+    /// re-attaching it if not.
+    ///
+    /// If `None`, the watcher is not checked.
+    pub watcher_check_interval: Option<Duration>,
+
+    /// The IDs of items to ignore, compared case-insensitively.
+    ///
+    /// No events are sent for ignored items, and they are not included in the client's state.
+    /// Changing this on a running client removes items which are now ignored,
+    /// and adds those which no longer are.
+    pub ignore_ids: Vec<String>,
+
+    /// Corrections to apply to the icon pixmaps of specific items,
+    /// keyed by item ID and compared case-insensitively.
+    ///
+    /// Common problems such as the wrong byte order are corrected automatically,
+    /// but some can only be fixed for items known to have them.
+    pub pixmap_quirks: HashMap<String, Vec<PixmapQuirk>>,
+
+    /// The maximum total size in bytes of icon pixmaps kept in the cached state.
+    ///
+    /// When exceeded, the pixmaps of the least recently used items are dropped from the state,
+    /// keeping the rest of their properties.
+    /// Events are always sent with the full pixmaps,
+    /// but [snapshots](Client::snapshot) and [`Client::items`] are taken from the state,
+    /// so may be missing them.
+    /// Snapshots list these items in [`Snapshot::evicted`](super::Snapshot::evicted).
+    /// Use [`Client::item_with_pixmaps`] to fetch dropped pixmaps again.
+    ///
+    /// If `None`, which is the default, pixmaps are always kept.
+    pub pixmap_budget: Option<usize>,
+
+    /// A callback deciding which items may register with the embedded watcher.
+    ///
+    /// This can only be set when the client is created,
+    /// and has no effect if another process is acting as the watcher.
+    ///
+    /// If `None`, which is the default, all items are accepted.
+    pub admission_policy: Option<AdmissionPolicy>,
+
+    /// A callback invoked after every D-Bus call the client makes,
+    /// with the method, destination, duration and result of the call.
+    ///
+    /// If `None`, which is the default, calls are not reported.
+    pub call_observer: Option<CallObserver>,
+
+    /// Whether an activation request identical to the last request
+    /// still waiting to be sent to the same item is merged into it,
+    /// rather than being sent again.
+    pub coalesce_activations: bool,
+
+    /// The kinds of update which are not sent
+    /// if they would leave the cached item unchanged.
+    ///
+    /// Some items, notably Electron apps, repeatedly announce changes
+    /// to their icon or title without changing anything.
+    /// Use [`UpdateKind::ALL`] to suppress every kind.
+    ///
+    /// If empty, which is the default, all updates are sent.
+    pub suppress_unchanged: Vec<UpdateKind>,
+
+    /// How long to hold back updates of each kind after receiving one,
+    /// so that a burst of updates of the same kind to an item is sent as only the last.
+    ///
+    /// The update is sent once the window has passed since the first update of the burst,
+    /// so items which change constantly still send an update every window.
+    /// The cached state is always updated immediately.
+    ///
+    /// Kinds without a window, which is all of them by default, are sent immediately.
+    pub debounce: HashMap<UpdateKind, Duration>,
+}
+
+impl ClientOptions {
+    /// Checks whether an item with the given ID should be ignored.
+    pub(super) fn is_ignored(&self, id: &str) -> bool {
+        self.ignore_ids
+            .iter()
+            .any(|ignored| ignored.eq_ignore_ascii_case(id))
+    }
+
+    /// Checks that `options` only differs from these in options
+    /// which can be changed on a running client.
+    pub(super) fn check_runtime_change(&self, options: &Self) -> Result<()> {
+        let admission_policy = match (&self.admission_policy, &options.admission_policy) {
+            (None, None) => true,
+            (Some(current), Some(new)) => current.ptr_eq(new),
+            _ => false,
+        };
+
+        let unchanged = [
+            (
+                "event_capacity",
+                self.event_capacity == options.event_capacity,
+            ),
+            (
+                "request_host_name",
+                self.request_host_name == options.request_host_name,
+            ),
+            ("host_name", self.host_name == options.host_name),
+            (
+                "embedded_watcher",
+                self.embedded_watcher == options.embedded_watcher,
+            ),
+            (
+                "watcher_freedesktop_alias",
+                self.watcher_freedesktop_alias == options.watcher_freedesktop_alias,
+            ),
+            ("admission_policy", admission_policy),
+        ];
+
+        match unchanged.into_iter().find(|(_, unchanged)| !unchanged) {
+            Some((name, _)) => Err(Error::CreationOnlyOption(name)),
+            None => Ok(()),
+        }
+    }
+
+    /// Gets the menu item properties to request when getting a menu layout.
+    pub(super) fn menu_property_names(&self) -> Vec<&str> {
+        self.menu_properties.iter().map(String::as_str).collect()
+    }
+
+    /// Gets the pixmap corrections for an item with the given ID.
+    pub(super) fn pixmap_quirks_for(&self, id: &str) -> &[PixmapQuirk] {
+        self.pixmap_quirks
+            .iter()
+            .find(|(quirk_id, _)| quirk_id.eq_ignore_ascii_case(id))
+            .map_or(&[], |(_, quirks)| quirks)
+    }
+}
+
+impl Default for ClientOptions {
+    fn default() -> Self {
+        Self {
+            event_capacity: 32,
+            request_host_name: true,
+            host_name: None,
+            activation_timeout: Duration::from_secs(1),
+            property_timeout: Duration::from_secs(5),
+            menu_probe_timeout: Duration::from_secs(5),
+            layout_timeout: Duration::from_secs(3),
+            layout_coalesce_window: Some(Duration::from_millis(50)),
+            fetch_retry: RetryPolicy::default(),
+            lossy_parsing: false,
+            menu_depth: 10,
+            menu_properties: Vec::new(),
+            activation_queue_depth: 8,
+            coalesce_activations: true,
+            batch_window: None,
+            slow_item_threshold: Some(Duration::from_millis(500)),
+            embedded_watcher: true,
+            watcher_freedesktop_alias: true,
+            watcher_check_interval: Some(Duration::from_secs(5)),
+            animation_threshold: None,
+            admission_policy: None,
+            call_observer: None,
+            ignore_ids: Vec::new(),
+            pixmap_quirks: HashMap::new(),
+            pixmap_budget: None,
+            suppress_unchanged: Vec::new(),
+            debounce: HashMap::new(),
+        }
+    }
+}
+
+/// The bus a [`Client`] connects to.
+#[derive(Debug, Clone, Default)]
+enum Bus {
+    /// The session bus.
+    #[default]
+    Session,
+    /// An existing connection.
+    Connection(Connection),
+    /// The bus, or a single peer if `p2p` is set, at a D-Bus address.
+    Address { address: String, p2p: bool },
+}
+
+/// Builder for configuring and creating a [`Client`].
+#[derive(Debug, Clone, Default)]
+pub struct ClientBuilder {
+    pub(super) options: ClientOptions,
+    bus: Bus,
+}
+
+impl ClientBuilder {
+    /// Creates a new builder using the default options.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Replaces all options with `options`.
+    #[must_use]
+    pub fn options(mut self, options: ClientOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// Uses an existing bus connection rather than opening a new one,
+    /// so that a single connection can be shared with other services.
+    ///
+    /// The connection should be to the session bus.
+    /// The client serves the embedded watcher and registers its host on it,
+    /// and releases them again when it shuts down,
+    /// but does not close the connection.
+    #[must_use]
+    pub fn connection(mut self, connection: Connection) -> Self {
+        self.bus = Bus::Connection(connection);
+        self
+    }
+
+    /// Connects to the bus at a D-Bus address, such as `unix:path=/run/user/1000/bus`,
+    /// rather than the session bus.
+    ///
+    /// This is useful inside sandboxes, nested sessions, or for test buses.
+    #[must_use]
+    pub fn address(mut self, address: impl Into<String>) -> Self {
+        self.bus = Bus::Address {
+            address: address.into(),
+            p2p: false,
+        };
+        self
+    }
+
+    /// Connects directly to a single peer at a D-Bus address,
+    /// where there is no bus daemon.
+    ///
+    /// The peer must serve the `StatusNotifierWatcher` and its items itself,
+    /// as without a bus there are no names to own.
+    /// The embedded watcher is not started,
+    /// and items are assumed to stay around for as long as the connection.
+    #[must_use]
+    pub fn peer_address(mut self, address: impl Into<String>) -> Self {
+        self.bus = Bus::Address {
+            address: address.into(),
+            p2p: true,
+        };
+        self
+    }
+
+    /// Sets whether a well-known name is requested for the host.
+    ///
+    /// See [`ClientOptions::request_host_name`].
+    #[must_use]
+    pub fn request_host_name(mut self, request: bool) -> Self {
+        self.options.request_host_name = request;
+        self
+    }
+
+    /// Sets the well-known name to register the host under.
+    ///
+    /// See [`ClientOptions::host_name`].
+    #[must_use]
+    pub fn host_name(mut self, name: impl Into<String>) -> Self {
+        self.options.host_name = Some(name.into());
+        self
+    }
+
+    /// Sets how many events are held for each subscriber
+    /// before the oldest are dropped.
+    ///
+    /// See [`ClientOptions::event_capacity`].
+    #[must_use]
+    pub fn event_capacity(mut self, capacity: usize) -> Self {
+        self.options.event_capacity = capacity;
+        self
+    }
+
+    /// Sets how long to wait for an item to reply to an activation request.
+    ///
+    /// See [`ClientOptions::activation_timeout`].
+    #[must_use]
+    pub fn activation_timeout(mut self, timeout: Duration) -> Self {
+        self.options.activation_timeout = timeout;
+        self
+    }
+
+    /// Sets how long to wait for an item to reply when fetching its properties.
+    ///
+    /// See [`ClientOptions::property_timeout`].
+    #[must_use]
+    pub fn property_timeout(mut self, timeout: Duration) -> Self {
+        self.options.property_timeout = timeout;
+        self
+    }
+
+    /// Sets how long to wait for the initial layout of an item's menu.
+    ///
+    /// See [`ClientOptions::menu_probe_timeout`].
+    #[must_use]
+    pub fn menu_probe_timeout(mut self, timeout: Duration) -> Self {
+        self.options.menu_probe_timeout = timeout;
+        self
+    }
+
+    /// Sets how long to wait for an item to reply when fetching an updated menu layout.
+    ///
+    /// See [`ClientOptions::layout_timeout`].
+    #[must_use]
+    pub fn layout_timeout(mut self, timeout: Duration) -> Self {
+        self.options.layout_timeout = timeout;
+        self
+    }
+
+    /// Sets how long to wait for further layout changes to a menu after being told of one.
+    ///
+    /// See [`ClientOptions::layout_coalesce_window`].
+    #[must_use]
+    pub fn layout_coalesce_window(mut self, window: Option<Duration>) -> Self {
+        self.options.layout_coalesce_window = window;
+        self
+    }
+
+    /// Sets how many levels of submenus to fetch when getting a menu layout.
+    /// Pass `-1` to fetch all levels.
+    ///
+    /// See [`ClientOptions::menu_depth`].
+    #[must_use]
+    pub fn menu_depth(mut self, depth: i32) -> Self {
+        self.options.menu_depth = depth;
+        self
+    }
+
+    /// Sets the menu item properties to fetch when getting a menu layout.
+    ///
+    /// See [`ClientOptions::menu_properties`].
+    #[must_use]
+    pub fn menu_properties<I, S>(mut self, properties: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.options.menu_properties = properties.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Sets whether items with invalid properties are still added.
+    ///
+    /// See [`ClientOptions::lossy_parsing`].
+    #[must_use]
+    pub fn lossy_parsing(mut self, lossy: bool) -> Self {
+        self.options.lossy_parsing = lossy;
+        self
+    }
+
+    /// Sets how fetching an item's changed properties or menu layout is retried.
+    ///
+    /// See [`ClientOptions::fetch_retry`].
+    #[must_use]
+    pub fn fetch_retry(mut self, policy: RetryPolicy) -> Self {
+        self.options.fetch_retry = policy;
+        self
+    }
+
+    /// Sets the maximum number of activation requests
+    /// which can be waiting to be sent to a single item.
+    ///
+    /// See [`ClientOptions::activation_queue_depth`].
+    #[must_use]
+    pub fn activation_queue_depth(mut self, depth: usize) -> Self {
+        self.options.activation_queue_depth = depth;
+        self
+    }
+
+    /// Sets whether repeated identical activation requests are merged.
+    ///
+    /// See [`ClientOptions::coalesce_activations`].
+    #[must_use]
+    pub fn coalesce_activations(mut self, coalesce: bool) -> Self {
+        self.options.coalesce_activations = coalesce;
+        self
+    }
+
+    /// Enables sending updates which arrive within `window` of each other
+    /// as a single batch.
+    ///
+    /// See [`ClientOptions::batch_window`].
+    #[must_use]
+    pub fn batch_window(mut self, window: Duration) -> Self {
+        self.options.batch_window = Some(window);
+        self
+    }
+
+    /// Sets the average call latency above which an item is considered slow.
+    /// Pass `None` to disable checking.
+    ///
+    /// See [`ClientOptions::slow_item_threshold`].
+    #[must_use]
+    pub fn slow_item_threshold(mut self, threshold: Option<Duration>) -> Self {
+        self.options.slow_item_threshold = threshold;
+        self
+    }
+
+    /// Sets the time between icon changes below which an item is considered to be animating.
+    /// Pass `None` to disable animation detection.
+    ///
+    /// See [`ClientOptions::animation_threshold`].
+    #[must_use]
+    pub fn animation_threshold(mut self, threshold: Option<Duration>) -> Self {
+        self.options.animation_threshold = threshold;
+        self
+    }
+
+    /// Sets whether to serve a watcher from the client,
+    /// rather than only acting as a host.
+    ///
+    /// See [`ClientOptions::embedded_watcher`].
+    #[must_use]
+    pub fn embedded_watcher(mut self, embedded: bool) -> Self {
+        self.options.embedded_watcher = embedded;
+        self
+    }
+
+    /// Sets whether the embedded watcher is also served under the `org.freedesktop` name.
+    ///
+    /// See [`ClientOptions::watcher_freedesktop_alias`].
+    #[must_use]
+    pub fn watcher_freedesktop_alias(mut self, enabled: bool) -> Self {
+        self.options.watcher_freedesktop_alias = enabled;
+        self
+    }
+
+    /// Sets how often to check that the embedded watcher is still attached.
+    /// Pass `None` to disable checking.
+    ///
+    /// See [`ClientOptions::watcher_check_interval`].
+    #[must_use]
+    pub fn watcher_check_interval(mut self, interval: Option<Duration>) -> Self {
+        self.options.watcher_check_interval = interval;
+        self
+    }
+
+    /// Sets the IDs of items to ignore.
+    ///
+    /// See [`ClientOptions::ignore_ids`].
+    #[must_use]
+    pub fn ignore_ids<I, S>(mut self, ids: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.options.ignore_ids = ids.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Adds a correction to apply to the icon pixmaps of the item with the given ID.
+    ///
+    /// See [`ClientOptions::pixmap_quirks`].
+    #[must_use]
+    pub fn pixmap_quirk(mut self, id: impl Into<String>, quirk: PixmapQuirk) -> Self {
+        self.options
+            .pixmap_quirks
+            .entry(id.into())
+            .or_default()
+            .push(quirk);
+        self
+    }
+
+    /// Sets the kinds of update which are not sent
+    /// if they would leave the cached item unchanged.
+    ///
+    /// See [`ClientOptions::suppress_unchanged`].
+    #[must_use]
+    pub fn suppress_unchanged(mut self, kinds: impl IntoIterator<Item = UpdateKind>) -> Self {
+        self.options.suppress_unchanged = kinds.into_iter().collect();
+        self
+    }
+
+    /// Holds back updates of the given kind for `window` after receiving one,
+    /// sending only the last of a burst.
+    ///
+    /// See [`ClientOptions::debounce`].
+    #[must_use]
+    pub fn debounce(mut self, kind: UpdateKind, window: Duration) -> Self {
+        self.options.debounce.insert(kind, window);
+        self
+    }
+
+    /// Sets the maximum total size in bytes of icon pixmaps kept in the cached state.
+    ///
+    /// See [`ClientOptions::pixmap_budget`].
+    #[must_use]
+    pub fn pixmap_budget(mut self, budget: Option<usize>) -> Self {
+        self.options.pixmap_budget = budget;
+        self
+    }
+
+    /// Sets a callback deciding which items and hosts may register with the embedded watcher.
+    ///
+    /// See [`ClientOptions::admission_policy`].
+    #[must_use]
+    pub fn admission_policy(
+        mut self,
+        policy: impl Fn(&Registration<'_>) -> bool + Send + Sync + 'static,
+    ) -> Self {
+        self.options.admission_policy = Some(AdmissionPolicy::new(policy));
+        self
+    }
+
+    /// Sets a callback invoked after every D-Bus call the client makes.
+    ///
+    /// See [`ClientOptions::call_observer`].
+    #[must_use]
+    pub fn on_call(mut self, observer: impl Fn(&CallInfo<'_>) + Send + Sync + 'static) -> Self {
+        self.options.call_observer = Some(CallObserver::new(observer));
+        self
+    }
+
+    /// Creates and initializes the client.
+    ///
+    /// See [`Client::new`] for details.
+    ///
+    /// # Errors
+    ///
+    /// If the initialization fails for any reason,
+    /// for example if unable to connect to the bus,
+    /// or the address is invalid,
+    /// this method will return an error.
+    pub async fn build(self) -> crate::error::Result<Client> {
+        let connection = match self.bus {
+            Bus::Session => None,
+            Bus::Connection(connection) => Some(connection),
+            Bus::Address { address, p2p } => {
+                let mut builder = ConnectionBuilder::address(address.as_str())?;
+                if p2p {
+                    builder = builder.p2p();
+                }
+
+                Some(builder.build().await?)
+            }
+        };
+
+        Client::with_options(self.options, connection).await
+    }
+}
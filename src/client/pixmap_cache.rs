@@ -0,0 +1,188 @@
+use crate::item::{IconPixmap, StatusNotifierItem};
+use std::collections::{HashMap, HashSet};
+use std::sync::Mutex;
+
+#[derive(Debug, Default)]
+struct Entry {
+    /// The tick at which the item's pixmaps were last used.
+    last_used: u64,
+    /// The size in bytes of the item's cached pixmaps.
+    size: usize,
+    /// Whether the item's pixmaps were dropped to stay within budget.
+    evicted: bool,
+}
+
+#[derive(Debug, Default)]
+struct Entries {
+    tick: u64,
+    entries: HashMap<String, Entry>,
+}
+
+/// Tracks the size of each item's cached icon pixmaps and when they were last used,
+/// so that the least recently used can be evicted to stay within
+/// [`ClientOptions::pixmap_budget`](super::ClientOptions::pixmap_budget).
+#[derive(Debug, Default)]
+pub(crate) struct PixmapCache {
+    entries: Mutex<Entries>,
+}
+
+impl PixmapCache {
+    /// Records that an item's pixmaps were cached or used.
+    ///
+    /// An item whose pixmaps were evicted stays evicted until they are [restored](Self::restore),
+    /// as an update only brings back one of its pixmaps.
+    pub fn touch(&self, destination: &str, item: &StatusNotifierItem) {
+        self.update(destination, item, false);
+    }
+
+    /// Records that all of an item's pixmaps were fetched again after being evicted.
+    pub fn restore(&self, destination: &str, item: &StatusNotifierItem) {
+        self.update(destination, item, true);
+    }
+
+    fn update(&self, destination: &str, item: &StatusNotifierItem, restored: bool) {
+        let mut entries = self.entries.lock().expect("mutex lock should succeed");
+        entries.tick += 1;
+
+        let tick = entries.tick;
+        let entry = entries.entries.entry(destination.to_string()).or_default();
+        entry.last_used = tick;
+        entry.size = pixmap_size(item);
+        entry.evicted &= !restored;
+    }
+
+    /// Picks the least recently used items whose pixmaps must be evicted
+    /// for the total size to fit within `budget`,
+    /// marking them as evicted.
+    pub fn evict(&self, budget: usize) -> Vec<String> {
+        let mut entries = self.entries.lock().expect("mutex lock should succeed");
+
+        let mut total: usize = entries.entries.values().map(|entry| entry.size).sum();
+        if total <= budget {
+            return Vec::new();
+        }
+
+        let mut candidates: Vec<_> = entries
+            .entries
+            .iter_mut()
+            .filter(|(_, entry)| entry.size > 0)
+            .collect();
+        candidates.sort_unstable_by_key(|(_, entry)| entry.last_used);
+
+        let mut evicted = Vec::new();
+        for (destination, entry) in candidates {
+            if total <= budget {
+                break;
+            }
+
+            total -= entry.size;
+            entry.size = 0;
+            entry.evicted = true;
+            evicted.push(destination.clone());
+        }
+
+        evicted
+    }
+
+    /// Checks whether an item's pixmaps were evicted.
+    pub fn is_evicted(&self, destination: &str) -> bool {
+        self.entries
+            .lock()
+            .expect("mutex lock should succeed")
+            .entries
+            .get(destination)
+            .is_some_and(|entry| entry.evicted)
+    }
+
+    /// Gets every item whose pixmaps are currently evicted.
+    pub fn evicted(&self) -> HashSet<String> {
+        self.entries
+            .lock()
+            .expect("mutex lock should succeed")
+            .entries
+            .iter()
+            .filter(|(_, entry)| entry.evicted)
+            .map(|(destination, _)| destination.clone())
+            .collect()
+    }
+
+    /// Stops tracking an item.
+    pub fn remove(&self, destination: &str) {
+        self.entries
+            .lock()
+            .expect("mutex lock should succeed")
+            .entries
+            .remove(destination);
+    }
+
+    /// Stops tracking all items.
+    pub fn clear(&self) {
+        self.entries
+            .lock()
+            .expect("mutex lock should succeed")
+            .entries
+            .clear();
+    }
+}
+
+/// Drops an item's icon pixmaps, keeping everything else.
+pub(crate) fn evict_pixmaps(item: &mut StatusNotifierItem) {
+    item.icon_pixmap = None;
+    item.overlay_icon_pixmap = None;
+    item.attention_icon_pixmap = None;
+}
+
+/// Gets the size in bytes of an item's icon pixmaps.
+fn pixmap_size(item: &StatusNotifierItem) -> usize {
+    [
+        &item.icon_pixmap,
+        &item.overlay_icon_pixmap,
+        &item.attention_icon_pixmap,
+    ]
+    .into_iter()
+    .flatten()
+    .flatten()
+    .map(|pixmap: &IconPixmap| pixmap.pixels.len())
+    .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn item_with_pixels(len: usize) -> StatusNotifierItem {
+        StatusNotifierItem {
+            icon_pixmap: Some(vec![IconPixmap {
+                width: 1,
+                height: 1,
+                pixels: vec![0; len],
+            }]),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn evicts_least_recently_used() {
+        let cache = PixmapCache::default();
+        cache.touch("a", &item_with_pixels(100));
+        cache.touch("b", &item_with_pixels(100));
+        cache.touch("c", &item_with_pixels(100));
+
+        assert!(cache.evict(300).is_empty());
+
+        // using `a` again makes `b` the oldest
+        cache.touch("a", &item_with_pixels(100));
+        assert_eq!(cache.evict(150), ["b", "c"]);
+        assert!(cache.is_evicted("b"));
+        assert!(!cache.is_evicted("a"));
+
+        // updates do not bring back every pixmap
+        cache.touch("b", &item_with_pixels(100));
+        assert!(cache.is_evicted("b"));
+
+        cache.restore("b", &item_with_pixels(100));
+        assert!(!cache.is_evicted("b"));
+        assert_eq!(cache.evict(150), ["a"]);
+        assert_eq!(cache.evicted(), HashSet::from(["a".into(), "c".into()]));
+    }
+}
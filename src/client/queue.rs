@@ -0,0 +1,342 @@
+use super::{parse_address, send_activate, ActivateRequest, Context};
+use crate::error::{Error, Result};
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, VecDeque};
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio::sync::oneshot;
+use tracing::trace;
+
+/// A call waiting to be sent to an item.
+enum Job {
+    /// An activation request,
+    /// which can be merged into an identical pending request.
+    Activate {
+        request: ActivateRequest,
+        reply: oneshot::Sender<Result<()>>,
+    },
+    /// Any other call to the item, such as a menu event,
+    /// which sends its own reply.
+    Call(Pin<Box<dyn Future<Output = ()> + Send>>),
+}
+
+/// Serializes activation requests and menu calls per destination,
+/// so that calls to a single item never interleave.
+///
+/// Each destination with pending requests has a worker task,
+/// which sends them one at a time and exits once its queue is drained.
+///
+/// Callers waiting on a request whose reply is dropped,
+/// because the queue was closed or its worker aborted,
+/// should treat it as [`Error::ClientStopped`].
+#[derive(Debug)]
+pub(crate) struct ActivationQueue {
+    ctx: Context,
+    queues: Mutex<HashMap<String, VecDeque<Job>>>,
+    /// Whether the client has stopped, so requests are no longer accepted.
+    closed: AtomicBool,
+}
+
+impl std::fmt::Debug for Job {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Activate { request, .. } => f
+                .debug_struct("Activate")
+                .field("request", request)
+                .finish_non_exhaustive(),
+            Self::Call(_) => f.debug_tuple("Call").finish_non_exhaustive(),
+        }
+    }
+}
+
+impl ActivationQueue {
+    pub fn new(ctx: Context) -> Self {
+        Self {
+            ctx,
+            queues: Mutex::new(HashMap::new()),
+            closed: AtomicBool::new(false),
+        }
+    }
+
+    /// Adds a request to its destination's queue.
+    ///
+    /// Returns a receiver for the result of the call,
+    /// or `None` if the request was merged into an identical pending request.
+    pub fn push(
+        self: &Arc<Self>,
+        request: ActivateRequest,
+    ) -> Result<Option<oneshot::Receiver<Result<()>>>> {
+        let (tx, rx) = oneshot::channel();
+        let destination = parse_address(request.address()).0.to_string();
+
+        let queued = self.enqueue(destination, Job::Activate { request, reply: tx })?;
+        Ok(queued.then_some(rx))
+    }
+
+    /// Adds any other call to the queue of the item at `address`,
+    /// returning a receiver for its result.
+    pub fn push_call<T: Send + 'static>(
+        self: &Arc<Self>,
+        address: &str,
+        call: impl Future<Output = Result<T>> + Send + 'static,
+    ) -> Result<oneshot::Receiver<Result<T>>> {
+        let (tx, rx) = oneshot::channel();
+        let destination = parse_address(address).0.to_string();
+
+        let job = Job::Call(Box::pin(async move {
+            // the caller may have stopped waiting, which is fine
+            let _ = tx.send(call.await);
+        }));
+
+        self.enqueue(destination, job)?;
+        Ok(rx)
+    }
+
+    /// Adds a job to its destination's queue,
+    /// starting a worker for the destination if there is none.
+    ///
+    /// Returns `false` if the job was merged into an identical pending job.
+    fn enqueue(self: &Arc<Self>, destination: String, job: Job) -> Result<bool> {
+        let (depth, coalesce) = {
+            let options = self.ctx.options.read().expect("lock should succeed");
+            (options.activation_queue_depth, options.coalesce_activations)
+        };
+
+        let mut queues = self.queues.lock().expect("mutex lock should succeed");
+        if self.closed.load(Ordering::SeqCst) {
+            return Err(Error::ClientStopped);
+        }
+
+        match queues.entry(destination) {
+            Entry::Occupied(mut entry) => {
+                let pending = entry.get_mut();
+
+                if let (Job::Activate { request, .. }, Some(Job::Activate { request: last, .. })) =
+                    (&job, pending.back())
+                {
+                    if coalesce && request == last {
+                        trace!("coalescing activate request: {request:?}");
+                        return Ok(false);
+                    }
+                }
+
+                if pending.len() >= depth {
+                    return Err(Error::QueueFull);
+                }
+
+                pending.push_back(job);
+            }
+            Entry::Vacant(entry) => {
+                let destination = entry.key().clone();
+                entry.insert(VecDeque::from([job]));
+
+                let queue = self.clone();
+                self.ctx.spawn(|_| async move {
+                    queue.run(destination).await;
+                    Ok(())
+                });
+            }
+        }
+
+        Ok(true)
+    }
+
+    /// Stops accepting requests and drops every pending one,
+    /// so that their callers stop waiting.
+    ///
+    /// The workers are not stopped, as they are aborted along with the client's other tasks.
+    pub fn close(&self) {
+        self.closed.store(true, Ordering::SeqCst);
+        self.queues
+            .lock()
+            .expect("mutex lock should succeed")
+            .clear();
+    }
+
+    /// Sends requests for a destination in order
+    /// until there are none left.
+    async fn run(&self, destination: String) {
+        loop {
+            let job = {
+                let mut queues = self.queues.lock().expect("mutex lock should succeed");
+                match queues.get_mut(&destination).and_then(VecDeque::pop_front) {
+                    Some(job) => job,
+                    None => {
+                        queues.remove(&destination);
+                        return;
+                    }
+                }
+            };
+
+            match job {
+                Job::Activate { request, reply } => {
+                    let timeout = self
+                        .ctx
+                        .options
+                        .read()
+                        .expect("lock should succeed")
+                        .activation_timeout;
+
+                    let address = request.address().to_string();
+                    let res = self
+                        .ctx
+                        .timed(&address, send_activate(&self.ctx, request, timeout))
+                        .await;
+
+                    // the caller may have stopped waiting, which is fine
+                    let _ = reply.send(res);
+                }
+                Job::Call(call) => call.await,
+            }
+        }
+    }
+}
+
+/// Waits for the result of a queued call.
+///
+/// The reply is dropped if the client stops before the call is sent,
+/// which is reported as [`Error::ClientStopped`].
+pub(crate) async fn recv_reply<T>(reply: oneshot::Receiver<Result<T>>) -> Result<T> {
+    reply.await.unwrap_or(Err(Error::ClientStopped))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_bus::{wait_for_call, MockItem, TestBus};
+    use std::time::Duration;
+
+    fn activate(address: &str, x: i32) -> ActivateRequest {
+        ActivateRequest::Default {
+            address: address.to_string(),
+            x,
+            y: 0,
+            token: None,
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn sends_requests_in_order() {
+        let bus = TestBus::start();
+        let client = bus.client().await;
+
+        let item = MockItem::new("queued").delay(Duration::from_millis(100));
+        let calls = item.calls();
+        let _item = bus.serve_item(item).await;
+        let (address, _) = client
+            .wait_for_item("queued", Duration::from_secs(5))
+            .await
+            .expect("item should appear");
+
+        let queue = &client.activations;
+        let first = queue
+            .push(activate(&address, 1))
+            .expect("push should succeed");
+        let second = queue
+            .push(activate(&address, 2))
+            .expect("push should succeed");
+
+        // identical to the pending request, so merged into it
+        let merged = queue
+            .push(activate(&address, 2))
+            .expect("push should succeed");
+        assert!(merged.is_none());
+
+        for reply in [first, second] {
+            let reply = reply.expect("request should not be merged");
+            reply
+                .await
+                .expect("worker should reply")
+                .expect("call should succeed");
+        }
+
+        assert_eq!(
+            *calls.lock().expect("mutex lock should succeed"),
+            [
+                "start Activate 1",
+                "end Activate 1",
+                "start Activate 2",
+                "end Activate 2"
+            ]
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn fails_pending_requests_on_shutdown() {
+        let bus = TestBus::start();
+        let client = bus.client().await;
+
+        let item = MockItem::new("stopping").delay(Duration::from_secs(5));
+        let calls = item.calls();
+        let _item = bus.serve_item(item).await;
+        let (address, _) = client
+            .wait_for_item("stopping", Duration::from_secs(5))
+            .await
+            .expect("item should appear");
+
+        let in_flight = tokio::spawn({
+            let client = client.clone();
+            let request = activate(&address, 1);
+            async move { client.activate(request).await }
+        });
+        wait_for_call(&calls, "start Activate 1").await;
+
+        let queued = client
+            .activations
+            .push(activate(&address, 2))
+            .expect("push should succeed")
+            .expect("request should not be merged");
+
+        client
+            .clone()
+            .shutdown()
+            .await
+            .expect("shutdown should succeed");
+
+        assert!(matches!(
+            in_flight.await.expect("task should not panic"),
+            Err(Error::ClientStopped)
+        ));
+        assert!(queued.await.is_err());
+        assert!(matches!(
+            client.activate(activate(&address, 3)).await,
+            Err(Error::ClientStopped)
+        ));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn limits_queue_depth() {
+        let bus = TestBus::start();
+        let client = bus
+            .builder()
+            .activation_queue_depth(1)
+            .build()
+            .await
+            .expect("client should start");
+
+        let item = MockItem::new("full").delay(Duration::from_millis(200));
+        let calls = item.calls();
+        let _item = bus.serve_item(item).await;
+        let (address, _) = client
+            .wait_for_item("full", Duration::from_secs(5))
+            .await
+            .expect("item should appear");
+
+        let queue = &client.activations;
+        queue
+            .push(activate(&address, 1))
+            .expect("push should succeed");
+        wait_for_call(&calls, "start Activate 1").await;
+
+        // the request in flight no longer counts towards the depth
+        queue
+            .push(activate(&address, 2))
+            .expect("push should succeed");
+        assert!(matches!(
+            queue.push(activate(&address, 3)),
+            Err(Error::QueueFull)
+        ));
+    }
+}
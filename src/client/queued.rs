@@ -0,0 +1,262 @@
+use super::Event;
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex, Weak};
+use tokio::sync::Notify;
+
+/// What happens when an event is sent to a [`QueuedReceiver`] which is full.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OverflowPolicy {
+    /// The client stops handling changes to items and menus
+    /// until the receiver has room, so no events are lost.
+    ///
+    /// A receiver which stops receiving holds up every other subscriber,
+    /// so this should only be used by consumers which are always receiving.
+    /// The queue may briefly hold a few events more than its capacity,
+    /// as a single change can send several events.
+    Block,
+    /// The oldest event in the queue is dropped to make room.
+    ///
+    /// This is the default, as a stalled receiver cannot hold up the client.
+    #[default]
+    DropOldest,
+    /// The new event is dropped.
+    DropNewest,
+}
+
+#[derive(Debug, Default)]
+struct Events {
+    events: VecDeque<Event>,
+    /// How many events have been dropped because the queue was full.
+    dropped: u64,
+    /// Whether either the client or the receiver has gone away.
+    closed: bool,
+}
+
+#[derive(Debug)]
+struct Queue {
+    events: Mutex<Events>,
+    capacity: usize,
+    policy: OverflowPolicy,
+    /// Notifies the receiver that an event was pushed, or the queue closed.
+    pushed: Notify,
+    /// Notifies the client that an event was taken, or the queue closed.
+    taken: Notify,
+}
+
+impl Queue {
+    fn lock(&self) -> std::sync::MutexGuard<'_, Events> {
+        self.events.lock().expect("mutex lock should succeed")
+    }
+
+    fn push(&self, event: &Event) {
+        {
+            let mut events = self.lock();
+            if events.closed {
+                return;
+            }
+
+            if events.events.len() >= self.capacity {
+                match self.policy {
+                    OverflowPolicy::Block => {}
+                    OverflowPolicy::DropOldest => {
+                        events.events.pop_front();
+                        events.dropped += 1;
+                    }
+                    OverflowPolicy::DropNewest => {
+                        events.dropped += 1;
+                        return;
+                    }
+                }
+            }
+
+            events.events.push_back(event.clone());
+        }
+
+        self.pushed.notify_one();
+    }
+
+    fn pop(&self) -> Option<Event> {
+        let event = self.lock().events.pop_front()?;
+        self.taken.notify_waiters();
+        Some(event)
+    }
+
+    /// Checks whether the client must wait before sending more events.
+    fn is_blocking(&self) -> bool {
+        let events = self.lock();
+        self.policy == OverflowPolicy::Block
+            && !events.closed
+            && events.events.len() >= self.capacity
+    }
+
+    fn close(&self) {
+        self.lock().closed = true;
+        self.pushed.notify_one();
+        self.taken.notify_waiters();
+    }
+}
+
+/// A receiver for client events with its own bounded queue,
+/// so that it does not miss events it has not yet received
+/// unless its [`OverflowPolicy`] allows it.
+///
+/// See [`Client::subscribe_queued`](super::Client::subscribe_queued).
+#[derive(Debug)]
+pub struct QueuedReceiver {
+    queue: Arc<Queue>,
+}
+
+impl QueuedReceiver {
+    /// Receives the next event,
+    /// returning `None` once the client has stopped and all remaining events have been received.
+    ///
+    /// # Panics
+    ///
+    /// If the queue mutex is poisoned.
+    pub async fn recv(&mut self) -> Option<Event> {
+        loop {
+            let pushed = self.queue.pushed.notified();
+
+            if let Some(event) = self.queue.pop() {
+                return Some(event);
+            }
+
+            if self.queue.lock().closed {
+                return None;
+            }
+
+            pushed.await;
+        }
+    }
+
+    /// Receives the next event if one is waiting, without waiting for one.
+    ///
+    /// # Panics
+    ///
+    /// If the queue mutex is poisoned.
+    pub fn try_recv(&mut self) -> Option<Event> {
+        self.queue.pop()
+    }
+
+    /// Gets how many events have been dropped because the queue was full.
+    ///
+    /// # Panics
+    ///
+    /// If the queue mutex is poisoned.
+    #[must_use]
+    pub fn dropped(&self) -> u64 {
+        self.queue.lock().dropped
+    }
+}
+
+impl Drop for QueuedReceiver {
+    fn drop(&mut self) {
+        // a client blocked on this receiver must not wait for it forever
+        self.queue.close();
+    }
+}
+
+/// The queues of every [`QueuedReceiver`].
+#[derive(Debug, Default)]
+pub(crate) struct QueuedSubscribers {
+    queues: Mutex<Vec<Weak<Queue>>>,
+}
+
+impl QueuedSubscribers {
+    /// Creates a new receiver.
+    pub fn subscribe(&self, capacity: usize, policy: OverflowPolicy) -> QueuedReceiver {
+        let queue = Arc::new(Queue {
+            events: Mutex::default(),
+            capacity: capacity.max(1),
+            policy,
+            pushed: Notify::new(),
+            taken: Notify::new(),
+        });
+
+        self.queues
+            .lock()
+            .expect("mutex lock should succeed")
+            .push(Arc::downgrade(&queue));
+
+        QueuedReceiver { queue }
+    }
+
+    /// Sends an event to every receiver,
+    /// forgetting any which have been dropped.
+    pub fn send(&self, event: &Event) {
+        let mut queues = self.queues.lock().expect("mutex lock should succeed");
+        queues.retain(|queue| match queue.upgrade() {
+            Some(queue) => {
+                queue.push(event);
+                true
+            }
+            None => false,
+        });
+    }
+
+    /// Waits until no receiver with [`OverflowPolicy::Block`] is full.
+    pub async fn wait_for_room(&self) {
+        loop {
+            let blocking = self
+                .queues
+                .lock()
+                .expect("mutex lock should succeed")
+                .iter()
+                .filter_map(Weak::upgrade)
+                .find(|queue| queue.is_blocking());
+
+            let Some(queue) = blocking else {
+                return;
+            };
+
+            let taken = queue.taken.notified();
+            tokio::pin!(taken);
+            taken.as_mut().enable();
+
+            if queue.is_blocking() {
+                taken.await;
+            }
+        }
+    }
+
+    /// Closes every receiver, so that they stop once they have received all remaining events.
+    pub fn close(&self) {
+        let queues = std::mem::take(&mut *self.queues.lock().expect("mutex lock should succeed"));
+        for queue in queues.iter().filter_map(Weak::upgrade) {
+            queue.close();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn applies_overflow_policy() {
+        let subscribers = QueuedSubscribers::default();
+        let mut oldest = subscribers.subscribe(2, OverflowPolicy::default());
+        let mut newest = subscribers.subscribe(2, OverflowPolicy::DropNewest);
+        let mut block = subscribers.subscribe(2, OverflowPolicy::Block);
+
+        for address in ["a", "b", "c"] {
+            subscribers.send(&Event::Remove(address.to_string()));
+        }
+
+        let received = |rx: &mut QueuedReceiver| {
+            std::iter::from_fn(|| rx.try_recv())
+                .map(|event| match event {
+                    Event::Remove(address) => address,
+                    _ => unreachable!(),
+                })
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(received(&mut oldest), ["b", "c"]);
+        assert_eq!(oldest.dropped(), 1);
+        assert_eq!(received(&mut newest), ["a", "b"]);
+        assert_eq!(newest.dropped(), 1);
+        assert_eq!(received(&mut block), ["a", "b", "c"]);
+        assert_eq!(block.dropped(), 0);
+    }
+}
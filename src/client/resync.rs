@@ -0,0 +1,75 @@
+use super::pixmap_cache::PixmapCache;
+use super::{snapshot_with, Event, ItemTimestamps, State};
+use std::collections::HashMap;
+use std::sync::atomic::AtomicU64;
+use std::sync::{Arc, Mutex};
+use tokio::sync::broadcast::{self, error::RecvError};
+use tracing::warn;
+
+/// A receiver for client events which recovers from falling behind,
+/// by sending an [`Event::Resync`] with the current state in place of the missed events.
+///
+/// See [`Client::subscribe_resync`](super::Client::subscribe_resync).
+#[derive(Debug)]
+pub struct ResyncReceiver {
+    rx: broadcast::Receiver<Event>,
+    // the state is held directly rather than through the client,
+    // so that the receiver does not keep the channel open once the client is dropped
+    items: Arc<Mutex<State>>,
+    timestamps: Arc<Mutex<HashMap<String, ItemTimestamps>>>,
+    generation: Arc<AtomicU64>,
+    pixmaps: Arc<PixmapCache>,
+}
+
+impl ResyncReceiver {
+    pub(super) fn new(
+        rx: broadcast::Receiver<Event>,
+        items: Arc<Mutex<State>>,
+        timestamps: Arc<Mutex<HashMap<String, ItemTimestamps>>>,
+        generation: Arc<AtomicU64>,
+        pixmaps: Arc<PixmapCache>,
+    ) -> Self {
+        Self {
+            rx,
+            items,
+            timestamps,
+            generation,
+            pixmaps,
+        }
+    }
+
+    /// Receives the next event.
+    ///
+    /// If the receiver fell behind and events were dropped,
+    /// an [`Event::Resync`] is returned instead,
+    /// and the following events apply on top of its snapshot.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`RecvError::Closed`] once the client has stopped
+    /// and all remaining events have been received.
+    /// [`RecvError::Lagged`] is never returned.
+    ///
+    /// # Panics
+    ///
+    /// If the state mutex is poisoned.
+    pub async fn recv(&mut self) -> Result<Event, RecvError> {
+        match self.rx.recv().await {
+            Err(RecvError::Lagged(missed)) => {
+                warn!(missed, "event receiver fell behind, resyncing");
+
+                let (snapshot, rx) = snapshot_with(
+                    &self.items,
+                    &self.timestamps,
+                    &self.generation,
+                    &self.pixmaps,
+                    || self.rx.resubscribe(),
+                );
+                self.rx = rx;
+
+                Ok(Event::Resync(Arc::new(snapshot)))
+            }
+            res => res,
+        }
+    }
+}
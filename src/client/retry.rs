@@ -0,0 +1,134 @@
+use super::is_missing_object;
+use crate::error::{Error, Result};
+use std::future::Future;
+use std::time::Duration;
+use tokio::time::sleep;
+use tracing::debug;
+
+/// How failed calls to fetch an item's properties or menu layout are retried.
+///
+/// The delay between attempts doubles after each failure, up to `max_delay`.
+/// Calls which fail because the item or object no longer exists are not retried.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// The maximum number of attempts, including the first.
+    /// A value of `1` disables retrying.
+    pub max_attempts: u32,
+    /// The delay before the first retry.
+    pub initial_delay: Duration,
+    /// The longest delay between two attempts.
+    pub max_delay: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 4,
+            initial_delay: Duration::from_millis(100),
+            max_delay: Duration::from_secs(2),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// A policy which never retries.
+    #[must_use]
+    pub fn none() -> Self {
+        Self {
+            max_attempts: 1,
+            ..Self::default()
+        }
+    }
+
+    /// Gets the delay before retrying after the given failed attempt,
+    /// counting from `1`.
+    #[must_use]
+    pub fn delay(&self, attempt: u32) -> Duration {
+        let factor = 2u32.saturating_pow(attempt.saturating_sub(1));
+        self.initial_delay
+            .saturating_mul(factor)
+            .min(self.max_delay)
+    }
+}
+
+/// Makes a call, retrying it according to `policy` if it fails.
+///
+/// The error from the last attempt is returned if all attempts fail.
+pub(super) async fn retry<T, F, Fut>(policy: &RetryPolicy, mut call: F) -> Result<T>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T>>,
+{
+    let mut attempt = 1;
+
+    loop {
+        match call().await {
+            Ok(value) => return Ok(value),
+            Err(err) if attempt < policy.max_attempts && is_retryable(&err) => {
+                let delay = policy.delay(attempt);
+                debug!("attempt {attempt} failed, retrying in {delay:?}: {err}");
+
+                sleep(delay).await;
+                attempt += 1;
+            }
+            Err(err) => return Err(err),
+        }
+    }
+}
+
+/// Checks whether a call which failed with `err` could succeed if tried again.
+fn is_retryable(err: &Error) -> bool {
+    match err {
+        Error::Timeout => true,
+        Error::ZBus(err) => !is_missing_object(err),
+        Error::ZBusFdo(err) => !matches!(
+            err,
+            zbus::fdo::Error::ServiceUnknown(_)
+                | zbus::fdo::Error::UnknownObject(_)
+                | zbus::fdo::Error::UnknownInterface(_)
+                | zbus::fdo::Error::UnknownMethod(_)
+                | zbus::fdo::Error::UnknownProperty(_)
+        ),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    #[test]
+    fn backs_off_exponentially() {
+        let policy = RetryPolicy {
+            max_attempts: 10,
+            initial_delay: Duration::from_millis(100),
+            max_delay: Duration::from_millis(500),
+        };
+
+        assert_eq!(policy.delay(1), Duration::from_millis(100));
+        assert_eq!(policy.delay(2), Duration::from_millis(200));
+        assert_eq!(policy.delay(3), Duration::from_millis(400));
+        assert_eq!(policy.delay(4), Duration::from_millis(500));
+        assert_eq!(policy.delay(40), Duration::from_millis(500));
+    }
+
+    #[tokio::test]
+    async fn stops_after_max_attempts() {
+        let policy = RetryPolicy {
+            max_attempts: 4,
+            initial_delay: Duration::from_millis(1),
+            max_delay: Duration::from_millis(1),
+        };
+        let attempts = AtomicU32::new(0);
+
+        let res: Result<()> = retry(&policy, || async {
+            attempts.fetch_add(1, Ordering::SeqCst);
+            Err(Error::Timeout)
+        })
+        .await;
+
+        assert!(matches!(res, Err(Error::Timeout)));
+        assert_eq!(attempts.load(Ordering::SeqCst), 4);
+    }
+}
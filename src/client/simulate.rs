@@ -0,0 +1,279 @@
+//! Injection of fake items into a running [`Client`],
+//! for developing and testing tray UIs without any applications installed.
+//!
+//! Simulated items go through the same state and events as real ones,
+//! so anything consuming the client sees no difference.
+//! They do not exist on the bus though,
+//! so activating them or their menus will fail.
+//!
+//! ```no_run
+//! # use std::time::Duration;
+//! # use system_tray::client::{Client, UpdateEvent};
+//! # use system_tray::client::simulate::SimulatedEvent;
+//! # use system_tray::item::{Status, StatusNotifierItem};
+//! # async fn run(client: Client) -> system_tray::error::Result<()> {
+//! let simulator = client.simulate();
+//!
+//! simulator.add_item(
+//!     ":sim.1",
+//!     StatusNotifierItem {
+//!         id: String::from("chat"),
+//!         icon_name: Some(String::from("user-available")),
+//!         ..Default::default()
+//!     },
+//! )?;
+//!
+//! simulator
+//!     .play([
+//!         (
+//!             Duration::from_secs(1),
+//!             SimulatedEvent::Update(
+//!                 String::from(":sim.1"),
+//!                 UpdateEvent::Status(Status::NeedsAttention),
+//!             ),
+//!         ),
+//!         (Duration::from_secs(2), SimulatedEvent::Remove(String::from(":sim.1"))),
+//!     ])
+//!     .await?;
+//! # Ok(())
+//! # }
+//! ```
+
+use super::{Client, UpdateEvent};
+use crate::error::{Error, Result};
+use crate::item::StatusNotifierItem;
+use crate::menu::TrayMenu;
+use std::time::Duration;
+
+/// A single change to apply to the simulated tray.
+#[derive(Debug, Clone)]
+pub enum SimulatedEvent {
+    /// Adds an item at the given address.
+    Add(String, Box<StatusNotifierItem>),
+    /// Sets the menu of the item at the given address.
+    Menu(String, TrayMenu),
+    /// Sends an update for the item at the given address.
+    Update(String, UpdateEvent),
+    /// Removes the item at the given address.
+    Remove(String),
+}
+
+/// Injects fake items, menus and updates into a [`Client`].
+///
+/// Get one using [`Client::simulate`].
+///
+/// All methods panic if the state mutex is poisoned.
+#[derive(Debug, Clone)]
+pub struct Simulator<'a> {
+    client: &'a Client,
+}
+
+impl<'a> Simulator<'a> {
+    pub(super) fn new(client: &'a Client) -> Self {
+        Self { client }
+    }
+
+    /// Adds an item at `address`,
+    /// replacing any existing item there.
+    ///
+    /// Addresses of real items are unique bus names,
+    /// so use something which cannot clash, such as `:sim.1`.
+    ///
+    /// # Errors
+    ///
+    /// If sending the event fails.
+    pub fn add_item(&self, address: &str, item: StatusNotifierItem) -> Result<()> {
+        self.client.ctx.send_add(address, item)
+    }
+
+    /// Sets the menu of the item at `address`.
+    ///
+    /// # Errors
+    ///
+    /// If there is no item at `address`, an [`Error::UnknownItem`] is returned.
+    /// Otherwise, if sending the event fails.
+    pub fn set_menu(&self, address: &str, menu: TrayMenu) -> Result<()> {
+        self.ensure_exists(address)?;
+
+        let generation = self.client.ctx.cache_menu(address, &menu);
+        self.client
+            .ctx
+            .send_update_at(generation, address, UpdateEvent::Menu(menu.into()))
+    }
+
+    /// Sends an update for the item at `address`.
+    ///
+    /// # Errors
+    ///
+    /// If there is no item at `address`, an [`Error::UnknownItem`] is returned.
+    /// Otherwise, if sending the event fails.
+    pub fn update(&self, address: &str, event: UpdateEvent) -> Result<()> {
+        self.ensure_exists(address)?;
+        self.client.ctx.send_update(address, event)
+    }
+
+    /// Removes the item at `address`.
+    ///
+    /// # Errors
+    ///
+    /// If there is no item at `address`, an [`Error::UnknownItem`] is returned.
+    /// Otherwise, if sending the event fails.
+    pub fn remove_item(&self, address: &str) -> Result<()> {
+        self.ensure_exists(address)?;
+        self.client.ctx.send_remove(address)
+    }
+
+    /// Applies a single simulated event.
+    ///
+    /// # Errors
+    ///
+    /// See the method corresponding to the event.
+    pub fn apply(&self, event: SimulatedEvent) -> Result<()> {
+        match event {
+            SimulatedEvent::Add(address, item) => self.add_item(&address, *item),
+            SimulatedEvent::Menu(address, menu) => self.set_menu(&address, menu),
+            SimulatedEvent::Update(address, event) => self.update(&address, event),
+            SimulatedEvent::Remove(address) => self.remove_item(&address),
+        }
+    }
+
+    /// Applies a sequence of events,
+    /// waiting for the given delay before each one.
+    ///
+    /// # Errors
+    ///
+    /// If applying any event fails, in which case the rest are not applied.
+    pub async fn play(
+        &self,
+        events: impl IntoIterator<Item = (Duration, SimulatedEvent)>,
+    ) -> Result<()> {
+        for (delay, event) in events {
+            tokio::time::sleep(delay).await;
+            self.apply(event)?;
+        }
+
+        Ok(())
+    }
+
+    fn ensure_exists(&self, address: &str) -> Result<()> {
+        let items = self
+            .client
+            .ctx
+            .items
+            .lock()
+            .expect("mutex lock should succeed");
+
+        if items.contains_key(address) {
+            Ok(())
+        } else {
+            Err(Error::UnknownItem(address.to_string()))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::Event;
+    use crate::item::Status;
+    use crate::test_bus::{recv_until, TestBus};
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn sends_simulated_items_through_client() {
+        let bus = TestBus::start();
+        let client = bus.client().await;
+        let mut rx = client.subscribe();
+        let simulator = client.simulate();
+
+        simulator
+            .add_item(
+                ":sim.1",
+                StatusNotifierItem {
+                    id: String::from("simulated"),
+                    ..Default::default()
+                },
+            )
+            .expect("item should be added");
+        let item = recv_until(&mut rx, |event| match event {
+            Event::Add(address, item) if address == ":sim.1" => Some(item),
+            _ => None,
+        })
+        .await;
+        assert_eq!(item.id, "simulated");
+
+        simulator
+            .update(
+                ":sim.1",
+                UpdateEvent::Title(Some(String::from("Simulated"))),
+            )
+            .expect("update should be sent");
+        let title = recv_until(&mut rx, |event| match event {
+            Event::Update(address, UpdateEvent::Title(title)) if address == ":sim.1" => Some(title),
+            _ => None,
+        })
+        .await;
+        assert_eq!(title.as_deref(), Some("Simulated"));
+
+        simulator
+            .play([
+                (
+                    Duration::ZERO,
+                    SimulatedEvent::Update(
+                        String::from(":sim.1"),
+                        UpdateEvent::Status(Status::NeedsAttention),
+                    ),
+                ),
+                (
+                    Duration::ZERO,
+                    SimulatedEvent::Remove(String::from(":sim.1")),
+                ),
+            ])
+            .await
+            .expect("events should be applied");
+        let status = recv_until(&mut rx, |event| match event {
+            Event::Update(address, UpdateEvent::Status(status)) if address == ":sim.1" => {
+                Some(status)
+            }
+            Event::Remove(address) if address == ":sim.1" => {
+                panic!("status should be updated before removal")
+            }
+            _ => None,
+        })
+        .await;
+        assert_eq!(status, Status::NeedsAttention);
+
+        recv_until(&mut rx, |event| match event {
+            Event::Remove(address) if address == ":sim.1" => Some(()),
+            _ => None,
+        })
+        .await;
+
+        assert!(!client
+            .items()
+            .lock()
+            .expect("mutex lock should succeed")
+            .contains_key(":sim.1"));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn rejects_unknown_items() {
+        let bus = TestBus::start();
+        let client = bus.client().await;
+        let simulator = client.simulate();
+
+        let is_unknown = |res: Result<()>| matches!(res, Err(Error::UnknownItem(address)) if address == ":sim.2");
+
+        assert!(is_unknown(
+            simulator.update(":sim.2", UpdateEvent::Status(Status::Passive))
+        ));
+        assert!(is_unknown(simulator.remove_item(":sim.2")));
+        assert!(is_unknown(
+            simulator
+                .play([(
+                    Duration::ZERO,
+                    SimulatedEvent::Remove(String::from(":sim.2"))
+                )])
+                .await
+        ));
+    }
+}
@@ -18,14 +18,18 @@ use zbus::zvariant::{OwnedValue, Value};
 use serde::{Deserialize, Serialize};
 use zbus::zvariant::Type;
 
+/// The reply to `GetLayout`.
+/// `id` is the layout revision.
 #[derive(Deserialize, Serialize, Type, PartialEq, Debug)]
-pub(crate) struct MenuLayout {
+pub struct MenuLayout {
     pub id: u32,
     pub fields: SubMenuLayout,
 }
 
+/// A single node of a menu layout,
+/// with its children left as variants.
 #[derive(Deserialize, Serialize, Type, PartialEq, Debug)]
-pub(crate) struct SubMenuLayout {
+pub struct SubMenuLayout {
     pub id: i32,
     pub fields: HashMap<String, OwnedValue>,
     pub submenus: Vec<OwnedValue>,
@@ -37,22 +41,22 @@ type GroupProperties = Vec<(i32, HashMap<String, zbus::zvariant::OwnedValue>)>;
 #[derive(Deserialize, Type, Debug, Clone)]
 pub struct PropertiesUpdate<'a> {
     #[serde(borrow)]
-    pub(crate) updated: Vec<UpdatedProps<'a>>,
-    pub(crate) removed: Vec<RemovedProps<'a>>,
+    pub updated: Vec<UpdatedProps<'a>>,
+    pub removed: Vec<RemovedProps<'a>>,
 }
 
 #[derive(Deserialize, Type, Debug, Clone)]
 pub struct UpdatedProps<'a> {
-    pub(crate) id: i32,
+    pub id: i32,
     #[serde(borrow)]
-    pub(crate) fields: HashMap<&'a str, Value<'a>>,
+    pub fields: HashMap<&'a str, Value<'a>>,
 }
 
 #[derive(Deserialize, Type, Debug, Clone)]
 pub struct RemovedProps<'a> {
-    pub(crate) id: i32,
+    pub id: i32,
     #[serde(borrow)]
-    pub(crate) fields: Vec<&'a str>,
+    pub fields: Vec<&'a str>,
 }
 
 #[dbus_proxy(interface = "com.canonical.dbusmenu", assume_defaults = true)]
@@ -67,6 +71,8 @@ trait DBusMenu {
         timestamp: u32,
     ) -> zbus::Result<()>;
 
+    fn event_group(&self, events: &[(i32, &str, Value<'_>, u32)]) -> zbus::Result<Vec<i32>>;
+
     fn get_group_properties(
         &self,
         ids: &[i32],
@@ -95,6 +101,9 @@ trait DBusMenu {
     #[dbus_proxy(signal)]
     fn layout_updated(&self, revision: u32, parent: i32) -> zbus::Result<()>;
 
+    #[dbus_proxy(property)]
+    fn icon_theme_path(&self) -> zbus::Result<Vec<String>>;
+
     #[dbus_proxy(property)]
     fn status(&self) -> zbus::Result<String>;
 
@@ -34,6 +34,12 @@ impl DBusProps {
     pub fn get_object_path(&self, key: &str) -> Option<String> {
         self.get::<ObjectPath>(key).map(ToString::to_string)
     }
+
+    /// Gets `key` from the map if present and not empty,
+    /// as Ayatana items clear their label by setting it to an empty string.
+    pub fn get_label(&self, key: &str) -> Option<String> {
+        self.get_string(key).filter(|label| !label.is_empty())
+    }
 }
 
 pub(crate) trait OwnedValueExt {
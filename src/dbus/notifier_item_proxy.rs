@@ -29,6 +29,9 @@ trait StatusNotifierItem {
     /// SecondaryActivate method
     fn secondary_activate(&self, x: i32, y: i32) -> zbus::Result<()>;
 
+    /// ProvideXdgActivationToken method
+    fn provide_xdg_activation_token(&self, token: &str) -> zbus::Result<()>;
+
     /// NewAttentionIcon signal
     #[dbus_proxy(signal)]
     fn new_attention_icon(&self) -> zbus::Result<()>;
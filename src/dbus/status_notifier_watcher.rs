@@ -1,7 +1,10 @@
 use crate::names;
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use std::sync::{Arc, Mutex};
+use tokio::sync::broadcast;
 use tracing::{debug, error, info, warn};
+use zbus::fdo::RequestNameFlags;
+use zbus::zvariant::Value;
 use zbus::{
     dbus_interface, export::ordered_stream::OrderedStreamExt, Connection, Interface, MessageHeader,
     SignalContext,
@@ -10,38 +13,276 @@ use zbus::{
 /// An instance of [`org.kde.StatusNotifierWatcher`]. It only tracks what tray items and trays
 /// exist, and doesn't have any logic for displaying items (for that, see [`Host`][`crate::Host`]).
 ///
-/// While this is usually run alongside the tray, it can also be used standalone.
+/// By default, the watcher is served under both the `org.kde` and `org.freedesktop` names,
+/// since some applications and toolkits only look for one or the other.
+/// Items and hosts registering on either are tracked together.
+/// See [`StatusNotifierWatcher::freedesktop_alias`].
+///
+/// While this is usually run alongside the tray, it can also be used standalone
+/// by calling [`StatusNotifierWatcher::run`].
 ///
 /// [`org.kde.StatusNotifierWatcher`]: https://freedesktop.org/wiki/Specifications/StatusNotifierItem/StatusNotifierWatcher/
 #[derive(Debug, Default)]
 pub struct StatusNotifierWatcher {
-    tasks: tokio::task::JoinSet<()>,
+    state: WatcherState,
+    /// The flags used when requesting the well-known names.
+    name_flags: Vec<RequestNameFlags>,
+}
+
+/// How many [`WatcherEvent`]s are held for each subscriber
+/// before the oldest are dropped.
+const EVENT_CAPACITY: usize = 64;
+
+/// A change to the hosts and items registered with a [`StatusNotifierWatcher`],
+/// sent directly to subscribers in the same process.
+///
+/// Services are identified by the same unique names and addresses
+/// sent in the watcher's D-Bus signals, such as `:1.23/StatusNotifierItem`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WatcherEvent {
+    /// A host registered.
+    HostRegistered(String),
+    /// A host left the bus.
+    HostUnregistered(String),
+    /// An item registered.
+    ItemRegistered(String),
+    /// An item unregistered or left the bus.
+    ItemUnregistered(String),
+}
+
+/// Whether a [`Registration`] is for an item or a host.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RegistrationKind {
+    Item,
+    Host,
+}
+
+/// A request to register with the embedded watcher,
+/// passed to an [`AdmissionPolicy`] to decide whether to accept it.
+#[derive(Debug, Clone)]
+pub struct Registration<'a> {
+    /// Whether an item or a host is registering.
+    pub kind: RegistrationKind,
+    /// The service name sent by the caller.
+    /// This is usually a well-known name such as `org.kde.StatusNotifierItem-1234-1`,
+    /// but may be a unique name or object path.
+    pub service: &'a str,
+    /// The unique name of the connection the item or host belongs to.
+    pub owner: &'a str,
+    /// The ID of the process owning the connection, if it could be determined.
+    pub pid: Option<u32>,
+}
+
+/// A callback deciding whether items and hosts may register with the embedded watcher.
+///
+/// Returning `false` rejects the registration with an `AccessDenied` error,
+/// so the item never appears in the tray.
+/// This allows restricted deployments, such as kiosks,
+/// to limit which applications may show tray icons.
+#[derive(Clone)]
+pub struct AdmissionPolicy(Arc<dyn Fn(&Registration<'_>) -> bool + Send + Sync>);
+
+impl AdmissionPolicy {
+    /// Creates a policy from a callback.
+    pub fn new(policy: impl Fn(&Registration<'_>) -> bool + Send + Sync + 'static) -> Self {
+        Self(Arc::new(policy))
+    }
+
+    /// Checks whether two policies are the same callback.
+    pub(crate) fn ptr_eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+
+    /// Checks whether a registration is allowed.
+    #[must_use]
+    pub fn admits(&self, registration: &Registration<'_>) -> bool {
+        (self.0)(registration)
+    }
+}
+
+impl std::fmt::Debug for AdmissionPolicy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("AdmissionPolicy").finish_non_exhaustive()
+    }
+}
+
+/// State shared between the interfaces the watcher is served under.
+#[derive(Debug, Clone)]
+struct WatcherState {
+    tasks: Arc<Mutex<tokio::task::JoinSet<()>>>,
 
     hosts: Arc<Mutex<HashSet<String>>>,
     items: Arc<Mutex<HashSet<String>>>,
+
+    policy: Option<AdmissionPolicy>,
+
+    /// Whether the watcher is also served under the `org.freedesktop` names.
+    freedesktop: bool,
+
+    /// Sends registrations to subscribers in the same process.
+    events: broadcast::Sender<WatcherEvent>,
+}
+
+impl Default for WatcherState {
+    fn default() -> Self {
+        Self {
+            tasks: Arc::default(),
+            hosts: Arc::default(),
+            items: Arc::default(),
+            policy: None,
+            freedesktop: true,
+            events: broadcast::channel(EVENT_CAPACITY).0,
+        }
+    }
 }
 
-/// Implementation of the `StatusNotifierWatcher` service.
+/// The watcher served as `org.kde.StatusNotifierWatcher`.
+#[derive(Debug)]
+struct KdeWatcher(WatcherState);
+
+/// The watcher served as `org.freedesktop.StatusNotifierWatcher`.
+#[derive(Debug)]
+struct FreedesktopWatcher(WatcherState);
+
+/// Implements the `StatusNotifierWatcher` service on an interface type,
+/// delegating to the shared [`WatcherState`].
 ///
 /// Methods and properties correspond to methods and properties on the DBus service that can be
 /// used by others, while signals are events that we generate that other services listen to.
-#[dbus_interface(name = "org.kde.StatusNotifierWatcher")]
-impl StatusNotifierWatcher {
-    /// RegisterStatusNotifierHost method
-    async fn register_status_notifier_host(
-        &mut self,
+macro_rules! watcher_interface {
+    ($interface:ident, $name:tt) => {
+        #[dbus_interface(name = $name)]
+        impl $interface {
+            /// RegisterStatusNotifierHost method
+            async fn register_status_notifier_host(
+                &self,
+                service: &str,
+                #[zbus(header)] hdr: MessageHeader<'_>,
+                #[zbus(connection)] con: &Connection,
+                #[zbus(signal_context)] ctxt: SignalContext<'_>,
+            ) -> zbus::fdo::Result<()> {
+                self.0.register_host(service, hdr, con, ctxt).await
+            }
+
+            /// StatusNotifierHostRegistered signal.
+            #[dbus_interface(signal)]
+            async fn status_notifier_host_registered(ctxt: &SignalContext<'_>) -> zbus::Result<()>;
+
+            /// StatusNotifierHostUnregistered signal
+            #[dbus_interface(signal)]
+            async fn status_notifier_host_unregistered(
+                ctxt: &SignalContext<'_>,
+            ) -> zbus::Result<()>;
+
+            /// IsStatusNotifierHostRegistered property
+            #[dbus_interface(property)]
+            fn is_status_notifier_host_registered(&self) -> bool {
+                self.0.is_host_registered()
+            }
+
+            /// RegisterStatusNotifierItem method
+            async fn register_status_notifier_item(
+                &self,
+                service: &str,
+                #[zbus(header)] hdr: MessageHeader<'_>,
+                #[zbus(connection)] con: &Connection,
+                #[zbus(signal_context)] ctxt: SignalContext<'_>,
+            ) -> zbus::fdo::Result<()> {
+                self.0.register_item(service, hdr, con, ctxt).await
+            }
+
+            async fn unregister_status_notifier_item(
+                &self,
+                service: &str,
+                #[zbus(header)] hdr: MessageHeader<'_>,
+                #[zbus(connection)] con: &Connection,
+                #[zbus(signal_context)] ctxt: SignalContext<'_>,
+            ) -> zbus::fdo::Result<()> {
+                self.0.unregister_item(service, hdr, con, ctxt).await
+            }
+
+            /// StatusNotifierItemRegistered signal
+            #[dbus_interface(signal)]
+            async fn status_notifier_item_registered(
+                ctxt: &zbus::SignalContext<'_>,
+                service: &str,
+            ) -> zbus::Result<()>;
+
+            /// StatusNotifierItemUnregistered signal
+            #[dbus_interface(signal)]
+            async fn status_notifier_item_unregistered(
+                ctxt: &zbus::SignalContext<'_>,
+                service: &str,
+            ) -> zbus::Result<()>;
+
+            /// RegisteredStatusNotifierItems property
+            #[dbus_interface(property)]
+            fn registered_status_notifier_items(&self) -> Vec<String> {
+                self.0.registered_items()
+            }
+
+            /// ProtocolVersion property
+            #[dbus_interface(property)]
+            fn protocol_version(&self) -> i32 {
+                0
+            }
+        }
+    };
+}
+
+watcher_interface!(KdeWatcher, "org.kde.StatusNotifierWatcher");
+watcher_interface!(FreedesktopWatcher, "org.freedesktop.StatusNotifierWatcher");
+
+/// Emits a signal on each interface the watcher is served under.
+macro_rules! emit {
+    ($state:expr, $signal:ident($ctxt:expr $(, $arg:expr)*)) => {{
+        let kde = KdeWatcher::$signal($ctxt $(, $arg)*).await;
+        if $state.freedesktop {
+            kde.and(FreedesktopWatcher::$signal($ctxt $(, $arg)*).await)
+        } else {
+            kde
+        }
+    }};
+}
+
+impl WatcherState {
+    /// Spawns a background task,
+    /// first cleaning up any which have finished
+    /// so that they do not build up as hosts and items come and go.
+    fn spawn(&self, task: impl std::future::Future<Output = ()> + Send + 'static) {
+        let mut tasks = self.tasks.lock().expect("mutex lock should succeed");
+        while tasks.try_join_next().is_some() {}
+        tasks.spawn(task);
+    }
+
+    /// Sends an event to subscribers in the same process, if there are any.
+    fn send(&self, event: WatcherEvent) {
+        let _ = self.events.send(event);
+    }
+
+    /// Stops all background tasks.
+    fn abort_tasks(&self) {
+        let tasks = std::mem::take(&mut *self.tasks.lock().expect("mutex lock should succeed"));
+        drop(tasks);
+    }
+
+    async fn register_host(
+        &self,
         service: &str,
-        #[zbus(header)] hdr: MessageHeader<'_>,
-        #[zbus(connection)] con: &Connection,
-        #[zbus(signal_context)] ctxt: SignalContext<'_>,
+        hdr: MessageHeader<'_>,
+        con: &Connection,
+        ctxt: SignalContext<'_>,
     ) -> zbus::fdo::Result<()> {
-        // TODO: right now, we convert everything to the unique bus name (something like :1.234).
-        //  However, it might make more sense to listen to the actual name they give us, so that if
-        //  the connection dissociates itself from the org.kde.StatusNotifierHost-{pid}-{nr} name
-        //  but still remains around, we drop them as a host.
-        //  (This also applies to RegisterStatusNotifierItem)
+        // hosts are identified by their unique bus name (something like :1.234),
+        // but are dropped once they release the name they registered with,
+        // such as org.kde.StatusNotifierHost-{pid}-{nr}, even if the connection remains around.
+        // (This also applies to RegisterStatusNotifierItem)
+
+        let claimed = service;
+        let (name, service, _) = parse_service(service, hdr, con).await?;
+        self.check_admission(RegistrationKind::Host, claimed, &service, con)
+            .await?;
 
-        let (service, _) = parse_service(service, hdr, con).await?;
         info!("new host: {}", service);
 
         let added_first = {
@@ -55,17 +296,18 @@ impl StatusNotifierWatcher {
         };
 
         if added_first {
-            self.is_status_notifier_host_registered_changed(&ctxt)
-                .await?;
+            self.host_registered_changed(&ctxt, true).await?;
         }
-        StatusNotifierWatcher::status_notifier_host_registered(&ctxt).await?;
+        emit!(self, status_notifier_host_registered(&ctxt))?;
+        self.send(WatcherEvent::HostRegistered(service.to_string()));
 
-        self.tasks.spawn({
+        self.spawn({
+            let state = self.clone();
             let hosts = self.hosts.clone();
             let ctxt = ctxt.to_owned();
             let con = con.to_owned();
             async move {
-                if let Err(e) = wait_for_service_exit(&con, service.as_ref().into()).await {
+                if let Err(e) = wait_for_release(&con, name.as_ref(), &service).await {
                     error!("failed to wait for service exit: {}", e);
                 }
                 info!("lost host: {}", service);
@@ -77,49 +319,36 @@ impl StatusNotifierWatcher {
                 };
 
                 if removed_last {
-                    if let Err(e) =
-                        StatusNotifierWatcher::is_status_notifier_host_registered_refresh(&ctxt)
-                            .await
-                    {
+                    if let Err(e) = state.host_registered_changed(&ctxt, false).await {
                         error!("failed to signal Watcher: {}", e);
                     }
                 }
-                if let Err(e) =
-                    StatusNotifierWatcher::status_notifier_host_unregistered(&ctxt).await
-                {
+                if let Err(e) = emit!(state, status_notifier_host_unregistered(&ctxt)) {
                     error!("failed to signal Watcher: {}", e);
                 }
+                state.send(WatcherEvent::HostUnregistered(service.to_string()));
             }
         });
 
         Ok(())
     }
 
-    /// StatusNotifierHostRegistered signal.
-    #[dbus_interface(signal)]
-    async fn status_notifier_host_registered(ctxt: &SignalContext<'_>) -> zbus::Result<()>;
-
-    /// StatusNotifierHostUnregistered signal
-    #[dbus_interface(signal)]
-    async fn status_notifier_host_unregistered(ctxt: &SignalContext<'_>) -> zbus::Result<()>;
-
-    /// IsStatusNotifierHostRegistered property
-    #[dbus_interface(property)]
-    fn is_status_notifier_host_registered(&self) -> bool {
+    fn is_host_registered(&self) -> bool {
         let hosts = self.hosts.lock().expect("mutex lock should succeed");
         !hosts.is_empty()
     }
 
-    /// RegisterStatusNotifierItem method
-    async fn register_status_notifier_item(
-        &mut self,
+    async fn register_item(
+        &self,
         service: &str,
-        #[zbus(header)] hdr: MessageHeader<'_>,
-        #[zbus(connection)] con: &Connection,
-        #[zbus(signal_context)] ctxt: SignalContext<'_>,
+        hdr: MessageHeader<'_>,
+        con: &Connection,
+        ctxt: SignalContext<'_>,
     ) -> zbus::fdo::Result<()> {
-        let (service, objpath) = parse_service(service, hdr, con).await?;
-        let service = zbus::names::BusName::Unique(service);
+        let claimed = service;
+        let (name, service, objpath) = parse_service(service, hdr, con).await?;
+        self.check_admission(RegistrationKind::Item, claimed, &service, con)
+            .await?;
 
         let item = format!("{service}{objpath}");
 
@@ -133,52 +362,67 @@ impl StatusNotifierWatcher {
         }
         info!("new item: {}", item);
 
-        self.registered_status_notifier_items_changed(&ctxt).await?;
-        StatusNotifierWatcher::status_notifier_item_registered(&ctxt, item.as_ref()).await?;
+        self.registered_items_changed(&ctxt).await?;
+        emit!(self, status_notifier_item_registered(&ctxt, item.as_ref()))?;
+        self.send(WatcherEvent::ItemRegistered(item.clone()));
 
-        self.tasks.spawn({
-            let items = self.items.clone();
+        self.spawn({
+            let state = self.clone();
             let ctxt = ctxt.to_owned();
             let con = con.to_owned();
             async move {
-                if let Err(e) = wait_for_service_exit(&con, service.as_ref()).await {
+                if let Err(e) = wait_for_release(&con, name.as_ref(), &service).await {
                     error!("failed to wait for service exit: {}", e);
                 }
                 debug!("gone item: {}", &item);
 
-                {
-                    let mut items = items.lock().expect("mutex lock should succeed");
-                    items.remove(&item);
+                let removed = {
+                    let mut items = state.items.lock().expect("mutex lock should succeed");
+                    items.remove(&item)
+                };
+
+                // the item may have unregistered itself already
+                if !removed {
+                    return;
                 }
 
-                if let Err(e) =
-                    StatusNotifierWatcher::registered_status_notifier_items_refresh(&ctxt).await
-                {
+                if let Err(e) = state.registered_items_changed(&ctxt).await {
                     error!("failed to signal Watcher: {}", e);
                 }
-                if let Err(e) =
-                    StatusNotifierWatcher::status_notifier_item_unregistered(&ctxt, item.as_ref())
-                        .await
-                {
+                if let Err(e) = emit!(
+                    state,
+                    status_notifier_item_unregistered(&ctxt, item.as_ref())
+                ) {
                     error!("failed to signal Watcher: {}", e);
                 }
+                state.send(WatcherEvent::ItemUnregistered(item));
             }
         });
 
         Ok(())
     }
 
-    async fn unregister_status_notifier_item(
-        &mut self,
+    async fn unregister_item(
+        &self,
         service: &str,
-        #[zbus(header)] hdr: MessageHeader<'_>,
-        #[zbus(connection)] con: &Connection,
-        #[zbus(signal_context)] context: SignalContext<'_>,
+        hdr: MessageHeader<'_>,
+        con: &Connection,
+        ctxt: SignalContext<'_>,
     ) -> zbus::fdo::Result<()> {
         debug!("received item unregister: {service}");
 
-        let (service, objpath) = parse_service(service, hdr, con).await?;
-        let service = zbus::names::BusName::Unique(service);
+        let sender = hdr.sender()?.map(|sender| sender.to_owned());
+        let (_, service, objpath) = parse_service(service, hdr, con).await?;
+
+        // only the item itself may unregister while it is on the bus,
+        // so that other clients cannot remove it from the tray.
+        // hosts unregister items which have already left
+        if sender.as_ref() != Some(&service) && has_owner(con, &service).await? {
+            info!("rejected unregister of {service} by {sender:?}");
+            return Err(zbus::fdo::Error::AccessDenied(
+                "only the item's owner may unregister it".to_string(),
+            ));
+        }
 
         let item = format!("{service}{objpath}");
 
@@ -187,38 +431,102 @@ impl StatusNotifierWatcher {
             .expect("mutex lock should succeed")
             .remove(&item);
 
-        if let Err(err) = Self::status_notifier_item_unregistered(&context, &item).await {
+        if let Err(err) = emit!(self, status_notifier_item_unregistered(&ctxt, &item)) {
             error!("{err:?}");
         }
+        self.send(WatcherEvent::ItemUnregistered(item));
 
         Ok(())
     }
 
-    /// StatusNotifierItemRegistered signal
-    #[dbus_interface(signal)]
-    async fn status_notifier_item_registered(
-        ctxt: &zbus::SignalContext<'_>,
+    /// Runs the admission policy, if there is one,
+    /// returning an error if the registration is rejected.
+    async fn check_admission(
+        &self,
+        kind: RegistrationKind,
         service: &str,
-    ) -> zbus::Result<()>;
+        owner: &zbus::names::UniqueName<'_>,
+        con: &Connection,
+    ) -> zbus::fdo::Result<()> {
+        let Some(policy) = &self.policy else {
+            return Ok(());
+        };
 
-    /// StatusNotifierItemUnregistered signal
-    #[dbus_interface(signal)]
-    async fn status_notifier_item_unregistered(
-        ctxt: &zbus::SignalContext<'_>,
-        service: &str,
-    ) -> zbus::Result<()>;
+        // the host embedded alongside the watcher is always allowed
+        if con.unique_name().is_some_and(|name| name == owner) {
+            return Ok(());
+        }
 
-    /// RegisteredStatusNotifierItems property
-    #[dbus_interface(property)]
-    fn registered_status_notifier_items(&self) -> Vec<String> {
+        let dbus = zbus::fdo::DBusProxy::new(con).await?;
+        let pid = dbus
+            .get_connection_unix_process_id(zbus::names::BusName::Unique(owner.as_ref()))
+            .await
+            .ok();
+
+        let registration = Registration {
+            kind,
+            service,
+            owner: owner.as_str(),
+            pid,
+        };
+
+        if policy.admits(&registration) {
+            Ok(())
+        } else {
+            info!("rejected registration by policy: {registration:?}");
+            Err(zbus::fdo::Error::AccessDenied(
+                "registration rejected by policy".to_string(),
+            ))
+        }
+    }
+
+    fn registered_items(&self) -> Vec<String> {
         let items = self.items.lock().expect("mutex lock should succeed");
         items.iter().cloned().collect()
     }
 
-    /// ProtocolVersion property
-    #[dbus_interface(property)]
-    fn protocol_version(&self) -> i32 {
-        0
+    /// Emits the new value of the `IsStatusNotifierHostRegistered` property.
+    async fn host_registered_changed(
+        &self,
+        ctxt: &SignalContext<'_>,
+        registered: bool,
+    ) -> zbus::Result<()> {
+        self.properties_changed(
+            ctxt,
+            "IsStatusNotifierHostRegistered",
+            Value::from(registered),
+        )
+        .await
+    }
+
+    /// Emits the new value of the `RegisteredStatusNotifierItems` property.
+    async fn registered_items_changed(&self, ctxt: &SignalContext<'_>) -> zbus::Result<()> {
+        let items = self.registered_items();
+        self.properties_changed(ctxt, "RegisteredStatusNotifierItems", Value::from(items))
+            .await
+    }
+
+    /// Emits a property change on each interface the watcher is served under.
+    async fn properties_changed(
+        &self,
+        ctxt: &SignalContext<'_>,
+        property: &str,
+        value: Value<'_>,
+    ) -> zbus::Result<()> {
+        let changed = HashMap::from([(property, &value)]);
+
+        let interfaces = if self.freedesktop {
+            &[KdeWatcher::name(), FreedesktopWatcher::name()][..]
+        } else {
+            &[KdeWatcher::name()][..]
+        };
+
+        for interface in interfaces {
+            zbus::fdo::Properties::properties_changed(ctxt, interface.clone(), &changed, &[])
+                .await?;
+        }
+
+        Ok(())
     }
 }
 
@@ -228,54 +536,244 @@ impl StatusNotifierWatcher {
         Self::default()
     }
 
+    /// Create a new Watcher which only accepts registrations allowed by `policy`.
+    pub fn with_policy(policy: AdmissionPolicy) -> Self {
+        Self {
+            state: WatcherState {
+                policy: Some(policy),
+                ..WatcherState::default()
+            },
+            ..Self::default()
+        }
+    }
+
+    /// Sets the flags used when requesting the well-known names.
+    ///
+    /// By default no flags are set, so the Watcher queues behind any existing watcher
+    /// and keeps the names once it has them.
+    /// Use [`RequestNameFlags::ReplaceExisting`] to take over from a watcher which allows it,
+    /// and [`RequestNameFlags::AllowReplacement`] to yield to one which asks to take over.
+    ///
+    /// Setting [`RequestNameFlags::DoNotQueue`] means the Watcher never gets the names
+    /// if another watcher already has them.
+    #[must_use]
+    pub fn name_flags(mut self, flags: impl IntoIterator<Item = RequestNameFlags>) -> Self {
+        self.name_flags = flags.into_iter().collect();
+        self
+    }
+
+    /// Sets whether the Watcher is also served as `org.freedesktop.StatusNotifierWatcher`,
+    /// owning that name and serving that interface alongside the `org.kde` ones.
+    ///
+    /// This is enabled by default,
+    /// as some items and hosts only look for the `org.freedesktop` name.
+    #[must_use]
+    pub fn freedesktop_alias(mut self, enabled: bool) -> Self {
+        self.state.freedesktop = enabled;
+        self
+    }
+
+    /// Subscribes to hosts and items registering with the Watcher,
+    /// receiving them directly rather than through its D-Bus signals.
+    ///
+    /// Only registrations made with this Watcher are received,
+    /// so none are while another watcher owns the names.
+    pub fn subscribe(&self) -> broadcast::Receiver<WatcherEvent> {
+        self.state.events.subscribe()
+    }
+
+    /// Sends events to `events` instead of the Watcher's own channel,
+    /// so that subscribers keep receiving them from a replacement Watcher.
+    pub(crate) fn events_to(mut self, events: broadcast::Sender<WatcherEvent>) -> Self {
+        self.state.events = events;
+        self
+    }
+
+    /// Run the Watcher on a new session bus connection,
+    /// returning a handle to stop it again.
+    ///
+    /// This is intended for running the Watcher as a long-lived standalone service.
+    pub async fn run(self) -> zbus::Result<WatcherHandle> {
+        let connection = Connection::session().await?;
+        self.run_on(connection).await
+    }
+
+    /// Run the Watcher on `connection`, taking ownership of it,
+    /// returning a handle to stop it again.
+    pub(crate) async fn run_on(self, connection: Connection) -> zbus::Result<WatcherHandle> {
+        let events = self.state.events.clone();
+        self.attach_to(&connection).await?;
+
+        Ok(WatcherHandle {
+            connection,
+            events,
+            stopped: false,
+        })
+    }
+
     /// Attach and run the Watcher (in the background) on a connection.
+    ///
+    /// This serves both watcher interfaces and requests both well-known names,
+    /// unless the [`freedesktop_alias`](Self::freedesktop_alias) is disabled.
     pub async fn attach_to(self, con: &zbus::Connection) -> zbus::Result<()> {
-        if !con.object_server().at(names::WATCHER_OBJECT, self).await? {
+        let object_server = con.object_server();
+
+        let flags = self.name_flags;
+        let freedesktop = self.state.freedesktop;
+        if !object_server
+            .at(names::WATCHER_OBJECT, KdeWatcher(self.state.clone()))
+            .await?
+            || (freedesktop
+                && !object_server
+                    .at(names::WATCHER_OBJECT, FreedesktopWatcher(self.state))
+                    .await?)
+        {
             return Err(zbus::Error::Failure(format!(
                 "Object already exists at {} on this connection -- is StatusNotifierWatcher already running?",
                 names::WATCHER_OBJECT
             )));
         }
 
-        // not AllowReplacement, not ReplaceExisting, not DoNotQueue
-        let flags: [zbus::fdo::RequestNameFlags; 0] = [];
-        match con
-            .request_name_with_flags(names::WATCHER_BUS, flags.into_iter().collect())
+        let bus_names = if freedesktop {
+            &[names::WATCHER_BUS, names::WATCHER_BUS_FREEDESKTOP][..]
+        } else {
+            &[names::WATCHER_BUS][..]
+        };
+
+        for &name in bus_names {
+            match con
+                .request_name_with_flags(name, flags.iter().copied().collect())
+                .await
+            {
+                Ok(_) | Err(zbus::Error::NameTaken) => {} // defer to existing
+                Err(e) => return Err(e),
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Checks whether a Watcher is currently attached to a connection.
+    pub async fn is_attached(con: &zbus::Connection) -> bool {
+        con.object_server()
+            .interface::<_, KdeWatcher>(names::WATCHER_OBJECT)
+            .await
+            .is_ok()
+    }
+
+    /// Remove a running Watcher from a connection,
+    /// releasing its well-known names and stopping its background tasks.
+    pub async fn detach_from(con: &zbus::Connection) -> zbus::Result<()> {
+        let object_server = con.object_server();
+
+        // the tasks each hold a copy of the state,
+        // so are not stopped by removing the interfaces alone.
+        // the interface may already be gone, in which case the names are still released
+        if let Ok(watcher) = object_server
+            .interface::<_, KdeWatcher>(names::WATCHER_OBJECT)
+            .await
+        {
+            watcher.get().await.0.abort_tasks();
+            object_server
+                .remove::<KdeWatcher, _>(names::WATCHER_OBJECT)
+                .await?;
+        }
+
+        // the freedesktop alias may have been disabled
+        if object_server
+            .interface::<_, FreedesktopWatcher>(names::WATCHER_OBJECT)
             .await
+            .is_ok()
         {
-            Ok(_) | Err(zbus::Error::NameTaken) => Ok(()), // defer to existing
-            Err(e) => Err(e),
+            object_server
+                .remove::<FreedesktopWatcher, _>(names::WATCHER_OBJECT)
+                .await?;
         }
+
+        // releasing a name which is not owned does nothing.
+        // zbus forgets names lost to a watcher which replaced this one,
+        // even though they are queued for and regained once it exits,
+        // so those are released on the bus directly
+        let dbus = zbus::fdo::DBusProxy::new(con).await?;
+        for name in [names::WATCHER_BUS, names::WATCHER_BUS_FREEDESKTOP] {
+            if !con.release_name(name).await? {
+                dbus.release_name(name.try_into()?).await?;
+            }
+        }
+
+        Ok(())
     }
+}
 
-    /// Equivalent to `is_status_notifier_host_registered_invalidate`, but without requiring
-    /// `self`.
-    async fn is_status_notifier_host_registered_refresh(
-        ctxt: &SignalContext<'_>,
-    ) -> zbus::Result<()> {
-        zbus::fdo::Properties::properties_changed(
-            ctxt,
-            Self::name(),
-            &std::collections::HashMap::new(),
-            &["IsStatusNotifierHostRegistered"],
-        )
-        .await
+/// A handle to a Watcher started using [`StatusNotifierWatcher::run`].
+///
+/// The Watcher is stopped when this is dropped,
+/// but only [`WatcherHandle::stop`] reports whether that succeeded.
+#[derive(Debug)]
+pub struct WatcherHandle {
+    connection: Connection,
+    events: broadcast::Sender<WatcherEvent>,
+    stopped: bool,
+}
+
+impl WatcherHandle {
+    /// Gets the connection the Watcher is served on.
+    pub fn connection(&self) -> &Connection {
+        &self.connection
     }
 
-    /// Equivalent to `registered_status_notifier_items_invalidate`, but without requiring `self`.
-    async fn registered_status_notifier_items_refresh(
-        ctxt: &SignalContext<'_>,
-    ) -> zbus::Result<()> {
-        zbus::fdo::Properties::properties_changed(
-            ctxt,
-            Self::name(),
-            &std::collections::HashMap::new(),
-            &["RegisteredStatusNotifierItems"],
-        )
-        .await
+    /// Subscribes to hosts and items registering with the Watcher.
+    ///
+    /// See [`StatusNotifierWatcher::subscribe`].
+    pub fn subscribe(&self) -> broadcast::Receiver<WatcherEvent> {
+        self.events.subscribe()
+    }
+
+    /// Checks whether the Watcher currently owns the `org.kde.StatusNotifierWatcher` name,
+    /// rather than waiting in the queue behind another watcher.
+    pub async fn is_owner(&self) -> zbus::Result<bool> {
+        let dbus = zbus::fdo::DBusProxy::new(&self.connection).await?;
+        let owner = dbus.get_name_owner(names::WATCHER_BUS.try_into()?).await?;
+
+        Ok(self.connection.unique_name() == Some(&owner))
+    }
+
+    /// Stops the Watcher,
+    /// releasing its well-known names so that any queued watcher takes over.
+    pub async fn stop(mut self) -> zbus::Result<()> {
+        self.stopped = true;
+        StatusNotifierWatcher::detach_from(&self.connection).await
+    }
+}
+
+impl Drop for WatcherHandle {
+    fn drop(&mut self) {
+        if self.stopped {
+            return;
+        }
+
+        // the background tasks each hold a copy of the connection,
+        // so it is not closed by the handle going away on its own
+        if let Ok(handle) = tokio::runtime::Handle::try_current() {
+            let connection = self.connection.clone();
+            handle.spawn(async move {
+                if let Err(err) = StatusNotifierWatcher::detach_from(&connection).await {
+                    error!("failed to stop watcher: {err}");
+                }
+            });
+        }
     }
 }
 
+/// Checks whether a unique name is still connected to the bus.
+async fn has_owner(
+    con: &Connection,
+    name: &zbus::names::UniqueName<'_>,
+) -> zbus::fdo::Result<bool> {
+    let dbus = zbus::fdo::DBusProxy::new(con).await?;
+    dbus.name_has_owner(name.as_ref().into()).await
+}
+
 /// Decode the service name that others give to us, into the [bus
 /// name](https://dbus2.github.io/zbus/concepts.html#bus-name--service-name) and the [object
 /// path](https://dbus2.github.io/zbus/concepts.html#objects-and-object-paths) within the
@@ -283,20 +781,34 @@ impl StatusNotifierWatcher {
 ///
 /// The freedesktop.org specification has the format of this be just the bus name, however some
 /// status items pass non-conforming values. One common one is just the object path.
+///
+/// The name they registered with is returned alongside the unique name of its owner,
+/// so that they can be dropped once they release it.
 async fn parse_service<'a>(
     service: &'a str,
     hdr: MessageHeader<'_>,
     con: &Connection,
-) -> zbus::fdo::Result<(zbus::names::UniqueName<'static>, &'a str)> {
+) -> zbus::fdo::Result<(
+    zbus::names::BusName<'static>,
+    zbus::names::UniqueName<'static>,
+    &'a str,
+)> {
     if service.starts_with('/') {
         // they sent us just the object path
         if let Some(sender) = hdr.sender()? {
-            Ok((sender.to_owned(), service))
+            let sender = sender.to_owned();
+            Ok((sender.clone().into(), sender, service))
         } else {
             warn!("unknown sender");
             Err(zbus::fdo::Error::InvalidArgs("Unknown bus address".into()))
         }
     } else {
+        // they may have sent us both the bus name and object path
+        let (service, objpath) = match service.find('/') {
+            Some(index) => service.split_at(index),
+            None => (service, names::ITEM_OBJECT),
+        };
+
         // parse the bus name they gave us
         let busname: zbus::names::BusName = match service.try_into() {
             Ok(x) => x,
@@ -306,15 +818,15 @@ async fn parse_service<'a>(
             }
         };
 
-        if let zbus::names::BusName::Unique(unique) = busname {
-            Ok((unique.to_owned(), names::ITEM_OBJECT))
+        if let zbus::names::BusName::Unique(unique) = &busname {
+            Ok((busname.to_owned(), unique.to_owned(), objpath))
         } else {
             // they gave us a "well-known name" like org.kde.StatusNotifierHost-81830-0, we need to
-            // convert this into the actual identifier for their bus (e.g. :1.234), so that even if
-            // they remove that well-known name it's fine.
+            // convert this into the actual identifier for their bus (e.g. :1.234), so that they
+            // are still identified the same way if they register under another name.
             let dbus = zbus::fdo::DBusProxy::new(con).await?;
-            match dbus.get_name_owner(busname).await {
-                Ok(owner) => Ok((owner.into_inner(), names::ITEM_OBJECT)),
+            match dbus.get_name_owner(busname.as_ref()).await {
+                Ok(owner) => Ok((busname.to_owned(), owner.into_inner(), objpath)),
                 Err(e) => {
                     warn!("failed to get owner of {:?}: {}", service, e);
                     Err(e)
@@ -324,27 +836,287 @@ async fn parse_service<'a>(
     }
 }
 
-/// Wait for a `DBus` service to disappear
-async fn wait_for_service_exit(
+/// Wait for `owner` to stop owning `name`,
+/// either by releasing it or by disappearing from the bus.
+async fn wait_for_release(
     con: &Connection,
-    service: zbus::names::BusName<'_>,
+    name: zbus::names::BusName<'_>,
+    owner: &zbus::names::UniqueName<'_>,
 ) -> zbus::fdo::Result<()> {
     let dbus = zbus::fdo::DBusProxy::new(con).await?;
     let mut owner_changes = dbus
-        .receive_name_owner_changed_with_args(&[(0, &service)])
+        .receive_name_owner_changed_with_args(&[(0, &name)])
         .await?;
 
-    if !dbus.name_has_owner(service.as_ref()).await? {
-        // service has already disappeared
-        return Ok(());
+    match dbus.get_name_owner(name.as_ref()).await {
+        Ok(current) if current == *owner => {}
+        // service has already disappeared, or given up the name
+        _ => return Ok(()),
     }
 
     while let Some(sig) = owner_changes.next().await {
         let args = sig.args()?;
-        if args.new_owner().is_none() {
+        if args.new_owner().as_ref() != Some(owner) {
             break;
         }
     }
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_bus::{register_item, TestBus};
+    use std::time::Duration;
+    use tokio::time::timeout;
+
+    /// Waits until `handle` owns the watcher name, or stops owning it.
+    async fn wait_for_owner(handle: &WatcherHandle, owner: bool) {
+        timeout(Duration::from_secs(5), async {
+            while handle.is_owner().await.expect("bus should be queried") != owner {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("watcher name should change owner");
+    }
+
+    /// Receives a watcher event, failing if none arrives within 5 seconds.
+    async fn recv_event(rx: &mut broadcast::Receiver<WatcherEvent>) -> WatcherEvent {
+        timeout(Duration::from_secs(5), rx.recv())
+            .await
+            .expect("event should be received")
+            .expect("watcher should be running")
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn runs_standalone() {
+        let bus = TestBus::start();
+        let first = StatusNotifierWatcher::new()
+            .name_flags([RequestNameFlags::AllowReplacement])
+            .run_on(bus.connect().await)
+            .await
+            .expect("watcher should start");
+        assert!(first.is_owner().await.expect("bus should be queried"));
+
+        let mut rx = first.subscribe();
+        let item = bus.connect().await;
+        let name = item.unique_name().expect("connection should be on a bus");
+        register_item(&item, name).await;
+        assert_eq!(
+            recv_event(&mut rx).await,
+            WatcherEvent::ItemRegistered(format!("{name}/StatusNotifierItem"))
+        );
+
+        // the first watcher queues behind one which takes over,
+        // and gets the name back once that stops
+        let second = StatusNotifierWatcher::new()
+            .name_flags([RequestNameFlags::ReplaceExisting])
+            .run_on(bus.connect().await)
+            .await
+            .expect("watcher should start");
+        assert!(second.is_owner().await.expect("bus should be queried"));
+        assert!(!first.is_owner().await.expect("bus should be queried"));
+
+        second.stop().await.expect("watcher should stop");
+        wait_for_owner(&first, true).await;
+
+        let connection = first.connection().clone();
+        first.stop().await.expect("watcher should stop");
+        let dbus = zbus::fdo::DBusProxy::new(&connection)
+            .await
+            .expect("proxy should be created");
+        assert!(!dbus
+            .name_has_owner(names::WATCHER_BUS.try_into().expect("name should be valid"))
+            .await
+            .expect("bus should be queried"));
+    }
+
+    /// Gets the unique name of the owner of `name`, if any.
+    async fn name_owner(connection: &Connection, name: &str) -> Option<String> {
+        let dbus = zbus::fdo::DBusProxy::new(connection)
+            .await
+            .expect("proxy should be created");
+        dbus.get_name_owner(name.try_into().expect("name should be valid"))
+            .await
+            .ok()
+            .map(|owner| owner.to_string())
+    }
+
+    /// Gets the items registered with the watcher owning the name.
+    async fn registered_items(connection: &Connection) -> Vec<String> {
+        connection
+            .call_method(
+                Some(names::WATCHER_BUS),
+                names::WATCHER_OBJECT,
+                Some("org.freedesktop.DBus.Properties"),
+                "Get",
+                &(names::WATCHER_BUS, "RegisteredStatusNotifierItems"),
+            )
+            .await
+            .expect("property should be read")
+            .body::<zbus::zvariant::OwnedValue>()
+            .expect("reply should be a variant")
+            .try_into()
+            .expect("property should be a list of strings")
+    }
+
+    /// Unregisters `service` from the watcher owning the name.
+    async fn unregister_item(connection: &Connection, service: &str) -> zbus::Result<()> {
+        connection
+            .call_method(
+                Some(names::WATCHER_BUS),
+                names::WATCHER_OBJECT,
+                Some(names::WATCHER_BUS),
+                "UnregisterStatusNotifierItem",
+                &(service,),
+            )
+            .await
+            .map(|_| ())
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn only_owner_unregisters_item() {
+        let bus = TestBus::start();
+        let watcher = StatusNotifierWatcher::new()
+            .run_on(bus.connect().await)
+            .await
+            .expect("watcher should start");
+        let mut rx = watcher.subscribe();
+
+        let item = bus.connect().await;
+        let name = item.unique_name().expect("connection should be on a bus");
+        register_item(&item, name).await;
+        let address = format!("{name}/StatusNotifierItem");
+        assert_eq!(
+            recv_event(&mut rx).await,
+            WatcherEvent::ItemRegistered(address.clone())
+        );
+
+        let other = bus.connect().await;
+        let denied = unregister_item(&other, name).await;
+        assert!(matches!(
+            denied,
+            Err(zbus::Error::MethodError(name, _, _))
+                if name == "org.freedesktop.DBus.Error.AccessDenied"
+        ));
+        assert_eq!(registered_items(&other).await, [address.as_str()]);
+
+        unregister_item(&item, name)
+            .await
+            .expect("item should unregister");
+        assert_eq!(
+            recv_event(&mut rx).await,
+            WatcherEvent::ItemUnregistered(address)
+        );
+        assert!(registered_items(&other).await.is_empty());
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn serves_freedesktop_alias() {
+        let bus = TestBus::start();
+        let watcher = StatusNotifierWatcher::new()
+            .run_on(bus.connect().await)
+            .await
+            .expect("watcher should start");
+        let owner = watcher.connection().unique_name().map(ToString::to_string);
+
+        let item = bus.connect().await;
+        assert_eq!(name_owner(&item, names::WATCHER_BUS).await, owner);
+        assert_eq!(
+            name_owner(&item, names::WATCHER_BUS_FREEDESKTOP).await,
+            owner
+        );
+
+        // items registering under either name are tracked together
+        let mut rx = watcher.subscribe();
+        let name = item.unique_name().expect("connection should be on a bus");
+        item.call_method(
+            Some(names::WATCHER_BUS_FREEDESKTOP),
+            names::WATCHER_OBJECT,
+            Some(names::WATCHER_BUS_FREEDESKTOP),
+            "RegisterStatusNotifierItem",
+            &(name.as_str(),),
+        )
+        .await
+        .expect("item should register");
+
+        let address = format!("{name}/StatusNotifierItem");
+        assert_eq!(
+            recv_event(&mut rx).await,
+            WatcherEvent::ItemRegistered(address.clone())
+        );
+
+        assert_eq!(registered_items(&item).await, [address]);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn disables_freedesktop_alias() {
+        let bus = TestBus::start();
+        let watcher = StatusNotifierWatcher::new()
+            .freedesktop_alias(false)
+            .run_on(bus.connect().await)
+            .await
+            .expect("watcher should start");
+
+        let connection = watcher.connection();
+        assert_eq!(
+            name_owner(connection, names::WATCHER_BUS).await,
+            connection.unique_name().map(ToString::to_string)
+        );
+        assert_eq!(
+            name_owner(connection, names::WATCHER_BUS_FREEDESKTOP).await,
+            None
+        );
+        assert!(connection
+            .object_server()
+            .interface::<_, FreedesktopWatcher>(names::WATCHER_OBJECT)
+            .await
+            .is_err());
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn rejects_registration_denied_by_policy() {
+        let bus = TestBus::start();
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let policy = AdmissionPolicy::new({
+            let seen = seen.clone();
+            move |registration| {
+                seen.lock()
+                    .expect("mutex lock should succeed")
+                    .push((registration.kind, registration.owner.to_string()));
+                false
+            }
+        });
+        let watcher = StatusNotifierWatcher::with_policy(policy)
+            .run_on(bus.connect().await)
+            .await
+            .expect("watcher should start");
+        let mut rx = watcher.subscribe();
+
+        let item = bus.connect().await;
+        let name = item.unique_name().expect("connection should be on a bus");
+        let denied = item
+            .call_method(
+                Some(names::WATCHER_BUS),
+                names::WATCHER_OBJECT,
+                Some(names::WATCHER_BUS),
+                "RegisterStatusNotifierItem",
+                &(name.as_str(),),
+            )
+            .await;
+        assert!(matches!(
+            denied,
+            Err(zbus::Error::MethodError(error, _, _))
+                if error == "org.freedesktop.DBus.Error.AccessDenied"
+        ));
+
+        assert_eq!(
+            *seen.lock().expect("mutex lock should succeed"),
+            [(RegistrationKind::Item, name.to_string())]
+        );
+        assert!(registered_items(&item).await.is_empty());
+        assert!(rx.try_recv().is_err());
+    }
+}
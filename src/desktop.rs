@@ -0,0 +1,264 @@
+use std::collections::HashSet;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// The parts of a freedesktop `.desktop` file relevant to displaying an item.
+/// <https://specifications.freedesktop.org/desktop-entry-spec/latest/>
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DesktopEntry {
+    /// The desktop file ID, which is the file name without the `.desktop` extension.
+    pub id: String,
+    /// The location of the desktop file.
+    pub path: PathBuf,
+    /// The specific name of the application, such as "Mozilla".
+    pub name: Option<String>,
+    /// The themed icon name, or an absolute path to an icon file.
+    pub icon: Option<String>,
+    /// The command used to launch the application.
+    pub exec: Option<String>,
+    /// The window class the application is known to map windows with.
+    pub startup_wm_class: Option<String>,
+    /// Whether the entry is marked as deleted,
+    /// which hides any entry with the same ID later in the search path.
+    pub hidden: bool,
+}
+
+impl DesktopEntry {
+    /// Parses the `[Desktop Entry]` group of a desktop file.
+    ///
+    /// Returns `None` if the group is missing.
+    /// Localized keys are ignored.
+    #[must_use]
+    pub fn parse(path: &Path, contents: &str) -> Option<Self> {
+        let id = path.file_stem()?.to_string_lossy().to_string();
+
+        let mut entry = Self {
+            id,
+            path: path.to_path_buf(),
+            name: None,
+            icon: None,
+            exec: None,
+            startup_wm_class: None,
+            hidden: false,
+        };
+
+        let mut in_group = false;
+        let mut found_group = false;
+
+        for line in contents.lines().map(str::trim) {
+            if line.starts_with('[') {
+                in_group = line == "[Desktop Entry]";
+                found_group |= in_group;
+                continue;
+            }
+
+            if !in_group {
+                continue;
+            }
+
+            let Some((key, value)) = line.split_once('=') else {
+                continue;
+            };
+
+            let value = value.trim().to_string();
+            match key.trim() {
+                "Name" => entry.name = Some(value),
+                "Icon" => entry.icon = Some(value),
+                "Exec" => entry.exec = Some(value),
+                "StartupWMClass" => entry.startup_wm_class = Some(value),
+                "Hidden" => entry.hidden = value == "true",
+                _ => {}
+            }
+        }
+
+        found_group.then_some(entry)
+    }
+}
+
+/// An index of the desktop files installed on the system,
+/// used to resolve the application behind a tray item.
+#[derive(Debug, Clone, Default)]
+pub struct DesktopEntries {
+    entries: Vec<DesktopEntry>,
+}
+
+impl DesktopEntries {
+    /// Loads all desktop files from the `applications` directory
+    /// of each XDG data directory.
+    ///
+    /// Files in directories earlier in the search path take precedence,
+    /// so user entries override system ones,
+    /// and a user entry marked as hidden removes the system one.
+    ///
+    /// This reads every file synchronously,
+    /// so should not be called from async code without moving it to a blocking thread.
+    #[must_use]
+    pub fn load() -> Self {
+        Self::load_from(&data_dirs())
+    }
+
+    fn load_from(data_dirs: &[PathBuf]) -> Self {
+        let mut entries = vec![];
+        let mut seen = HashSet::new();
+
+        for dir in data_dirs {
+            let mut files = vec![];
+            collect_desktop_files(&dir.join("applications"), &mut files);
+
+            for path in files {
+                let Ok(contents) = fs::read_to_string(&path) else {
+                    continue;
+                };
+
+                let Some(entry) = DesktopEntry::parse(&path, &contents) else {
+                    continue;
+                };
+
+                // hidden entries are not kept, but still shadow later ones
+                if seen.insert(entry.id.clone()) && !entry.hidden {
+                    entries.push(entry);
+                }
+            }
+        }
+
+        Self { entries }
+    }
+
+    /// Finds the desktop entry best matching an item's `Id` and `Title`.
+    ///
+    /// Matches are tried in order of confidence:
+    ///
+    /// - The desktop file ID equals `id`, or ends with `.{id}` (for reverse-DNS IDs).
+    /// - The `StartupWMClass` equals `id`.
+    /// - The `Name` equals `id` or `title`.
+    ///
+    /// All comparisons are case-insensitive.
+    #[must_use]
+    pub fn find(&self, id: &str, title: Option<&str>) -> Option<&DesktopEntry> {
+        let eq = |a: &str, b: &str| a.eq_ignore_ascii_case(b);
+
+        let by_id = |entry: &&DesktopEntry| {
+            eq(&entry.id, id)
+                || entry
+                    .id
+                    .rsplit_once('.')
+                    .is_some_and(|(_, suffix)| eq(suffix, id))
+        };
+
+        let by_class = |entry: &&DesktopEntry| {
+            entry
+                .startup_wm_class
+                .as_deref()
+                .is_some_and(|class| eq(class, id))
+        };
+
+        let by_name = |entry: &&DesktopEntry| {
+            entry
+                .name
+                .as_deref()
+                .is_some_and(|name| eq(name, id) || title.is_some_and(|title| eq(name, title)))
+        };
+
+        self.entries
+            .iter()
+            .find(by_id)
+            .or_else(|| self.entries.iter().find(by_class))
+            .or_else(|| self.entries.iter().find(by_name))
+    }
+}
+
+/// Gets the XDG data directories in order of precedence.
+pub(crate) fn data_dirs() -> Vec<PathBuf> {
+    let data_home = env::var_os("XDG_DATA_HOME")
+        .map(PathBuf::from)
+        .filter(|path| path.is_absolute())
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".local/share")));
+
+    let data_dirs = env::var("XDG_DATA_DIRS")
+        .ok()
+        .filter(|dirs| !dirs.is_empty())
+        .unwrap_or_else(|| String::from("/usr/local/share:/usr/share"));
+
+    data_home
+        .into_iter()
+        .chain(data_dirs.split(':').map(PathBuf::from))
+        .collect()
+}
+
+fn collect_desktop_files(dir: &Path, files: &mut Vec<PathBuf>) {
+    let Ok(read_dir) = fs::read_dir(dir) else {
+        return;
+    };
+
+    for entry in read_dir.flatten() {
+        let path = entry.path();
+        if path.is_dir() {
+            collect_desktop_files(&path, files);
+        } else if path.extension().is_some_and(|ext| ext == "desktop") {
+            files.push(path);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FIREFOX: &str = "[Desktop Entry]
+Name=Firefox
+Name[de]=Feuerfuchs
+Icon=firefox
+Exec=firefox %u
+
+[Desktop Action new-window]
+Name=New Window
+";
+
+    #[test]
+    fn parse_entry() {
+        let entry = DesktopEntry::parse(Path::new("/apps/org.mozilla.firefox.desktop"), FIREFOX)
+            .expect("entry should parse");
+
+        assert_eq!(entry.id, "org.mozilla.firefox");
+        assert_eq!(entry.name.as_deref(), Some("Firefox"));
+        assert_eq!(entry.icon.as_deref(), Some("firefox"));
+        assert_eq!(entry.exec.as_deref(), Some("firefox %u"));
+    }
+
+    #[test]
+    fn find_by_reverse_dns_id() {
+        let entries = DesktopEntries {
+            entries: vec![DesktopEntry::parse(
+                Path::new("/apps/org.mozilla.firefox.desktop"),
+                FIREFOX,
+            )
+            .expect("entry should parse")],
+        };
+
+        assert!(entries.find("Firefox", None).is_some());
+        assert!(entries.find("chromium", Some("Chromium")).is_none());
+    }
+
+    #[test]
+    fn hidden_entries_shadow_later_ones() {
+        let root = env::temp_dir().join(format!("system-tray-desktop-{}", std::process::id()));
+        let (user, system) = (root.join("user"), root.join("system"));
+
+        for (dir, contents) in [
+            (&user, "[Desktop Entry]\nName=Firefox\nHidden=true\n"),
+            (&system, FIREFOX),
+        ] {
+            let apps = dir.join("applications");
+            fs::create_dir_all(&apps).expect("directory should be created");
+            fs::write(apps.join("firefox.desktop"), contents).expect("file should be written");
+        }
+
+        let shadowed = DesktopEntries::load_from(&[user, system.clone()]);
+        let unshadowed = DesktopEntries::load_from(&[system]);
+        let _ = fs::remove_dir_all(&root);
+
+        assert!(shadowed.find("firefox", None).is_none());
+        assert!(unshadowed.find("firefox", None).is_some());
+    }
+}
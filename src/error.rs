@@ -4,18 +4,65 @@ use tokio::sync::broadcast::error::SendError;
 
 pub type Result<T> = std::result::Result<T, Error>;
 
+/// An error from the client or one of its items.
+///
+/// Some variants only exist when their feature is enabled,
+/// so matches on this must include a wildcard arm
+/// to keep the features additive.
 #[derive(Error, Debug)]
+#[non_exhaustive]
 pub enum Error {
     #[error("dbus properties missing one or more required fields")]
     MissingProperty(&'static str),
     #[error("failed to send event through tokio broadcast channel")]
     EventSend(#[from] SendError<Event>),
-    #[error("zbus error")]
+    #[error("zbus error: {0}")]
     ZBus(#[from] zbus::Error),
-    #[error("zbus fdo error")]
+    #[error("zbus fdo error: {0}")]
     ZBusFdo(#[from] zbus::fdo::Error),
     #[error("zbus variant error")]
     ZBusVariant(#[from] zbus::zvariant::Error),
     #[error("invalid data error")]
     InvalidData(&'static str),
+    #[error("item sent invalid properties: {}", .0.join(", "))]
+    InvalidProperties(Vec<&'static str>),
+    #[error("timed out waiting for reply")]
+    Timeout,
+    #[error("too many requests are already queued for this item")]
+    QueueFull,
+    #[error("the client has stopped")]
+    ClientStopped,
+    #[error("no item exists at '{0}'")]
+    UnknownItem(String),
+    #[error("'{0}' can only be set when the client is created")]
+    CreationOnlyOption(&'static str),
+    #[error("activation failed: {0}")]
+    Activation(#[from] ActivationError),
+    #[cfg(feature = "image")]
+    #[error("image error: {0}")]
+    Image(#[from] image::ImageError),
+    #[cfg(feature = "dbusmenu-gtk3")]
+    #[error("glib error: {0}")]
+    Glib(#[from] gtk::glib::Error),
+    #[error("io error: {0}")]
+    Io(#[from] std::io::Error),
+}
+
+/// The reason an item failed to handle an activation request.
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum ActivationError {
+    /// The item is no longer on the bus.
+    #[error("the item has gone away")]
+    ServiceGone,
+    /// The item does not implement the requested activation,
+    /// for example if it has no secondary activation or no menu.
+    #[error("the item does not support this activation")]
+    MethodUnsupported,
+    /// The item did not reply in time.
+    #[error("the item did not reply in time")]
+    Timeout,
+    /// The item replied with an error.
+    /// Contains the error name and message sent by the item.
+    #[error("the item rejected the activation: {0}")]
+    Rejected(String),
 }
@@ -0,0 +1,199 @@
+use crate::client::{Client, ClientBuilder, Event};
+use crate::error::{Error, Result};
+use std::future::Future;
+use std::thread;
+use tokio::runtime::{Builder, Handle};
+use tokio::sync::oneshot;
+use tracing::error;
+
+/// A [`Client`] for applications driven by a glib main loop, such as GTK bars.
+///
+/// The client needs Tokio, so it runs on a runtime on its own background thread,
+/// while its events are delivered to handlers on the default glib `MainContext`.
+/// This removes the need for applications to set up a runtime and bridge channels themselves.
+///
+/// The background thread stops when this is dropped.
+///
+/// # Example
+///
+/// ```no_run
+/// use system_tray::client::Client;
+/// use system_tray::glib_client::GlibClient;
+///
+/// glib::MainContext::default().spawn_local(async {
+///     let client = GlibClient::new(Client::builder()).await.unwrap();
+///
+///     client.connect_event(|event| {
+///         println!("{event:?}"); // update widgets...
+///     });
+/// });
+/// ```
+#[derive(Debug)]
+pub struct GlibClient {
+    client: Client,
+    runtime: Handle,
+    /// Stops the background thread when dropped.
+    _shutdown: oneshot::Sender<()>,
+}
+
+impl GlibClient {
+    /// Creates a client using the options in `builder`,
+    /// starting it on a new background thread.
+    ///
+    /// The returned future can be awaited on any executor,
+    /// including a glib `MainContext`.
+    ///
+    /// # Errors
+    ///
+    /// If the background thread cannot be started,
+    /// or the client cannot be created.
+    /// See [`ClientBuilder::build`].
+    pub async fn new(builder: ClientBuilder) -> Result<Self> {
+        let (ready_tx, ready_rx) = oneshot::channel();
+        let (shutdown_tx, shutdown_rx) = oneshot::channel::<()>();
+
+        let runtime = Builder::new_current_thread().enable_all().build()?;
+        let handle = runtime.handle().clone();
+
+        thread::Builder::new()
+            .name(String::from("system-tray"))
+            .spawn(move || {
+                runtime.block_on(async move {
+                    let client = match builder.build().await {
+                        Ok(client) => client,
+                        Err(err) => {
+                            let _ = ready_tx.send(Err(err));
+                            return;
+                        }
+                    };
+
+                    if ready_tx.send(Ok(client.clone())).is_err() {
+                        return;
+                    }
+
+                    // the sender is dropped along with the glib client
+                    let _ = shutdown_rx.await;
+
+                    // this also stops the client for futures started by `run`
+                    if let Err(err) = client.shutdown().await {
+                        error!("failed to shut down client: {err}");
+                    }
+                });
+            })?;
+
+        // the thread only drops the sender without replying if it panicked
+        let client = ready_rx.await.map_err(|_| Error::ClientStopped)??;
+
+        Ok(Self {
+            client,
+            runtime: handle,
+            _shutdown: shutdown_tx,
+        })
+    }
+
+    /// Gets the client.
+    ///
+    /// Methods which do not need Tokio, such as [`Client::items`], can be called directly.
+    /// Async methods should be called through [`GlibClient::run`] instead.
+    #[must_use]
+    pub fn client(&self) -> &Client {
+        &self.client
+    }
+
+    /// Runs a future using the client on its background thread,
+    /// returning a future which can be awaited on any executor,
+    /// including a glib `MainContext`.
+    ///
+    /// The returned future resolves to `None` if the client stopped before `f` completed.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # use system_tray::client::ActivateRequest;
+    /// # async fn f(client: system_tray::glib_client::GlibClient, request: ActivateRequest) {
+    /// let res = client
+    ///     .run(|client| async move { client.activate(request).await })
+    ///     .await;
+    /// # }
+    /// ```
+    pub fn run<F, T>(&self, f: impl FnOnce(Client) -> F) -> impl Future<Output = Option<T>>
+    where
+        F: Future<Output = T> + Send + 'static,
+        T: Send + 'static,
+    {
+        let task = self.runtime.spawn(f(self.client.clone()));
+        async move { task.await.ok() }
+    }
+
+    /// Calls `handler` with every event, on the default glib `MainContext`.
+    ///
+    /// Events are received as with [`Client::subscribe_resync`],
+    /// so a handler which falls behind is called with an [`Event::Resync`].
+    /// The handler is called until the returned handle is aborted,
+    /// or the client stops.
+    ///
+    /// # Panics
+    ///
+    /// If called from a thread other than the one which owns the default `MainContext`,
+    /// which is usually the main thread.
+    pub fn connect_event(&self, mut handler: impl FnMut(&Event) + 'static) -> glib::JoinHandle<()> {
+        let mut rx = self.client.subscribe_resync();
+
+        glib::MainContext::default().spawn_local(async move {
+            while let Ok(event) = rx.recv().await {
+                handler(&event);
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_bus::{serve_item_on, MockItem, TestBus};
+    use std::time::Duration;
+    use tokio::sync::mpsc;
+    use zbus::ConnectionBuilder;
+
+    #[test]
+    fn delivers_events_on_main_context() {
+        let bus = TestBus::start();
+        let address = bus.address().to_string();
+        let context = glib::MainContext::default();
+
+        context.block_on(async {
+            // awaited on the main context, without a tokio runtime
+            let client = GlibClient::new(bus.builder())
+                .await
+                .expect("client should start");
+
+            let (tx, mut rx) = mpsc::unbounded_channel();
+            let _handle = client.connect_event(move |event| {
+                let _ = tx.send(event.clone());
+            });
+
+            let _item = client
+                .run(|_| async move {
+                    let connection = ConnectionBuilder::address(address.as_str())
+                        .expect("address should be valid")
+                        .build()
+                        .await
+                        .expect("connection should succeed");
+                    serve_item_on(&connection, MockItem::new("glib")).await;
+                    connection
+                })
+                .await
+                .expect("client should be running");
+
+            glib::future_with_timeout(Duration::from_secs(5), async {
+                while let Some(event) = rx.recv().await {
+                    if matches!(&event, Event::Add(_, item) if item.id == "glib") {
+                        return;
+                    }
+                }
+            })
+            .await
+            .expect("item should be added");
+        });
+    }
+}
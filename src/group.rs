@@ -0,0 +1,289 @@
+use crate::client::{Event, Snapshot};
+use crate::item::StatusNotifierItem;
+use std::collections::HashMap;
+
+/// A change to the groups tracked by [`ItemGroups`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GroupEvent {
+    /// The first item for an application was added,
+    /// creating a new group.
+    Add { key: String, address: String },
+    /// An item was added to or removed from an existing group.
+    /// The new number of items in the group is sent,
+    /// along with the address of its representative,
+    /// which changes if the previous representative was removed.
+    Count {
+        key: String,
+        count: usize,
+        representative: String,
+    },
+    /// The last item for an application was removed.
+    Remove { key: String },
+}
+
+/// Clusters items belonging to the same application.
+///
+/// Some applications register one item per window.
+/// Feeding client events through this allows bars to render one icon per application,
+/// optionally with a count, instead of one icon per item.
+///
+/// Items are grouped by their `Id`, compared case-insensitively.
+/// The first item added to a group is considered its representative.
+///
+/// ```no_run
+/// # use system_tray::client::Client;
+/// # use system_tray::group::ItemGroups;
+/// # async fn run(client: Client) {
+/// let mut groups = ItemGroups::new();
+/// let mut tray_rx = client.subscribe();
+///
+/// while let Ok(ev) = tray_rx.recv().await {
+///     for group_ev in groups.handle(&ev) {
+///         println!("{group_ev:?}");
+///     }
+/// }
+/// # }
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct ItemGroups {
+    /// Map of group keys to item addresses, in the order they were added.
+    members: HashMap<String, Vec<String>>,
+    /// Map of item addresses to group keys.
+    keys: HashMap<String, String>,
+}
+
+impl ItemGroups {
+    /// Creates an empty set of groups.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Gets the key used to group an item.
+    #[must_use]
+    pub fn key_for(item: &StatusNotifierItem) -> String {
+        item.id.to_lowercase()
+    }
+
+    /// Updates the groups from a client event,
+    /// returning the resulting changes in order.
+    ///
+    /// An item re-added with a different id moves between groups,
+    /// so changes both its old and new group.
+    /// A resync rebuilds the groups from its snapshot.
+    pub fn handle(&mut self, event: &Event) -> Vec<GroupEvent> {
+        match event {
+            Event::Add(address, item) => self.add(address, Self::key_for(item)),
+            Event::Remove(address) => self.remove(address).into_iter().collect(),
+            Event::Resync(snapshot) => self.resync(snapshot),
+            _ => vec![],
+        }
+    }
+
+    /// Gets the key of the group an item belongs to.
+    #[must_use]
+    pub fn group_of(&self, address: &str) -> Option<&str> {
+        self.keys.get(address).map(String::as_str)
+    }
+
+    /// Gets the addresses of all items in a group,
+    /// with the representative item first.
+    #[must_use]
+    pub fn members(&self, key: &str) -> &[String] {
+        self.members.get(key).map_or(&[], Vec::as_slice)
+    }
+
+    /// Replaces every item with those in a snapshot,
+    /// keeping the representatives of groups which are still present.
+    fn resync(&mut self, snapshot: &Snapshot) -> Vec<GroupEvent> {
+        let items = &snapshot.items;
+        let mut removed: Vec<_> = self
+            .keys
+            .keys()
+            .filter(|address| !items.contains_key(*address))
+            .cloned()
+            .collect();
+        removed.sort_unstable();
+
+        let mut events: Vec<_> = removed
+            .iter()
+            .filter_map(|address| self.remove(address))
+            .collect();
+
+        let mut addresses: Vec<_> = items.keys().collect();
+        addresses.sort_unstable();
+
+        for address in addresses {
+            let (item, _) = &items[address];
+            events.extend(self.add(address, Self::key_for(item)));
+        }
+
+        events
+    }
+
+    fn add(&mut self, address: &str, key: String) -> Vec<GroupEvent> {
+        if self.group_of(address) == Some(key.as_str()) {
+            return vec![];
+        }
+
+        // the item was re-added with a different id,
+        // so move it out of its old group first
+        let mut events: Vec<_> = self.remove(address).into_iter().collect();
+
+        self.keys.insert(address.to_string(), key.clone());
+        let members = self.members.entry(key.clone()).or_default();
+        members.push(address.to_string());
+
+        events.push(if members.len() == 1 {
+            GroupEvent::Add {
+                key,
+                address: address.to_string(),
+            }
+        } else {
+            GroupEvent::Count {
+                count: members.len(),
+                representative: members[0].clone(),
+                key,
+            }
+        });
+
+        events
+    }
+
+    fn remove(&mut self, address: &str) -> Option<GroupEvent> {
+        let key = self.keys.remove(address)?;
+        let members = self.members.get_mut(&key)?;
+        members.retain(|member| member != address);
+
+        if members.is_empty() {
+            self.members.remove(&key);
+            Some(GroupEvent::Remove { key })
+        } else {
+            Some(GroupEvent::Count {
+                count: members.len(),
+                representative: members[0].clone(),
+                key,
+            })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+    use std::sync::Arc;
+
+    #[test]
+    fn groups_by_key() {
+        let mut groups = ItemGroups::new();
+
+        assert_eq!(
+            groups.add(":1.1", String::from("app")),
+            [GroupEvent::Add {
+                key: String::from("app"),
+                address: String::from(":1.1")
+            }]
+        );
+        assert_eq!(
+            groups.add(":1.2", String::from("app")),
+            [GroupEvent::Count {
+                key: String::from("app"),
+                count: 2,
+                representative: String::from(":1.1")
+            }]
+        );
+        assert_eq!(groups.members("app"), [":1.1", ":1.2"]);
+
+        // removing the representative promotes the next item
+        assert_eq!(
+            groups.remove(":1.1"),
+            Some(GroupEvent::Count {
+                key: String::from("app"),
+                count: 1,
+                representative: String::from(":1.2")
+            })
+        );
+        assert_eq!(
+            groups.remove(":1.2"),
+            Some(GroupEvent::Remove {
+                key: String::from("app")
+            })
+        );
+        assert!(groups.members("app").is_empty());
+    }
+
+    #[test]
+    fn moves_rekeyed_items() {
+        let mut groups = ItemGroups::new();
+        groups.add(":1.1", String::from("old"));
+        groups.add(":1.2", String::from("new"));
+
+        assert_eq!(
+            groups.add(":1.1", String::from("new")),
+            [
+                GroupEvent::Remove {
+                    key: String::from("old")
+                },
+                GroupEvent::Count {
+                    key: String::from("new"),
+                    count: 2,
+                    representative: String::from(":1.2")
+                }
+            ]
+        );
+        assert!(groups.members("old").is_empty());
+        assert_eq!(groups.group_of(":1.1"), Some("new"));
+
+        // re-adding with the same id changes nothing
+        assert!(groups.add(":1.1", String::from("new")).is_empty());
+    }
+
+    #[test]
+    fn resyncs_from_snapshot() {
+        let mut groups = ItemGroups::new();
+        groups.add(":1.1", String::from("app"));
+        groups.add(":1.2", String::from("app"));
+        groups.add(":1.3", String::from("gone"));
+
+        let item = |id: &str| {
+            (
+                StatusNotifierItem {
+                    id: id.to_string(),
+                    ..Default::default()
+                },
+                None,
+            )
+        };
+        let snapshot = Snapshot {
+            generation: 0,
+            items: [(":1.2", item("App")), (":1.4", item("new"))]
+                .into_iter()
+                .map(|(address, item)| (address.to_string(), item))
+                .collect(),
+            timestamps: HashMap::new(),
+            evicted: HashSet::new(),
+        };
+
+        assert_eq!(
+            groups.handle(&Event::Resync(Arc::new(snapshot))),
+            [
+                GroupEvent::Count {
+                    key: String::from("app"),
+                    count: 1,
+                    representative: String::from(":1.2")
+                },
+                GroupEvent::Remove {
+                    key: String::from("gone")
+                },
+                GroupEvent::Add {
+                    key: String::from("new"),
+                    address: String::from(":1.4")
+                }
+            ]
+        );
+        assert_eq!(groups.members("app"), [":1.2"]);
+        assert!(groups.members("gone").is_empty());
+        assert_eq!(groups.group_of(":1.4"), Some("new"));
+    }
+}
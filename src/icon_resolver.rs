@@ -0,0 +1,570 @@
+use crate::desktop::data_dirs;
+use crate::item::StatusNotifierItem;
+use crate::menu::{MenuItem, TrayMenu};
+use std::collections::{HashMap, HashSet};
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+/// The file extensions icons are looked up with, in order of preference.
+const EXTENSIONS: &[&str] = &["png", "svg", "xpm"];
+
+/// The theme every other theme implicitly inherits from.
+const FALLBACK_THEME: &str = "hicolor";
+
+/// Resolves icon names to files,
+/// following the freedesktop icon theme specification.
+/// <https://specifications.freedesktop.org/icon-theme-spec/latest/>
+///
+/// Parsed themes and lookup results are cached,
+/// so a single resolver should be shared for the lifetime of the tray.
+/// Use [`IconResolver::clear_cache`] if icons are installed or the theme changes.
+///
+/// # Example
+///
+/// ```no_run
+/// use system_tray::icon_resolver::IconResolver;
+///
+/// let resolver = IconResolver::new("Adwaita");
+/// let path = resolver.resolve("network-wireless", 24, &[] as &[&str]);
+/// ```
+#[derive(Debug)]
+pub struct IconResolver {
+    theme: String,
+    scale: u32,
+    base_dirs: Vec<PathBuf>,
+    fallback_dirs: Vec<PathBuf>,
+    themes: Mutex<HashMap<String, Option<Arc<Theme>>>>,
+    cache: Mutex<HashMap<CacheKey, Option<PathBuf>>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct CacheKey {
+    name: String,
+    size: u32,
+    extra_dirs: Vec<PathBuf>,
+}
+
+impl IconResolver {
+    /// Creates a resolver looking icons up in `theme`,
+    /// then its parent themes, then the `hicolor` theme,
+    /// searching the standard XDG icon directories.
+    #[must_use]
+    pub fn new(theme: impl Into<String>) -> Self {
+        let home = env::var_os("HOME").map(PathBuf::from);
+        let data_dirs = data_dirs();
+
+        let base_dirs = home
+            .iter()
+            .map(|home| home.join(".icons"))
+            .chain(data_dirs.iter().map(|dir| dir.join("icons")))
+            .collect();
+
+        let fallback_dirs = data_dirs.iter().map(|dir| dir.join("pixmaps")).collect();
+
+        Self::with_dirs(theme, base_dirs, fallback_dirs)
+    }
+
+    /// Creates a resolver looking icons up in `theme`,
+    /// searching only the given directories.
+    ///
+    /// Themes are looked up in `base_dirs`,
+    /// with icons which are not part of any theme looked up in `fallback_dirs`.
+    #[must_use]
+    pub fn with_dirs(
+        theme: impl Into<String>,
+        base_dirs: Vec<PathBuf>,
+        fallback_dirs: Vec<PathBuf>,
+    ) -> Self {
+        Self {
+            theme: theme.into(),
+            scale: 1,
+            base_dirs,
+            fallback_dirs,
+            themes: Mutex::default(),
+            cache: Mutex::default(),
+        }
+    }
+
+    /// Sets the scale factor of the display icons are shown on,
+    /// so that icons made for high DPI displays are preferred.
+    #[must_use]
+    pub fn scale(mut self, scale: u32) -> Self {
+        self.scale = scale.max(1);
+        self
+    }
+
+    /// Resolves an icon name to the file closest to `size` pixels.
+    ///
+    /// Each of `extra_dirs` is checked first,
+    /// both for icon files directly inside it and as a directory of icon themes.
+    /// These are typically an item's [`icon_theme_path`](StatusNotifierItem::icon_theme_path).
+    ///
+    /// Names which are absolute paths are returned as-is if the file exists.
+    ///
+    /// # Panics
+    ///
+    /// If a cache mutex is poisoned.
+    pub fn resolve(
+        &self,
+        name: &str,
+        size: u32,
+        extra_dirs: &[impl AsRef<Path>],
+    ) -> Option<PathBuf> {
+        if name.is_empty() {
+            return None;
+        }
+
+        let path = Path::new(name);
+        if path.is_absolute() {
+            return path.is_file().then(|| path.to_path_buf());
+        }
+
+        let key = CacheKey {
+            name: name.to_string(),
+            size,
+            extra_dirs: extra_dirs
+                .iter()
+                .map(|dir| dir.as_ref().to_path_buf())
+                .filter(|dir| !dir.as_os_str().is_empty())
+                .collect(),
+        };
+
+        if let Some(path) = self
+            .cache
+            .lock()
+            .expect("mutex lock should succeed")
+            .get(&key)
+        {
+            return path.clone();
+        }
+
+        let path = self.lookup(&key);
+
+        self.cache
+            .lock()
+            .expect("mutex lock should succeed")
+            .insert(key, path.clone());
+
+        path
+    }
+
+    /// Resolves the icon an item should currently display.
+    /// See [`StatusNotifierItem::effective_icon`].
+    ///
+    /// Returns `None` if the item only provides a pixmap.
+    ///
+    /// # Panics
+    ///
+    /// If a cache mutex is poisoned.
+    pub fn resolve_item(
+        &self,
+        item: &StatusNotifierItem,
+        size: u32,
+        prefer_attention: bool,
+    ) -> Option<PathBuf> {
+        let name = item.effective_icon(prefer_attention).name?;
+        self.resolve(name, size, item.icon_theme_path.as_slice())
+    }
+
+    /// Resolves the icon of a menu item,
+    /// using the menu's [`icon_theme_path`](TrayMenu::icon_theme_path).
+    ///
+    /// Returns `None` if the item has no icon name,
+    /// such as when it only provides [`icon_data`](MenuItem::icon_data).
+    ///
+    /// # Panics
+    ///
+    /// If a cache mutex is poisoned.
+    pub fn resolve_menu_item(
+        &self,
+        item: &MenuItem,
+        menu: &TrayMenu,
+        size: u32,
+    ) -> Option<PathBuf> {
+        let name = item.icon_name.as_deref()?;
+        self.resolve(name, size, &menu.icon_theme_path)
+    }
+
+    /// Resolves an icon name as with [`IconResolver::resolve`], and decodes it.
+    ///
+    /// Only PNG icons can be decoded,
+    /// so `None` is returned if the closest icon is in another format.
+    ///
+    /// # Panics
+    ///
+    /// If a cache mutex is poisoned.
+    #[cfg(feature = "image")]
+    pub fn load(
+        &self,
+        name: &str,
+        size: u32,
+        extra_dirs: &[impl AsRef<Path>],
+    ) -> Option<image::DynamicImage> {
+        let path = self.resolve(name, size, extra_dirs)?;
+        if path.extension().is_none_or(|ext| ext != "png") {
+            return None;
+        }
+
+        image::open(path).ok()
+    }
+
+    /// Forgets all parsed themes and resolved icons.
+    ///
+    /// # Panics
+    ///
+    /// If a cache mutex is poisoned.
+    pub fn clear_cache(&self) {
+        self.themes
+            .lock()
+            .expect("mutex lock should succeed")
+            .clear();
+        self.cache
+            .lock()
+            .expect("mutex lock should succeed")
+            .clear();
+    }
+
+    fn lookup(&self, key: &CacheKey) -> Option<PathBuf> {
+        let base_dirs = key
+            .extra_dirs
+            .iter()
+            .chain(&self.base_dirs)
+            .cloned()
+            .collect::<Vec<_>>();
+
+        find_in_dirs(&key.name, &key.extra_dirs)
+            .or_else(|| {
+                self.find_in_theme(
+                    &self.theme,
+                    &key.name,
+                    key.size,
+                    &base_dirs,
+                    &mut HashSet::new(),
+                )
+            })
+            .or_else(|| {
+                self.find_in_theme(
+                    FALLBACK_THEME,
+                    &key.name,
+                    key.size,
+                    &base_dirs,
+                    &mut HashSet::new(),
+                )
+            })
+            .or_else(|| find_in_dirs(&key.name, &self.fallback_dirs))
+    }
+
+    /// Looks an icon up in a theme and then its parents.
+    fn find_in_theme(
+        &self,
+        theme_name: &str,
+        name: &str,
+        size: u32,
+        base_dirs: &[PathBuf],
+        visited: &mut HashSet<String>,
+    ) -> Option<PathBuf> {
+        if !visited.insert(theme_name.to_string()) {
+            return None;
+        }
+
+        let theme = self.theme(theme_name)?;
+
+        theme
+            .lookup(theme_name, name, size, self.scale, base_dirs)
+            .or_else(|| {
+                theme
+                    .parents
+                    .iter()
+                    .find_map(|parent| self.find_in_theme(parent, name, size, base_dirs, visited))
+            })
+    }
+
+    /// Gets a parsed theme, loading its index from the first base directory which has one.
+    fn theme(&self, name: &str) -> Option<Arc<Theme>> {
+        let mut themes = self.themes.lock().expect("mutex lock should succeed");
+
+        themes
+            .entry(name.to_string())
+            .or_insert_with(|| {
+                self.base_dirs
+                    .iter()
+                    .find_map(|dir| fs::read_to_string(dir.join(name).join("index.theme")).ok())
+                    .map(|index| Arc::new(Theme::parse(&index)))
+            })
+            .clone()
+    }
+}
+
+/// Looks for an icon file directly inside any of `dirs`.
+fn find_in_dirs(name: &str, dirs: &[PathBuf]) -> Option<PathBuf> {
+    dirs.iter().find_map(|dir| {
+        EXTENSIONS
+            .iter()
+            .map(|ext| dir.join(format!("{name}.{ext}")))
+            .find(|path| path.is_file())
+    })
+}
+
+/// The parts of a theme's `index.theme` file needed to look icons up.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+struct Theme {
+    parents: Vec<String>,
+    directories: Vec<Directory>,
+}
+
+impl Theme {
+    fn parse(index: &str) -> Self {
+        let mut groups: HashMap<&str, HashMap<&str, &str>> = HashMap::new();
+        let mut group = None;
+
+        for line in index.lines().map(str::trim) {
+            if let Some(name) = line
+                .strip_prefix('[')
+                .and_then(|line| line.strip_suffix(']'))
+            {
+                group = Some(name);
+                continue;
+            }
+
+            if let (Some(group), Some((key, value))) = (group, line.split_once('=')) {
+                groups
+                    .entry(group)
+                    .or_default()
+                    .insert(key.trim(), value.trim());
+            }
+        }
+
+        let list = |value: Option<&&str>| {
+            value
+                .into_iter()
+                .flat_map(|value| value.split(','))
+                .map(str::trim)
+                .filter(|value| !value.is_empty())
+                .map(ToString::to_string)
+                .collect::<Vec<_>>()
+        };
+
+        let theme = groups.get("Icon Theme");
+        let parents = list(theme.and_then(|theme| theme.get("Inherits")));
+
+        let directories = list(theme.and_then(|theme| theme.get("Directories")))
+            .into_iter()
+            .chain(list(theme.and_then(|theme| theme.get("ScaledDirectories"))))
+            .filter_map(|path| {
+                let group = groups.get(path.as_str())?;
+                Directory::parse(path, group)
+            })
+            .collect();
+
+        Self {
+            parents,
+            directories,
+        }
+    }
+
+    /// Looks an icon up in this theme only,
+    /// preferring an exact size match and otherwise taking the closest.
+    fn lookup(
+        &self,
+        theme_name: &str,
+        name: &str,
+        size: u32,
+        scale: u32,
+        base_dirs: &[PathBuf],
+    ) -> Option<PathBuf> {
+        // the first existing file for the icon in a directory of the theme
+        let find = |directory: &Directory| {
+            base_dirs.iter().find_map(|base| {
+                let dir = base.join(theme_name).join(&directory.path);
+                find_in_dirs(name, &[dir])
+            })
+        };
+
+        let exact = self
+            .directories
+            .iter()
+            .filter(|directory| directory.matches_size(size, scale))
+            .find_map(find);
+
+        exact.or_else(|| {
+            self.directories
+                .iter()
+                .filter_map(|directory| {
+                    Some((directory.size_distance(size, scale), find(directory)?))
+                })
+                .min_by_key(|(distance, _)| *distance)
+                .map(|(_, path)| path)
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum DirectoryType {
+    Fixed,
+    Scalable,
+    Threshold,
+}
+
+/// A directory of icons within a theme, with the sizes it provides.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Directory {
+    path: String,
+    size: u32,
+    scale: u32,
+    min_size: u32,
+    max_size: u32,
+    threshold: u32,
+    kind: DirectoryType,
+}
+
+impl Directory {
+    fn parse(path: String, group: &HashMap<&str, &str>) -> Option<Self> {
+        let number = |key: &str| group.get(key).and_then(|value| value.parse::<u32>().ok());
+
+        let size = number("Size")?;
+        let kind = match group.get("Type").copied() {
+            Some("Fixed") => DirectoryType::Fixed,
+            Some("Scalable") => DirectoryType::Scalable,
+            _ => DirectoryType::Threshold,
+        };
+
+        Some(Self {
+            path,
+            size,
+            scale: number("Scale").unwrap_or(1),
+            min_size: number("MinSize").unwrap_or(size),
+            max_size: number("MaxSize").unwrap_or(size),
+            threshold: number("Threshold").unwrap_or(2),
+            kind,
+        })
+    }
+
+    /// See `DirectoryMatchesSize` in the specification.
+    fn matches_size(&self, size: u32, scale: u32) -> bool {
+        if self.scale != scale {
+            return false;
+        }
+
+        match self.kind {
+            DirectoryType::Fixed => self.size == size,
+            DirectoryType::Scalable => (self.min_size..=self.max_size).contains(&size),
+            DirectoryType::Threshold => (self.size.saturating_sub(self.threshold)
+                ..=self.size + self.threshold)
+                .contains(&size),
+        }
+    }
+
+    /// See `DirectorySizeDistance` in the specification.
+    fn size_distance(&self, size: u32, scale: u32) -> u32 {
+        let (min, max) = match self.kind {
+            DirectoryType::Fixed => (self.size, self.size),
+            DirectoryType::Scalable => (self.min_size, self.max_size),
+            DirectoryType::Threshold => (
+                self.size.saturating_sub(self.threshold),
+                self.size + self.threshold,
+            ),
+        };
+
+        // at most one of these is non-zero
+        let target = size * scale;
+        (min * self.scale).saturating_sub(target) + target.saturating_sub(max * self.scale)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HICOLOR: &str = "[Icon Theme]
+Name=Hicolor
+Directories=16x16/apps,48x48/apps,scalable/apps
+
+[16x16/apps]
+Size=16
+Type=Threshold
+
+[48x48/apps]
+Size=48
+Type=Fixed
+
+[scalable/apps]
+Size=128
+MinSize=64
+MaxSize=256
+Type=Scalable
+";
+
+    const CUSTOM: &str = "[Icon Theme]
+Name=Custom
+Inherits=hicolor
+Directories=24x24/status
+
+[24x24/status]
+Size=24
+";
+
+    fn touch(path: &Path) {
+        fs::create_dir_all(path.parent().expect("path should have a parent"))
+            .expect("directory should be created");
+        fs::write(path, b"").expect("file should be written");
+    }
+
+    #[test]
+    fn resolves_following_the_spec() {
+        let root = env::temp_dir().join(format!("system-tray-icons-{}", std::process::id()));
+        let icons = root.join("icons");
+        let pixmaps = root.join("pixmaps");
+        let app_dir = root.join("app");
+
+        fs::create_dir_all(icons.join("hicolor")).expect("directory should be created");
+        fs::create_dir_all(icons.join("custom")).expect("directory should be created");
+        fs::write(icons.join("hicolor/index.theme"), HICOLOR).expect("file should be written");
+        fs::write(icons.join("custom/index.theme"), CUSTOM).expect("file should be written");
+
+        touch(&icons.join("hicolor/16x16/apps/app.png"));
+        touch(&icons.join("hicolor/48x48/apps/app.png"));
+        touch(&icons.join("hicolor/scalable/apps/app.svg"));
+        touch(&icons.join("custom/24x24/status/wifi.png"));
+        touch(&pixmaps.join("legacy.xpm"));
+        touch(&app_dir.join("bundled.png"));
+        touch(&app_dir.join("hicolor/16x16/apps/themed.png"));
+
+        let resolver =
+            IconResolver::with_dirs("custom", vec![icons.clone()], vec![pixmaps.clone()]);
+        let none: &[&Path] = &[];
+
+        assert_eq!(
+            resolver.resolve("wifi", 24, none),
+            Some(icons.join("custom/24x24/status/wifi.png"))
+        );
+        assert_eq!(
+            resolver.resolve("app", 17, none),
+            Some(icons.join("hicolor/16x16/apps/app.png"))
+        );
+        assert_eq!(
+            resolver.resolve("app", 100, none),
+            Some(icons.join("hicolor/scalable/apps/app.svg"))
+        );
+        assert_eq!(
+            resolver.resolve("app", 40, none),
+            Some(icons.join("hicolor/48x48/apps/app.png"))
+        );
+        assert_eq!(
+            resolver.resolve("legacy", 24, none),
+            Some(pixmaps.join("legacy.xpm"))
+        );
+        assert_eq!(
+            resolver.resolve("bundled", 24, &[&app_dir]),
+            Some(app_dir.join("bundled.png"))
+        );
+        assert_eq!(
+            resolver.resolve("themed", 16, &[&app_dir]),
+            Some(app_dir.join("hicolor/16x16/apps/themed.png"))
+        );
+        assert_eq!(resolver.resolve("missing", 24, none), None);
+
+        fs::remove_dir_all(root).expect("directory should be removed");
+    }
+}
@@ -1,12 +1,14 @@
 use crate::dbus::DBusProps;
 use crate::error::{Error, Result};
 use serde::Deserialize;
+use std::collections::HashMap;
 use std::fmt::{Debug, Formatter};
-use zbus::zvariant::{Array, Structure, Value};
+use std::time::Duration;
+use zbus::zvariant::{Array, OwnedValue, Structure, Value};
 
 /// Represents an item to display inside the tray.
 /// <https://www.freedesktop.org/wiki/Specifications/StatusNotifierItem/StatusNotifierItem/>
-#[derive(Deserialize, Debug, Clone)]
+#[derive(Deserialize, Debug, Clone, Default)]
 pub struct StatusNotifierItem {
     /// A name that should be unique for this application and consistent between sessions, such as the application name itself.
     pub id: String,
@@ -16,8 +18,8 @@ pub struct StatusNotifierItem {
     /// The allowed values for the Category property are:
     ///
     /// - `ApplicationStatus`: The item describes the status of a generic application, for instance the current state of a media player.
-    ///     In the case where the category of the item can not be known, such as when the item is being proxied from another incompatible or emulated system,
-    ///     `ApplicationStatus` can be used a sensible default fallback.
+    ///   In the case where the category of the item can not be known, such as when the item is being proxied from another incompatible or emulated system,
+    ///   `ApplicationStatus` can be used a sensible default fallback.
     /// - `Communications`: The item describes the status of communication oriented applications, like an instant messenger or an email client.
     /// - `SystemServices`: The item describes services of the system not seen as a stand alone application by the user, such as an indicator for the activity of a disk indexing service.
     /// - `Hardware`: The item describes the state and control of a particular hardware, such as an indicator of the battery charge or sound card volume control.
@@ -33,7 +35,7 @@ pub struct StatusNotifierItem {
     /// - Passive: The item doesn't convey important information to the user, it can be considered an "idle" status and is likely that visualizations will chose to hide it.
     /// - Active: The item is active, is more important that the item will be shown in some way to the user.
     /// - `NeedsAttention`: The item carries really important information for the user, such as battery charge running out and is wants to incentive the direct user intervention.
-    ///     Visualizations should emphasize in some way the items with `NeedsAttention` status.
+    ///   Visualizations should emphasize in some way the items with `NeedsAttention` status.
     pub status: Status,
 
     /// The windowing-system dependent identifier for a window, the application can choose one of its windows to be available through this property or just set 0 if it's not interested.
@@ -86,9 +88,148 @@ pub struct StatusNotifierItem {
 
     /// `DBus` path to an object which should implement the `com.canonical.dbusmenu` interface
     pub menu: Option<String>,
+
+    /// Text to show next to the icon, such as the current keyboard layout.
+    ///
+    /// This is an Ayatana extension, read from the `XAyatanaLabel` property.
+    pub label: Option<String>,
+
+    /// The longest text [`StatusNotifierItem::label`] is expected to contain,
+    /// so that space can be reserved for it to avoid the tray resizing.
+    ///
+    /// This is an Ayatana extension, read from the `XAyatanaLabelGuide` property.
+    pub label_guide: Option<String>,
+
+    /// Where the item should be placed relative to other items,
+    /// in ascending order.
+    /// Items without an index are usually placed by registration order.
+    ///
+    /// This is an Ayatana extension, read from the `XAyatanaOrderingIndex` property.
+    pub ordering_index: Option<u32>,
+
+    /// Properties which are not part of the specification,
+    /// such as KDE or Ayatana extensions, keyed by property name.
+    ///
+    /// These are read when the item is added, and are not kept up to date.
+    #[serde(default)]
+    pub extra: HashMap<String, OwnedValue>,
+}
+
+/// The properties defined by the specification,
+/// which are parsed into the fields of a [`StatusNotifierItem`]
+/// rather than kept in [`StatusNotifierItem::extra`].
+const KNOWN_PROPERTIES: &[&str] = &[
+    "Id",
+    "Category",
+    "Title",
+    "Status",
+    "WindowId",
+    "IconThemePath",
+    "IconName",
+    "IconPixmap",
+    "OverlayIconName",
+    "OverlayIconPixmap",
+    "AttentionIconName",
+    "AttentionIconPixmap",
+    "AttentionMovieName",
+    "ToolTip",
+    "ItemIsMenu",
+    "Menu",
+    "XAyatanaLabel",
+    "XAyatanaLabelGuide",
+    "XAyatanaOrderingIndex",
+];
+
+/// The icon a host should display for an item,
+/// as selected by [`StatusNotifierItem::effective_icon`].
+///
+/// Visualizations are encouraged to prefer `name` over `pixmap` when both are present,
+/// falling back to the pixmap if the name cannot be found in the icon theme.
+/// The overlay, if any, should be drawn on top of the main icon.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct EffectiveIcon<'a> {
+    /// The Freedesktop-compliant name of the icon.
+    pub name: Option<&'a str>,
+    /// ARGB32 binary representation of the icon.
+    pub pixmap: Option<&'a [IconPixmap]>,
+    /// The Freedesktop-compliant name of the overlay icon.
+    pub overlay_name: Option<&'a str>,
+    /// ARGB32 binary representation of the overlay icon.
+    pub overlay_pixmap: Option<&'a [IconPixmap]>,
+    /// Whether the attention icon was selected instead of the normal icon.
+    pub is_attention: bool,
+}
+
+impl StatusNotifierItem {
+    /// Applies pixmap corrections to all of the item's icons.
+    ///
+    /// This is done automatically by the client
+    /// for quirks configured using [`ClientOptions::pixmap_quirks`](crate::client::ClientOptions::pixmap_quirks).
+    pub fn apply_pixmap_quirks(&mut self, quirks: &[PixmapQuirk]) {
+        for pixmaps in [
+            &mut self.icon_pixmap,
+            &mut self.overlay_icon_pixmap,
+            &mut self.attention_icon_pixmap,
+        ]
+        .into_iter()
+        .flatten()
+        {
+            apply_quirks(pixmaps, quirks);
+        }
+
+        if let Some(tooltip) = &mut self.tool_tip {
+            tooltip.apply_pixmap_quirks(quirks);
+        }
+    }
+
+    /// Gets the icon which should be displayed given the current [`Status`].
+    ///
+    /// When the item is in the `NeedsAttention` state and `prefer_attention` is set,
+    /// the attention icon is used if the item provides one.
+    /// Otherwise, the normal icon is used.
+    /// The overlay icon applies to either.
+    ///
+    /// Empty icon names and pixmap lists are treated as missing,
+    /// since many items send those instead of leaving the property unset.
+    #[must_use]
+    pub fn effective_icon(&self, prefer_attention: bool) -> EffectiveIcon<'_> {
+        let attention_name = non_empty_name(self.attention_icon_name.as_ref());
+        let attention_pixmap = non_empty_pixmap(self.attention_icon_pixmap.as_ref());
+
+        let is_attention = prefer_attention
+            && matches!(self.status, Status::NeedsAttention)
+            && (attention_name.is_some() || attention_pixmap.is_some());
+
+        let (name, pixmap) = if is_attention {
+            (attention_name, attention_pixmap)
+        } else {
+            (
+                non_empty_name(self.icon_name.as_ref()),
+                non_empty_pixmap(self.icon_pixmap.as_ref()),
+            )
+        };
+
+        EffectiveIcon {
+            name,
+            pixmap,
+            overlay_name: non_empty_name(self.overlay_icon_name.as_ref()),
+            overlay_pixmap: non_empty_pixmap(self.overlay_icon_pixmap.as_ref()),
+            is_attention,
+        }
+    }
+}
+
+fn non_empty_name(name: Option<&String>) -> Option<&str> {
+    name.map(String::as_str).filter(|name| !name.is_empty())
 }
 
-#[derive(Debug, Clone, Copy, Deserialize, Default)]
+fn non_empty_pixmap(pixmap: Option<&Vec<IconPixmap>>) -> Option<&[IconPixmap]> {
+    pixmap
+        .map(Vec::as_slice)
+        .filter(|pixmap| !pixmap.is_empty())
+}
+
+#[derive(Debug, Clone, Copy, Deserialize, Default, PartialEq, Eq)]
 pub enum Category {
     #[default]
     ApplicationStatus,
@@ -97,6 +238,19 @@ pub enum Category {
     Hardware,
 }
 
+impl Category {
+    /// Gets the name of the category as sent over the bus.
+    #[must_use]
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::ApplicationStatus => "ApplicationStatus",
+            Self::Communications => "Communications",
+            Self::SystemServices => "SystemServices",
+            Self::Hardware => "Hardware",
+        }
+    }
+}
+
 impl From<&str> for Category {
     fn from(value: &str) -> Self {
         match value {
@@ -108,7 +262,7 @@ impl From<&str> for Category {
     }
 }
 
-#[derive(Debug, Clone, Copy, Deserialize, Default)]
+#[derive(Debug, Clone, Copy, Deserialize, Default, PartialEq, Eq)]
 pub enum Status {
     #[default]
     Unknown,
@@ -117,6 +271,19 @@ pub enum Status {
     NeedsAttention,
 }
 
+impl Status {
+    /// Gets the name of the status as sent over the bus.
+    #[must_use]
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::Unknown => "Unknown",
+            Self::Passive => "Passive",
+            Self::Active => "Active",
+            Self::NeedsAttention => "NeedsAttention",
+        }
+    }
+}
+
 impl From<&str> for Status {
     fn from(value: &str) -> Self {
         match value {
@@ -128,7 +295,22 @@ impl From<&str> for Status {
     }
 }
 
-#[derive(Deserialize, Clone)]
+/// A single resolution of an icon, as sent over the bus.
+///
+/// # Pixel layout
+///
+/// `pixels` holds `width * height` pixels in ARGB32 format, in network (big-endian) byte order.
+/// That means each pixel is four bytes in the order `[A, R, G, B]` regardless of the host endianness.
+/// Rows are stored top-to-bottom with no padding, so the stride is always `width * 4` bytes.
+///
+/// The colour channels are *not* premultiplied by alpha.
+/// Uploading the data as-is to a GPU texture that expects premultiplied alpha
+/// (the default for most blending setups in wgpu, iced, smithay and friends)
+/// causes dark halos around anti-aliased edges.
+/// Use [`IconPixmap::to_premultiplied_rgba`] to get data suitable for that case.
+///
+/// For other layouts, see [`IconPixmap::to_rgba8`] and [`IconPixmap::to_argb_native`].
+#[derive(Deserialize, Clone, PartialEq, Eq)]
 pub struct IconPixmap {
     pub width: i32,
     pub height: i32,
@@ -146,7 +328,147 @@ impl Debug for IconPixmap {
 }
 
 impl IconPixmap {
-    fn from_array(array: &Array) -> Result<Vec<Self>> {
+    /// The number of bytes in a single row of pixels.
+    #[must_use]
+    pub fn stride(&self) -> usize {
+        self.width.max(0) as usize * 4
+    }
+
+    /// Converts the pixel data into RGBA8, as used by most image libraries.
+    ///
+    /// The output has the same dimensions and row order as the input:
+    /// top-to-bottom rows, with a stride of `width * 4` bytes and no padding.
+    /// Each pixel is four bytes in the order `[R, G, B, A]`,
+    /// with the colour channels *not* premultiplied.
+    ///
+    /// Any trailing bytes which do not make up a whole pixel are ignored.
+    #[must_use]
+    pub fn to_rgba8(&self) -> Vec<u8> {
+        self.pixels
+            .chunks_exact(4)
+            .flat_map(|argb| [argb[1], argb[2], argb[3], argb[0]])
+            .collect()
+    }
+
+    /// Converts the pixel data into ARGB32 values in the host's byte order,
+    /// with alpha in the most significant byte.
+    ///
+    /// The colour channels are *not* premultiplied.
+    /// Formats which expect premultiplied values, such as Cairo's `ARGB32`,
+    /// should premultiply them first.
+    ///
+    /// Any trailing bytes which do not make up a whole pixel are ignored.
+    #[must_use]
+    pub fn to_argb_native(&self) -> Vec<u32> {
+        self.pixels
+            .chunks_exact(4)
+            .map(|argb| u32::from_be_bytes([argb[0], argb[1], argb[2], argb[3]]))
+            .collect()
+    }
+
+    /// Converts the pixel data into RGBA8 with premultiplied alpha.
+    ///
+    /// The output has the same dimensions and row order as the input:
+    /// top-to-bottom rows, with a stride of `width * 4` bytes and no padding.
+    /// Each pixel is four bytes in the order `[R, G, B, A]`,
+    /// with the colour channels multiplied by the alpha channel.
+    ///
+    /// Any trailing bytes which do not make up a whole pixel are ignored.
+    #[must_use]
+    pub fn to_premultiplied_rgba(&self) -> Vec<u8> {
+        self.pixels
+            .chunks_exact(4)
+            .flat_map(|argb| {
+                let [a, r, g, b] = [argb[0], argb[1], argb[2], argb[3]];
+                [premultiply(r, a), premultiply(g, a), premultiply(b, a), a]
+            })
+            .collect()
+    }
+
+    /// Picks the most suitable of several resolutions of an icon
+    /// to display at `size` pixels.
+    ///
+    /// Icons are compared by their larger dimension.
+    /// An exact match is preferred, then the smallest icon larger than `size`
+    /// so that it can be scaled down without losing detail,
+    /// then the largest icon available.
+    #[must_use]
+    pub fn best_for(pixmaps: &[Self], size: i32) -> Option<&Self> {
+        let extent = |pixmap: &Self| pixmap.width.max(pixmap.height);
+
+        pixmaps
+            .iter()
+            .filter(|pixmap| extent(pixmap) >= size)
+            .min_by_key(|pixmap| extent(pixmap))
+            .or_else(|| pixmaps.iter().max_by_key(|pixmap| extent(pixmap)))
+    }
+
+    /// Scales the icon down so that its larger dimension is at most `size` pixels,
+    /// keeping its aspect ratio.
+    ///
+    /// Each output pixel is the average of the pixels it covers,
+    /// weighted by their alpha so that transparent pixels do not darken edges.
+    /// Icons which are already small enough are returned unchanged.
+    #[must_use]
+    pub fn scale_down(&self, size: i32) -> Self {
+        let extent = self.width.max(self.height);
+        if size <= 0 || extent <= size {
+            return self.clone();
+        }
+
+        let (src_width, src_height) = (self.width as usize, self.height as usize);
+        let scale = |dimension: usize| (dimension * size as usize / extent as usize).max(1);
+        let (width, height) = (scale(src_width), scale(src_height));
+
+        // the range of source pixels covered by an output pixel, along one dimension
+        let span = |i: usize, src: usize, dst: usize| {
+            let start = i * src / dst;
+            start..((i + 1) * src / dst).max(start + 1)
+        };
+
+        let mut pixels = Vec::with_capacity(width * height * 4);
+
+        for y in 0..height {
+            let rows = span(y, src_height, height);
+
+            for x in 0..width {
+                let columns = span(x, src_width, width);
+
+                let mut sum = [0u64; 4];
+                let mut count = 0;
+
+                for row in rows.clone() {
+                    for column in columns.clone() {
+                        let offset = (row * src_width + column) * 4;
+                        let Some(&[a, r, g, b]) = self.pixels.get(offset..offset + 4) else {
+                            continue;
+                        };
+
+                        let a = u64::from(a);
+                        sum[0] += a;
+                        sum[1] += u64::from(r) * a;
+                        sum[2] += u64::from(g) * a;
+                        sum[3] += u64::from(b) * a;
+                        count += 1;
+                    }
+                }
+
+                let [a, r, g, b] = sum;
+                let channel = |value: u64| value.checked_div(a).unwrap_or_default() as u8;
+                let alpha = a.checked_div(count).unwrap_or_default() as u8;
+
+                pixels.extend_from_slice(&[alpha, channel(r), channel(g), channel(b)]);
+            }
+        }
+
+        Self {
+            width: width as i32,
+            height: height as i32,
+            pixels,
+        }
+    }
+
+    pub(crate) fn from_array(array: &Array) -> Result<Vec<Self>> {
         array
             .iter()
             .map(|pixmap| {
@@ -186,15 +508,240 @@ impl IconPixmap {
                     width,
                     height,
                     pixels,
-                })
+                }
+                .sanitize())
             })
+            .filter_map(Result::transpose)
             .collect()
     }
+
+    /// Corrects common problems with pixmaps from buggy emitters,
+    /// returning `None` if the pixmap is unusable.
+    ///
+    /// Pixmaps without any pixels are dropped,
+    /// and pixel data which does not match the dimensions is padded or truncated to fit.
+    /// Data which appears to have the alpha channel last,
+    /// as sent by items writing native-endian `u32`s on little-endian machines,
+    /// is converted back to network byte order.
+    fn sanitize(mut self) -> Option<Self> {
+        if self.width <= 0 || self.height <= 0 || self.pixels.is_empty() {
+            return None;
+        }
+
+        let expected_len = (self.width as usize)
+            .checked_mul(self.height as usize)?
+            .checked_mul(4)
+            .filter(|&len| len <= MAX_PIXMAP_BYTES)?;
+
+        if self.pixels.len() != expected_len {
+            // missing pixels are left transparent
+            self.pixels.resize(expected_len, 0);
+        }
+
+        if has_trailing_alpha(&self.pixels) {
+            for pixel in self.pixels.chunks_exact_mut(4) {
+                pixel.reverse();
+            }
+        }
+
+        Some(self)
+    }
+
+    /// Applies a correction for an item known to send broken pixmaps.
+    pub fn apply_quirk(&mut self, quirk: PixmapQuirk) {
+        for pixel in self.pixels.chunks_exact_mut(4) {
+            match quirk {
+                PixmapQuirk::SwapRedBlue => pixel.swap(1, 3),
+                PixmapQuirk::Premultiplied => {
+                    let alpha = pixel[0];
+                    for channel in &mut pixel[1..] {
+                        *channel = unpremultiply(*channel, alpha);
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(feature = "image")]
+impl IconPixmap {
+    /// Converts the icon into an RGBA image.
+    ///
+    /// Returns `None` if the pixel data does not match the dimensions.
+    #[must_use]
+    pub fn to_image(&self) -> Option<image::RgbaImage> {
+        let width = u32::try_from(self.width).ok()?;
+        let height = u32::try_from(self.height).ok()?;
+        image::RgbaImage::from_raw(width, height, self.to_rgba8())
+    }
+
+    /// Creates an icon from an image, converting it to ARGB32.
+    ///
+    /// # Panics
+    ///
+    /// If the image is wider or taller than `i32::MAX` pixels.
+    #[must_use]
+    pub fn from_image(image: &image::DynamicImage) -> Self {
+        let image = image.to_rgba8();
+
+        Self {
+            width: i32::try_from(image.width()).expect("image width should fit in an i32"),
+            height: i32::try_from(image.height()).expect("image height should fit in an i32"),
+            pixels: image
+                .pixels()
+                .flat_map(|pixel| {
+                    let [r, g, b, a] = pixel.0;
+                    [a, r, g, b]
+                })
+                .collect(),
+        }
+    }
+
+    /// Encodes the icon as a PNG.
+    ///
+    /// # Errors
+    ///
+    /// If the pixel data does not match the dimensions,
+    /// or the image cannot be encoded.
+    pub fn to_png(&self) -> Result<Vec<u8>> {
+        let image = self
+            .to_image()
+            .ok_or(Error::InvalidData("pixel data does not match dimensions"))?;
+
+        let mut png = std::io::Cursor::new(Vec::new());
+        image.write_to(&mut png, image::ImageFormat::Png)?;
+        Ok(png.into_inner())
+    }
+}
+
+#[cfg(feature = "dbusmenu-gtk3")]
+impl IconPixmap {
+    /// Converts the icon into a GDK pixbuf.
+    ///
+    /// Returns `None` if the pixel data does not match the dimensions.
+    #[must_use]
+    pub fn to_pixbuf(&self) -> Option<gtk::gdk_pixbuf::Pixbuf> {
+        use gtk::gdk_pixbuf::{Colorspace, Pixbuf};
+        use gtk::glib::Bytes;
+
+        let expected_len = self.stride() * usize::try_from(self.height).ok()?;
+        if self.width <= 0 || self.height <= 0 || self.pixels.len() != expected_len {
+            return None;
+        }
+
+        let pixels = Bytes::from_owned(self.to_rgba8());
+        Some(Pixbuf::from_bytes(
+            &pixels,
+            Colorspace::Rgb,
+            true,
+            8,
+            self.width,
+            self.height,
+            self.width * 4,
+        ))
+    }
+}
+
+/// Applies each quirk to each pixmap.
+pub(crate) fn apply_quirks(pixmaps: &mut [IconPixmap], quirks: &[PixmapQuirk]) {
+    for pixmap in pixmaps {
+        for &quirk in quirks {
+            pixmap.apply_quirk(quirk);
+        }
+    }
+}
+
+/// The largest pixmap accepted, in bytes.
+/// This is far beyond any sensible icon size,
+/// and guards against allocating huge buffers for bogus dimensions.
+const MAX_PIXMAP_BYTES: usize = 2048 * 2048 * 4;
+
+/// Guesses whether ARGB pixel data actually has its alpha channel as the last byte.
+///
+/// Alpha values are mostly either fully transparent or fully opaque,
+/// whereas colour channels vary.
+/// If the last byte of each pixel looks like that and the first does not,
+/// the channels are most likely in the wrong order.
+fn has_trailing_alpha(pixels: &[u8]) -> bool {
+    let count = |offset: usize, predicate: fn(u8) -> bool| {
+        pixels
+            .chunks_exact(4)
+            .filter(|pixel| predicate(pixel[offset]))
+            .count()
+    };
+
+    let total = pixels.len() / 4;
+    let is_extreme = |value| value == 0 || value == u8::MAX;
+
+    // an icon which is entirely transparent but has colour is certainly wrong
+    if count(0, |value| value == 0) == total && count(3, |value| value != 0) > 0 {
+        return true;
+    }
+
+    let first = count(0, is_extreme);
+    let last = count(3, is_extreme);
+
+    last * 10 >= total * 9 && first * 2 < total
+}
+
+/// A correction for an item known to send broken pixmaps,
+/// for problems which cannot be reliably detected.
+///
+/// Quirks are configured per item using [`ClientOptions::pixmap_quirks`](crate::client::ClientOptions::pixmap_quirks).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PixmapQuirk {
+    /// The red and blue channels are swapped,
+    /// typically making icons look blue-tinted.
+    /// This is common for items sending RGBA or ABGR data.
+    SwapRedBlue,
+    /// The colour channels are already premultiplied by alpha,
+    /// making semi-transparent edges look too dark.
+    Premultiplied,
+}
+
+/// Divides a premultiplied colour channel by its alpha value, rounding to the nearest integer.
+fn unpremultiply(channel: u8, alpha: u8) -> u8 {
+    if alpha == 0 {
+        return 0;
+    }
+
+    let value = (u16::from(channel) * 255 + u16::from(alpha) / 2) / u16::from(alpha);
+    value.min(255) as u8
+}
+
+/// Multiplies a colour channel by an alpha value, rounding to the nearest integer.
+fn premultiply(channel: u8, alpha: u8) -> u8 {
+    let value = u16::from(channel) * u16::from(alpha) + 127;
+    ((value + (value >> 8)) >> 8) as u8
+}
+
+/// A single frame of an [`AnimatedIcon`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct IconFrame {
+    /// The Freedesktop-compliant name of the icon.
+    pub name: Option<String>,
+    /// ARGB32 binary representation of the icon.
+    pub pixmap: Option<Vec<IconPixmap>>,
+}
+
+/// An icon animation, detected from an item changing its icon rapidly.
+///
+/// Some items, such as download indicators, animate by replacing their icon
+/// many times per second.
+/// Rather than sending an update for every frame,
+/// the client collects the frames so that hosts can play the animation themselves.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AnimatedIcon {
+    /// The frames of the animation, in order.
+    /// The animation loops back to the first frame after the last.
+    pub frames: Vec<IconFrame>,
+    /// The average time between frames.
+    pub interval: Duration,
 }
 
 /// Data structure that describes extra information associated to this item, that can be visualized for instance by a tooltip
 /// (or by any other mean the visualization consider appropriate.
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, Deserialize, PartialEq, Eq)]
 pub struct Tooltip {
     pub icon_name: String,
     pub icon_data: Vec<IconPixmap>,
@@ -202,6 +749,108 @@ pub struct Tooltip {
     pub description: String,
 }
 
+impl Tooltip {
+    /// Applies pixmap corrections to the tooltip icon.
+    pub fn apply_pixmap_quirks(&mut self, quirks: &[PixmapQuirk]) {
+        apply_quirks(&mut self.icon_data, quirks);
+    }
+
+    /// Gets the text of the tooltip without markup,
+    /// for hosts which cannot render it.
+    ///
+    /// The specification allows a subset of HTML in tooltips.
+    /// Tags are removed, with line breaks and paragraphs becoming new lines,
+    /// and entities are decoded.
+    /// The title and description are separated by a new line.
+    #[must_use]
+    pub fn plain_text(&self) -> String {
+        let title = markup_to_plain(&self.title);
+        let description = markup_to_plain(&self.description);
+
+        match (title.is_empty(), description.is_empty()) {
+            (_, true) => title,
+            (true, false) => description,
+            (false, false) => format!("{title}\n{description}"),
+        }
+    }
+}
+
+/// Converts tooltip markup to plain text.
+/// See [`Tooltip::plain_text`].
+fn markup_to_plain(markup: &str) -> String {
+    let mut plain = String::with_capacity(markup.len());
+    let mut rest = markup;
+
+    while let Some(start) = rest.find(['<', '&']) {
+        plain.push_str(&rest[..start]);
+        rest = &rest[start..];
+
+        if rest.starts_with('&') {
+            let entity = rest[1..]
+                .find(';')
+                .and_then(|end| Some((decode_entity(&rest[1..=end])?, end)));
+
+            match entity {
+                Some((c, end)) => {
+                    plain.push(c);
+                    rest = &rest[end + 2..];
+                }
+                None => {
+                    plain.push('&');
+                    rest = &rest[1..];
+                }
+            }
+
+            continue;
+        }
+
+        let is_tag = rest[1..].starts_with(|c: char| c.is_ascii_alphabetic() || c == '/');
+        let Some(end) = rest.find('>').filter(|_| is_tag) else {
+            plain.push('<');
+            rest = &rest[1..];
+            continue;
+        };
+
+        let name = rest[1..end]
+            .trim_start_matches('/')
+            .split(|c: char| c.is_whitespace() || c == '/')
+            .next()
+            .unwrap_or_default();
+
+        // paragraphs only need a break if there is text before them
+        let is_break = name.eq_ignore_ascii_case("br")
+            || (name.eq_ignore_ascii_case("p") && !plain.is_empty() && !plain.ends_with('\n'));
+        if is_break {
+            plain.push('\n');
+        }
+
+        rest = &rest[end + 1..];
+    }
+
+    plain.push_str(rest);
+    plain.trim().to_string()
+}
+
+/// Decodes the name of an HTML entity, without its `&` and `;`.
+fn decode_entity(name: &str) -> Option<char> {
+    match name {
+        "amp" => Some('&'),
+        "lt" => Some('<'),
+        "gt" => Some('>'),
+        "quot" => Some('"'),
+        "apos" => Some('\''),
+        "nbsp" => Some(' '),
+        _ => {
+            let code = name.strip_prefix('#')?;
+            let code = match code.strip_prefix(['x', 'X']) {
+                Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+                None => code.parse().ok()?,
+            };
+            char::from_u32(code)
+        }
+    }
+}
+
 impl TryFrom<&Structure<'_>> for Tooltip {
     type Error = Error;
 
@@ -258,6 +907,10 @@ impl TryFrom<DBusProps> for StatusNotifierItem {
                 item_is_menu: props.get("ItemIsMenu").copied().unwrap_or_default(),
                 category: props.get_category(),
                 menu: props.get_object_path("Menu"),
+                label: props.get_label("XAyatanaLabel"),
+                label_guide: props.get_label("XAyatanaLabelGuide"),
+                ordering_index: props.get::<u32>("XAyatanaOrderingIndex").copied(),
+                extra: props.get_extra(),
             })
         } else {
             Err(Error::MissingProperty("Id"))
@@ -265,6 +918,85 @@ impl TryFrom<DBusProps> for StatusNotifierItem {
     }
 }
 
+impl StatusNotifierItem {
+    /// Parses an item from its properties,
+    /// skipping any which are present but invalid rather than failing.
+    ///
+    /// Skipped properties take their default values,
+    /// and their names are returned alongside the item.
+    /// A missing or invalid `Id` is still an error.
+    pub(crate) fn try_from_lossy(props: &DBusProps) -> Result<(Self, Vec<&'static str>)> {
+        let id = props.get_string("Id").ok_or(Error::MissingProperty("Id"))?;
+
+        let mut props = LossyProps {
+            props,
+            invalid: Vec::new(),
+        };
+
+        let item = Self {
+            id,
+            title: props.check("Title", DBusProps::get_string),
+            status: props
+                .check("Status", |props, key| {
+                    props.get::<str>(key).map(Status::from)
+                })
+                .unwrap_or_default(),
+            window_id: props
+                .check("WindowId", |props, key| props.get::<u32>(key).copied())
+                .unwrap_or_default(),
+            icon_theme_path: props.check("IconThemePath", DBusProps::get_string),
+            icon_name: props.check("IconName", DBusProps::get_string),
+            icon_pixmap: props.check("IconPixmap", DBusProps::get_icon_pixmap),
+            overlay_icon_name: props.check("OverlayIconName", DBusProps::get_string),
+            overlay_icon_pixmap: props.check("OverlayIconPixmap", DBusProps::get_icon_pixmap),
+            attention_icon_name: props.check("AttentionIconName", DBusProps::get_string),
+            attention_icon_pixmap: props.check("AttentionIconPixmap", DBusProps::get_icon_pixmap),
+            attention_movie_name: props.check("AttentionMovieName", DBusProps::get_string),
+            tool_tip: props.check("ToolTip", |props, _| props.get_tooltip().ok().flatten()),
+            item_is_menu: props
+                .check("ItemIsMenu", |props, key| props.get::<bool>(key).copied())
+                .unwrap_or_default(),
+            category: props
+                .check("Category", |props, key| {
+                    props.get::<str>(key).map(Category::from)
+                })
+                .unwrap_or_default(),
+            menu: props.check("Menu", DBusProps::get_object_path),
+            label: props.check("XAyatanaLabel", DBusProps::get_label),
+            label_guide: props.check("XAyatanaLabelGuide", DBusProps::get_label),
+            ordering_index: props.check("XAyatanaOrderingIndex", |props, key| {
+                props.get::<u32>(key).copied()
+            }),
+            extra: props.props.get_extra(),
+        };
+
+        Ok((item, props.invalid))
+    }
+}
+
+/// Properties being parsed leniently,
+/// keeping track of those which are present but could not be parsed.
+struct LossyProps<'a> {
+    props: &'a DBusProps,
+    invalid: Vec<&'static str>,
+}
+
+impl LossyProps<'_> {
+    /// Parses the property `key` using `parse`,
+    /// recording it as invalid if it is present but could not be parsed.
+    fn check<T>(
+        &mut self,
+        key: &'static str,
+        parse: impl FnOnce(&DBusProps, &str) -> Option<T>,
+    ) -> Option<T> {
+        let value = parse(self.props, key);
+        if value.is_none() && self.props.contains_key(key) {
+            self.invalid.push(key);
+        }
+        value
+    }
+}
+
 impl DBusProps {
     fn get_category(&self) -> Category {
         self.get::<str>("Category")
@@ -283,9 +1015,261 @@ impl DBusProps {
             .and_then(|arr| IconPixmap::from_array(arr).ok())
     }
 
+    fn get_extra(&self) -> HashMap<String, OwnedValue> {
+        self.iter()
+            .filter(|(key, _)| !KNOWN_PROPERTIES.contains(&key.as_str()))
+            .map(|(key, value)| (key.clone(), value.clone()))
+            .collect()
+    }
+
     fn get_tooltip(&self) -> Result<Option<Tooltip>> {
         self.get::<Structure>("ToolTip")
             .map(Tooltip::try_from)
             .transpose()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use zbus::zvariant::Str;
+
+    fn item(status: Status) -> StatusNotifierItem {
+        StatusNotifierItem {
+            id: String::from("test"),
+            category: Category::default(),
+            title: None,
+            status,
+            window_id: 0,
+            icon_theme_path: None,
+            icon_name: Some(String::from("normal")),
+            icon_pixmap: None,
+            overlay_icon_name: Some(String::new()),
+            overlay_icon_pixmap: None,
+            attention_icon_name: Some(String::from("attention")),
+            attention_icon_pixmap: None,
+            attention_movie_name: None,
+            tool_tip: None,
+            item_is_menu: false,
+            menu: None,
+            label: None,
+            label_guide: None,
+            ordering_index: None,
+            extra: HashMap::new(),
+        }
+    }
+
+    #[test]
+    fn effective_icon_uses_attention_when_needed() {
+        let item = item(Status::NeedsAttention);
+        let icon = item.effective_icon(true);
+        assert_eq!(icon.name, Some("attention"));
+        assert!(icon.is_attention);
+        assert_eq!(icon.overlay_name, None);
+    }
+
+    #[test]
+    fn effective_icon_uses_normal_otherwise() {
+        assert_eq!(
+            item(Status::Active).effective_icon(true).name,
+            Some("normal")
+        );
+        assert_eq!(
+            item(Status::NeedsAttention).effective_icon(false).name,
+            Some("normal")
+        );
+
+        let mut item = item(Status::NeedsAttention);
+        item.attention_icon_name = Some(String::new());
+        assert_eq!(item.effective_icon(true).name, Some("normal"));
+    }
+
+    #[test]
+    fn tooltip_plain_text() {
+        let tooltip = Tooltip {
+            icon_name: String::new(),
+            icon_data: Vec::new(),
+            title: String::from("<b>Downloads</b>"),
+            description: String::from(
+                "<p>3 files &amp; 2 folders</p><p>a &lt; b<br/>&#x2713; done &bogus;</p>",
+            ),
+        };
+
+        assert_eq!(
+            tooltip.plain_text(),
+            "Downloads\n3 files & 2 folders\na < b\n\u{2713} done &bogus;"
+        );
+    }
+
+    #[test]
+    fn lossy_parsing_skips_invalid_properties() {
+        let props = DBusProps(HashMap::from([
+            ("Id".to_string(), OwnedValue::from(Str::from("app"))),
+            ("Title".to_string(), OwnedValue::from(Str::from("App"))),
+            ("WindowId".to_string(), OwnedValue::from(-1i32)),
+            ("ToolTip".to_string(), OwnedValue::from(Str::from("broken"))),
+            (
+                "XAyatanaLabel".to_string(),
+                OwnedValue::from(Str::from("EN")),
+            ),
+            ("XAyatanaOrderingIndex".to_string(), OwnedValue::from(3u32)),
+            (
+                "IconAccessibleDesc".to_string(),
+                OwnedValue::from(Str::from("Keyboard")),
+            ),
+        ]));
+
+        let (item, invalid) = StatusNotifierItem::try_from_lossy(&props).expect("to be parsed");
+        assert_eq!(item.id, "app");
+        assert_eq!(item.title.as_deref(), Some("App"));
+        assert_eq!(item.window_id, 0);
+        assert!(item.tool_tip.is_none());
+        assert_eq!(item.label.as_deref(), Some("EN"));
+        assert_eq!(item.ordering_index, Some(3));
+        assert_eq!(invalid, ["WindowId", "ToolTip"]);
+        assert_eq!(
+            item.extra
+                .get("IconAccessibleDesc")
+                .and_then(|desc| desc.downcast_ref::<str>()),
+            Some("Keyboard")
+        );
+        assert!(!item.extra.contains_key("Title"));
+        assert!(!item.extra.contains_key("XAyatanaLabel"));
+
+        let props = DBusProps(HashMap::from([(
+            "Title".to_string(),
+            OwnedValue::from(Str::from("App")),
+        )]));
+        assert!(StatusNotifierItem::try_from_lossy(&props).is_err());
+    }
+
+    #[test]
+    fn picks_and_scales_pixmaps() {
+        let pixmap = |size: i32| IconPixmap {
+            width: size,
+            height: size,
+            pixels: vec![255; (size * size * 4) as usize],
+        };
+        let pixmaps = [pixmap(16), pixmap(64), pixmap(32)];
+
+        let best = |size| IconPixmap::best_for(&pixmaps, size).map(|pixmap| pixmap.width);
+        assert_eq!(best(32), Some(32));
+        assert_eq!(best(24), Some(32));
+        assert_eq!(best(128), Some(64));
+        assert_eq!(IconPixmap::best_for(&[], 16), None);
+
+        // opaque red next to transparent black should stay red, not darken
+        let edge = IconPixmap {
+            width: 2,
+            height: 1,
+            pixels: vec![255, 255, 0, 0, 0, 0, 0, 0],
+        };
+        let scaled = edge.scale_down(1);
+        assert_eq!((scaled.width, scaled.height), (1, 1));
+        assert_eq!(scaled.pixels, [127, 255, 0, 0]);
+
+        assert_eq!(pixmap(16).scale_down(24), pixmap(16));
+    }
+
+    #[test]
+    fn converts_pixel_formats() {
+        let pixmap = IconPixmap {
+            width: 2,
+            height: 1,
+            pixels: vec![0x80, 0xff, 0x40, 0x00, 0xff, 0x01, 0x02, 0x03, 0xaa],
+        };
+
+        assert_eq!(
+            pixmap.to_rgba8(),
+            [0xff, 0x40, 0x00, 0x80, 0x01, 0x02, 0x03, 0xff]
+        );
+        assert_eq!(pixmap.to_argb_native(), [0x80ff_4000, 0xff01_0203]);
+        assert_eq!(
+            pixmap.to_premultiplied_rgba(),
+            [0x80, 0x20, 0x00, 0x80, 0x01, 0x02, 0x03, 0xff]
+        );
+    }
+
+    #[cfg(feature = "image")]
+    #[test]
+    fn png_round_trip() {
+        let pixmap = IconPixmap {
+            width: 2,
+            height: 1,
+            pixels: vec![0x80, 0xff, 0x40, 0x00, 0xff, 0x01, 0x02, 0x03],
+        };
+
+        let png = pixmap.to_png().expect("to be encoded");
+        let image = image::load_from_memory(&png).expect("to be decoded");
+        assert_eq!(IconPixmap::from_image(&image), pixmap);
+    }
+
+    #[test]
+    fn premultiply_extremes() {
+        assert_eq!(premultiply(255, 255), 255);
+        assert_eq!(premultiply(255, 0), 0);
+        assert_eq!(premultiply(0, 255), 0);
+        assert_eq!(premultiply(200, 128), 100);
+    }
+
+    #[test]
+    fn sanitizes_broken_pixmaps() {
+        let pixmap = |width, height, pixels: Vec<u8>| IconPixmap {
+            width,
+            height,
+            pixels,
+        };
+
+        assert!(pixmap(0, 1, vec![255; 4]).sanitize().is_none());
+        assert!(pixmap(1, 1, Vec::new()).sanitize().is_none());
+
+        // short data is padded with transparent pixels
+        let padded = pixmap(2, 1, vec![255, 1, 2, 3])
+            .sanitize()
+            .expect("to be kept");
+        assert_eq!(padded.pixels, vec![255, 1, 2, 3, 0, 0, 0, 0]);
+
+        // alpha last (BGRA) is converted to ARGB
+        let bgra = [[30, 20, 10, 255], [0, 0, 0, 0], [60, 50, 40, 255]].concat();
+        let fixed = pixmap(3, 1, bgra).sanitize().expect("to be kept");
+        assert_eq!(
+            fixed.pixels,
+            [[255, 10, 20, 30], [0, 0, 0, 0], [255, 40, 50, 60]].concat()
+        );
+
+        // correct data is untouched
+        let argb = [[255, 10, 20, 30], [0, 0, 0, 0], [128, 40, 50, 60]].concat();
+        let kept = pixmap(3, 1, argb.clone()).sanitize().expect("to be kept");
+        assert_eq!(kept.pixels, argb);
+    }
+
+    #[test]
+    fn applies_pixmap_quirks() {
+        let mut pixmap = IconPixmap {
+            width: 1,
+            height: 1,
+            pixels: vec![128, 100, 50, 0],
+        };
+
+        pixmap.apply_quirk(PixmapQuirk::SwapRedBlue);
+        assert_eq!(pixmap.pixels, vec![128, 0, 50, 100]);
+
+        pixmap.apply_quirk(PixmapQuirk::Premultiplied);
+        assert_eq!(pixmap.pixels, vec![128, 0, 100, 199]);
+    }
+
+    #[test]
+    fn premultiplied_rgba_reorders_channels() {
+        let pixmap = IconPixmap {
+            width: 2,
+            height: 1,
+            pixels: vec![255, 10, 20, 30, 0, 255, 255, 255],
+        };
+
+        assert_eq!(pixmap.stride(), 8);
+        assert_eq!(
+            pixmap.to_premultiplied_rgba(),
+            vec![10, 20, 30, 255, 0, 0, 0, 0]
+        );
+    }
+}
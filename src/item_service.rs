@@ -0,0 +1,813 @@
+use crate::dbus::notifier_watcher_proxy::StatusNotifierWatcherProxy;
+use crate::error::Result;
+use crate::item::{Category, IconPixmap, Status, StatusNotifierItem, Tooltip};
+use crate::names::{self, ITEM_OBJECT};
+use std::fmt::{Debug, Formatter};
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio::task::AbortHandle;
+use tracing::{debug, error, warn};
+use zbus::export::futures_util::StreamExt;
+use zbus::fdo::{DBusProxy, RequestNameFlags, RequestNameReply};
+use zbus::names::WellKnownName;
+use zbus::zvariant::{ObjectPath, OwnedObjectPath};
+use zbus::{dbus_interface, Connection, SignalContext};
+
+/// Icon pixmaps as sent over the bus.
+type DBusPixmaps = Vec<(i32, i32, Vec<u8>)>;
+
+/// A tooltip as sent over the bus.
+type DBusTooltip = (String, DBusPixmaps, String, String);
+
+type PointerCallback = Arc<dyn Fn(i32, i32) + Send + Sync>;
+type ScrollCallback = Arc<dyn Fn(i32, &str) + Send + Sync>;
+
+/// Counter used to give each item published by this process a unique bus name.
+static NEXT_ITEM: AtomicU32 = AtomicU32::new(1);
+
+/// Callbacks invoked when a host interacts with a published item.
+#[derive(Clone, Default)]
+struct Callbacks {
+    activate: Option<PointerCallback>,
+    secondary_activate: Option<PointerCallback>,
+    context_menu: Option<PointerCallback>,
+    scroll: Option<ScrollCallback>,
+}
+
+impl Debug for Callbacks {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Callbacks")
+            .field("activate", &self.activate.is_some())
+            .field("secondary_activate", &self.secondary_activate.is_some())
+            .field("context_menu", &self.context_menu.is_some())
+            .field("scroll", &self.scroll.is_some())
+            .finish()
+    }
+}
+
+/// The item served as `org.kde.StatusNotifierItem`.
+#[derive(Debug)]
+struct ItemInterface {
+    item: Arc<Mutex<StatusNotifierItem>>,
+    callbacks: Callbacks,
+}
+
+impl ItemInterface {
+    fn read<T>(&self, f: impl FnOnce(&StatusNotifierItem) -> T) -> T {
+        f(&self.item.lock().expect("mutex lock should succeed"))
+    }
+
+    /// Calls a pointer callback,
+    /// replying with an error if the application did not set one.
+    fn call(callback: Option<&PointerCallback>, x: i32, y: i32) -> zbus::fdo::Result<()> {
+        let callback = callback.ok_or_else(|| {
+            zbus::fdo::Error::NotSupported(String::from("not supported by this item"))
+        })?;
+
+        callback(x, y);
+        Ok(())
+    }
+}
+
+#[dbus_interface(name = "org.kde.StatusNotifierItem")]
+impl ItemInterface {
+    /// Activate method
+    fn activate(&self, x: i32, y: i32) -> zbus::fdo::Result<()> {
+        Self::call(self.callbacks.activate.as_ref(), x, y)
+    }
+
+    /// SecondaryActivate method
+    fn secondary_activate(&self, x: i32, y: i32) -> zbus::fdo::Result<()> {
+        Self::call(self.callbacks.secondary_activate.as_ref(), x, y)
+    }
+
+    /// ContextMenu method
+    fn context_menu(&self, x: i32, y: i32) -> zbus::fdo::Result<()> {
+        Self::call(self.callbacks.context_menu.as_ref(), x, y)
+    }
+
+    /// Scroll method
+    fn scroll(&self, delta: i32, orientation: &str) -> zbus::fdo::Result<()> {
+        let callback = self.callbacks.scroll.as_ref().ok_or_else(|| {
+            zbus::fdo::Error::NotSupported(String::from("not supported by this item"))
+        })?;
+
+        callback(delta, orientation);
+        Ok(())
+    }
+
+    /// NewTitle signal
+    #[dbus_interface(signal)]
+    async fn new_title(ctxt: &SignalContext<'_>) -> zbus::Result<()>;
+
+    /// NewIcon signal
+    #[dbus_interface(signal)]
+    async fn new_icon(ctxt: &SignalContext<'_>) -> zbus::Result<()>;
+
+    /// NewAttentionIcon signal
+    #[dbus_interface(signal)]
+    async fn new_attention_icon(ctxt: &SignalContext<'_>) -> zbus::Result<()>;
+
+    /// NewOverlayIcon signal
+    #[dbus_interface(signal)]
+    async fn new_overlay_icon(ctxt: &SignalContext<'_>) -> zbus::Result<()>;
+
+    /// NewToolTip signal
+    #[dbus_interface(signal)]
+    async fn new_tool_tip(ctxt: &SignalContext<'_>) -> zbus::Result<()>;
+
+    /// NewStatus signal
+    #[dbus_interface(signal)]
+    async fn new_status(ctxt: &SignalContext<'_>, status: &str) -> zbus::Result<()>;
+
+    /// Id property
+    #[dbus_interface(property)]
+    fn id(&self) -> String {
+        self.read(|item| item.id.clone())
+    }
+
+    /// Category property
+    #[dbus_interface(property)]
+    fn category(&self) -> String {
+        self.read(|item| item.category.as_str().to_string())
+    }
+
+    /// Title property
+    #[dbus_interface(property)]
+    fn title(&self) -> String {
+        self.read(|item| item.title.clone().unwrap_or_default())
+    }
+
+    /// Status property
+    #[dbus_interface(property)]
+    fn status(&self) -> String {
+        self.read(|item| item.status.as_str().to_string())
+    }
+
+    /// WindowId property
+    #[dbus_interface(property)]
+    fn window_id(&self) -> u32 {
+        self.read(|item| item.window_id)
+    }
+
+    /// IconThemePath property
+    #[dbus_interface(property)]
+    fn icon_theme_path(&self) -> String {
+        self.read(|item| item.icon_theme_path.clone().unwrap_or_default())
+    }
+
+    /// IconName property
+    #[dbus_interface(property)]
+    fn icon_name(&self) -> String {
+        self.read(|item| item.icon_name.clone().unwrap_or_default())
+    }
+
+    /// IconPixmap property
+    #[dbus_interface(property)]
+    fn icon_pixmap(&self) -> DBusPixmaps {
+        self.read(|item| to_dbus_pixmaps(item.icon_pixmap.as_deref()))
+    }
+
+    /// OverlayIconName property
+    #[dbus_interface(property)]
+    fn overlay_icon_name(&self) -> String {
+        self.read(|item| item.overlay_icon_name.clone().unwrap_or_default())
+    }
+
+    /// OverlayIconPixmap property
+    #[dbus_interface(property)]
+    fn overlay_icon_pixmap(&self) -> DBusPixmaps {
+        self.read(|item| to_dbus_pixmaps(item.overlay_icon_pixmap.as_deref()))
+    }
+
+    /// AttentionIconName property
+    #[dbus_interface(property)]
+    fn attention_icon_name(&self) -> String {
+        self.read(|item| item.attention_icon_name.clone().unwrap_or_default())
+    }
+
+    /// AttentionIconPixmap property
+    #[dbus_interface(property)]
+    fn attention_icon_pixmap(&self) -> DBusPixmaps {
+        self.read(|item| to_dbus_pixmaps(item.attention_icon_pixmap.as_deref()))
+    }
+
+    /// AttentionMovieName property
+    #[dbus_interface(property)]
+    fn attention_movie_name(&self) -> String {
+        self.read(|item| item.attention_movie_name.clone().unwrap_or_default())
+    }
+
+    /// ToolTip property
+    #[dbus_interface(property)]
+    fn tool_tip(&self) -> DBusTooltip {
+        self.read(|item| match &item.tool_tip {
+            Some(tooltip) => (
+                tooltip.icon_name.clone(),
+                to_dbus_pixmaps(Some(&tooltip.icon_data)),
+                tooltip.title.clone(),
+                tooltip.description.clone(),
+            ),
+            None => DBusTooltip::default(),
+        })
+    }
+
+    /// ItemIsMenu property
+    #[dbus_interface(property)]
+    fn item_is_menu(&self) -> bool {
+        self.read(|item| item.item_is_menu)
+    }
+
+    /// Menu property
+    #[dbus_interface(property)]
+    fn menu(&self) -> OwnedObjectPath {
+        self.read(|item| {
+            item.menu
+                .as_deref()
+                .and_then(|menu| ObjectPath::try_from(menu).ok())
+                .unwrap_or_else(|| ObjectPath::from_static_str_unchecked("/"))
+                .into()
+        })
+    }
+}
+
+fn to_dbus_pixmaps(pixmaps: Option<&[IconPixmap]>) -> DBusPixmaps {
+    pixmaps
+        .unwrap_or_default()
+        .iter()
+        .map(|pixmap| (pixmap.width, pixmap.height, pixmap.pixels.clone()))
+        .collect()
+}
+
+/// Builder for describing an item before publishing it.
+///
+/// Get one using [`ItemService::builder`].
+#[derive(Debug, Clone)]
+pub struct ItemServiceBuilder {
+    item: StatusNotifierItem,
+    callbacks: Callbacks,
+}
+
+impl ItemServiceBuilder {
+    /// Sets the category of the item.
+    #[must_use]
+    pub fn category(mut self, category: Category) -> Self {
+        self.item.category = category;
+        self
+    }
+
+    /// Sets the title of the item, usually the application name.
+    #[must_use]
+    pub fn title(mut self, title: impl Into<String>) -> Self {
+        self.item.title = Some(title.into());
+        self
+    }
+
+    /// Sets the initial status of the item.
+    /// Defaults to [`Status::Active`].
+    #[must_use]
+    pub fn status(mut self, status: Status) -> Self {
+        self.item.status = status;
+        self
+    }
+
+    /// Sets the ID of the window the item belongs to, if any.
+    #[must_use]
+    pub fn window_id(mut self, window_id: u32) -> Self {
+        self.item.window_id = window_id;
+        self
+    }
+
+    /// Sets an extra path to search for the item's named icons.
+    #[must_use]
+    pub fn icon_theme_path(mut self, path: impl Into<String>) -> Self {
+        self.item.icon_theme_path = Some(path.into());
+        self
+    }
+
+    /// Sets the Freedesktop-compliant name of the icon.
+    #[must_use]
+    pub fn icon_name(mut self, name: impl Into<String>) -> Self {
+        self.item.icon_name = Some(name.into());
+        self
+    }
+
+    /// Sets the icon as ARGB32 pixmaps, for hosts which cannot find the named icon.
+    #[must_use]
+    pub fn icon_pixmap(mut self, pixmap: Vec<IconPixmap>) -> Self {
+        self.item.icon_pixmap = Some(pixmap);
+        self
+    }
+
+    /// Sets the name of an icon to draw over the main icon.
+    #[must_use]
+    pub fn overlay_icon_name(mut self, name: impl Into<String>) -> Self {
+        self.item.overlay_icon_name = Some(name.into());
+        self
+    }
+
+    /// Sets an icon to draw over the main icon as ARGB32 pixmaps.
+    #[must_use]
+    pub fn overlay_icon_pixmap(mut self, pixmap: Vec<IconPixmap>) -> Self {
+        self.item.overlay_icon_pixmap = Some(pixmap);
+        self
+    }
+
+    /// Sets the name of the icon shown while the item needs attention.
+    #[must_use]
+    pub fn attention_icon_name(mut self, name: impl Into<String>) -> Self {
+        self.item.attention_icon_name = Some(name.into());
+        self
+    }
+
+    /// Sets the icon shown while the item needs attention as ARGB32 pixmaps.
+    #[must_use]
+    pub fn attention_icon_pixmap(mut self, pixmap: Vec<IconPixmap>) -> Self {
+        self.item.attention_icon_pixmap = Some(pixmap);
+        self
+    }
+
+    /// Sets the name of an animation shown while the item needs attention.
+    #[must_use]
+    pub fn attention_movie_name(mut self, name: impl Into<String>) -> Self {
+        self.item.attention_movie_name = Some(name.into());
+        self
+    }
+
+    /// Sets the tooltip of the item.
+    #[must_use]
+    pub fn tool_tip(mut self, tooltip: Tooltip) -> Self {
+        self.item.tool_tip = Some(tooltip);
+        self
+    }
+
+    /// Sets whether the item only supports showing its menu,
+    /// so hosts should show the menu on activation.
+    #[must_use]
+    pub fn item_is_menu(mut self, item_is_menu: bool) -> Self {
+        self.item.item_is_menu = item_is_menu;
+        self
+    }
+
+    /// Sets the object path of the item's `com.canonical.dbusmenu` menu,
+    /// which must be served on the item's connection.
+    #[must_use]
+    pub fn menu(mut self, path: impl Into<String>) -> Self {
+        self.item.menu = Some(path.into());
+        self
+    }
+
+    /// Sets a callback invoked when the item is activated, usually by a left click.
+    ///
+    /// The callback receives the screen coordinates of the click.
+    /// Without a callback, activation is rejected with a `NotSupported` error.
+    #[must_use]
+    pub fn on_activate(mut self, callback: impl Fn(i32, i32) + Send + Sync + 'static) -> Self {
+        self.callbacks.activate = Some(Arc::new(callback));
+        self
+    }
+
+    /// Sets a callback invoked on secondary activation, usually by a middle click.
+    ///
+    /// See [`ItemServiceBuilder::on_activate`].
+    #[must_use]
+    pub fn on_secondary_activate(
+        mut self,
+        callback: impl Fn(i32, i32) + Send + Sync + 'static,
+    ) -> Self {
+        self.callbacks.secondary_activate = Some(Arc::new(callback));
+        self
+    }
+
+    /// Sets a callback invoked when the host asks the item to show its own context menu.
+    ///
+    /// This is only needed by items without a [`menu`](ItemServiceBuilder::menu).
+    /// See [`ItemServiceBuilder::on_activate`].
+    #[must_use]
+    pub fn on_context_menu(mut self, callback: impl Fn(i32, i32) + Send + Sync + 'static) -> Self {
+        self.callbacks.context_menu = Some(Arc::new(callback));
+        self
+    }
+
+    /// Sets a callback invoked when the item is scrolled over.
+    ///
+    /// The callback receives the scroll delta and its orientation,
+    /// which is either `vertical` or `horizontal`.
+    #[must_use]
+    pub fn on_scroll(mut self, callback: impl Fn(i32, &str) + Send + Sync + 'static) -> Self {
+        self.callbacks.scroll = Some(Arc::new(callback));
+        self
+    }
+
+    /// Publishes the item on the session bus
+    /// and registers it with the `StatusNotifierWatcher`.
+    ///
+    /// The item is registered again whenever a new watcher starts.
+    /// If no watcher is running yet, the item is registered once one starts.
+    ///
+    /// # Errors
+    ///
+    /// If connecting to the bus or serving the item fails,
+    /// or if the watcher rejects the registration.
+    pub async fn publish(self) -> Result<ItemService> {
+        let connection = Connection::session().await?;
+        self.publish_on(connection).await
+    }
+
+    /// Publishes the item on the bus of `connection`.
+    /// See [`ItemServiceBuilder::publish`].
+    pub(crate) async fn publish_on(self, connection: Connection) -> Result<ItemService> {
+        // served before the name is requested,
+        // so that hosts which see the name appear can call the item straight away
+        let item = Arc::new(Mutex::new(self.item));
+        connection
+            .object_server()
+            .at(
+                ITEM_OBJECT,
+                ItemInterface {
+                    item: item.clone(),
+                    callbacks: self.callbacks,
+                },
+            )
+            .await?;
+
+        let bus_name = request_item_name(&connection).await?;
+
+        // subscribe before registering so that a watcher starting in between is not missed
+        let dbus_proxy = DBusProxy::new(&connection).await?;
+        let mut watcher_changed = dbus_proxy
+            .receive_name_owner_changed_with_args(&[(0, names::WATCHER_BUS)])
+            .await?;
+
+        let watcher_proxy = StatusNotifierWatcherProxy::new(&connection).await?;
+        match watcher_proxy.register_status_notifier_item(&bus_name).await {
+            Ok(()) => {}
+            Err(err) if is_missing_watcher(&err) => {
+                warn!("no watcher is running, {bus_name} will register once one starts");
+            }
+            Err(err) => return Err(err.into()),
+        }
+
+        let registration = {
+            let bus_name = bus_name.clone();
+
+            tokio::spawn(async move {
+                while let Some(signal) = watcher_changed.next().await {
+                    let Ok(args) = signal.args() else {
+                        continue;
+                    };
+
+                    if args.new_owner().is_none() {
+                        continue;
+                    }
+
+                    debug!("watcher changed, registering {bus_name} again");
+                    if let Err(err) = watcher_proxy.register_status_notifier_item(&bus_name).await {
+                        error!("failed to register {bus_name}: {err}");
+                    }
+                }
+            })
+            .abort_handle()
+        };
+
+        Ok(ItemService {
+            connection,
+            bus_name,
+            item,
+            registration,
+        })
+    }
+}
+
+/// Requests a well-known name of the form `org.kde.StatusNotifierItem-<pid>-<n>`
+/// for a new item.
+async fn request_item_name(connection: &Connection) -> Result<String> {
+    let pid = std::process::id();
+
+    loop {
+        let n = NEXT_ITEM.fetch_add(1, Ordering::Relaxed);
+        let name = format!("org.kde.StatusNotifierItem-{pid}-{n}");
+        let wellknown = WellKnownName::try_from(name.as_str()).map_err(zbus::Error::from)?;
+
+        let flags = [RequestNameFlags::DoNotQueue];
+        match connection
+            .request_name_with_flags(wellknown, flags.into_iter().collect())
+            .await?
+        {
+            RequestNameReply::PrimaryOwner | RequestNameReply::AlreadyOwner => break Ok(name),
+            RequestNameReply::Exists => {}
+            // the request is never queued with `DoNotQueue`,
+            // but would mean the name belongs to someone else
+            RequestNameReply::InQueue => return Err(zbus::Error::NameTaken.into()),
+        }
+    }
+}
+
+/// Checks whether an error indicates that no watcher is running.
+fn is_missing_watcher(err: &zbus::Error) -> bool {
+    match err {
+        zbus::Error::MethodError(name, _, _) => {
+            name.as_str() == "org.freedesktop.DBus.Error.ServiceUnknown"
+        }
+        zbus::Error::FDO(err) => matches!(**err, zbus::fdo::Error::ServiceUnknown(_)),
+        _ => false,
+    }
+}
+
+/// Which signal to emit after changing an item.
+#[derive(Debug, Clone, Copy)]
+enum Change {
+    Title,
+    Icon,
+    AttentionIcon,
+    OverlayIcon,
+    ToolTip,
+    Status,
+}
+
+/// A `StatusNotifierItem` published on the bus by this process.
+///
+/// Changes made through the handle are signalled to hosts immediately.
+/// The item is removed from the bus when the handle is dropped or [closed](ItemService::close).
+pub struct ItemService {
+    connection: Connection,
+    bus_name: String,
+    item: Arc<Mutex<StatusNotifierItem>>,
+    registration: AbortHandle,
+}
+
+impl Debug for ItemService {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ItemService")
+            .field("bus_name", &self.bus_name)
+            .finish_non_exhaustive()
+    }
+}
+
+impl ItemService {
+    /// Creates a builder for an item with the given ID.
+    ///
+    /// The ID should be unique to the application and consistent between sessions,
+    /// such as the application name.
+    #[must_use]
+    pub fn builder(id: impl Into<String>) -> ItemServiceBuilder {
+        ItemServiceBuilder {
+            item: StatusNotifierItem {
+                id: id.into(),
+                status: Status::Active,
+                ..StatusNotifierItem::default()
+            },
+            callbacks: Callbacks::default(),
+        }
+    }
+
+    /// Gets the well-known bus name the item is published under.
+    #[must_use]
+    pub fn bus_name(&self) -> &str {
+        &self.bus_name
+    }
+
+    /// Gets the connection the item is served on.
+    ///
+    /// This can be used to serve the item's menu on the same connection.
+    pub fn connection(&self) -> &Connection {
+        &self.connection
+    }
+
+    /// Gets a copy of the item's current properties.
+    ///
+    /// # Panics
+    ///
+    /// If the item mutex is poisoned.
+    #[must_use]
+    pub fn item(&self) -> StatusNotifierItem {
+        self.item.lock().expect("mutex lock should succeed").clone()
+    }
+
+    /// Sets the title of the item.
+    ///
+    /// # Errors
+    ///
+    /// If the change cannot be signalled.
+    pub async fn set_title(&self, title: impl Into<String>) -> Result<()> {
+        let title = title.into();
+        self.update(Change::Title, |item| item.title = Some(title))
+            .await
+    }
+
+    /// Sets the status of the item.
+    ///
+    /// # Errors
+    ///
+    /// If the change cannot be signalled.
+    pub async fn set_status(&self, status: Status) -> Result<()> {
+        self.update(Change::Status, |item| item.status = status)
+            .await
+    }
+
+    /// Sets the name of the item's icon.
+    ///
+    /// # Errors
+    ///
+    /// If the change cannot be signalled.
+    pub async fn set_icon_name(&self, name: impl Into<String>) -> Result<()> {
+        let name = name.into();
+        self.update(Change::Icon, |item| item.icon_name = Some(name))
+            .await
+    }
+
+    /// Sets the item's icon pixmaps.
+    ///
+    /// # Errors
+    ///
+    /// If the change cannot be signalled.
+    pub async fn set_icon_pixmap(&self, pixmap: Vec<IconPixmap>) -> Result<()> {
+        self.update(Change::Icon, |item| item.icon_pixmap = Some(pixmap))
+            .await
+    }
+
+    /// Sets or clears the name of the icon drawn over the main icon.
+    ///
+    /// # Errors
+    ///
+    /// If the change cannot be signalled.
+    pub async fn set_overlay_icon_name(&self, name: Option<String>) -> Result<()> {
+        self.update(Change::OverlayIcon, |item| item.overlay_icon_name = name)
+            .await
+    }
+
+    /// Sets or clears the name of the icon shown while the item needs attention.
+    ///
+    /// # Errors
+    ///
+    /// If the change cannot be signalled.
+    pub async fn set_attention_icon_name(&self, name: Option<String>) -> Result<()> {
+        self.update(Change::AttentionIcon, |item| {
+            item.attention_icon_name = name;
+        })
+        .await
+    }
+
+    /// Sets or clears the item's tooltip.
+    ///
+    /// # Errors
+    ///
+    /// If the change cannot be signalled.
+    pub async fn set_tool_tip(&self, tooltip: Option<Tooltip>) -> Result<()> {
+        self.update(Change::ToolTip, |item| item.tool_tip = tooltip)
+            .await
+    }
+
+    /// Removes the item from the bus, waiting for this to complete.
+    ///
+    /// # Errors
+    ///
+    /// If the bus name cannot be released.
+    pub async fn close(self) -> Result<()> {
+        self.registration.abort();
+
+        self.connection
+            .object_server()
+            .remove::<ItemInterface, _>(ITEM_OBJECT)
+            .await?;
+
+        let name = WellKnownName::try_from(self.bus_name.as_str()).map_err(zbus::Error::from)?;
+        self.connection.release_name(name).await?;
+
+        Ok(())
+    }
+
+    /// Applies a change to the item and emits the matching signal.
+    async fn update(&self, change: Change, f: impl FnOnce(&mut StatusNotifierItem)) -> Result<()> {
+        let status = {
+            let mut item = self.item.lock().expect("mutex lock should succeed");
+            f(&mut item);
+            item.status
+        };
+
+        let ctxt = SignalContext::new(&self.connection, ITEM_OBJECT)?;
+
+        match change {
+            Change::Title => ItemInterface::new_title(&ctxt).await?,
+            Change::Icon => ItemInterface::new_icon(&ctxt).await?,
+            Change::AttentionIcon => ItemInterface::new_attention_icon(&ctxt).await?,
+            Change::OverlayIcon => ItemInterface::new_overlay_icon(&ctxt).await?,
+            Change::ToolTip => ItemInterface::new_tool_tip(&ctxt).await?,
+            Change::Status => ItemInterface::new_status(&ctxt, status.as_str()).await?,
+        }
+
+        Ok(())
+    }
+}
+
+impl Drop for ItemService {
+    fn drop(&mut self) {
+        self.registration.abort();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::{ActivateRequest, Event, UpdateEvent};
+    use crate::test_bus::{recv_until, TestBus};
+    use std::time::Duration;
+    use tokio::sync::mpsc;
+    use tokio::time::timeout;
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn publishes_item() {
+        let bus = TestBus::start();
+        let client = bus.client().await;
+
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let service = ItemService::builder("service")
+            .title("Service")
+            .icon_name("service")
+            .on_activate(move |x, y| {
+                let _ = tx.send((x, y));
+            })
+            .publish_on(bus.connect().await)
+            .await
+            .expect("item should be published");
+
+        let (address, item) = client
+            .wait_for_item("service", Duration::from_secs(5))
+            .await
+            .expect("item should appear");
+        assert_eq!(
+            Some(address.as_str()),
+            service.connection().unique_name().map(|name| name.as_str())
+        );
+        assert_eq!(item.title.as_deref(), Some("Service"));
+        assert_eq!(item.icon_name.as_deref(), Some("service"));
+
+        client
+            .activate(ActivateRequest::Default {
+                address: address.clone(),
+                x: 1,
+                y: 2,
+                token: None,
+            })
+            .await
+            .expect("activation should succeed");
+        assert_eq!(
+            timeout(Duration::from_secs(5), rx.recv()).await,
+            Ok(Some((1, 2)))
+        );
+
+        let mut events = client.subscribe();
+
+        // the client only watches for changes after adding the item,
+        // so the change is repeated until it is seen
+        timeout(Duration::from_secs(5), async {
+            loop {
+                service
+                    .set_title("Changed")
+                    .await
+                    .expect("title should be set");
+
+                let changed = timeout(Duration::from_millis(200), async {
+                    loop {
+                        if let Ok(Event::Update(_, UpdateEvent::Title(title))) = events.recv().await
+                        {
+                            break title;
+                        }
+                    }
+                })
+                .await;
+
+                if let Ok(title) = changed {
+                    break title;
+                }
+            }
+        })
+        .await
+        .map(|title| assert_eq!(title.as_deref(), Some("Changed")))
+        .expect("title change should be sent");
+
+        service.close().await.expect("item should be removed");
+        recv_until(&mut events, |event| match event {
+            Event::Remove(removed) if removed == address => Some(()),
+            _ => None,
+        })
+        .await;
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn registers_once_watcher_starts() {
+        let bus = TestBus::start();
+
+        // there is no watcher until the client starts
+        let _service = ItemService::builder("early")
+            .publish_on(bus.connect().await)
+            .await
+            .expect("item should be published without a watcher");
+
+        let client = bus.client().await;
+        client
+            .wait_for_item("early", Duration::from_secs(5))
+            .await
+            .expect("item should register with the new watcher");
+    }
+}
@@ -12,9 +12,9 @@
 /// #[tokio::main]
 /// async fn main() {
 ///     let client = Client::new().await.unwrap();
-///     let mut tray_rx = client.subscribe();
+///     let (snapshot, mut tray_rx) = client.subscribe_with_state();
 ///
-///     let initial_items = client.items();
+///     let initial_items = snapshot.items;
 ///
 ///     // do something with initial items...
 ///
@@ -29,20 +29,80 @@ mod dbus;
 /// and associated types.
 pub mod client;
 
+/// Abstraction over sources of tray items.
+pub mod backend;
+
 /// Error and result types.
 pub mod error;
 
+/// Resolution of items to their application's desktop entry.
+pub mod desktop;
+
+/// Optional layer for grouping items by application.
+pub mod group;
+
+/// Running the client alongside a glib main loop.
+#[cfg(feature = "glib")]
+pub mod glib_client;
+
+/// Resolution of icon names to files using freedesktop icon themes.
+#[cfg(feature = "icon-resolver")]
+pub mod icon_resolver;
+
 /// `StatusNotifierItem` item representation.
 pub mod item;
 
+/// Publishing this application's own `StatusNotifierItem` on the bus.
+pub mod item_service;
+
 /// `DBusMenu` menu representation.
 pub mod menu;
 
+/// Optional ready-to-render list of tray entries.
+pub mod model;
+
+/// Detection of Flatpak and Snap confinement.
+pub mod sandbox;
+
+/// Raw `zbus` proxies for the `StatusNotifierItem`, `StatusNotifierWatcher`
+/// and `DBusMenu` interfaces.
+///
+/// These allow making calls which this crate does not wrap yet,
+/// without duplicating the interface definitions.
+///
+/// The proxies are generated from the interface definitions and follow `zbus`,
+/// so they are **not** covered by the same stability guarantees as the rest of the crate.
+/// They may change in any release, including when `zbus` is upgraded.
+pub mod proxies {
+    pub use crate::dbus::dbus_menu_proxy;
+    pub use crate::dbus::notifier_item_proxy;
+    pub use crate::dbus::notifier_watcher_proxy;
+}
+
+/// Running a standalone `StatusNotifierWatcher` service.
+pub mod watcher {
+    pub use crate::dbus::status_notifier_watcher::{
+        AdmissionPolicy, Registration, RegistrationKind, StatusNotifierWatcher, WatcherEvent,
+        WatcherHandle,
+    };
+    pub use zbus::fdo::RequestNameFlags;
+}
+
 #[cfg(feature = "dbusmenu-gtk3")]
 pub mod gtk_menu;
 
+/// Utilities for testing code which consumes tray events.
+#[cfg(feature = "test-util")]
+pub mod test_util;
+
+#[cfg(test)]
+mod test_bus;
+
 pub(crate) mod names {
+    pub const DBUS_BUS: &str = "org.freedesktop.DBus";
+
     pub const WATCHER_BUS: &str = "org.kde.StatusNotifierWatcher";
+    pub const WATCHER_BUS_FREEDESKTOP: &str = "org.freedesktop.StatusNotifierWatcher";
     pub const WATCHER_OBJECT: &str = "/StatusNotifierWatcher";
 
     pub const ITEM_OBJECT: &str = "/StatusNotifierItem";
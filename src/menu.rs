@@ -1,19 +1,54 @@
 use crate::dbus::dbus_menu_proxy::{MenuLayout, PropertiesUpdate, UpdatedProps};
 use crate::error::{Error, Result};
 use serde::Deserialize;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, OnceLock};
 use zbus::zvariant;
 use zbus::zvariant::{Array, OwnedValue, Structure, Value};
 
+/// Serving menus defined in Rust over `com.canonical.dbusmenu`.
+pub mod server;
+mod shortcut;
+
+pub use shortcut::{KeyPress, Modifier, Modifiers, Shortcut};
+
 /// A menu that should be displayed when clicking corresponding tray icon
-#[derive(Debug, Clone)]
+///
+/// Submenus are stored behind an [`Arc`], so cloning a menu is cheap
+/// and clones share their items.
+/// Modifying a clone, for example using [`TrayMenu::replace_submenu`],
+/// only copies the items along the path to the change.
+///
+/// Looking up items by id uses an index of where each item is,
+/// which is built on first use.
+/// The index is checked before use, so `submenus` can still be modified directly.
+#[derive(Clone, Default)]
 pub struct TrayMenu {
     /// The unique identifier of the menu
     pub id: u32,
     /// A recursive list of submenus
-    pub submenus: Vec<MenuItem>,
+    pub submenus: Arc<Vec<MenuItem>>,
+    /// Extra directories to look up the icon names of items in,
+    /// from the menu's `IconThemePath` property.
+    pub icon_theme_path: Vec<String>,
+    // boxed to keep menus, and so events, small while the index is unused
+    index: OnceLock<Box<MenuIndex>>,
+}
+
+impl std::fmt::Debug for TrayMenu {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TrayMenu")
+            .field("id", &self.id)
+            .field("submenus", &self.submenus)
+            .field("icon_theme_path", &self.icon_theme_path)
+            .finish_non_exhaustive()
+    }
 }
 
+/// Maps the id of every item in a menu to its position,
+/// as the index of each item along the path to it.
+type MenuIndex = HashMap<i32, Vec<usize>>;
+
 /// List of properties taken from:
 /// <https://github.com/AyatanaIndicators/libdbusmenu/blob/4d03141aea4e2ad0f04ab73cf1d4f4bcc4a19f6c/libdbusmenu-glib/dbus-menu.xml#L75>
 #[derive(Debug, Clone, Deserialize, Default)]
@@ -30,6 +65,9 @@ pub struct MenuItem {
     ///  - the first of those remaining underscore characters (unless it is
     ///    the last character in the string) indicates that the following
     ///    character is the access key.
+    ///
+    /// See [`MenuItem::label_plain`] and [`MenuItem::access_key`]
+    /// to get the text to display and the access key.
     pub label: Option<String>,
     /// Whether the item can be activated or not.
     pub enabled: bool,
@@ -48,6 +86,8 @@ pub struct MenuItem {
     ///   [["Control", "S"]]
     /// - A complex shortcut like Ctrl+Q, Alt+X is represented as:
     ///   [["Control", "Q"], ["Alt", "X"]]
+    ///
+    /// See [`MenuItem::parse_shortcut`] for a typed representation.
     pub shortcut: Option<Vec<Vec<String>>>,
     /// How the menuitem feels the information it's displaying to the
     /// user should be presented.
@@ -69,8 +109,669 @@ pub struct MenuItem {
     /// user should be presented.
     /// See [`Disposition`]
     pub disposition: Disposition,
+    /// Properties which are not part of the specification,
+    /// such as `x-kde-*` extensions or `accessible-desc`, keyed by property name.
+    #[serde(default)]
+    pub extra: HashMap<String, OwnedValue>,
     /// Nested submenu items belonging to this item.
-    pub submenu: Vec<MenuItem>,
+    pub submenu: Arc<Vec<MenuItem>>,
+}
+
+/// The properties defined by the specification,
+/// which are parsed into the fields of a [`MenuItem`]
+/// rather than kept in [`MenuItem::extra`].
+const KNOWN_PROPERTIES: &[&str] = &[
+    "type",
+    "label",
+    "enabled",
+    "visible",
+    "icon-name",
+    "icon-data",
+    "shortcut",
+    "toggle-type",
+    "toggle-state",
+    "children-display",
+    "children_display",
+    "disposition",
+];
+
+/// A menu item found by [`TrayMenu::search`].
+#[derive(Debug, Clone)]
+pub struct MenuSearchResult {
+    /// The matching item.
+    pub item: MenuItem,
+    /// The labels of the submenus containing the item,
+    /// from the top level down.
+    pub path: Vec<String>,
+}
+
+/// The differences between two versions of a menu,
+/// found by [`TrayMenu::diff`].
+///
+/// Item ids are listed in menu order.
+#[derive(Debug, Clone, Default)]
+pub struct MenuChanges {
+    /// Property changes to items in both versions,
+    /// which can be applied using [`TrayMenu::apply_diff`].
+    pub updated: Vec<MenuDiff>,
+    /// The ids of items which are only in the new version.
+    pub added: Vec<i32>,
+    /// The ids of items which are only in the old version.
+    pub removed: Vec<i32>,
+    /// The ids of items whose children were added, removed or reordered,
+    /// or `0` if the top-level items were.
+    pub restructured: Vec<i32>,
+    /// The ids of items in both versions whose type, toggle type, shortcut
+    /// or children display changed.
+    /// These cannot be expressed as a [`MenuDiff`],
+    /// so the items should be rebuilt from the new version.
+    pub replaced: Vec<i32>,
+}
+
+impl MenuChanges {
+    /// Checks whether the two versions of the menu are the same.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.updated.is_empty()
+            && self.added.is_empty()
+            && self.removed.is_empty()
+            && self.restructured.is_empty()
+            && self.replaced.is_empty()
+    }
+}
+
+impl TrayMenu {
+    /// Creates a menu from its top-level items.
+    #[must_use]
+    pub fn new(id: u32, submenus: impl Into<Arc<Vec<MenuItem>>>) -> Self {
+        Self {
+            id,
+            submenus: submenus.into(),
+            icon_theme_path: Vec::new(),
+            index: OnceLock::new(),
+        }
+    }
+
+    /// Recursively searches the menu for an enabled, visible item
+    /// whose shortcut is the single key press of `key` with exactly `modifiers` held.
+    ///
+    /// The key is compared case-insensitively.
+    #[must_use]
+    pub fn find_by_shortcut(&self, modifiers: &[Modifier], key: &str) -> Option<&MenuItem> {
+        find_by_shortcut(&self.submenus, modifiers, key)
+    }
+
+    /// Recursively searches the menu for enabled, visible items
+    /// whose label contains `query`, compared case-insensitively.
+    ///
+    /// Only items which can be clicked to trigger an action are included,
+    /// not separators or items which open a submenu.
+    /// Results are in menu order.
+    #[must_use]
+    pub fn search(&self, query: &str) -> Vec<MenuSearchResult> {
+        let mut results = Vec::new();
+        search(
+            &self.submenus,
+            &query.to_lowercase(),
+            &mut Vec::new(),
+            &mut results,
+        );
+        results
+    }
+
+    /// Finds the item with the given id, wherever it is in the menu.
+    #[must_use]
+    pub fn find(&self, id: i32) -> Option<&MenuItem> {
+        match self.indexed_path(id) {
+            Some(path) => item_at(&self.submenus, path),
+            // the index may be out of date if `submenus` was modified directly
+            None => find(&self.submenus, id),
+        }
+    }
+
+    /// Looks up the position of an item in the index,
+    /// if the index has it and is correct for it.
+    fn indexed_path(&self, id: i32) -> Option<&[usize]> {
+        let path = self
+            .index
+            .get_or_init(|| Box::new(index(&self.submenus)))
+            .get(&id)?;
+
+        item_at(&self.submenus, path)
+            .is_some_and(|item| item.id == id)
+            .then_some(path.as_slice())
+    }
+
+    /// Replaces the children of the item with id `parent_id`,
+    /// or the top-level items if `parent_id` is `0`.
+    ///
+    /// Returns `false` if no item with the id exists.
+    pub fn replace_submenu(
+        &mut self,
+        parent_id: i32,
+        submenu: impl Into<Arc<Vec<MenuItem>>>,
+    ) -> bool {
+        let submenu = submenu.into();
+
+        if parent_id == 0 {
+            self.submenus = submenu;
+            self.index = OnceLock::new();
+            return true;
+        }
+
+        match find_mut(&mut self.submenus, parent_id) {
+            Some(parent) => {
+                parent.submenu = submenu;
+                self.index = OnceLock::new();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Applies property changes from [`UpdateEvent::MenuDiff`](crate::client::UpdateEvent::MenuDiff)
+    /// to the items they refer to, wherever they are in the menu.
+    ///
+    /// Changes to items which are not in the menu are ignored.
+    pub fn apply_diff(&mut self, diffs: &[MenuDiff]) {
+        let mut rebuilt = false;
+
+        for diff in diffs {
+            let path = match self.indexed_path(diff.id) {
+                Some(path) => path.to_vec(),
+                // rebuild an out of date index once,
+                // rather than for every missing item
+                None if !rebuilt => {
+                    rebuilt = true;
+                    self.index = OnceLock::new();
+
+                    let Some(path) = self.indexed_path(diff.id) else {
+                        continue;
+                    };
+                    path.to_vec()
+                }
+                None => continue,
+            };
+
+            if let Some(item) = item_at_mut(&mut self.submenus, &path) {
+                item.apply_diff(diff);
+            }
+        }
+    }
+
+    /// Finds the changes between two versions of a menu,
+    /// matching up items by id.
+    ///
+    /// This allows UIs receiving whole menus from [`UpdateEvent::Menu`](crate::client::UpdateEvent::Menu)
+    /// to update only the parts which changed, rather than rebuilding the whole menu.
+    #[must_use]
+    pub fn diff(old: &TrayMenu, new: &TrayMenu) -> MenuChanges {
+        let old_items: HashMap<_, _> = flatten(&old.submenus)
+            .into_iter()
+            .map(|item| (item.id, item))
+            .collect();
+        let new_items = flatten(&new.submenus);
+
+        let mut changes = MenuChanges::default();
+
+        if !same_ids(&old.submenus, &new.submenus) {
+            changes.restructured.push(0);
+        }
+
+        for item in &new_items {
+            let Some(old_item) = old_items.get(&item.id) else {
+                changes.added.push(item.id);
+                continue;
+            };
+
+            if !same_ids(&old_item.submenu, &item.submenu) {
+                changes.restructured.push(item.id);
+            }
+
+            let (diff, replaced) = diff_item(old_item, item);
+            if let Some(diff) = diff {
+                changes.updated.push(diff);
+            }
+            if replaced {
+                changes.replaced.push(item.id);
+            }
+        }
+
+        let new_ids: HashSet<_> = new_items.iter().map(|item| item.id).collect();
+        changes.removed = flatten(&old.submenus)
+            .into_iter()
+            .map(|item| item.id)
+            .filter(|id| !new_ids.contains(id))
+            .collect();
+
+        changes
+    }
+}
+
+/// Lists every item in a menu, parents before their children.
+fn flatten(items: &[MenuItem]) -> Vec<&MenuItem> {
+    fn walk<'a>(items: &'a [MenuItem], flat: &mut Vec<&'a MenuItem>) {
+        for item in items {
+            flat.push(item);
+            walk(&item.submenu, flat);
+        }
+    }
+
+    let mut flat = Vec::new();
+    walk(items, &mut flat);
+    flat
+}
+
+/// Checks whether two lists of items have the same ids in the same order.
+fn same_ids(old: &[MenuItem], new: &[MenuItem]) -> bool {
+    old.len() == new.len() && old.iter().zip(new).all(|(old, new)| old.id == new.id)
+}
+
+/// Finds the property changes between two versions of an item,
+/// and whether any of them cannot be expressed as a [`MenuDiff`].
+fn diff_item(old: &MenuItem, new: &MenuItem) -> (Option<MenuDiff>, bool) {
+    /// Gets the new value if it differs from the old one.
+    fn changed<T: PartialEq + Clone>(old: &T, new: &T) -> Option<T> {
+        (old != new).then(|| new.clone())
+    }
+
+    let update = MenuItemUpdate {
+        label: changed(&old.label, &new.label),
+        enabled: changed(&old.enabled, &new.enabled),
+        visible: changed(&old.visible, &new.visible),
+        icon_name: changed(&old.icon_name, &new.icon_name),
+        icon_data: changed(&old.icon_data, &new.icon_data),
+        toggle_state: changed(&old.toggle_state, &new.toggle_state),
+        disposition: changed(&old.disposition, &new.disposition),
+        extra: new
+            .extra
+            .iter()
+            .filter(|(key, value)| old.extra.get(*key) != Some(*value))
+            .map(|(key, value)| (key.clone(), value.clone()))
+            .collect(),
+    };
+
+    // the remaining properties can only be changed by resetting them to their defaults
+    let mut remove = Vec::new();
+    let mut replaced = false;
+
+    let mut reset = |name: &str, is_changed: bool, is_default: bool| {
+        if is_changed {
+            if is_default {
+                remove.push(name.to_string());
+            } else {
+                replaced = true;
+            }
+        }
+    };
+
+    reset(
+        "shortcut",
+        old.shortcut != new.shortcut,
+        new.shortcut.is_none(),
+    );
+    reset(
+        "toggle-type",
+        old.toggle_type != new.toggle_type,
+        new.toggle_type == ToggleType::default(),
+    );
+    reset(
+        "children-display",
+        old.children_display != new.children_display,
+        new.children_display.is_none(),
+    );
+    reset(
+        "type",
+        old.menu_type != new.menu_type,
+        new.menu_type == MenuType::default(),
+    );
+
+    remove.extend(
+        old.extra
+            .keys()
+            .filter(|key| !new.extra.contains_key(*key))
+            .cloned(),
+    );
+
+    let is_unchanged = update.label.is_none()
+        && update.enabled.is_none()
+        && update.visible.is_none()
+        && update.icon_name.is_none()
+        && update.icon_data.is_none()
+        && update.toggle_state.is_none()
+        && update.disposition.is_none()
+        && update.extra.is_empty()
+        && remove.is_empty();
+
+    let diff = (!is_unchanged).then_some(MenuDiff {
+        id: new.id,
+        update,
+        remove,
+    });
+
+    (diff, replaced)
+}
+
+fn index(items: &[MenuItem]) -> MenuIndex {
+    fn walk(items: &[MenuItem], path: &mut Vec<usize>, index: &mut MenuIndex) {
+        for (i, item) in items.iter().enumerate() {
+            path.push(i);
+            index.insert(item.id, path.clone());
+            walk(&item.submenu, path, index);
+            path.pop();
+        }
+    }
+
+    let mut index = HashMap::new();
+    walk(items, &mut Vec::new(), &mut index);
+    index
+}
+
+/// Gets the item at a position from [`index`].
+fn item_at<'a>(items: &'a [MenuItem], path: &[usize]) -> Option<&'a MenuItem> {
+    let (first, rest) = path.split_first()?;
+    let item = items.get(*first)?;
+
+    if rest.is_empty() {
+        Some(item)
+    } else {
+        item_at(&item.submenu, rest)
+    }
+}
+
+/// Gets the item at a position from [`index`] for modification,
+/// copying only the shared lists along the path to it.
+fn item_at_mut<'a>(items: &'a mut Arc<Vec<MenuItem>>, path: &[usize]) -> Option<&'a mut MenuItem> {
+    let (first, rest) = path.split_first()?;
+    let item = Arc::make_mut(items).get_mut(*first)?;
+
+    if rest.is_empty() {
+        Some(item)
+    } else {
+        item_at_mut(&mut item.submenu, rest)
+    }
+}
+
+fn find(items: &[MenuItem], id: i32) -> Option<&MenuItem> {
+    items.iter().find_map(|item| {
+        if item.id == id {
+            Some(item)
+        } else {
+            find(&item.submenu, id)
+        }
+    })
+}
+
+fn search(
+    items: &[MenuItem],
+    query: &str,
+    path: &mut Vec<String>,
+    results: &mut Vec<MenuSearchResult>,
+) {
+    for item in items
+        .iter()
+        .filter(|item| item.visible && item.menu_type == MenuType::Standard)
+    {
+        let label = item.label_plain().unwrap_or_default();
+
+        if item.submenu.is_empty() {
+            if item.enabled && label.to_lowercase().contains(query) {
+                results.push(MenuSearchResult {
+                    item: item.clone(),
+                    path: path.clone(),
+                });
+            }
+        } else if item.enabled {
+            path.push(label);
+            search(&item.submenu, query, path, results);
+            path.pop();
+        }
+    }
+}
+
+/// Iterates over the characters of a label to display,
+/// along with whether each is the access key.
+/// See [`MenuItem::label`].
+fn label_chars(label: &str) -> impl Iterator<Item = (char, bool)> + '_ {
+    let mut chars = label.chars();
+    let mut found_key = false;
+
+    std::iter::from_fn(move || match chars.next()? {
+        '_' => match chars.next()? {
+            '_' => Some(('_', false)),
+            c => Some((c, !std::mem::replace(&mut found_key, true))),
+        },
+        c => Some((c, false)),
+    })
+}
+
+/// Finds an item for modification,
+/// copying only the shared lists along the path to it.
+fn find_mut(items: &mut Arc<Vec<MenuItem>>, id: i32) -> Option<&mut MenuItem> {
+    let index = items
+        .iter()
+        .position(|item| item.id == id || find(&item.submenu, id).is_some())?;
+
+    let item = &mut Arc::make_mut(items)[index];
+    if item.id == id {
+        Some(item)
+    } else {
+        find_mut(&mut item.submenu, id)
+    }
+}
+
+fn find_by_shortcut<'a>(
+    items: &'a [MenuItem],
+    modifiers: &[Modifier],
+    key: &str,
+) -> Option<&'a MenuItem> {
+    items.iter().find_map(|item| {
+        if item.enabled && item.visible && item.matches_shortcut(modifiers, key) {
+            Some(item)
+        } else {
+            find_by_shortcut(&item.submenu, modifiers, key)
+        }
+    })
+}
+
+impl MenuItem {
+    /// Applies property changes to the item,
+    /// resetting removed properties to their defaults.
+    pub(crate) fn apply_diff(&mut self, diff: &MenuDiff) {
+        let update = &diff.update;
+
+        if let Some(label) = &update.label {
+            self.label.clone_from(label);
+        }
+        if let Some(enabled) = update.enabled {
+            self.enabled = enabled;
+        }
+        if let Some(visible) = update.visible {
+            self.visible = visible;
+        }
+        if let Some(icon_name) = &update.icon_name {
+            self.icon_name.clone_from(icon_name);
+        }
+        if let Some(icon_data) = &update.icon_data {
+            self.icon_data.clone_from(icon_data);
+        }
+        if let Some(toggle_state) = update.toggle_state {
+            self.toggle_state = toggle_state;
+        }
+        if let Some(disposition) = update.disposition {
+            self.disposition = disposition;
+        }
+        self.extra.extend(
+            update
+                .extra
+                .iter()
+                .map(|(key, value)| (key.clone(), value.clone())),
+        );
+
+        for property in &diff.remove {
+            match property.as_str() {
+                "label" => self.label = None,
+                "enabled" => self.enabled = true,
+                "visible" => self.visible = true,
+                "icon-name" => self.icon_name = None,
+                "icon-data" => self.icon_data = None,
+                "shortcut" => self.shortcut = None,
+                "toggle-type" => self.toggle_type = ToggleType::default(),
+                "toggle-state" => self.toggle_state = ToggleState::default(),
+                "children-display" => self.children_display = None,
+                "disposition" => self.disposition = Disposition::default(),
+                "type" => self.menu_type = MenuType::default(),
+                property => {
+                    self.extra.remove(property);
+                }
+            }
+        }
+    }
+
+    /// Gets the item's label as it should be displayed,
+    /// without the underscores marking its access key.
+    #[must_use]
+    pub fn label_plain(&self) -> Option<String> {
+        self.label
+            .as_deref()
+            .map(|label| label_chars(label).map(|(c, _)| c).collect())
+    }
+
+    /// Gets the character marked as the item's access key in its label, if any.
+    #[must_use]
+    pub fn access_key(&self) -> Option<char> {
+        self.label
+            .as_deref()
+            .and_then(|label| label_chars(label).find_map(|(c, is_key)| is_key.then_some(c)))
+    }
+
+    /// Gets the item's label with its access key marked
+    /// using the convention of a particular toolkit.
+    ///
+    /// Characters used as the marker are escaped,
+    /// so that they are displayed as written.
+    #[must_use]
+    pub fn label_with_mnemonic(&self, style: MnemonicStyle) -> Option<String> {
+        let Some(marker) = style.marker() else {
+            return self.label_plain();
+        };
+
+        self.label.as_deref().map(|label| {
+            let mut converted = String::with_capacity(label.len());
+
+            for (c, is_key) in label_chars(label) {
+                if is_key {
+                    converted.push(marker);
+                }
+
+                if c == marker {
+                    converted.push(marker);
+                }
+
+                converted.push(c);
+            }
+
+            converted
+        })
+    }
+
+    /// Parses the item's shortcut, if it has a valid one.
+    #[must_use]
+    pub fn parse_shortcut(&self) -> Option<Shortcut> {
+        self.shortcut.as_deref().and_then(Shortcut::parse)
+    }
+
+    /// Checks whether the item's shortcut is the single key press
+    /// of `key` with exactly `modifiers` held.
+    ///
+    /// The key is compared case-insensitively.
+    #[must_use]
+    pub fn matches_shortcut(&self, modifiers: &[Modifier], key: &str) -> bool {
+        let Some(Shortcut(presses)) = self.parse_shortcut() else {
+            return false;
+        };
+
+        let modifiers = modifiers.iter().copied().collect();
+        matches!(presses.as_slice(), [press] if press.matches(modifiers, key))
+    }
+}
+
+#[cfg(feature = "image")]
+impl MenuItem {
+    /// Decodes the item's icon from its PNG data, if it has one.
+    ///
+    /// # Errors
+    ///
+    /// If the icon data is not a valid PNG.
+    pub fn icon_image(&self) -> Result<Option<image::DynamicImage>> {
+        let Some(data) = self.icon_data.as_deref() else {
+            return Ok(None);
+        };
+
+        let image = image::load_from_memory_with_format(data, image::ImageFormat::Png)?;
+        Ok(Some(image))
+    }
+
+    /// Sets the item's icon data by encoding an image as a PNG.
+    ///
+    /// # Errors
+    ///
+    /// If the image cannot be encoded.
+    pub fn set_icon_image(&mut self, image: &image::DynamicImage) -> Result<()> {
+        let mut png = std::io::Cursor::new(Vec::new());
+        image.write_to(&mut png, image::ImageFormat::Png)?;
+        self.icon_data = Some(png.into_inner());
+        Ok(())
+    }
+}
+
+#[cfg(feature = "dbusmenu-gtk3")]
+impl MenuItem {
+    /// Decodes the item's icon from its PNG data into a GDK pixbuf, if it has one.
+    ///
+    /// # Errors
+    ///
+    /// If the icon data is not a valid PNG.
+    pub fn icon_pixbuf(&self) -> Result<Option<gtk::gdk_pixbuf::Pixbuf>> {
+        use gtk::gdk_pixbuf::prelude::*;
+        use gtk::gdk_pixbuf::PixbufLoader;
+
+        let Some(data) = self.icon_data.as_deref() else {
+            return Ok(None);
+        };
+
+        let loader = PixbufLoader::with_type("png")?;
+        loader.write(data)?;
+        loader.close()?;
+
+        Ok(loader.pixbuf())
+    }
+}
+
+/// How a menu item's access key is marked in its label.
+/// See [`MenuItem::label_with_mnemonic`].
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Default)]
+pub enum MnemonicStyle {
+    /// The access key is not marked.
+    #[default]
+    Plain,
+    /// The access key follows an underscore, as in GTK and `DBusMenu` itself.
+    /// Literal underscores are doubled.
+    Gtk,
+    /// The access key follows an ampersand, as in Qt.
+    /// Literal ampersands are doubled.
+    Qt,
+}
+
+impl MnemonicStyle {
+    /// Gets the character placed before the access key, if any.
+    const fn marker(self) -> Option<char> {
+        match self {
+            Self::Plain => None,
+            Self::Gtk => Some('_'),
+            Self::Qt => Some('&'),
+        }
+    }
 }
 
 #[derive(Debug, Clone, Deserialize, Default)]
@@ -111,6 +812,10 @@ pub struct MenuItemUpdate {
     /// user should be presented.
     /// See [`Disposition`]
     pub disposition: Option<Disposition>,
+    /// Changed properties which are not part of the specification.
+    /// See [`MenuItem::extra`].
+    #[serde(default)]
+    pub extra: HashMap<String, OwnedValue>,
 }
 
 #[derive(Debug, Deserialize, Copy, Clone, Eq, PartialEq, Default)]
@@ -208,12 +913,9 @@ impl TryFrom<MenuLayout> for TrayMenu {
             .submenus
             .iter()
             .map(MenuItem::try_from)
-            .collect::<std::result::Result<_, _>>()?;
+            .collect::<std::result::Result<Vec<_>, _>>()?;
 
-        Ok(Self {
-            id: value.id,
-            submenus,
-        })
+        Ok(Self::new(value.id, submenus))
     }
 }
 
@@ -245,9 +947,7 @@ impl TryFrom<&OwnedValue> for MenuItem {
                 .map(str::to_string);
 
             // see: https://github.com/gnustep/libs-dbuskit/blob/4dc9b56216e46e0e385b976b0605b965509ebbbd/Bundles/DBusMenu/com.canonical.dbusmenu.xml#L76
-            menu.label = dict
-                .get::<str, str>("label")?
-                .map(|label| label.replace('_', ""));
+            menu.label = dict.get::<str, str>("label")?.map(str::to_string);
 
             if let Some(enabled) = dict.get::<str, bool>("enabled")? {
                 menu.enabled = *enabled;
@@ -292,6 +992,11 @@ impl TryFrom<&OwnedValue> for MenuItem {
                 .flatten()
                 .map(MenuType::from)
                 .unwrap_or_default();
+
+            menu.extra = HashMap::<String, OwnedValue>::try_from(dict.clone())?
+                .into_iter()
+                .filter(|(key, _)| !KNOWN_PROPERTIES.contains(&key.as_str()))
+                .collect();
         };
 
         if let Some(Value::Array(array)) = fields.next() {
@@ -302,7 +1007,7 @@ impl TryFrom<&OwnedValue> for MenuItem {
                 submenu.push(menu);
             }
 
-            menu.submenu = submenu;
+            menu.submenu = Arc::new(submenu);
         }
 
         Ok(menu)
@@ -355,7 +1060,7 @@ impl TryFrom<UpdatedProps<'_>> for MenuItemUpdate {
         Ok(Self {
             label: dict
                 .get("label")
-                .map(|v| v.downcast_ref::<str>().map(ToString::to_string)),
+                .map(|v| v.downcast_ref::<str>().map(str::to_string)),
 
             enabled: dict
                 .get("enabled")
@@ -382,6 +1087,12 @@ impl TryFrom<UpdatedProps<'_>> for MenuItemUpdate {
                 .get("disposition")
                 .and_then(Value::downcast_ref::<str>)
                 .map(Disposition::from),
+
+            extra: dict
+                .iter()
+                .filter(|(key, _)| !KNOWN_PROPERTIES.contains(key))
+                .map(|(key, value)| ((*key).to_string(), OwnedValue::from(value)))
+                .collect(),
         })
     }
 }
@@ -396,3 +1107,238 @@ fn get_icon_data(array: &Array) -> Result<Vec<u8>> {
         })
         .collect::<Result<Vec<_>>>()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn item(id: i32, shortcut: &[&str]) -> MenuItem {
+        MenuItem {
+            id,
+            enabled: true,
+            visible: true,
+            shortcut: Some(vec![shortcut.iter().map(ToString::to_string).collect()]),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn search_nested_labels() {
+        let labelled = |id, label: &str| MenuItem {
+            label: Some(label.to_string()),
+            ..item(id, &[])
+        };
+
+        let mut parent = labelled(1, "Recent Files");
+        parent.submenu = Arc::new(vec![labelled(2, "notes.txt"), labelled(3, "todo.txt")]);
+
+        let menu = TrayMenu::new(0, vec![parent, labelled(4, "Open File")]);
+
+        let results = menu.search("FILE");
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].item.id, 4);
+        assert!(results[0].path.is_empty());
+
+        let results = menu.search("txt");
+        assert_eq!(
+            results
+                .iter()
+                .map(|result| result.item.id)
+                .collect::<Vec<_>>(),
+            [2, 3]
+        );
+        assert_eq!(results[0].path, ["Recent Files"]);
+    }
+
+    #[test]
+    fn parse_labels() {
+        let labelled = |label: &str| MenuItem {
+            label: Some(label.to_string()),
+            ..MenuItem::default()
+        };
+
+        let item = labelled("Save _As... _x");
+        assert_eq!(item.label_plain().as_deref(), Some("Save As... x"));
+        assert_eq!(item.access_key(), Some('A'));
+
+        let item = labelled("my__file.txt");
+        assert_eq!(item.label_plain().as_deref(), Some("my_file.txt"));
+        assert_eq!(item.access_key(), None);
+
+        let item = labelled("_Open my__file & more");
+        assert_eq!(
+            item.label_with_mnemonic(MnemonicStyle::Gtk).as_deref(),
+            Some("_Open my__file & more")
+        );
+        assert_eq!(
+            item.label_with_mnemonic(MnemonicStyle::Qt).as_deref(),
+            Some("&Open my_file && more")
+        );
+        assert_eq!(
+            item.label_with_mnemonic(MnemonicStyle::Plain).as_deref(),
+            Some("Open my_file & more")
+        );
+
+        assert_eq!(
+            labelled("trailing_").label_plain().as_deref(),
+            Some("trailing")
+        );
+    }
+
+    #[test]
+    fn find_nested_shortcut() {
+        let mut parent = item(1, &["Control", "Q"]);
+        parent.submenu = Arc::new(vec![item(2, &["Control", "Shift", "S"])]);
+
+        let menu = TrayMenu::new(0, vec![parent]);
+
+        let found = menu.find_by_shortcut(&[Modifier::Shift, Modifier::Control], "s");
+        assert_eq!(found.map(|item| item.id), Some(2));
+
+        assert!(menu.find_by_shortcut(&[Modifier::Shift], "S").is_none());
+        assert!(menu.find_by_shortcut(&[], "Q").is_none());
+    }
+
+    #[test]
+    fn replace_submenu_shares_untouched_items() {
+        let mut parent = item(1, &[]);
+        parent.submenu = Arc::new(vec![item(2, &[])]);
+
+        let menu = TrayMenu::new(0, vec![parent, item(3, &[])]);
+
+        let mut patched = menu.clone();
+        assert!(patched.replace_submenu(2, vec![item(4, &[])]));
+        assert!(!patched.replace_submenu(5, vec![]));
+
+        assert!(menu.find(4).is_none());
+        assert_eq!(patched.find(4).map(|item| item.id), Some(4));
+
+        // the untouched sibling's submenu is still shared
+        assert!(Arc::ptr_eq(
+            &menu.submenus[1].submenu,
+            &patched.submenus[1].submenu
+        ));
+    }
+
+    #[test]
+    fn apply_nested_diffs() {
+        let mut parent = item(1, &[]);
+        parent.submenu = Arc::new(vec![item(2, &["Control", "S"])]);
+
+        let mut menu = TrayMenu::new(0, vec![parent]);
+
+        menu.apply_diff(&[
+            MenuDiff {
+                id: 2,
+                update: MenuItemUpdate {
+                    label: Some(Some("Save".to_string())),
+                    enabled: Some(false),
+                    ..Default::default()
+                },
+                remove: vec!["shortcut".to_string()],
+            },
+            MenuDiff {
+                id: 5,
+                update: MenuItemUpdate {
+                    visible: Some(false),
+                    ..Default::default()
+                },
+                remove: vec![],
+            },
+        ]);
+
+        let saved = menu.find(2).expect("nested item should exist");
+        assert_eq!(saved.label.as_deref(), Some("Save"));
+        assert!(!saved.enabled);
+        assert!(saved.shortcut.is_none());
+        assert!(menu.find(1).is_some_and(|item| item.visible));
+    }
+
+    #[test]
+    fn diff_applies_to_old_version() {
+        let labelled = |id, label: &str| MenuItem {
+            label: Some(label.to_string()),
+            ..item(id, &[])
+        };
+
+        let mut parent = labelled(1, "Recent Files");
+        parent.submenu = Arc::new(vec![labelled(2, "notes.txt"), labelled(3, "todo.txt")]);
+        let old = TrayMenu::new(0, vec![parent, labelled(4, "Quit")]);
+
+        let mut parent = labelled(1, "Recent Files");
+        parent.submenu = Arc::new(vec![labelled(3, "todo.txt"), labelled(5, "diary.txt")]);
+        let new = TrayMenu::new(0, vec![parent, labelled(4, "Exit")]);
+
+        let changes = TrayMenu::diff(&old, &new);
+        assert_eq!(changes.added, [5]);
+        assert_eq!(changes.removed, [2]);
+        assert_eq!(changes.restructured, [1]);
+        assert!(changes.replaced.is_empty());
+        assert_eq!(
+            changes
+                .updated
+                .iter()
+                .map(|diff| diff.id)
+                .collect::<Vec<_>>(),
+            [4]
+        );
+
+        let mut patched = old.clone();
+        patched.apply_diff(&changes.updated);
+        assert_eq!(
+            patched.find(4).and_then(|item| item.label.as_deref()),
+            Some("Exit")
+        );
+
+        assert!(TrayMenu::diff(&new, &new).is_empty());
+    }
+
+    #[test]
+    fn diff_extra_properties() {
+        let desc = |text: &str| OwnedValue::from(zvariant::Str::from(text));
+
+        let mut old = item(1, &[]);
+        old.extra
+            .insert("accessible-desc".to_string(), desc("Quit"));
+        old.extra.insert("x-kde-title".to_string(), desc("Title"));
+
+        let mut new = old.clone();
+        new.extra
+            .insert("accessible-desc".to_string(), desc("Exit"));
+        new.extra.remove("x-kde-title");
+
+        let old = TrayMenu::new(0, vec![old]);
+        let new = TrayMenu::new(0, vec![new]);
+
+        let changes = TrayMenu::diff(&old, &new);
+        assert_eq!(changes.updated.len(), 1);
+        assert_eq!(changes.updated[0].remove, ["x-kde-title"]);
+
+        let mut patched = old.clone();
+        patched.apply_diff(&changes.updated);
+        let extra = &patched.find(1).expect("item should exist").extra;
+        assert_eq!(extra.get("accessible-desc"), Some(&desc("Exit")));
+        assert!(!extra.contains_key("x-kde-title"));
+    }
+
+    #[test]
+    fn find_after_direct_modification() {
+        let mut menu = TrayMenu::new(0, vec![item(1, &[]), item(2, &[])]);
+        assert_eq!(menu.find(2).map(|item| item.id), Some(2));
+
+        // invalidates the index without the menu knowing
+        Arc::make_mut(&mut menu.submenus).remove(0);
+        assert_eq!(menu.find(2).map(|item| item.id), Some(2));
+        assert!(menu.find(1).is_none());
+
+        menu.apply_diff(&[MenuDiff {
+            id: 2,
+            update: MenuItemUpdate {
+                enabled: Some(false),
+                ..Default::default()
+            },
+            remove: vec![],
+        }]);
+        assert!(menu.find(2).is_some_and(|item| !item.enabled));
+    }
+}
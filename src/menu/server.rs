@@ -0,0 +1,740 @@
+use super::{find, find_mut, Disposition, MenuItem, MenuType, ToggleState, ToggleType, TrayMenu};
+use crate::error::Result;
+use std::collections::HashMap;
+use std::fmt::{Debug, Formatter};
+use std::sync::{Arc, Mutex};
+use zbus::zvariant::{OwnedValue, Structure, Value};
+use zbus::{dbus_interface, Connection, SignalContext};
+
+type ActivateCallback = Arc<dyn Fn(i32) + Send + Sync>;
+
+/// A menu node as sent over the bus, with its children wrapped in variants.
+type DBusLayout = (i32, HashMap<String, OwnedValue>, Vec<OwnedValue>);
+
+/// A single entry in a served menu, such as a button, separator, checkmark or submenu.
+///
+/// Entries are assigned IDs when the menu is served,
+/// numbered from `1` in depth-first order.
+#[derive(Clone)]
+pub struct MenuEntry {
+    item: MenuItem,
+    children: Vec<MenuEntry>,
+    on_activate: Option<ActivateCallback>,
+}
+
+impl Debug for MenuEntry {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("MenuEntry")
+            .field("item", &self.item)
+            .field("children", &self.children)
+            .field("on_activate", &self.on_activate.is_some())
+            .finish()
+    }
+}
+
+impl MenuEntry {
+    /// Creates a standard entry which can be clicked.
+    ///
+    /// An underscore in the label marks the following character as the access key.
+    #[must_use]
+    pub fn new(label: impl Into<String>) -> Self {
+        Self {
+            item: MenuItem {
+                label: Some(label.into()),
+                enabled: true,
+                visible: true,
+                ..MenuItem::default()
+            },
+            children: Vec::new(),
+            on_activate: None,
+        }
+    }
+
+    /// Creates a separator.
+    #[must_use]
+    pub fn separator() -> Self {
+        let mut entry = Self::new("");
+        entry.item.label = None;
+        entry.item.menu_type = MenuType::Separator;
+        entry
+    }
+
+    /// Creates an entry with a checkmark, which is toggled when clicked.
+    #[must_use]
+    pub fn checkmark(label: impl Into<String>, checked: bool) -> Self {
+        Self::toggle(label, ToggleType::Checkmark, checked)
+    }
+
+    /// Creates an entry which is part of a radio group.
+    ///
+    /// Consecutive radio entries in the same menu form a group,
+    /// of which only one is selected.
+    /// Clicking an entry selects it and deselects the rest of its group.
+    #[must_use]
+    pub fn radio(label: impl Into<String>, selected: bool) -> Self {
+        Self::toggle(label, ToggleType::Radio, selected)
+    }
+
+    /// Creates an entry which opens a submenu.
+    #[must_use]
+    pub fn submenu(label: impl Into<String>, children: Vec<MenuEntry>) -> Self {
+        let mut entry = Self::new(label);
+        entry.children = children;
+        entry
+    }
+
+    fn toggle(label: impl Into<String>, toggle_type: ToggleType, on: bool) -> Self {
+        let mut entry = Self::new(label);
+        entry.item.toggle_type = toggle_type;
+        entry.item.toggle_state = if on {
+            ToggleState::On
+        } else {
+            ToggleState::Off
+        };
+        entry
+    }
+
+    /// Sets whether the entry can be clicked.
+    #[must_use]
+    pub fn enabled(mut self, enabled: bool) -> Self {
+        self.item.enabled = enabled;
+        self
+    }
+
+    /// Sets whether the entry is shown.
+    #[must_use]
+    pub fn visible(mut self, visible: bool) -> Self {
+        self.item.visible = visible;
+        self
+    }
+
+    /// Sets the name of the entry's icon, following the freedesktop.org icon spec.
+    #[must_use]
+    pub fn icon_name(mut self, name: impl Into<String>) -> Self {
+        self.item.icon_name = Some(name.into());
+        self
+    }
+
+    /// Sets the entry's icon as PNG data.
+    #[must_use]
+    pub fn icon_data(mut self, png: Vec<u8>) -> Self {
+        self.item.icon_data = Some(png);
+        self
+    }
+
+    /// Sets the entry's keyboard shortcut.
+    ///
+    /// See [`MenuItem::shortcut`] for the format.
+    #[must_use]
+    pub fn shortcut(mut self, shortcut: Vec<Vec<String>>) -> Self {
+        self.item.shortcut = Some(shortcut);
+        self
+    }
+
+    /// Sets how the entry should be presented.
+    #[must_use]
+    pub fn disposition(mut self, disposition: Disposition) -> Self {
+        self.item.disposition = disposition;
+        self
+    }
+
+    /// Sets a callback invoked when the entry is clicked.
+    ///
+    /// The callback receives the ID of the entry.
+    /// Checkmark and radio entries have already been toggled when it runs.
+    #[must_use]
+    pub fn on_activate(mut self, callback: impl Fn(i32) + Send + Sync + 'static) -> Self {
+        self.on_activate = Some(Arc::new(callback));
+        self
+    }
+}
+
+/// The current menu and the callbacks for its entries.
+#[derive(Default)]
+struct MenuState {
+    /// The menu, with its ID used as the layout revision.
+    menu: TrayMenu,
+    callbacks: HashMap<i32, ActivateCallback>,
+}
+
+impl Debug for MenuState {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("MenuState")
+            .field("menu", &self.menu)
+            .finish_non_exhaustive()
+    }
+}
+
+impl MenuState {
+    /// Replaces the menu with `entries`, assigning new IDs.
+    fn replace(&mut self, entries: Vec<MenuEntry>) {
+        self.callbacks.clear();
+
+        let mut next_id = 1;
+        let submenus = build_items(entries, &mut next_id, &mut self.callbacks);
+
+        self.menu.id = self.menu.id.wrapping_add(1);
+        self.menu.submenus = Arc::new(submenus);
+    }
+
+    /// Toggles a checkmark or radio item after it is clicked,
+    /// returning the IDs of all items which changed.
+    fn toggle(&mut self, id: i32) -> Vec<i32> {
+        let Some(item) = find(&self.menu.submenus, id) else {
+            return Vec::new();
+        };
+
+        match item.toggle_type {
+            ToggleType::Checkmark => {
+                if let Some(item) = find_mut(&mut self.menu.submenus, id) {
+                    item.toggle_state = match item.toggle_state {
+                        ToggleState::On => ToggleState::Off,
+                        ToggleState::Off | ToggleState::Indeterminate => ToggleState::On,
+                    };
+                }
+
+                vec![id]
+            }
+            ToggleType::Radio => {
+                let Some(siblings) = siblings_mut(&mut self.menu.submenus, id) else {
+                    return Vec::new();
+                };
+
+                let index = siblings
+                    .iter()
+                    .position(|item| item.id == id)
+                    .expect("item to be in its own list");
+
+                // the group is the run of radio items around the clicked one
+                let is_radio = |item: &MenuItem| item.toggle_type == ToggleType::Radio;
+                let start = siblings[..index]
+                    .iter()
+                    .rposition(|item| !is_radio(item))
+                    .map_or(0, |i| i + 1);
+                let end = siblings[index..]
+                    .iter()
+                    .position(|item| !is_radio(item))
+                    .map_or(siblings.len(), |i| index + i);
+
+                let mut changed = Vec::new();
+                for item in &mut siblings[start..end] {
+                    let state = if item.id == id {
+                        ToggleState::On
+                    } else {
+                        ToggleState::Off
+                    };
+
+                    if item.toggle_state != state {
+                        item.toggle_state = state;
+                        changed.push(item.id);
+                    }
+                }
+
+                changed
+            }
+            ToggleType::CannotBeToggled => Vec::new(),
+        }
+    }
+}
+
+/// Converts entries into menu items, assigning IDs depth-first
+/// and collecting their callbacks.
+fn build_items(
+    entries: Vec<MenuEntry>,
+    next_id: &mut i32,
+    callbacks: &mut HashMap<i32, ActivateCallback>,
+) -> Vec<MenuItem> {
+    entries
+        .into_iter()
+        .map(|entry| {
+            let mut item = entry.item;
+            item.id = *next_id;
+            *next_id += 1;
+
+            if let Some(callback) = entry.on_activate {
+                callbacks.insert(item.id, callback);
+            }
+
+            if !entry.children.is_empty() {
+                item.children_display = Some(String::from("submenu"));
+                item.submenu = Arc::new(build_items(entry.children, next_id, callbacks));
+            }
+
+            item
+        })
+        .collect()
+}
+
+/// Finds the list containing the item with the given id for modification.
+fn siblings_mut(items: &mut Arc<Vec<MenuItem>>, id: i32) -> Option<&mut Vec<MenuItem>> {
+    if items.iter().any(|item| item.id == id) {
+        return Some(Arc::make_mut(items));
+    }
+
+    let index = items
+        .iter()
+        .position(|item| find(&item.submenu, id).is_some())?;
+
+    siblings_mut(&mut Arc::make_mut(items)[index].submenu, id)
+}
+
+/// Gets the bus properties of an item,
+/// limited to `names` unless it is empty.
+fn properties(item: &MenuItem, names: &[String]) -> HashMap<String, OwnedValue> {
+    let mut properties: HashMap<String, OwnedValue> = HashMap::new();
+    let mut insert = |name: &str, value: Value<'_>| {
+        if names.is_empty() || names.iter().any(|wanted| wanted == name) {
+            properties.insert(name.to_string(), value.into());
+        }
+    };
+
+    if item.menu_type == MenuType::Separator {
+        insert("type", Value::from("separator"));
+    }
+
+    if let Some(label) = &item.label {
+        insert("label", Value::from(label.as_str()));
+    }
+
+    insert("enabled", Value::from(item.enabled));
+    insert("visible", Value::from(item.visible));
+
+    if let Some(icon_name) = &item.icon_name {
+        insert("icon-name", Value::from(icon_name.as_str()));
+    }
+
+    if let Some(icon_data) = &item.icon_data {
+        insert("icon-data", Value::from(icon_data.clone()));
+    }
+
+    if let Some(shortcut) = &item.shortcut {
+        insert("shortcut", Value::from(shortcut.clone()));
+    }
+
+    let toggle_type = match item.toggle_type {
+        ToggleType::Checkmark => Some("checkmark"),
+        ToggleType::Radio => Some("radio"),
+        ToggleType::CannotBeToggled => None,
+    };
+
+    if let Some(toggle_type) = toggle_type {
+        insert("toggle-type", Value::from(toggle_type));
+
+        let state = match item.toggle_state {
+            ToggleState::Off => 0,
+            ToggleState::On => 1,
+            ToggleState::Indeterminate => -1,
+        };
+        insert("toggle-state", Value::from(state));
+    }
+
+    if let Some(children_display) = &item.children_display {
+        insert("children-display", Value::from(children_display.as_str()));
+    }
+
+    let disposition = match item.disposition {
+        Disposition::Normal => None,
+        Disposition::Informative => Some("informative"),
+        Disposition::Warning => Some("warning"),
+        Disposition::Alert => Some("alert"),
+    };
+
+    if let Some(disposition) = disposition {
+        insert("disposition", Value::from(disposition));
+    }
+
+    properties
+}
+
+/// Builds the layout of a node and its children,
+/// recursing `depth` levels, or fully if negative.
+fn layout(
+    id: i32,
+    fields: HashMap<String, OwnedValue>,
+    children: &[MenuItem],
+    depth: i32,
+    names: &[String],
+) -> DBusLayout {
+    let children = if depth == 0 {
+        Vec::new()
+    } else {
+        children
+            .iter()
+            .map(|child| {
+                let (id, fields, submenus) = layout(
+                    child.id,
+                    properties(child, names),
+                    &child.submenu,
+                    depth - 1,
+                    names,
+                );
+
+                Value::from(Structure::from((id, fields, submenus))).into()
+            })
+            .collect()
+    };
+
+    (id, fields, children)
+}
+
+/// The menu served as `com.canonical.dbusmenu`.
+#[derive(Debug)]
+struct MenuInterface(Arc<Mutex<MenuState>>);
+
+impl MenuInterface {
+    /// Handles an event, returning an error if the item does not exist.
+    async fn handle_event(
+        &self,
+        id: i32,
+        event_id: &str,
+        ctxt: &SignalContext<'_>,
+    ) -> zbus::fdo::Result<()> {
+        let (callback, changed) = {
+            let mut state = self.0.lock().expect("mutex lock should succeed");
+            // `0` is the root menu, which can be opened and closed
+            if id != 0 && find(&state.menu.submenus, id).is_none() {
+                return Err(unknown_item(id));
+            }
+
+            if event_id != "clicked" {
+                return Ok(());
+            }
+
+            let changed = state.toggle(id);
+            let changed = changed
+                .into_iter()
+                .filter_map(|id| find(&state.menu.submenus, id))
+                .map(|item| (item.id, properties(item, &[])))
+                .collect::<Vec<_>>();
+
+            (state.callbacks.get(&id).cloned(), changed)
+        };
+
+        if !changed.is_empty() {
+            Self::items_properties_updated(ctxt, changed, Vec::new()).await?;
+        }
+
+        if let Some(callback) = callback {
+            callback(id);
+        }
+
+        Ok(())
+    }
+}
+
+fn unknown_item(id: i32) -> zbus::fdo::Error {
+    zbus::fdo::Error::InvalidArgs(format!("no menu item with id {id}"))
+}
+
+#[dbus_interface(name = "com.canonical.dbusmenu")]
+impl MenuInterface {
+    /// GetLayout method
+    fn get_layout(
+        &self,
+        parent_id: i32,
+        recursion_depth: i32,
+        property_names: Vec<String>,
+    ) -> zbus::fdo::Result<(u32, DBusLayout)> {
+        let state = self.0.lock().expect("mutex lock should succeed");
+
+        let layout = if parent_id == 0 {
+            layout(
+                0,
+                HashMap::new(),
+                &state.menu.submenus,
+                recursion_depth,
+                &property_names,
+            )
+        } else {
+            let parent =
+                find(&state.menu.submenus, parent_id).ok_or_else(|| unknown_item(parent_id))?;
+            layout(
+                parent.id,
+                properties(parent, &property_names),
+                &parent.submenu,
+                recursion_depth,
+                &property_names,
+            )
+        };
+
+        Ok((state.menu.id, layout))
+    }
+
+    /// GetGroupProperties method
+    fn get_group_properties(
+        &self,
+        ids: Vec<i32>,
+        property_names: Vec<String>,
+    ) -> Vec<(i32, HashMap<String, OwnedValue>)> {
+        let state = self.0.lock().expect("mutex lock should succeed");
+
+        ids.into_iter()
+            .filter_map(|id| find(&state.menu.submenus, id))
+            .map(|item| (item.id, properties(item, &property_names)))
+            .collect()
+    }
+
+    /// GetProperty method
+    fn get_property(&self, id: i32, name: String) -> zbus::fdo::Result<OwnedValue> {
+        let state = self.0.lock().expect("mutex lock should succeed");
+        let item = find(&state.menu.submenus, id).ok_or_else(|| unknown_item(id))?;
+
+        properties(item, std::slice::from_ref(&name))
+            .remove(&name)
+            .ok_or_else(|| {
+                zbus::fdo::Error::InvalidArgs(format!("item {id} has no property {name}"))
+            })
+    }
+
+    /// Event method
+    async fn event(
+        &self,
+        id: i32,
+        event_id: String,
+        _data: OwnedValue,
+        _timestamp: u32,
+        #[zbus(signal_context)] ctxt: SignalContext<'_>,
+    ) -> zbus::fdo::Result<()> {
+        self.handle_event(id, &event_id, &ctxt).await
+    }
+
+    /// EventGroup method
+    async fn event_group(
+        &self,
+        events: Vec<(i32, String, OwnedValue, u32)>,
+        #[zbus(signal_context)] ctxt: SignalContext<'_>,
+    ) -> Vec<i32> {
+        let mut errors = Vec::new();
+        for (id, event_id, _, _) in events {
+            if self.handle_event(id, &event_id, &ctxt).await.is_err() {
+                errors.push(id);
+            }
+        }
+
+        errors
+    }
+
+    /// AboutToShow method
+    fn about_to_show(&self, _id: i32) -> bool {
+        false
+    }
+
+    /// AboutToShowGroup method
+    fn about_to_show_group(&self, ids: Vec<i32>) -> (Vec<i32>, Vec<i32>) {
+        let state = self.0.lock().expect("mutex lock should succeed");
+
+        let errors = ids
+            .into_iter()
+            .filter(|id| *id != 0 && find(&state.menu.submenus, *id).is_none())
+            .collect();
+
+        (Vec::new(), errors)
+    }
+
+    /// ItemsPropertiesUpdated signal
+    #[dbus_interface(signal)]
+    async fn items_properties_updated(
+        ctxt: &SignalContext<'_>,
+        updated_props: Vec<(i32, HashMap<String, OwnedValue>)>,
+        removed_props: Vec<(i32, Vec<String>)>,
+    ) -> zbus::Result<()>;
+
+    /// LayoutUpdated signal
+    #[dbus_interface(signal)]
+    async fn layout_updated(
+        ctxt: &SignalContext<'_>,
+        revision: u32,
+        parent: i32,
+    ) -> zbus::Result<()>;
+
+    /// Version property
+    #[dbus_interface(property)]
+    fn version(&self) -> u32 {
+        3
+    }
+
+    /// TextDirection property
+    #[dbus_interface(property)]
+    fn text_direction(&self) -> String {
+        String::from("ltr")
+    }
+
+    /// Status property
+    #[dbus_interface(property)]
+    fn status(&self) -> String {
+        String::from("normal")
+    }
+
+    /// IconThemePath property
+    #[dbus_interface(property)]
+    fn icon_theme_path(&self) -> Vec<String> {
+        Vec::new()
+    }
+}
+
+/// A menu served on the bus by this process.
+///
+/// The menu is removed from the bus when [closed](MenuServer::close).
+#[derive(Debug)]
+pub struct MenuServer {
+    connection: Connection,
+    path: String,
+    state: Arc<Mutex<MenuState>>,
+}
+
+impl MenuServer {
+    /// Serves a menu at `path` on `connection`.
+    ///
+    /// To attach the menu to a published item, use the item's connection
+    /// and pass the same path to [`ItemServiceBuilder::menu`](crate::item_service::ItemServiceBuilder::menu).
+    ///
+    /// # Errors
+    ///
+    /// If the path is invalid or another menu is already served at it.
+    pub async fn serve(
+        connection: &Connection,
+        path: impl Into<String>,
+        entries: Vec<MenuEntry>,
+    ) -> Result<Self> {
+        let path = path.into();
+
+        let mut state = MenuState::default();
+        state.replace(entries);
+        let state = Arc::new(Mutex::new(state));
+
+        let added = connection
+            .object_server()
+            .at(path.as_str(), MenuInterface(state.clone()))
+            .await?;
+
+        if !added {
+            return Err(zbus::Error::Failure(format!("a menu is already served at {path}")).into());
+        }
+
+        Ok(Self {
+            connection: connection.clone(),
+            path,
+            state,
+        })
+    }
+
+    /// Gets the object path the menu is served at.
+    #[must_use]
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// Gets a copy of the menu as currently served,
+    /// including the IDs assigned to each entry.
+    ///
+    /// # Panics
+    ///
+    /// If the state mutex is poisoned.
+    #[must_use]
+    pub fn menu(&self) -> TrayMenu {
+        self.state
+            .lock()
+            .expect("mutex lock should succeed")
+            .menu
+            .clone()
+    }
+
+    /// Replaces the whole menu, assigning new IDs to the entries.
+    ///
+    /// # Errors
+    ///
+    /// If the change cannot be signalled.
+    pub async fn set_entries(&self, entries: Vec<MenuEntry>) -> Result<()> {
+        let revision = {
+            let mut state = self.state.lock().expect("mutex lock should succeed");
+            state.replace(entries);
+            state.menu.id
+        };
+
+        let ctxt = SignalContext::new(&self.connection, self.path.as_str())?;
+        MenuInterface::layout_updated(&ctxt, revision, 0).await?;
+        Ok(())
+    }
+
+    /// Changes the properties of the entry with the given ID,
+    /// such as its label or toggle state.
+    ///
+    /// The entry's ID and children cannot be changed this way;
+    /// use [`MenuServer::set_entries`] to change the structure of the menu.
+    ///
+    /// Returns `false` if no entry with the ID exists.
+    ///
+    /// # Errors
+    ///
+    /// If the change cannot be signalled.
+    pub async fn update(&self, id: i32, f: impl FnOnce(&mut MenuItem)) -> Result<bool> {
+        let properties = {
+            let mut state = self.state.lock().expect("mutex lock should succeed");
+            let Some(item) = find_mut(&mut state.menu.submenus, id) else {
+                return Ok(false);
+            };
+
+            let submenu = item.submenu.clone();
+            f(item);
+            item.id = id;
+            item.submenu = submenu;
+
+            properties(item, &[])
+        };
+
+        let ctxt = SignalContext::new(&self.connection, self.path.as_str())?;
+        MenuInterface::items_properties_updated(&ctxt, vec![(id, properties)], Vec::new()).await?;
+        Ok(true)
+    }
+
+    /// Removes the menu from the bus.
+    ///
+    /// # Errors
+    ///
+    /// If the menu cannot be removed.
+    pub async fn close(self) -> Result<()> {
+        self.connection
+            .object_server()
+            .remove::<MenuInterface, _>(self.path.as_str())
+            .await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn toggles_radio_group() {
+        let mut state = MenuState::default();
+        state.replace(vec![
+            MenuEntry::checkmark("Mute", false),
+            MenuEntry::radio("Low", true),
+            MenuEntry::radio("High", false),
+            MenuEntry::separator(),
+            MenuEntry::radio("Other", true),
+        ]);
+
+        assert_eq!(state.toggle(3), [2, 3]);
+        assert_eq!(state.toggle(1), [1]);
+
+        let states = state
+            .menu
+            .submenus
+            .iter()
+            .map(|item| item.toggle_state)
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            states,
+            [
+                ToggleState::On,
+                ToggleState::Off,
+                ToggleState::On,
+                ToggleState::On,
+                ToggleState::On
+            ]
+        );
+    }
+}
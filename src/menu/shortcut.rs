@@ -0,0 +1,213 @@
+use bitflags::bitflags;
+use std::fmt;
+
+/// A keyboard modifier used in a [`MenuItem::shortcut`](super::MenuItem::shortcut).
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum Modifier {
+    Control,
+    Alt,
+    Shift,
+    Super,
+}
+
+impl Modifier {
+    /// Gets the string used to represent the modifier in the `DBusMenu` protocol.
+    #[must_use]
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::Control => "Control",
+            Self::Alt => "Alt",
+            Self::Shift => "Shift",
+            Self::Super => "Super",
+        }
+    }
+
+    /// Parses the string used to represent the modifier in the `DBusMenu` protocol.
+    #[must_use]
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "Control" => Some(Self::Control),
+            "Alt" => Some(Self::Alt),
+            "Shift" => Some(Self::Shift),
+            "Super" => Some(Self::Super),
+            _ => None,
+        }
+    }
+}
+
+bitflags! {
+    /// The set of modifiers held during a [`KeyPress`].
+    #[derive(Debug, Copy, Clone, Default, Eq, PartialEq, Hash)]
+    pub struct Modifiers: u8 {
+        const CONTROL = 1;
+        const ALT = 1 << 1;
+        const SHIFT = 1 << 2;
+        const SUPER = 1 << 3;
+    }
+}
+
+impl From<Modifier> for Modifiers {
+    fn from(modifier: Modifier) -> Self {
+        match modifier {
+            Modifier::Control => Self::CONTROL,
+            Modifier::Alt => Self::ALT,
+            Modifier::Shift => Self::SHIFT,
+            Modifier::Super => Self::SUPER,
+        }
+    }
+}
+
+impl FromIterator<Modifier> for Modifiers {
+    fn from_iter<T: IntoIterator<Item = Modifier>>(iter: T) -> Self {
+        iter.into_iter().map(Self::from).collect()
+    }
+}
+
+impl fmt::Display for Modifiers {
+    /// Formats the modifiers as they are usually shown in menus,
+    /// such as `Ctrl+Shift`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        const NAMES: [(Modifiers, &str); 4] = [
+            (Modifiers::CONTROL, "Ctrl"),
+            (Modifiers::ALT, "Alt"),
+            (Modifiers::SHIFT, "Shift"),
+            (Modifiers::SUPER, "Super"),
+        ];
+
+        let mut names = NAMES
+            .iter()
+            .filter(|(modifier, _)| self.contains(*modifier))
+            .map(|(_, name)| name);
+
+        if let Some(name) = names.next() {
+            f.write_str(name)?;
+        }
+
+        for name in names {
+            write!(f, "+{name}")?;
+        }
+
+        Ok(())
+    }
+}
+
+/// A single key press of a [`Shortcut`].
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct KeyPress {
+    /// The modifiers held.
+    pub modifiers: Modifiers,
+    /// The name of the key pressed, such as `S` or `Delete`.
+    pub key: String,
+}
+
+impl KeyPress {
+    /// Parses a key press from the `DBusMenu` format,
+    /// which lists the modifiers followed by the key.
+    ///
+    /// Returns `None` if there is no key, or a modifier is not recognised.
+    #[must_use]
+    pub fn parse(press: &[String]) -> Option<Self> {
+        let (key, modifiers) = press.split_last()?;
+
+        let modifiers = modifiers
+            .iter()
+            .map(|modifier| Modifier::from_name(modifier))
+            .collect::<Option<Modifiers>>()?;
+
+        Some(Self {
+            modifiers,
+            key: key.clone(),
+        })
+    }
+
+    /// Checks whether this is a press of `key` with exactly `modifiers` held.
+    ///
+    /// The key is compared case-insensitively.
+    #[must_use]
+    pub fn matches(&self, modifiers: Modifiers, key: &str) -> bool {
+        self.modifiers == modifiers && self.key.eq_ignore_ascii_case(key)
+    }
+}
+
+impl fmt::Display for KeyPress {
+    /// Formats the key press as it is usually shown in menus,
+    /// such as `Ctrl+Shift+S`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.modifiers.is_empty() {
+            f.write_str(&self.key)
+        } else {
+            write!(f, "{}+{}", self.modifiers, self.key)
+        }
+    }
+}
+
+/// A keyboard shortcut for a menu item,
+/// made up of one or more key presses made in sequence.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Shortcut(pub Vec<KeyPress>);
+
+impl Shortcut {
+    /// Parses a shortcut from the `DBusMenu` format.
+    /// See [`MenuItem::shortcut`](super::MenuItem::shortcut).
+    ///
+    /// Returns `None` if there are no key presses, or any is invalid.
+    #[must_use]
+    pub fn parse(presses: &[Vec<String>]) -> Option<Self> {
+        if presses.is_empty() {
+            return None;
+        }
+
+        presses
+            .iter()
+            .map(|press| KeyPress::parse(press))
+            .collect::<Option<_>>()
+            .map(Self)
+    }
+}
+
+impl fmt::Display for Shortcut {
+    /// Formats the shortcut as it is usually shown in menus,
+    /// such as `Ctrl+Q, Alt+X`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, press) in self.0.iter().enumerate() {
+            if i > 0 {
+                f.write_str(", ")?;
+            }
+
+            write!(f, "{press}")?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn presses(presses: &[&[&str]]) -> Vec<Vec<String>> {
+        presses
+            .iter()
+            .map(|press| press.iter().map(ToString::to_string).collect())
+            .collect()
+    }
+
+    #[test]
+    fn parse_and_display() {
+        let shortcut =
+            Shortcut::parse(&presses(&[&["Shift", "Control", "S"]])).expect("to be parsed");
+        assert_eq!(
+            shortcut.0[0].modifiers,
+            Modifiers::CONTROL | Modifiers::SHIFT
+        );
+        assert_eq!(shortcut.to_string(), "Ctrl+Shift+S");
+
+        let shortcut = Shortcut::parse(&presses(&[&["Control", "Q"], &["Alt", "X"], &["F1"]]))
+            .expect("to be parsed");
+        assert_eq!(shortcut.to_string(), "Ctrl+Q, Alt+X, F1");
+
+        assert!(Shortcut::parse(&presses(&[&["Hyper", "S"]])).is_none());
+        assert!(Shortcut::parse(&presses(&[&[]])).is_none());
+        assert!(Shortcut::parse(&[]).is_none());
+    }
+}
@@ -0,0 +1,464 @@
+use crate::client::{Event, Snapshot, UpdateEvent};
+use crate::item::{EffectiveIcon, IconPixmap, Status, StatusNotifierItem, Tooltip};
+
+/// A change to the entries of a [`TrayModel`].
+///
+/// Indices refer to the entry list *after* the change is applied,
+/// except for `Removed`, which refers to the index the entry had before removal.
+/// Changes returned together should be applied in order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ModelChange {
+    /// A new entry was inserted at the index.
+    Inserted(usize),
+    /// The entry at the index changed and should be re-rendered.
+    Updated(usize),
+    /// The entry at the index was removed.
+    Removed(usize),
+}
+
+/// An owned copy of an item's [`EffectiveIcon`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ResolvedIcon {
+    /// The Freedesktop-compliant name of the icon.
+    pub name: Option<String>,
+    /// ARGB32 binary representation of the icon.
+    pub pixmap: Option<Vec<IconPixmap>>,
+    /// The Freedesktop-compliant name of the overlay icon.
+    pub overlay_name: Option<String>,
+    /// ARGB32 binary representation of the overlay icon.
+    pub overlay_pixmap: Option<Vec<IconPixmap>>,
+    /// Whether the attention icon was selected instead of the normal icon.
+    pub is_attention: bool,
+}
+
+impl From<EffectiveIcon<'_>> for ResolvedIcon {
+    fn from(icon: EffectiveIcon<'_>) -> Self {
+        Self {
+            name: icon.name.map(str::to_string),
+            pixmap: icon.pixmap.map(<[IconPixmap]>::to_vec),
+            overlay_name: icon.overlay_name.map(str::to_string),
+            overlay_pixmap: icon.overlay_pixmap.map(<[IconPixmap]>::to_vec),
+            is_attention: icon.is_attention,
+        }
+    }
+}
+
+/// A single item in a [`TrayModel`], ready to render.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TrayEntry {
+    /// The address of the item on the bus.
+    pub address: String,
+    /// The text to display for the item.
+    /// This is the item's title, falling back to its id.
+    pub label: String,
+    /// The icon to display for the item.
+    pub icon: ResolvedIcon,
+    /// The item's tooltip, if it has one.
+    pub tooltip: Option<Tooltip>,
+    /// The item's current status.
+    pub status: Status,
+    /// The object path of the item's menu.
+    /// This is `None` if the item has no menu, or its menu never responded.
+    pub menu: Option<String>,
+    /// Whether the item prefers showing its menu over being activated.
+    pub item_is_menu: bool,
+}
+
+impl TrayEntry {
+    fn new(address: &str, item: &StatusNotifierItem, prefer_attention: bool) -> Self {
+        let label = item
+            .title
+            .as_ref()
+            .filter(|title| !title.is_empty())
+            .unwrap_or(&item.id)
+            .clone();
+
+        Self {
+            address: address.to_string(),
+            label,
+            icon: item.effective_icon(prefer_attention).into(),
+            tooltip: item.tool_tip.clone(),
+            status: item.status,
+            menu: item.menu.clone(),
+            item_is_menu: item.item_is_menu,
+        }
+    }
+}
+
+type Filter = Box<dyn Fn(&StatusNotifierItem) -> bool + Send + Sync>;
+
+/// Maintains an ordered, filtered list of [`TrayEntry`] from client events.
+///
+/// Each event returns index-based changes,
+/// which map directly onto the insert/update/remove operations of most list widgets,
+/// so UIs do not need to reconcile items themselves.
+///
+/// Entries are kept in the order their items were first added.
+/// By default, items with the `Passive` status are hidden;
+/// use [`TrayModel::with_filter`] to change this.
+///
+/// ```no_run
+/// # use system_tray::client::Client;
+/// # use system_tray::model::{ModelChange, TrayModel};
+/// # async fn run(client: Client) {
+/// let mut model = TrayModel::new();
+/// let mut tray_rx = client.subscribe();
+///
+/// while let Ok(ev) = tray_rx.recv().await {
+///     for change in model.handle(&ev) {
+///         match change {
+///             ModelChange::Inserted(index) => println!("insert {:?}", model.get(index)),
+///             ModelChange::Updated(index) => println!("update {:?}", model.get(index)),
+///             ModelChange::Removed(index) => println!("remove {index}"),
+///         }
+///     }
+/// }
+/// # }
+/// ```
+pub struct TrayModel {
+    /// All known items, in the order they were added.
+    /// Hidden items are kept so they can be shown again
+    /// at the correct position.
+    items: Vec<(String, StatusNotifierItem)>,
+    /// The visible entries.
+    entries: Vec<TrayEntry>,
+    filter: Filter,
+    prefer_attention: bool,
+}
+
+impl std::fmt::Debug for TrayModel {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TrayModel")
+            .field("entries", &self.entries)
+            .field("prefer_attention", &self.prefer_attention)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Default for TrayModel {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TrayModel {
+    /// Creates an empty model which hides passive items.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::with_filter(|item| item.status != Status::Passive)
+    }
+
+    /// Creates an empty model which only shows items matching `filter`.
+    #[must_use]
+    pub fn with_filter(
+        filter: impl Fn(&StatusNotifierItem) -> bool + Send + Sync + 'static,
+    ) -> Self {
+        Self {
+            items: Vec::new(),
+            entries: Vec::new(),
+            filter: Box::new(filter),
+            prefer_attention: true,
+        }
+    }
+
+    /// Sets whether entries should use an item's attention icon
+    /// while it needs attention.
+    ///
+    /// See [`StatusNotifierItem::effective_icon`].
+    #[must_use]
+    pub fn prefer_attention(mut self, prefer_attention: bool) -> Self {
+        self.prefer_attention = prefer_attention;
+        self
+    }
+
+    /// Gets the visible entries, in display order.
+    #[must_use]
+    pub fn entries(&self) -> &[TrayEntry] {
+        &self.entries
+    }
+
+    /// Gets the visible entry at an index.
+    #[must_use]
+    pub fn get(&self, index: usize) -> Option<&TrayEntry> {
+        self.entries.get(index)
+    }
+
+    /// Gets the index of the visible entry for an item.
+    #[must_use]
+    pub fn index_of(&self, address: &str) -> Option<usize> {
+        self.entries
+            .iter()
+            .position(|entry| entry.address == address)
+    }
+
+    /// Gets the number of visible entries.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Checks whether there are no visible entries.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Updates the model from a client event,
+    /// returning the resulting changes to the entry list.
+    pub fn handle(&mut self, event: &Event) -> Vec<ModelChange> {
+        match event {
+            Event::Add(address, item) => {
+                match self.item_mut(address) {
+                    Some(existing) => *existing = (**item).clone(),
+                    None => self.items.push((address.clone(), (**item).clone())),
+                }
+                self.reconcile(address)
+            }
+            Event::Update(address, update) => self.update(address, std::slice::from_ref(update)),
+            Event::UpdateBatch(address, updates) => self.update(address, updates),
+            Event::Remove(address) => {
+                self.items.retain(|(addr, _)| addr != address);
+                self.index_of(address)
+                    .map(|index| {
+                        self.entries.remove(index);
+                        ModelChange::Removed(index)
+                    })
+                    .into_iter()
+                    .collect()
+            }
+            Event::Resync(snapshot) => self.resync(snapshot),
+            _ => vec![],
+        }
+    }
+
+    /// Replaces every item with those in a snapshot,
+    /// keeping the position of items which are still present.
+    ///
+    /// Items whose pixmaps were evicted from the snapshot keep those already in the model.
+    fn resync(&mut self, snapshot: &Snapshot) -> Vec<ModelChange> {
+        let items = &snapshot.items;
+        let removed: Vec<_> = self
+            .items
+            .iter()
+            .filter(|(address, _)| !items.contains_key(address))
+            .map(|(address, _)| address.clone())
+            .collect();
+
+        let mut changes: Vec<_> = removed
+            .into_iter()
+            .flat_map(|address| self.handle(&Event::Remove(address)))
+            .collect();
+
+        let mut addresses: Vec<_> = items.keys().collect();
+        addresses.sort_unstable();
+
+        for address in addresses {
+            let (item, _) = &items[address];
+            match self.item_mut(address) {
+                Some(existing) if snapshot.evicted.contains(address) => {
+                    let mut item = item.clone();
+                    item.icon_pixmap = existing.icon_pixmap.take();
+                    item.overlay_icon_pixmap = existing.overlay_icon_pixmap.take();
+                    item.attention_icon_pixmap = existing.attention_icon_pixmap.take();
+                    *existing = item;
+                }
+                Some(existing) => existing.clone_from(item),
+                None => self.items.push((address.clone(), item.clone())),
+            }
+            changes.extend(self.reconcile(address));
+        }
+
+        changes
+    }
+
+    fn item_mut(&mut self, address: &str) -> Option<&mut StatusNotifierItem> {
+        self.items
+            .iter_mut()
+            .find(|(addr, _)| addr == address)
+            .map(|(_, item)| item)
+    }
+
+    fn update(&mut self, address: &str, updates: &[UpdateEvent]) -> Vec<ModelChange> {
+        let Some(item) = self.item_mut(address) else {
+            return vec![];
+        };
+
+        for update in updates {
+            update.apply_to(item);
+        }
+
+        self.reconcile(address)
+    }
+
+    /// Brings the visible entry for an item in line with its current state.
+    fn reconcile(&mut self, address: &str) -> Vec<ModelChange> {
+        let Some(position) = self.items.iter().position(|(addr, _)| addr == address) else {
+            return vec![];
+        };
+
+        let item = &self.items[position].1;
+        let visible = (self.filter)(item);
+
+        match (self.index_of(address), visible) {
+            (Some(index), true) => {
+                let entry = TrayEntry::new(address, item, self.prefer_attention);
+                if self.entries[index] == entry {
+                    vec![]
+                } else {
+                    self.entries[index] = entry;
+                    vec![ModelChange::Updated(index)]
+                }
+            }
+            (Some(index), false) => {
+                self.entries.remove(index);
+                vec![ModelChange::Removed(index)]
+            }
+            (None, true) => {
+                let entry = TrayEntry::new(address, item, self.prefer_attention);
+
+                // keep the original insertion order by counting
+                // the visible items added before this one
+                let index = self.items[..position]
+                    .iter()
+                    .filter(|(addr, _)| self.index_of(addr).is_some())
+                    .count();
+
+                self.entries.insert(index, entry);
+                vec![ModelChange::Inserted(index)]
+            }
+            (None, false) => vec![],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::{HashMap, HashSet};
+    use std::sync::Arc;
+
+    fn item(id: &str, status: Status) -> Arc<StatusNotifierItem> {
+        Arc::new(StatusNotifierItem {
+            id: id.to_string(),
+            category: Default::default(),
+            title: None,
+            status,
+            window_id: 0,
+            icon_theme_path: None,
+            icon_name: None,
+            icon_pixmap: None,
+            overlay_icon_name: None,
+            overlay_icon_pixmap: None,
+            attention_icon_name: None,
+            attention_icon_pixmap: None,
+            attention_movie_name: None,
+            tool_tip: None,
+            item_is_menu: false,
+            menu: None,
+            label: None,
+            label_guide: None,
+            ordering_index: None,
+            extra: Default::default(),
+        })
+    }
+
+    #[test]
+    fn hidden_items_keep_position() {
+        let mut model = TrayModel::new();
+
+        model.handle(&Event::Add(":1.1".into(), item("a", Status::Active)));
+        model.handle(&Event::Add(":1.2".into(), item("b", Status::Passive)));
+        assert_eq!(
+            model.handle(&Event::Add(":1.3".into(), item("c", Status::Active))),
+            [ModelChange::Inserted(1)]
+        );
+
+        assert_eq!(
+            model.handle(&Event::Update(
+                ":1.2".into(),
+                UpdateEvent::Status(Status::Active)
+            )),
+            [ModelChange::Inserted(1)]
+        );
+        assert_eq!(model.get(1).map(|entry| entry.label.as_str()), Some("b"));
+
+        assert_eq!(
+            model.handle(&Event::Update(
+                ":1.2".into(),
+                UpdateEvent::Title(Some("B".into()))
+            )),
+            [ModelChange::Updated(1)]
+        );
+        assert_eq!(
+            model.handle(&Event::Remove(":1.1".into())),
+            [ModelChange::Removed(0)]
+        );
+        assert_eq!(model.index_of(":1.3"), Some(1));
+    }
+
+    #[test]
+    fn resyncs_from_snapshot() {
+        let mut model = TrayModel::new();
+
+        model.handle(&Event::Add(":1.1".into(), item("a", Status::Active)));
+        model.handle(&Event::Add(":1.2".into(), item("b", Status::Active)));
+
+        let items = [
+            (":1.2", item("b", Status::Passive)),
+            (":1.3", item("c", Status::Active)),
+        ]
+        .into_iter()
+        .map(|(address, item)| (address.to_string(), ((*item).clone(), None)))
+        .collect();
+
+        let snapshot = crate::client::Snapshot {
+            generation: 0,
+            items,
+            timestamps: HashMap::new(),
+            evicted: HashSet::new(),
+        };
+
+        assert_eq!(
+            model.handle(&Event::Resync(Arc::new(snapshot))),
+            [
+                ModelChange::Removed(0),
+                ModelChange::Removed(0),
+                ModelChange::Inserted(0)
+            ]
+        );
+        assert_eq!(model.index_of(":1.3"), Some(0));
+    }
+
+    #[test]
+    fn keeps_evicted_pixmaps_on_resync() {
+        let mut model = TrayModel::new();
+
+        let pixmap = vec![IconPixmap {
+            width: 1,
+            height: 1,
+            pixels: vec![0; 4],
+        }];
+        let mut with_pixmap = (*item("a", Status::Active)).clone();
+        with_pixmap.icon_pixmap = Some(pixmap.clone());
+        model.handle(&Event::Add(":1.1".into(), Arc::new(with_pixmap)));
+
+        let snapshot = crate::client::Snapshot {
+            generation: 0,
+            items: HashMap::from([(
+                ":1.1".to_string(),
+                ((*item("A", Status::Active)).clone(), None),
+            )]),
+            timestamps: HashMap::new(),
+            evicted: HashSet::from([":1.1".to_string()]),
+        };
+
+        assert_eq!(
+            model.handle(&Event::Resync(Arc::new(snapshot))),
+            [ModelChange::Updated(0)]
+        );
+
+        let entry = model.get(0).expect("entry should exist");
+        assert_eq!(entry.label, "A");
+        assert_eq!(entry.icon.pixmap, Some(pixmap));
+    }
+}
@@ -0,0 +1,169 @@
+use std::fmt::{Display, Formatter};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// A sandbox confining a process,
+/// detected from either this process or a remote item.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Sandbox {
+    /// The process is running inside Flatpak.
+    Flatpak {
+        /// The application ID.
+        app_id: String,
+        /// The location of the application's `/app` directory
+        /// on the host filesystem, if known.
+        app_path: Option<PathBuf>,
+    },
+    /// The process is running as a confined Snap.
+    Snap {
+        /// The name of the snap.
+        name: String,
+    },
+}
+
+impl Display for Sandbox {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Flatpak { app_id, .. } => write!(f, "flatpak '{app_id}'"),
+            Self::Snap { name } => write!(f, "snap '{name}'"),
+        }
+    }
+}
+
+impl Sandbox {
+    /// Detects the sandbox this process is running in, if any.
+    ///
+    /// When sandboxed, the session bus is usually filtered by a proxy,
+    /// and the filesystem and process IDs of other applications are not visible.
+    #[must_use]
+    pub fn current() -> Option<Self> {
+        if let Some(sandbox) = Self::flatpak_from_info(Path::new("/.flatpak-info")) {
+            return Some(sandbox);
+        }
+
+        std::env::var("SNAP_NAME")
+            .ok()
+            .filter(|name| !name.is_empty())
+            .map(|name| Self::Snap { name })
+    }
+
+    /// Detects the sandbox another process on the host is running in, if any.
+    ///
+    /// This relies on access to `/proc`,
+    /// so will not detect anything when called from inside a sandbox.
+    #[must_use]
+    pub fn of_process(pid: u32) -> Option<Self> {
+        let proc = PathBuf::from(format!("/proc/{pid}"));
+
+        if let Some(sandbox) = Self::flatpak_from_info(&proc.join("root/.flatpak-info")) {
+            return Some(sandbox);
+        }
+
+        let cgroup = fs::read_to_string(proc.join("cgroup")).ok()?;
+        snap_from_cgroup(&cgroup).map(|name| Self::Snap { name })
+    }
+
+    /// Translates a path as seen from inside the sandboxed process `pid`
+    /// into a path which can be read from the host.
+    ///
+    /// Flatpak application files under `/app` are mapped to their install location.
+    /// Other paths are accessed through the process's root directory,
+    /// falling back to the original path if that does not exist.
+    #[must_use]
+    pub fn translate_path(&self, pid: u32, path: &str) -> PathBuf {
+        if let Self::Flatpak {
+            app_path: Some(app_path),
+            ..
+        } = self
+        {
+            if let Some(relative) = path.strip_prefix("/app/") {
+                return app_path.join(relative);
+            }
+        }
+
+        let rooted = PathBuf::from(format!("/proc/{pid}/root{path}"));
+        if rooted.exists() {
+            rooted
+        } else {
+            PathBuf::from(path)
+        }
+    }
+
+    fn flatpak_from_info(path: &Path) -> Option<Self> {
+        let contents = fs::read_to_string(path).ok()?;
+        parse_flatpak_info(&contents)
+    }
+}
+
+/// Parses the keyfile Flatpak places at `/.flatpak-info` inside each sandbox.
+fn parse_flatpak_info(contents: &str) -> Option<Sandbox> {
+    let mut section = "";
+    let mut app_id = None;
+    let mut app_path = None;
+
+    for line in contents.lines().map(str::trim) {
+        if let Some(name) = line
+            .strip_prefix('[')
+            .and_then(|line| line.strip_suffix(']'))
+        {
+            section = name;
+            continue;
+        }
+
+        let Some((key, value)) = line.split_once('=') else {
+            continue;
+        };
+
+        match (section, key.trim()) {
+            ("Application", "name") => app_id = Some(value.trim().to_string()),
+            ("Instance", "app-path") => app_path = Some(PathBuf::from(value.trim())),
+            _ => {}
+        }
+    }
+
+    app_id.map(|app_id| Sandbox::Flatpak { app_id, app_path })
+}
+
+/// Gets the snap name from a process's cgroups.
+///
+/// Confined snap applications run in a scope named `snap.<name>.<app>-<id>.scope`.
+fn snap_from_cgroup(cgroup: &str) -> Option<String> {
+    cgroup
+        .lines()
+        .flat_map(|line| line.split('/'))
+        .find_map(|part| part.strip_prefix("snap."))
+        .and_then(|part| part.split('.').next())
+        .map(ToString::to_string)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_flatpak_info() {
+        let contents = "[Application]\nname=org.example.App\nruntime=runtime/org.gnome.Platform\n\n\
+                        [Instance]\ninstance-id=123\napp-path=/var/lib/flatpak/app/org.example.App/files\n";
+
+        let sandbox = parse_flatpak_info(contents).expect("to parse");
+        assert_eq!(
+            sandbox,
+            Sandbox::Flatpak {
+                app_id: String::from("org.example.App"),
+                app_path: Some(PathBuf::from("/var/lib/flatpak/app/org.example.App/files")),
+            }
+        );
+
+        assert_eq!(
+            sandbox.translate_path(1, "/app/share/icons"),
+            PathBuf::from("/var/lib/flatpak/app/org.example.App/files/share/icons")
+        );
+    }
+
+    #[test]
+    fn parses_snap_cgroup() {
+        let cgroup = "0::/user.slice/user-1000.slice/user@1000.service/app.slice/snap.firefox.firefox-1234.scope\n";
+        assert_eq!(snap_from_cgroup(cgroup), Some(String::from("firefox")));
+        assert_eq!(snap_from_cgroup("0::/user.slice/session-2.scope\n"), None);
+    }
+}
@@ -0,0 +1,401 @@
+//! A private bus and mock items for tests which need a real bus.
+//!
+//! Each [`TestBus`] runs its own `dbus-daemon`,
+//! so tests do not depend on a session bus and can run in parallel.
+
+use crate::client::{Client, ClientBuilder, Event, UpdateEvent};
+use crate::names::{ITEM_OBJECT, WATCHER_BUS, WATCHER_OBJECT};
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader};
+use std::process::{Child, Command, Stdio};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::broadcast;
+use tokio::time::sleep;
+use zbus::zvariant::{OwnedObjectPath, OwnedValue};
+use zbus::{dbus_interface, Connection, ConnectionBuilder, InterfaceRef, SignalContext};
+
+/// The calls received by a mock item, in the order they started and finished.
+pub type Calls = Arc<Mutex<Vec<String>>>;
+
+/// A menu layout as sent over the bus.
+type DBusLayout = (i32, HashMap<String, OwnedValue>, Vec<OwnedValue>);
+
+/// A `dbus-daemon` started for a single test,
+/// which is stopped when dropped.
+#[derive(Debug)]
+pub struct TestBus {
+    daemon: Child,
+    address: String,
+}
+
+impl TestBus {
+    pub fn start() -> Self {
+        let mut daemon = Command::new("dbus-daemon")
+            .args(["--session", "--nofork", "--print-address=1"])
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .expect("dbus-daemon should start");
+
+        let mut address = String::new();
+        BufReader::new(daemon.stdout.take().expect("stdout should be piped"))
+            .read_line(&mut address)
+            .expect("dbus-daemon should print its address");
+
+        Self {
+            daemon,
+            address: address.trim().to_string(),
+        }
+    }
+
+    pub fn address(&self) -> &str {
+        &self.address
+    }
+
+    /// Gets a builder for a client on this bus.
+    pub fn builder(&self) -> ClientBuilder {
+        Client::builder().address(self.address())
+    }
+
+    pub async fn client(&self) -> Client {
+        self.builder().build().await.expect("client should start")
+    }
+
+    pub async fn connect(&self) -> Connection {
+        ConnectionBuilder::address(self.address())
+            .expect("address should be valid")
+            .build()
+            .await
+            .expect("connection should succeed")
+    }
+
+    /// Serves an item on a new connection and registers it with the watcher,
+    /// returning the connection the item is served on.
+    pub async fn serve_item(&self, item: MockItem) -> Connection {
+        let connection = self.connect().await;
+        serve_item_on(&connection, item).await;
+        connection
+    }
+
+    /// Serves an item on a new connection owning the well-known name `name`,
+    /// and registers it with the watcher by that name.
+    ///
+    /// The item is served before the name is requested,
+    /// so that it can be called as soon as the name appears.
+    pub async fn serve_named_item(&self, item: MockItem, name: &str) -> Connection {
+        let connection = self.connect().await;
+        serve_item_at(&connection, item).await;
+        connection
+            .request_name(name)
+            .await
+            .expect("name should be requested");
+        register_item(&connection, name).await;
+        connection
+    }
+}
+
+impl Drop for TestBus {
+    fn drop(&mut self) {
+        let _ = self.daemon.kill();
+        let _ = self.daemon.wait();
+    }
+}
+
+/// Serves an item at the standard path of `connection`,
+/// along with a menu at `/Menu`, and registers it with the watcher.
+pub async fn serve_item_on(connection: &Connection, item: MockItem) {
+    serve_item_at(connection, item).await;
+
+    let name = connection
+        .unique_name()
+        .expect("connection should be on a bus")
+        .to_string();
+    register_item(connection, &name).await;
+}
+
+/// Serves an item at the standard path of `connection`,
+/// along with a menu at `/Menu`, without registering it.
+pub async fn serve_item_at(connection: &Connection, item: MockItem) {
+    let menu = MockMenu {
+        calls: item.calls.clone(),
+        delay: item.delay,
+    };
+
+    connection
+        .object_server()
+        .at(ITEM_OBJECT, item)
+        .await
+        .expect("item should be served");
+    connection
+        .object_server()
+        .at("/Menu", menu)
+        .await
+        .expect("menu should be served");
+}
+
+/// Registers an item with the watcher.
+pub async fn register_item(connection: &Connection, service: &str) {
+    connection
+        .call_method(
+            Some(WATCHER_BUS),
+            WATCHER_OBJECT,
+            Some(WATCHER_BUS),
+            "RegisterStatusNotifierItem",
+            &(service,),
+        )
+        .await
+        .expect("item should register");
+}
+
+/// An item which records the calls it receives,
+/// taking `delay` to reply to each.
+#[derive(Debug)]
+pub struct MockItem {
+    id: String,
+    title: String,
+    status: String,
+    icon_name: String,
+    calls: Calls,
+    delay: Duration,
+}
+
+impl MockItem {
+    pub fn new(id: &str) -> Self {
+        Self {
+            id: id.to_string(),
+            title: id.to_string(),
+            status: String::from("Active"),
+            icon_name: String::new(),
+            calls: Calls::default(),
+            delay: Duration::ZERO,
+        }
+    }
+
+    pub fn delay(mut self, delay: Duration) -> Self {
+        self.delay = delay;
+        self
+    }
+
+    /// Gets the calls received by the item and its menu.
+    pub fn calls(&self) -> Calls {
+        self.calls.clone()
+    }
+}
+
+/// Gets the item served at the standard path of `connection`.
+async fn served_item(connection: &Connection) -> InterfaceRef<MockItem> {
+    connection
+        .object_server()
+        .interface(ITEM_OBJECT)
+        .await
+        .expect("item should be served")
+}
+
+/// Changes the title of the item served on `connection`,
+/// and tells the host with `NewTitle`.
+pub async fn set_title(connection: &Connection, title: &str) {
+    let item = served_item(connection).await;
+    item.get_mut().await.title = title.to_string();
+    MockItem::new_title(item.signal_context())
+        .await
+        .expect("signal should be sent");
+}
+
+/// Changes the status of the item served on `connection`,
+/// and tells the host with `NewStatus`.
+pub async fn set_status(connection: &Connection, status: &str) {
+    let item = served_item(connection).await;
+    item.get_mut().await.status = status.to_string();
+    MockItem::new_status(item.signal_context(), status)
+        .await
+        .expect("signal should be sent");
+}
+
+/// Changes the icon name of the item served on `connection`,
+/// and tells the host with `NewIcon`.
+pub async fn set_icon(connection: &Connection, icon_name: &str) {
+    let item = served_item(connection).await;
+    item.get_mut().await.icon_name = icon_name.to_string();
+    MockItem::new_icon(item.signal_context())
+        .await
+        .expect("signal should be sent");
+}
+
+/// Waits until the client sending to `rx` is watching the item served on `connection` for changes,
+/// which only starts after the item is added.
+///
+/// This repeats `NewStatus` without changing the status until an update is received,
+/// so tests should not rely on seeing a single status update.
+pub async fn wait_until_watched(connection: &Connection, rx: &mut broadcast::Receiver<Event>) {
+    let item = served_item(connection).await;
+    let is_status = |update: &UpdateEvent| matches!(update, UpdateEvent::Status(_));
+
+    tokio::time::timeout(Duration::from_secs(5), async {
+        loop {
+            let status = item.get().await.status.clone();
+            MockItem::new_status(item.signal_context(), &status)
+                .await
+                .expect("signal should be sent");
+
+            let updated = tokio::time::timeout(Duration::from_millis(300), async {
+                loop {
+                    match rx.recv().await.expect("client should be running") {
+                        Event::Update(_, update) if is_status(&update) => return,
+                        Event::UpdateBatch(_, updates) if updates.iter().any(is_status) => return,
+                        _ => {}
+                    }
+                }
+            })
+            .await;
+
+            if updated.is_ok() {
+                return;
+            }
+        }
+    })
+    .await
+    .expect("item should be watched");
+}
+
+/// Receives events until `f` returns a value,
+/// failing if none does within 5 seconds.
+pub async fn recv_until<T>(
+    rx: &mut broadcast::Receiver<Event>,
+    mut f: impl FnMut(Event) -> Option<T>,
+) -> T {
+    tokio::time::timeout(Duration::from_secs(5), async {
+        loop {
+            let event = rx.recv().await.expect("client should be running");
+            if let Some(value) = f(event) {
+                return value;
+            }
+        }
+    })
+    .await
+    .expect("expected event should be received")
+}
+
+/// Waits until `call` has been recorded.
+pub async fn wait_for_call(calls: &Calls, call: &str) {
+    tokio::time::timeout(Duration::from_secs(5), async {
+        while !calls
+            .lock()
+            .expect("mutex lock should succeed")
+            .iter()
+            .any(|recorded| recorded == call)
+        {
+            sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .unwrap_or_else(|_| panic!("'{call}' should be recorded"));
+}
+
+/// Records a call, taking `delay` to complete it.
+async fn record(calls: &Calls, delay: Duration, call: String) {
+    calls
+        .lock()
+        .expect("mutex lock should succeed")
+        .push(format!("start {call}"));
+
+    sleep(delay).await;
+
+    calls
+        .lock()
+        .expect("mutex lock should succeed")
+        .push(format!("end {call}"));
+}
+
+#[dbus_interface(name = "org.kde.StatusNotifierItem")]
+impl MockItem {
+    async fn activate(&self, x: i32, _y: i32) {
+        record(&self.calls, self.delay, format!("Activate {x}")).await;
+    }
+
+    async fn secondary_activate(&self, x: i32, _y: i32) {
+        record(&self.calls, self.delay, format!("SecondaryActivate {x}")).await;
+    }
+
+    #[dbus_interface(property)]
+    fn id(&self) -> String {
+        self.id.clone()
+    }
+
+    #[dbus_interface(property)]
+    fn title(&self) -> String {
+        self.title.clone()
+    }
+
+    #[dbus_interface(property)]
+    fn status(&self) -> String {
+        self.status.clone()
+    }
+
+    #[dbus_interface(property)]
+    fn icon_name(&self) -> String {
+        self.icon_name.clone()
+    }
+
+    #[dbus_interface(property)]
+    fn menu(&self) -> OwnedObjectPath {
+        OwnedObjectPath::try_from("/Menu").expect("path should be valid")
+    }
+
+    #[dbus_interface(signal)]
+    async fn new_title(ctxt: &SignalContext<'_>) -> zbus::Result<()>;
+
+    #[dbus_interface(signal)]
+    async fn new_icon(ctxt: &SignalContext<'_>) -> zbus::Result<()>;
+
+    #[dbus_interface(signal)]
+    async fn new_status(ctxt: &SignalContext<'_>, status: &str) -> zbus::Result<()>;
+}
+
+/// The menu of a [`MockItem`], which records its calls alongside the item's.
+#[derive(Debug)]
+struct MockMenu {
+    calls: Calls,
+    delay: Duration,
+}
+
+#[dbus_interface(name = "com.canonical.dbusmenu")]
+impl MockMenu {
+    fn get_layout(
+        &self,
+        _parent_id: i32,
+        _recursion_depth: i32,
+        _property_names: Vec<String>,
+    ) -> (u32, DBusLayout) {
+        (1, (0, HashMap::new(), Vec::new()))
+    }
+
+    async fn event(&self, id: i32, event_id: String, _data: OwnedValue, _timestamp: u32) {
+        record(&self.calls, self.delay, format!("Event {id} {event_id}")).await;
+    }
+
+    async fn event_group(&self, events: Vec<(i32, String, OwnedValue, u32)>) -> Vec<i32> {
+        let events = events
+            .iter()
+            .map(|(id, event_id, _, _)| format!("{id} {event_id}"))
+            .collect::<Vec<_>>();
+
+        record(
+            &self.calls,
+            self.delay,
+            format!("EventGroup {}", events.join(",")),
+        )
+        .await;
+        Vec::new()
+    }
+
+    async fn about_to_show(&self, id: i32) -> bool {
+        record(&self.calls, self.delay, format!("AboutToShow {id}")).await;
+        false
+    }
+
+    #[dbus_interface(property)]
+    fn version(&self) -> u32 {
+        3
+    }
+}
@@ -0,0 +1,275 @@
+use crate::backend::TrayBackend;
+use crate::client::{Event, UpdateEvent};
+use std::fmt::{Debug, Formatter};
+use std::time::{Duration, Instant};
+use tokio::sync::broadcast;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::time::timeout_at;
+
+/// An event captured by an [`EventRecorder`].
+#[derive(Debug, Clone)]
+pub struct RecordedEvent {
+    /// The time since the recorder was created.
+    pub at: Duration,
+    /// The event itself.
+    pub event: Event,
+}
+
+/// A description of an event a test expects to receive.
+pub struct Expect {
+    description: String,
+    predicate: Box<dyn Fn(&Event) -> bool + Send + Sync>,
+}
+
+impl Debug for Expect {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.description)
+    }
+}
+
+impl Expect {
+    /// Expects an event matching `predicate`.
+    /// The description is shown if the event is not received.
+    pub fn new(
+        description: impl Into<String>,
+        predicate: impl Fn(&Event) -> bool + Send + Sync + 'static,
+    ) -> Self {
+        Self {
+            description: description.into(),
+            predicate: Box::new(predicate),
+        }
+    }
+
+    /// Expects an item with the given id to be added.
+    #[must_use]
+    pub fn add(id: &str) -> Self {
+        let id = id.to_string();
+        Self::new(
+            format!("Add with id '{id}'"),
+            move |event| matches!(event, Event::Add(_, item) if item.id == id),
+        )
+    }
+
+    /// Expects the item at `address` to be removed.
+    #[must_use]
+    pub fn remove(address: &str) -> Self {
+        let address = address.to_string();
+        Self::new(
+            format!("Remove of '{address}'"),
+            move |event| matches!(event, Event::Remove(addr) if *addr == address),
+        )
+    }
+
+    /// Expects an update to the item at `address` matching `predicate`,
+    /// either on its own or as part of a batch.
+    pub fn update(
+        address: &str,
+        predicate: impl Fn(&UpdateEvent) -> bool + Send + Sync + 'static,
+    ) -> Self {
+        let address = address.to_string();
+        Self::new(format!("Update of '{address}'"), move |event| match event {
+            Event::Update(addr, update) => *addr == address && predicate(update),
+            Event::UpdateBatch(addr, updates) => *addr == address && updates.iter().any(&predicate),
+            _ => false,
+        })
+    }
+
+    /// Expects the full menu of the item at `address` to be sent.
+    #[must_use]
+    pub fn menu(address: &str) -> Self {
+        Self::update(address, |update| matches!(update, UpdateEvent::Menu(_)))
+    }
+
+    fn matches(&self, event: &Event) -> bool {
+        (self.predicate)(event)
+    }
+}
+
+/// Captures the event stream of a tray for use in tests,
+/// with helpers for asserting which events arrive.
+///
+/// Expectations wait for a matching event, skipping any others,
+/// and panic if none arrives before the timeout.
+/// All received events are kept and shown in the panic message.
+///
+/// ```no_run
+/// # use system_tray::client::Client;
+/// # use system_tray::test_util::{EventRecorder, Expect};
+/// # async fn run(client: Client) {
+/// let mut recorder = EventRecorder::new(&client);
+///
+/// // ...start a mock item...
+///
+/// let address = recorder.expect_add("my-app").await;
+/// recorder
+///     .expect_sequence([Expect::menu(&address), Expect::remove(&address)])
+///     .await;
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct EventRecorder {
+    rx: broadcast::Receiver<Event>,
+    start: Instant,
+    timeout: Duration,
+    events: Vec<RecordedEvent>,
+}
+
+impl EventRecorder {
+    /// How long expectations wait for a matching event by default.
+    pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(5);
+
+    /// Starts recording events from a tray.
+    ///
+    /// Only events sent after this is called are recorded.
+    #[must_use]
+    pub fn new(backend: &dyn TrayBackend) -> Self {
+        Self::from_receiver(backend.subscribe())
+    }
+
+    /// Starts recording events from an existing receiver.
+    #[must_use]
+    pub fn from_receiver(rx: broadcast::Receiver<Event>) -> Self {
+        Self {
+            rx,
+            start: Instant::now(),
+            timeout: Self::DEFAULT_TIMEOUT,
+            events: Vec::new(),
+        }
+    }
+
+    /// Sets how long expectations wait for a matching event.
+    #[must_use]
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Gets all events received so far.
+    #[must_use]
+    pub fn events(&self) -> &[RecordedEvent] {
+        &self.events
+    }
+
+    /// Waits for the next event,
+    /// returning `None` if none arrives before the timeout or the stream closes.
+    pub async fn next(&mut self) -> Option<&Event> {
+        let deadline = tokio::time::Instant::now() + self.timeout;
+        self.recv_until(deadline).await
+    }
+
+    /// Waits for an event matching `expect`, returning it.
+    ///
+    /// # Panics
+    ///
+    /// If no matching event arrives before the timeout.
+    pub async fn expect(&mut self, expect: Expect) -> Event {
+        let deadline = tokio::time::Instant::now() + self.timeout;
+
+        loop {
+            match self.recv_until(deadline).await {
+                Some(event) if expect.matches(event) => return event.clone(),
+                Some(_) => {}
+                None => self.fail(&expect),
+            }
+        }
+    }
+
+    /// Waits for an item with the given id to be added,
+    /// returning its address.
+    ///
+    /// # Panics
+    ///
+    /// If the item is not added before the timeout.
+    pub async fn expect_add(&mut self, id: &str) -> String {
+        match self.expect(Expect::add(id)).await {
+            Event::Add(address, _) => address,
+            _ => unreachable!("expectation only matches add events"),
+        }
+    }
+
+    /// Waits for the item at `address` to be removed.
+    ///
+    /// # Panics
+    ///
+    /// If the item is not removed before the timeout.
+    pub async fn expect_remove(&mut self, address: &str) {
+        self.expect(Expect::remove(address)).await;
+    }
+
+    /// Waits for events matching each expectation in order,
+    /// allowing other events in between.
+    ///
+    /// The timeout applies to each expectation separately.
+    ///
+    /// # Panics
+    ///
+    /// If any expected event does not arrive before the timeout.
+    pub async fn expect_sequence(&mut self, expected: impl IntoIterator<Item = Expect>) {
+        for expect in expected {
+            self.expect(expect).await;
+        }
+    }
+
+    async fn recv_until(&mut self, deadline: tokio::time::Instant) -> Option<&Event> {
+        loop {
+            match timeout_at(deadline, self.rx.recv()).await {
+                Ok(Ok(event)) => {
+                    self.events.push(RecordedEvent {
+                        at: self.start.elapsed(),
+                        event,
+                    });
+
+                    return self.events.last().map(|recorded| &recorded.event);
+                }
+                Ok(Err(RecvError::Lagged(count))) => {
+                    tracing::warn!("event recorder lagged, {count} events were missed");
+                }
+                Ok(Err(RecvError::Closed)) | Err(_) => return None,
+            }
+        }
+    }
+
+    fn fail(&self, expect: &Expect) -> ! {
+        let received = self
+            .events
+            .iter()
+            .map(|recorded| format!("  {:?}: {:?}", recorded.at, recorded.event))
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        panic!(
+            "expected {expect:?} within {:?}, but it was not received.\nreceived events:\n{received}",
+            self.timeout
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn expects_in_order() {
+        let (tx, rx) = broadcast::channel(8);
+        let mut recorder =
+            EventRecorder::from_receiver(rx).with_timeout(Duration::from_millis(100));
+
+        tx.send(Event::Update(
+            String::from(":1.1"),
+            UpdateEvent::Title(None),
+        ))
+        .expect("send should succeed");
+        tx.send(Event::Remove(String::from(":1.1")))
+            .expect("send should succeed");
+
+        recorder
+            .expect_sequence([
+                Expect::update(":1.1", |update| matches!(update, UpdateEvent::Title(_))),
+                Expect::remove(":1.1"),
+            ])
+            .await;
+
+        assert_eq!(recorder.events().len(), 2);
+        assert!(recorder.next().await.is_none());
+    }
+}
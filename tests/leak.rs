@@ -0,0 +1,214 @@
+//! Soak test which repeatedly registers and drops mock items,
+//! checking that heap usage and task counts return to their baseline.
+//!
+//! Requires the `leak-test` feature and `dbus-daemon`,
+//! which is started for the test so that it never runs against a session bus:
+//!
+//! ```sh
+//! cargo test --features leak-test --test leak
+//! ```
+//!
+//! The number of iterations can be set with `SOAK_ITERATIONS`.
+
+#![cfg(feature = "leak-test")]
+
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader};
+use std::process::{Child, Command, Stdio};
+use std::time::Duration;
+use system_tray::client::Client;
+use system_tray::test_util::EventRecorder;
+use zbus::zvariant::{ObjectPath, OwnedObjectPath, OwnedValue};
+use zbus::{dbus_interface, ConnectionBuilder};
+
+#[global_allocator]
+static ALLOC: dhat::Alloc = dhat::Alloc;
+
+/// Iterations run before taking the baseline,
+/// so that lazily initialised state is not counted as a leak.
+const WARMUP_ITERATIONS: usize = 5;
+const DEFAULT_ITERATIONS: usize = 100;
+
+/// Allowed heap growth over the whole run, to absorb allocator
+/// and bus bookkeeping noise without hiding a per-item leak.
+const HEAP_TOLERANCE: usize = 64 * 1024;
+
+/// A `dbus-daemon` started for the test,
+/// which is stopped when dropped.
+struct Bus {
+    daemon: Child,
+    address: String,
+}
+
+impl Bus {
+    fn start() -> Self {
+        let mut daemon = Command::new("dbus-daemon")
+            .args(["--session", "--nofork", "--print-address=1"])
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .expect("dbus-daemon should start");
+
+        let mut address = String::new();
+        BufReader::new(daemon.stdout.take().expect("stdout should be piped"))
+            .read_line(&mut address)
+            .expect("dbus-daemon should print its address");
+
+        Self {
+            daemon,
+            address: address.trim().to_string(),
+        }
+    }
+}
+
+impl Drop for Bus {
+    fn drop(&mut self) {
+        let _ = self.daemon.kill();
+        let _ = self.daemon.wait();
+    }
+}
+
+struct Item;
+
+#[dbus_interface(name = "org.kde.StatusNotifierItem")]
+impl Item {
+    #[dbus_interface(property)]
+    fn id(&self) -> String {
+        String::from("soak-item")
+    }
+
+    #[dbus_interface(property)]
+    fn title(&self) -> String {
+        String::from("Soak item")
+    }
+
+    #[dbus_interface(property)]
+    fn status(&self) -> String {
+        String::from("Active")
+    }
+
+    #[dbus_interface(property)]
+    fn category(&self) -> String {
+        String::from("ApplicationStatus")
+    }
+
+    #[dbus_interface(property)]
+    fn icon_name(&self) -> String {
+        String::from("soak")
+    }
+
+    #[dbus_interface(property)]
+    fn menu(&self) -> OwnedObjectPath {
+        ObjectPath::from_static_str_unchecked("/Menu").into()
+    }
+}
+
+/// Revision and root item of a menu layout.
+type Layout = (u32, (i32, HashMap<String, OwnedValue>, Vec<OwnedValue>));
+
+struct Menu;
+
+#[dbus_interface(name = "com.canonical.dbusmenu")]
+impl Menu {
+    fn get_layout(
+        &self,
+        _parent_id: i32,
+        _recursion_depth: i32,
+        _property_names: Vec<String>,
+    ) -> Layout {
+        (1, (0, HashMap::new(), Vec::new()))
+    }
+}
+
+/// Starts a mock item, waits for the client to add it,
+/// then drops it and waits for the client to remove it.
+async fn cycle(bus: &Bus, recorder: &mut EventRecorder) {
+    let connection = ConnectionBuilder::address(bus.address.as_str())
+        .expect("to parse bus address")
+        .build()
+        .await
+        .expect("to connect to bus");
+    connection
+        .object_server()
+        .at("/StatusNotifierItem", Item)
+        .await
+        .expect("to serve item");
+    connection
+        .object_server()
+        .at("/Menu", Menu)
+        .await
+        .expect("to serve menu");
+
+    let watcher = zbus::Proxy::new(
+        &connection,
+        "org.kde.StatusNotifierWatcher",
+        "/StatusNotifierWatcher",
+        "org.kde.StatusNotifierWatcher",
+    )
+    .await
+    .expect("to create watcher proxy");
+
+    let service = connection.unique_name().expect("unique name").to_string();
+    let _: () = watcher
+        .call("RegisterStatusNotifierItem", &(service.as_str()))
+        .await
+        .expect("to register item");
+
+    let address = recorder.expect_add("soak-item").await;
+    drop(watcher);
+    drop(connection);
+    recorder.expect_remove(&address).await;
+}
+
+/// Gives connections and tasks torn down asynchronously time to finish.
+async fn settle() {
+    tokio::time::sleep(Duration::from_millis(500)).await;
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn items_do_not_leak() {
+    let bus = Bus::start();
+    let _profiler = dhat::Profiler::builder().testing().build();
+
+    let iterations = std::env::var("SOAK_ITERATIONS")
+        .ok()
+        .and_then(|iterations| iterations.parse().ok())
+        .unwrap_or(DEFAULT_ITERATIONS);
+
+    let client = Client::builder()
+        .address(bus.address.as_str())
+        .build()
+        .await
+        .expect("to create client");
+    let mut recorder = EventRecorder::new(&client);
+
+    for _ in 0..WARMUP_ITERATIONS {
+        cycle(&bus, &mut recorder).await;
+    }
+
+    settle().await;
+    let metrics = tokio::runtime::Handle::current().metrics();
+    let baseline_tasks = metrics.num_alive_tasks();
+    let baseline_bytes = dhat::HeapStats::get().curr_bytes;
+
+    for _ in 0..iterations {
+        // recorded events would otherwise count as growth
+        recorder = EventRecorder::new(&client);
+        cycle(&bus, &mut recorder).await;
+    }
+
+    drop(recorder);
+    settle().await;
+    let tasks = metrics.num_alive_tasks();
+    let bytes = dhat::HeapStats::get().curr_bytes;
+
+    assert!(
+        tasks <= baseline_tasks,
+        "task count grew from {baseline_tasks} to {tasks} over {iterations} items"
+    );
+
+    dhat::assert!(
+        bytes <= baseline_bytes + HEAP_TOLERANCE,
+        "heap grew from {baseline_bytes} to {bytes} bytes over {iterations} items"
+    );
+}